@@ -0,0 +1,133 @@
+#[macro_use]
+extern crate log;
+extern crate clap;
+extern crate futures;
+extern crate rdkafka;
+extern crate tokio;
+
+use clap::{App, Arg};
+use futures::{lazy, Future, Stream};
+
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::stream_consumer::{PartitionStream, StreamConsumer};
+use rdkafka::consumer::{Consumer, ConsumerContext};
+use rdkafka::Message;
+
+mod example_utils;
+use crate::example_utils::setup_logger;
+
+// Processes the messages of a single partition. Since every partition has its own stream, the
+// messages are received in the same order they are stored in the partition, and a slow partition
+// doesn't delay the others.
+fn process_partition<C: ConsumerContext>(
+    stream: PartitionStream<'static, C>,
+) -> impl Future<Item = (), Error = ()> {
+    let topic = stream.topic().to_owned();
+    let partition = stream.partition();
+    info!("Partition {} [{}] assigned", topic, partition);
+    stream
+        .filter_map(|result| match result {
+            Ok(msg) => Some(msg),
+            Err(kafka_error) => {
+                warn!("Error while receiving from Kafka: {:?}", kafka_error);
+                None
+            }
+        })
+        .for_each(|message| {
+            info!(
+                "Message received from {} [{}] at offset {}",
+                message.topic(),
+                message.partition(),
+                message.offset()
+            );
+            Ok(())
+        })
+        .map(move |_| info!("Partition {} [{}] revoked", topic, partition))
+}
+
+// Spawns a new task for every partition assigned to the consumer. The main stream is still
+// required to drive the consumer, but it won't receive any message once all the assigned
+// partitions are split.
+fn run_partition_processors(brokers: &str, group_id: &str, topics: &[&str]) {
+    let consumer: StreamConsumer = ClientConfig::new()
+        .set("group.id", group_id)
+        .set("bootstrap.servers", brokers)
+        .set("enable.partition.eof", "false")
+        .set("session.timeout.ms", "6000")
+        .create()
+        .expect("Consumer creation failed");
+
+    consumer
+        .subscribe(topics)
+        .expect("Can't subscribe to specified topics");
+
+    // Tasks spawned on the tokio runtime must be 'static, and so must be the consumer the
+    // partition streams borrow from.
+    let consumer: &'static StreamConsumer = Box::leak(Box::new(consumer));
+
+    let partition_streams = consumer.split_partition_queues();
+    let main_stream = consumer.start();
+
+    tokio::run(lazy(move || {
+        tokio::spawn(partition_streams.for_each(|stream| {
+            tokio::spawn(process_partition(stream));
+            Ok(())
+        }));
+        main_stream.for_each(|message| {
+            if let Ok(message) = message {
+                warn!(
+                    "Message from partition {} [{}] received on the main stream",
+                    message.topic(),
+                    message.partition()
+                );
+            }
+            Ok(())
+        })
+    }));
+}
+
+fn main() {
+    let matches = App::new("partition streams example")
+        .version(option_env!("CARGO_PKG_VERSION").unwrap_or(""))
+        .about("Consumes each partition on a separate task")
+        .arg(
+            Arg::with_name("brokers")
+                .short("b")
+                .long("brokers")
+                .help("Broker list in kafka format")
+                .takes_value(true)
+                .default_value("localhost:9092"),
+        )
+        .arg(
+            Arg::with_name("group-id")
+                .short("g")
+                .long("group-id")
+                .help("Consumer group id")
+                .takes_value(true)
+                .default_value("example_consumer_group_id"),
+        )
+        .arg(
+            Arg::with_name("log-conf")
+                .long("log-conf")
+                .help("Configure the logging format (example: 'rdkafka=trace')")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("topics")
+                .short("t")
+                .long("topics")
+                .help("Topic list")
+                .takes_value(true)
+                .multiple(true)
+                .required(true),
+        )
+        .get_matches();
+
+    setup_logger(true, matches.value_of("log-conf"));
+
+    let topics = matches.values_of("topics").unwrap().collect::<Vec<&str>>();
+    let brokers = matches.value_of("brokers").unwrap();
+    let group_id = matches.value_of("group-id").unwrap();
+
+    run_partition_processors(brokers, group_id, &topics);
+}
@@ -1,5 +1,5 @@
-use types::RDKafkaError;
-use types::RDKafkaError::*;
+use types::RDKafkaErrorCode;
+use types::RDKafkaErrorCode::*;
 use types::RDKafkaRespErr;
 use types::RDKafkaRespErr::*;
 
@@ -151,7 +151,7 @@ pub fn primitive_to_rd_kafka_resp_err_t(error: i32) -> Option<RDKafkaRespErr> {
     }
 }
 
-pub fn rd_kafka_resp_err_t_to_rdkafka_error(err: RDKafkaRespErr) -> RDKafkaError {
+pub fn rd_kafka_resp_err_t_to_rdkafka_error(err: RDKafkaRespErr) -> RDKafkaErrorCode {
     match err {
         RD_KAFKA_RESP_ERR__BEGIN => Begin,
         RD_KAFKA_RESP_ERR__BAD_MSG => BadMessage,
@@ -87,6 +87,12 @@ pub use bindings::rd_kafka_type_t as RDKafkaType;
 /// Configuration result
 pub use bindings::rd_kafka_conf_res_t as RDKafkaConfRes;
 
+/// SSL certificate type
+pub use bindings::rd_kafka_cert_type_t as RDKafkaCertType;
+
+/// SSL certificate encoding
+pub use bindings::rd_kafka_cert_enc_t as RDKafkaCertEnc;
+
 /// Response error
 pub use bindings::rd_kafka_resp_err_t as RDKafkaRespErr;
 
@@ -101,9 +107,13 @@ pub use bindings::rd_kafka_ConfigSource_t as RDKafkaConfigSource;
 
 /// Errors enum
 
-/// Error from the underlying rdkafka library.
+/// Error code from the underlying rdkafka library. It includes both the errors generated
+/// locally by librdkafka, whose codes are between -200 and -100, and the errors returned by the
+/// broker. Use `is_local` to tell them apart. New codes might be added in future librdkafka
+/// versions.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum RDKafkaError {
+#[non_exhaustive]
+pub enum RDKafkaErrorCode {
     #[doc(hidden)]
     Begin = -200,
     /// Received message is incorrect
@@ -387,13 +397,42 @@ pub enum RDKafkaError {
     EndAll,
 }
 
-impl From<RDKafkaRespErr> for RDKafkaError {
-    fn from(err: RDKafkaRespErr) -> RDKafkaError {
+/// Former name of `RDKafkaErrorCode`, kept for backwards compatibility.
+pub type RDKafkaError = RDKafkaErrorCode;
+
+impl RDKafkaErrorCode {
+    /// Returns true if the error was generated locally by librdkafka, and false if it was
+    /// returned by the broker.
+    pub fn is_local(&self) -> bool {
+        let code = *self as i32;
+        code > RDKafkaErrorCode::Begin as i32 && code < RDKafkaErrorCode::End as i32
+    }
+}
+
+impl From<RDKafkaRespErr> for RDKafkaErrorCode {
+    fn from(err: RDKafkaRespErr) -> RDKafkaErrorCode {
         helpers::rd_kafka_resp_err_t_to_rdkafka_error(err)
     }
 }
 
-impl fmt::Display for RDKafkaError {
+/// Converts a raw error code. Codes unknown to this version of librdkafka are converted to
+/// `RDKafkaErrorCode::Unknown`.
+impl From<i32> for RDKafkaErrorCode {
+    fn from(code: i32) -> RDKafkaErrorCode {
+        match helpers::primitive_to_rd_kafka_resp_err_t(code) {
+            Some(err) => err.into(),
+            None => RDKafkaErrorCode::Unknown,
+        }
+    }
+}
+
+impl From<RDKafkaErrorCode> for i32 {
+    fn from(code: RDKafkaErrorCode) -> i32 {
+        code as i32
+    }
+}
+
+impl fmt::Display for RDKafkaErrorCode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let description = match helpers::primitive_to_rd_kafka_resp_err_t(*self as i32) {
             Some(err) => {
@@ -409,7 +448,7 @@ impl fmt::Display for RDKafkaError {
     }
 }
 
-impl error::Error for RDKafkaError {
+impl error::Error for RDKafkaErrorCode {
     fn description(&self) -> &str {
         "Error from underlying rdkafka library"
     }
@@ -421,11 +460,29 @@ mod tests {
 
     #[test]
     fn test_display_error() {
-        let error: RDKafkaError = RDKafkaRespErr::RD_KAFKA_RESP_ERR__PARTITION_EOF.into();
+        let error: RDKafkaErrorCode = RDKafkaRespErr::RD_KAFKA_RESP_ERR__PARTITION_EOF.into();
         assert_eq!(
             "PartitionEOF (Broker: No more messages)",
             format!("{}", error)
         );
         assert_eq!("PartitionEOF", format!("{:?}", error));
     }
+
+    #[test]
+    fn test_error_code_conversion() {
+        let codes = [
+            (RDKafkaErrorCode::UnknownTopicOrPartition, 3, false),
+            (RDKafkaErrorCode::MessageTimedOut, -192, true),
+            (RDKafkaErrorCode::PartitionEOF, -191, true),
+            (RDKafkaErrorCode::NotLeaderForPartition, 6, false),
+            (RDKafkaErrorCode::Unknown, -1, false),
+        ];
+        for &(code, raw, local) in codes.iter() {
+            assert_eq!(RDKafkaErrorCode::from(raw), code);
+            assert_eq!(i32::from(code), raw);
+            assert_eq!(code.is_local(), local);
+        }
+        assert_eq!(RDKafkaErrorCode::from(-1000), RDKafkaErrorCode::Unknown);
+        assert_eq!(RDKafkaErrorCode::from(10_000), RDKafkaErrorCode::Unknown);
+    }
 }
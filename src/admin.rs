@@ -10,16 +10,18 @@ use crate::rdsys::types::*;
 use crate::client::{Client, ClientContext, DefaultClientContext, NativeQueue};
 use crate::config::{ClientConfig, FromClientConfig, FromClientConfigAndContext};
 use crate::error::{IsError, KafkaError, KafkaResult};
-use crate::util::{cstr_to_owned, timeout_to_ms, AsCArray, ErrBuf, IntoOpaque, WrappedCPointer};
+use crate::util::{
+    cstr_to_owned, timeout_to_ms, AsCArray, ErrBuf, IntoOpaque, Timeout, WrappedCPointer,
+};
 
 use futures::future::{self, Either};
 use futures::{Async, Canceled, Complete, Future, Oneshot, Poll};
 
 use std::collections::HashMap;
 use std::ffi::{CStr, CString};
-use std::mem;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::os::raw::{c_char, c_void};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
 use std::time::Duration;
 
@@ -34,6 +36,7 @@ use std::time::Duration;
 pub struct AdminClient<C: ClientContext> {
     client: Client<C>,
     queue: Arc<NativeQueue>,
+    pending_ops: Arc<PendingOps>,
     should_stop: Arc<AtomicBool>,
     handle: Option<JoinHandle<()>>,
 }
@@ -71,7 +74,8 @@ impl<C: ClientContext> AdminClient<C> {
         for t in topics {
             native_topics.push(t.to_native(&mut err_buf)?);
         }
-        let (native_opts, rx) = opts.to_native(self.client.native_ptr(), &mut err_buf)?;
+        let (native_opts, rx) =
+            opts.to_native(self.client.native_ptr(), &self.pending_ops, &mut err_buf)?;
         unsafe {
             rdsys::rd_kafka_CreateTopics(
                 self.client.native_ptr(),
@@ -114,7 +118,8 @@ impl<C: ClientContext> AdminClient<C> {
             };
             native_topics.push(native_topic);
         }
-        let (native_opts, rx) = opts.to_native(self.client.native_ptr(), &mut err_buf)?;
+        let (native_opts, rx) =
+            opts.to_native(self.client.native_ptr(), &self.pending_ops, &mut err_buf)?;
         unsafe {
             rdsys::rd_kafka_DeleteTopics(
                 self.client.native_ptr(),
@@ -134,6 +139,10 @@ impl<C: ClientContext> AdminClient<C> {
     /// at once, it is not transactional. Creation of partitions for some topics
     /// may succeed while others fail. Be sure to check the result of each
     /// individual operation.
+    ///
+    /// The number of partitions of a topic can't be decreased: requesting fewer
+    /// partitions than the topic currently has results in an
+    /// `RDKafkaErrorCode::InvalidPartitions` error for that topic.
     pub fn create_partitions<'a, I>(
         &self,
         partitions: I,
@@ -161,7 +170,8 @@ impl<C: ClientContext> AdminClient<C> {
         for p in partitions {
             native_partitions.push(p.to_native(&mut err_buf)?);
         }
-        let (native_opts, rx) = opts.to_native(self.client.native_ptr(), &mut err_buf)?;
+        let (native_opts, rx) =
+            opts.to_native(self.client.native_ptr(), &self.pending_ops, &mut err_buf)?;
         unsafe {
             rdsys::rd_kafka_CreatePartitions(
                 self.client.native_ptr(),
@@ -225,7 +235,8 @@ impl<C: ClientContext> AdminClient<C> {
                 ))
             });
         }
-        let (native_opts, rx) = opts.to_native(self.client.native_ptr(), &mut err_buf)?;
+        let (native_opts, rx) =
+            opts.to_native(self.client.native_ptr(), &self.pending_ops, &mut err_buf)?;
         unsafe {
             rdsys::rd_kafka_DescribeConfigs(
                 self.client.native_ptr(),
@@ -270,7 +281,8 @@ impl<C: ClientContext> AdminClient<C> {
         for c in configs {
             native_configs.push(c.to_native(&mut err_buf)?);
         }
-        let (native_opts, rx) = opts.to_native(self.client.native_ptr(), &mut err_buf)?;
+        let (native_opts, rx) =
+            opts.to_native(self.client.native_ptr(), &self.pending_ops, &mut err_buf)?;
         unsafe {
             rdsys::rd_kafka_AlterConfigs(
                 self.client.native_ptr(),
@@ -282,6 +294,38 @@ impl<C: ClientContext> AdminClient<C> {
         }
         Ok(rx)
     }
+
+    /// Returns the id of the cluster, waiting up to `timeout` for the metadata of the cluster to
+    /// be available. Fails with `RDKafkaErrorCode::OperationTimedOut` if the metadata wasn't
+    /// received in time, or if the brokers are too old to report the cluster id.
+    pub fn cluster_id<T: Into<Timeout>>(&self, timeout: T) -> KafkaResult<String> {
+        let client_ptr = self.client.native_ptr();
+        let cluster_id_ptr =
+            unsafe { rdsys::rd_kafka_clusterid(client_ptr, timeout.into().as_millis()) };
+        if cluster_id_ptr.is_null() {
+            return Err(KafkaError::MetadataFetch(
+                RDKafkaErrorCode::OperationTimedOut,
+            ));
+        }
+        let cluster_id = unsafe { cstr_to_owned(cluster_id_ptr) };
+        unsafe { rdsys::rd_kafka_mem_free(client_ptr, cluster_id_ptr as *mut c_void) };
+        Ok(cluster_id)
+    }
+
+    /// Returns the id of the controller broker, waiting up to `timeout` for the metadata of the
+    /// cluster to be available. Fails with `RDKafkaErrorCode::OperationTimedOut` if the metadata
+    /// wasn't received in time.
+    pub fn controller_id<T: Into<Timeout>>(&self, timeout: T) -> KafkaResult<i32> {
+        let controller_id = unsafe {
+            rdsys::rd_kafka_controllerid(self.client.native_ptr(), timeout.into().as_millis())
+        };
+        if controller_id < 0 {
+            return Err(KafkaError::MetadataFetch(
+                RDKafkaErrorCode::OperationTimedOut,
+            ));
+        }
+        Ok(controller_id)
+    }
 }
 
 impl FromClientConfig for AdminClient<DefaultClientContext> {
@@ -305,11 +349,13 @@ impl<C: ClientContext> FromClientConfigAndContext<C> for AdminClient<C> {
             context,
         )?;
         let queue = Arc::new(client.new_native_queue());
+        let pending_ops = Arc::new(PendingOps::default());
         let should_stop = Arc::new(AtomicBool::new(false));
-        let handle = start_poll_thread(queue.clone(), should_stop.clone());
+        let handle = start_poll_thread(queue.clone(), pending_ops.clone(), should_stop.clone());
         Ok(AdminClient {
             client,
             queue,
+            pending_ops,
             should_stop,
             handle: Some(handle),
         })
@@ -325,10 +371,16 @@ impl<C: ClientContext> Drop for AdminClient<C> {
             Ok(()) => trace!("Polling stopped"),
             Err(e) => warn!("Failure while terminating thread: {:?}", e),
         };
+        // The results of the operations still pending will never be received.
+        self.pending_ops.cancel_all();
     }
 }
 
-fn start_poll_thread(queue: Arc<NativeQueue>, should_stop: Arc<AtomicBool>) -> JoinHandle<()> {
+fn start_poll_thread(
+    queue: Arc<NativeQueue>,
+    pending_ops: Arc<PendingOps>,
+    should_stop: Arc<AtomicBool>,
+) -> JoinHandle<()> {
     thread::Builder::new()
         .name("admin client polling thread".into())
         .spawn(move || {
@@ -344,15 +396,52 @@ fn start_poll_thread(queue: Arc<NativeQueue>, should_stop: Arc<AtomicBool>) -> J
                     continue;
                 }
                 let event = unsafe { NativeEvent::from_ptr(event) };
-                let tx: Box<Complete<NativeEvent>> =
+                let id: usize =
                     unsafe { IntoOpaque::from_ptr(rdsys::rd_kafka_event_opaque(event.ptr())) };
-                let _ = tx.send(event);
+                pending_ops.complete(id, event);
             }
             trace!("Admin polling thread loop terminated");
         })
         .expect("Failed to start polling thread")
 }
 
+/// The admin operations waiting for their result event. Each operation is identified by the id
+/// passed to librdkafka as its opaque, and the polling thread completes its future when the
+/// event is received. Events of operations whose future was dropped are destroyed right away.
+#[derive(Default)]
+struct PendingOps {
+    next_id: AtomicUsize,
+    ops: Mutex<HashMap<usize, Complete<NativeEvent>>>,
+}
+
+impl PendingOps {
+    /// Registers a new operation, returning its id and the receiver of its result event.
+    fn register(&self) -> (usize, Oneshot<NativeEvent>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = futures::oneshot();
+        self.ops.lock().unwrap().insert(id, tx);
+        (id, rx)
+    }
+
+    /// Sends the result event of an operation to its future.
+    fn complete(&self, id: usize, event: NativeEvent) {
+        let tx = self.ops.lock().unwrap().remove(&id);
+        match tx {
+            Some(tx) => {
+                if tx.send(event).is_err() {
+                    trace!("Dropping the result of canceled admin operation {}", id);
+                }
+            }
+            None => warn!("Received the result of unknown admin operation {}", id),
+        }
+    }
+
+    /// Drops all the pending operations, so that their futures fail with `Canceled`.
+    fn cancel_all(&self) {
+        self.ops.lock().unwrap().clear();
+    }
+}
+
 struct NativeEvent {
     ptr: *mut RDKafkaEvent,
 }
@@ -369,13 +458,29 @@ impl NativeEvent {
     fn check_error(&self) -> KafkaResult<()> {
         let err = unsafe { rdsys::rd_kafka_event_error(self.ptr) };
         if err.is_error() {
-            Err(KafkaError::AdminOp(err.into()))
+            // The error string is owned by the event, so it's copied before the event is
+            // destroyed.
+            let errstr = unsafe { rdsys::rd_kafka_event_error_string(self.ptr) };
+            Err(unsafe { admin_op_error(err, errstr) })
         } else {
             Ok(())
         }
     }
 }
 
+/// Builds the error of a failed admin operation from its error code and error string. The error
+/// string is dropped if it's the generic description of the error code.
+unsafe fn admin_op_error(err: RDKafkaRespErr, errstr: *const c_char) -> KafkaError {
+    let detail = if errstr.is_null()
+        || CStr::from_ptr(errstr) == CStr::from_ptr(rdsys::rd_kafka_err2str(err))
+    {
+        None
+    } else {
+        Some(cstr_to_owned(errstr))
+    };
+    KafkaError::AdminOp(err.into(), detail)
+}
+
 impl Drop for NativeEvent {
     fn drop(&mut self) {
         trace!("Destroying event: {:?}", self.ptr);
@@ -456,6 +561,7 @@ impl AdminOptions {
     fn to_native(
         &self,
         client: *mut RDKafka,
+        pending_ops: &PendingOps,
         err_buf: &mut ErrBuf,
     ) -> KafkaResult<(NativeAdminOptions, Oneshot<NativeEvent>)> {
         let native_opts = unsafe {
@@ -513,12 +619,10 @@ impl AdminOptions {
             check_rdkafka_invalid_arg(res, err_buf)?;
         }
 
-        let (tx, rx) = futures::oneshot();
-        let tx = Box::new(tx);
+        let (id, rx) = pending_ops.register();
         unsafe {
-            rdsys::rd_kafka_AdminOptions_set_opaque(native_opts.ptr, IntoOpaque::as_ptr(&tx))
+            rdsys::rd_kafka_AdminOptions_set_opaque(native_opts.ptr, IntoOpaque::as_ptr(&id))
         };
-        mem::forget(tx);
 
         Ok((native_opts, rx))
     }
@@ -550,8 +654,8 @@ impl Drop for NativeAdminOptions {
 
 fn check_rdkafka_invalid_arg(res: RDKafkaRespErr, err_buf: &ErrBuf) -> KafkaResult<()> {
     match res.into() {
-        RDKafkaError::NoError => Ok(()),
-        RDKafkaError::InvalidArgument => {
+        RDKafkaErrorCode::NoError => Ok(()),
+        RDKafkaErrorCode::InvalidArgument => {
             let msg = if err_buf.len() == 0 {
                 "invalid argument".into()
             } else {
@@ -572,7 +676,7 @@ fn check_rdkafka_invalid_arg(res: RDKafkaRespErr, err_buf: &ErrBuf) -> KafkaResu
 
 /// The result of an individual CreateTopic, DeleteTopic, or
 /// CreatePartition operation.
-pub type TopicResult = Result<String, (String, RDKafkaError)>;
+pub type TopicResult = Result<String, (String, RDKafkaErrorCode)>;
 
 fn build_topic_results(topics: *const *const RDKafkaTopicResult, n: usize) -> Vec<TopicResult> {
     let mut out = Vec::with_capacity(n);
@@ -975,7 +1079,7 @@ impl Future for CreatePartitionsFuture {
 //
 
 /// The result of an individual DescribeConfig operation.
-pub type ConfigResourceResult = Result<ConfigResource, RDKafkaError>;
+pub type ConfigResourceResult = Result<ConfigResource, RDKafkaErrorCode>;
 
 /// Specification of a configurable resource.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -1142,6 +1246,37 @@ fn extract_config_source(config_source: RDKafkaConfigSource) -> KafkaResult<Conf
     }
 }
 
+fn extract_config_resource(resource: *const RDKafkaConfigResource) -> KafkaResult<ConfigResource> {
+    let specifier = extract_config_specifier(resource)?;
+    let mut entries_out = Vec::new();
+    let mut n = 0;
+    let entries = unsafe { rdsys::rd_kafka_ConfigResource_configs(resource, &mut n) };
+    for j in 0..n {
+        let entry = unsafe { *entries.offset(j as isize) };
+        let name = unsafe { cstr_to_owned(rdsys::rd_kafka_ConfigEntry_name(entry)) };
+        let value = unsafe {
+            let value = rdsys::rd_kafka_ConfigEntry_value(entry);
+            if value.is_null() {
+                None
+            } else {
+                Some(cstr_to_owned(value))
+            }
+        };
+        entries_out.push(ConfigEntry {
+            name,
+            value,
+            source: extract_config_source(unsafe { rdsys::rd_kafka_ConfigEntry_source(entry) })?,
+            is_read_only: unsafe { rdsys::rd_kafka_ConfigEntry_is_read_only(entry) } != 0,
+            is_default: unsafe { rdsys::rd_kafka_ConfigEntry_is_default(entry) } != 0,
+            is_sensitive: unsafe { rdsys::rd_kafka_ConfigEntry_is_sensitive(entry) } != 0,
+        });
+    }
+    Ok(ConfigResource {
+        specifier,
+        entries: entries_out,
+    })
+}
+
 struct DescribeConfigsFuture {
     rx: Oneshot<NativeEvent>,
 }
@@ -1168,43 +1303,7 @@ impl Future for DescribeConfigsFuture {
                 let mut out = Vec::with_capacity(n);
                 for i in 0..n {
                     let resource = unsafe { *resources.offset(i as isize) };
-                    let specifier = extract_config_specifier(resource)?;
-                    let mut entries_out = Vec::new();
-                    let mut n = 0;
-                    let entries =
-                        unsafe { rdsys::rd_kafka_ConfigResource_configs(resource, &mut n) };
-                    for j in 0..n {
-                        let entry = unsafe { *entries.offset(j as isize) };
-                        let name =
-                            unsafe { cstr_to_owned(rdsys::rd_kafka_ConfigEntry_name(entry)) };
-                        let value = unsafe {
-                            let value = rdsys::rd_kafka_ConfigEntry_value(entry);
-                            if value.is_null() {
-                                None
-                            } else {
-                                Some(cstr_to_owned(value))
-                            }
-                        };
-                        entries_out.push(ConfigEntry {
-                            name,
-                            value,
-                            source: extract_config_source(unsafe {
-                                rdsys::rd_kafka_ConfigEntry_source(entry)
-                            })?,
-                            is_read_only: unsafe {
-                                rdsys::rd_kafka_ConfigEntry_is_read_only(entry)
-                            } != 0,
-                            is_default: unsafe { rdsys::rd_kafka_ConfigEntry_is_default(entry) }
-                                != 0,
-                            is_sensitive: unsafe {
-                                rdsys::rd_kafka_ConfigEntry_is_sensitive(entry)
-                            } != 0,
-                        });
-                    }
-                    out.push(Ok(ConfigResource {
-                        specifier: specifier,
-                        entries: entries_out,
-                    }))
+                    out.push(Ok(extract_config_resource(resource)?));
                 }
                 Ok(Async::Ready(out))
             }
@@ -1220,7 +1319,7 @@ impl Future for DescribeConfigsFuture {
 
 /// The result of an individual AlterConfig operation.
 pub type AlterConfigsResult =
-    Result<OwnedResourceSpecifier, (OwnedResourceSpecifier, RDKafkaError)>;
+    Result<OwnedResourceSpecifier, (OwnedResourceSpecifier, RDKafkaErrorCode)>;
 
 /// Configuration for an AlterConfig operation.
 pub struct AlterConfig<'a> {
@@ -1317,3 +1416,191 @@ impl Future for AlterConfigsFuture {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    // The responses of the brokers can't be built with the librdkafka API, so the config
+    // resources are built as if they were part of an AlterConfigs request. The requests
+    // themselves are tested in the integration tests.
+    use super::*;
+
+    use std::ffi::CString;
+
+    fn native_config_resource(
+        typ: RDKafkaResourceType,
+        name: &str,
+        entries: &[(&str, &str)],
+    ) -> NativeConfigResource {
+        let name = CString::new(name).unwrap();
+        let resource = unsafe {
+            NativeConfigResource::from_ptr(rdsys::rd_kafka_ConfigResource_new(typ, name.as_ptr()))
+        };
+        for &(key, value) in entries {
+            let key = CString::new(key).unwrap();
+            let value = CString::new(value).unwrap();
+            let err = unsafe {
+                rdsys::rd_kafka_ConfigResource_set_config(
+                    resource.ptr(),
+                    key.as_ptr(),
+                    value.as_ptr(),
+                )
+            };
+            assert!(!err.is_error());
+        }
+        resource
+    }
+
+    #[test]
+    fn test_admin_op_error_detail() {
+        let errstr = CString::new("Failed while waiting for controller: Local: Timed out").unwrap();
+        let err = unsafe {
+            admin_op_error(
+                RDKafkaRespErr::RD_KAFKA_RESP_ERR__TIMED_OUT,
+                errstr.as_ptr(),
+            )
+        };
+        drop(errstr);
+        assert_eq!(
+            err.rdkafka_error_code(),
+            Some(RDKafkaErrorCode::OperationTimedOut)
+        );
+        assert_eq!(
+            err.detail(),
+            Some("Failed while waiting for controller: Local: Timed out")
+        );
+        assert_eq!(
+            err.to_string(),
+            "Admin operation error: OperationTimedOut (Local: Timed out): \
+             Failed while waiting for controller: Local: Timed out"
+        );
+
+        // A string that only describes the error code doesn't add anything.
+        let errstr =
+            unsafe { rdsys::rd_kafka_err2str(RDKafkaRespErr::RD_KAFKA_RESP_ERR__TIMED_OUT) };
+        let err = unsafe { admin_op_error(RDKafkaRespErr::RD_KAFKA_RESP_ERR__TIMED_OUT, errstr) };
+        assert_eq!(
+            err,
+            KafkaError::AdminOp(RDKafkaErrorCode::OperationTimedOut, None)
+        );
+        assert_eq!(err.detail(), None);
+        let err = unsafe {
+            admin_op_error(
+                RDKafkaRespErr::RD_KAFKA_RESP_ERR__TIMED_OUT,
+                std::ptr::null(),
+            )
+        };
+        assert_eq!(err.detail(), None);
+    }
+
+    // Results of operations whose future was dropped are discarded, and the futures of the
+    // operations still pending when the client is dropped fail.
+    #[test]
+    fn test_pending_ops_cancellation() {
+        let admin_client: AdminClient<DefaultClientContext> = ClientConfig::new()
+            .set("bootstrap.servers", "localhost:1")
+            .create()
+            .unwrap();
+        let topic = || NewTopic::new("topic1", 1, TopicReplication::Fixed(1));
+
+        let opts = AdminOptions::new().request_timeout(Duration::from_millis(1));
+        drop(admin_client.create_topics(&[topic()], &opts));
+        let res = admin_client.create_topics(&[topic()], &opts).wait();
+        assert_eq!(
+            res.unwrap_err().rdkafka_error_code(),
+            Some(RDKafkaErrorCode::OperationTimedOut)
+        );
+        assert!(admin_client.pending_ops.ops.lock().unwrap().is_empty());
+
+        let opts = AdminOptions::new().request_timeout(Duration::from_secs(60));
+        let topics = [topic()];
+        let future = admin_client.create_topics(&topics, &opts);
+        drop(admin_client);
+        assert_eq!(future.wait(), Err(KafkaError::Canceled));
+    }
+
+    #[test]
+    fn test_cluster_id_and_controller_id_timeout() {
+        let admin_client: AdminClient<DefaultClientContext> = ClientConfig::new()
+            .set("bootstrap.servers", "localhost:1")
+            .create()
+            .unwrap();
+        let timeout = Duration::from_millis(100);
+        assert_eq!(
+            admin_client.cluster_id(timeout),
+            Err(KafkaError::MetadataFetch(
+                RDKafkaErrorCode::OperationTimedOut
+            ))
+        );
+        assert_eq!(
+            admin_client.controller_id(timeout),
+            Err(KafkaError::MetadataFetch(
+                RDKafkaErrorCode::OperationTimedOut
+            ))
+        );
+    }
+
+    #[test]
+    fn test_extract_config_resource() {
+        let resource = native_config_resource(
+            RDKafkaResourceType::RD_KAFKA_RESOURCE_TOPIC,
+            "topic1",
+            &[("retention.ms", "1000"), ("cleanup.policy", "compact")],
+        );
+        let config = extract_config_resource(resource.ptr()).unwrap();
+        assert_eq!(
+            config.specifier,
+            OwnedResourceSpecifier::Topic("topic1".to_owned())
+        );
+        assert_eq!(
+            config.get("retention.ms"),
+            Some(&ConfigEntry {
+                name: "retention.ms".to_owned(),
+                value: Some("1000".to_owned()),
+                source: ConfigSource::Unknown,
+                is_read_only: false,
+                is_default: false,
+                is_sensitive: false,
+            })
+        );
+        assert_eq!(
+            config.entry_map()["cleanup.policy"].value.as_deref(),
+            Some("compact")
+        );
+
+        let resource =
+            native_config_resource(RDKafkaResourceType::RD_KAFKA_RESOURCE_BROKER, "1", &[]);
+        let config = extract_config_resource(resource.ptr()).unwrap();
+        assert_eq!(config.specifier, OwnedResourceSpecifier::Broker(1));
+        assert!(config.entries.is_empty());
+    }
+
+    #[test]
+    fn test_extract_config_resource_bogus_broker_id() {
+        let resource = native_config_resource(
+            RDKafkaResourceType::RD_KAFKA_RESOURCE_BROKER,
+            "broker1",
+            &[],
+        );
+        match extract_config_resource(resource.ptr()) {
+            Err(KafkaError::AdminOpCreation(msg)) => {
+                assert_eq!(msg, "bogus broker ID in kafka response: broker1")
+            }
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_new_partitions_to_native() {
+        let mut err_buf = ErrBuf::new();
+        assert!(NewPartitions::new("topic1", 6)
+            .assign(&[&[0], &[1], &[2]])
+            .to_native(&mut err_buf)
+            .is_ok());
+
+        // Topics can't have zero partitions.
+        match NewPartitions::new("topic1", 0).to_native(&mut err_buf) {
+            Err(KafkaError::AdminOpCreation(_)) => (),
+            res => panic!("Unexpected result: {:?}", res.map(|_| ())),
+        }
+    }
+}
@@ -2,6 +2,7 @@
 use crate::rdsys;
 use crate::rdsys::types::*;
 
+use std::collections::HashMap;
 use std::ffi::{CStr, CString};
 use std::mem;
 use std::os::raw::c_char;
@@ -9,16 +10,16 @@ use std::os::raw::c_void;
 use std::ptr;
 use std::slice;
 use std::string::ToString;
-use std::time::Duration;
 
 use serde_json;
 
 use crate::config::{ClientConfig, NativeClientConfig, RDKafkaLogLevel};
+use crate::consumer::base_consumer::ConsumerState;
 use crate::error::{IsError, KafkaError, KafkaResult};
 use crate::groups::GroupList;
-use crate::metadata::Metadata;
+use crate::metadata::{Metadata, OwnedMetadata};
 use crate::statistics::Statistics;
-use crate::util::{timeout_to_ms, ErrBuf};
+use crate::util::{cstr_to_owned, timeout_to_ms, ErrBuf, Timeout};
 
 /// Client-level context
 ///
@@ -29,7 +30,34 @@ use crate::util::{timeout_to_ms, ErrBuf};
 /// be overridden. Implementations of `ClientContext` must be thread safe, as they might be owned by
 /// multiple threads.
 pub trait ClientContext: Send + Sync {
-    /// Receives log lines from librdkafka.
+    /// Receives log lines from librdkafka. By default, the lines are logged using the `log`
+    /// crate, or emitted as `tracing` events with the facility and the message as fields if the
+    /// `tracing` feature is enabled.
+    #[cfg(feature = "tracing")]
+    fn log(&self, level: RDKafkaLogLevel, fac: &str, log_message: &str) {
+        match level {
+            RDKafkaLogLevel::Emerg
+            | RDKafkaLogLevel::Alert
+            | RDKafkaLogLevel::Critical
+            | RDKafkaLogLevel::Error => {
+                tracing::error!(target: "librdkafka", facility = fac, message = log_message)
+            }
+            RDKafkaLogLevel::Warning => {
+                tracing::warn!(target: "librdkafka", facility = fac, message = log_message)
+            }
+            RDKafkaLogLevel::Notice | RDKafkaLogLevel::Info => {
+                tracing::info!(target: "librdkafka", facility = fac, message = log_message)
+            }
+            RDKafkaLogLevel::Debug => {
+                tracing::debug!(target: "librdkafka", facility = fac, message = log_message)
+            }
+        }
+    }
+
+    /// Receives log lines from librdkafka. By default, the lines are logged using the `log`
+    /// crate, or emitted as `tracing` events with the facility and the message as fields if the
+    /// `tracing` feature is enabled.
+    #[cfg(not(feature = "tracing"))]
     fn log(&self, level: RDKafkaLogLevel, fac: &str, log_message: &str) {
         match level {
             RDKafkaLogLevel::Emerg
@@ -59,15 +87,58 @@ pub trait ClientContext: Send + Sync {
         info!("Client stats: {:?}", statistics);
     }
 
+    /// Receives the statistics of the librdkafka client as raw JSON. The default
+    /// implementation parses the JSON and passes the result to `stats`; override it to
+    /// read fields that are not part of `Statistics`, or to avoid parsing altogether.
+    fn stats_raw(&self, statistics: &[u8]) {
+        match serde_json::from_slice(statistics) {
+            Ok(stats) => self.stats(stats),
+            Err(e) => error!("Could not parse statistics JSON: {}", e),
+        }
+    }
+
     /// Receives global errors from the librdkafka client.
     fn error(&self, error: KafkaError, reason: &str) {
         error!("librdkafka: {}: {}", error, reason);
     }
 
-    // NOTE: when adding a new method, remember to add it to the FutureProducerContext as well.
+    /// Whether `generate_oauth_token` should be used to get the tokens of the SASL OAUTHBEARER
+    /// mechanism. It must be enabled when `sasl.mechanisms` is set to `OAUTHBEARER`, unless
+    /// `enable.sasl.oauthbearer.unsecure.jwt` is set to use the unsecured tokens generated by
+    /// librdkafka, for testing.
+    const ENABLE_REFRESH_OAUTH_TOKEN: bool = false;
+
+    /// Generates a new token for the SASL OAUTHBEARER mechanism. It is called when the client
+    /// is created, and then again before the lifetime of the current token expires.
+    /// `oauthbearer_config` is the value of the `sasl.oauthbearer.config` configuration
+    /// parameter, if set. If an error is returned, the client fails the authentication with
+    /// the error message, and calls this method again later.
+    ///
+    /// Only called if `ENABLE_REFRESH_OAUTH_TOKEN` is true.
+    #[allow(unused_variables)]
+    fn generate_oauth_token(&self, oauthbearer_config: Option<&str>) -> Result<OAuthToken, String> {
+        Err("generate_oauth_token is not implemented".to_string())
+    }
+
+    // NOTE: when adding a new method, remember to add it to the FutureProducerContext and the
+    // DeliveryStreamContext as well.
     // https://github.com/rust-lang/rfcs/pull/1406 will maybe help in the future.
 }
 
+/// A token for the SASL OAUTHBEARER mechanism, returned by
+/// `ClientContext::generate_oauth_token`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OAuthToken {
+    /// The token value, sent to the broker.
+    pub token: String,
+    /// The lifetime of the token, as the time it expires in milliseconds since the epoch.
+    pub lifetime_ms: i64,
+    /// The Kafka principal name associated to the token.
+    pub principal_name: String,
+    /// The SASL extensions sent to the broker with the token.
+    pub extensions: HashMap<String, String>,
+}
+
 /// An empty `ClientContext` that can be used when no context is needed. Default
 /// callback implementations will be used.
 #[derive(Clone, Default)]
@@ -83,6 +154,8 @@ impl ClientContext for DefaultClientContext {}
 /// or producers and consumers.
 pub struct NativeClient {
     ptr: *mut RDKafka,
+    // The state of the consumer, if the client is passed to the rebalance of a `BaseConsumer`.
+    consumer_state: *const ConsumerState,
 }
 
 // The library is completely thread safe, according to the documentation.
@@ -92,7 +165,28 @@ unsafe impl Send for NativeClient {}
 impl NativeClient {
     /// Wraps a pointer to an RDKafka object and returns a new NativeClient.
     pub(crate) unsafe fn from_ptr(ptr: *mut RDKafka) -> NativeClient {
-        NativeClient { ptr }
+        NativeClient {
+            ptr,
+            consumer_state: ptr::null(),
+        }
+    }
+
+    /// Wraps the pointer to the RDKafka object of a consumer being rebalanced, giving the
+    /// rebalance access to the state of the consumer. The state must outlive the NativeClient.
+    pub(crate) unsafe fn from_consumer_ptr(
+        ptr: *mut RDKafka,
+        consumer_state: &ConsumerState,
+    ) -> NativeClient {
+        NativeClient {
+            ptr,
+            consumer_state,
+        }
+    }
+
+    /// Returns the state of the consumer being rebalanced, if the client was passed to the
+    /// rebalance of a `BaseConsumer`.
+    pub(crate) fn consumer_state(&self) -> Option<&ConsumerState> {
+        unsafe { self.consumer_state.as_ref() }
     }
 
     /// Returns the wrapped pointer to RDKafka.
@@ -126,9 +220,20 @@ impl<C: ClientContext> Client<C> {
         native_config: NativeClientConfig,
         rd_kafka_type: RDKafkaType,
         context: C,
+    ) -> KafkaResult<Client<C>> {
+        Client::new_with_boxed_context(config, native_config, rd_kafka_type, Box::new(context))
+    }
+
+    /// Same as `Client::new`, but with an already boxed context. Since the context is not moved
+    /// anymore, its address can be registered in the native configuration before calling this
+    /// function.
+    pub(crate) fn new_with_boxed_context(
+        config: &ClientConfig,
+        native_config: NativeClientConfig,
+        rd_kafka_type: RDKafkaType,
+        mut boxed_context: Box<C>,
     ) -> KafkaResult<Client<C>> {
         let mut err_buf = ErrBuf::new();
-        let mut boxed_context = Box::new(context);
         unsafe {
             rdsys::rd_kafka_conf_set_opaque(
                 native_config.ptr(),
@@ -142,6 +247,14 @@ impl<C: ClientContext> Client<C> {
         unsafe {
             rdsys::rd_kafka_conf_set_error_cb(native_config.ptr(), Some(native_error_cb::<C>))
         };
+        if C::ENABLE_REFRESH_OAUTH_TOKEN {
+            unsafe {
+                rdsys::rd_kafka_conf_set_oauthbearer_token_refresh_cb(
+                    native_config.ptr(),
+                    Some(native_oauth_refresh_cb::<C>),
+                )
+            };
+        }
 
         let client_ptr = unsafe {
             rdsys::rd_kafka_new(
@@ -180,9 +293,30 @@ impl<C: ClientContext> Client<C> {
         self.context.as_ref()
     }
 
+    /// Returns the name of the client instance, as used by librdkafka in its logs. The name is
+    /// built from `client.id` and the type of the client, e.g. `my-app#producer-1`.
+    pub fn name(&self) -> String {
+        unsafe { cstr_to_owned(rdsys::rd_kafka_name(self.native_ptr())) }
+    }
+
+    /// Returns the fatal error raised by the client, if any, with its description. Once a fatal
+    /// error is raised the client can't be used anymore, and it should be recreated. In
+    /// librdkafka 1.2 fatal errors are only raised by idempotent producers.
+    pub fn fatal_error(&self) -> Option<(RDKafkaErrorCode, String)> {
+        let mut err_buf = ErrBuf::new();
+        let code = unsafe {
+            rdsys::rd_kafka_fatal_error(self.native_ptr(), err_buf.as_mut_ptr(), err_buf.len())
+        };
+        if code.is_error() {
+            Some((code.into(), err_buf.to_string()))
+        } else {
+            None
+        }
+    }
+
     /// Returns the metadata information for the specified topic, or for all topics in the cluster
     /// if no topic is specified.
-    pub fn fetch_metadata<T: Into<Option<Duration>>>(
+    pub fn fetch_metadata<T: Into<Timeout>>(
         &self,
         topic: Option<&str>,
         timeout: T,
@@ -202,7 +336,7 @@ impl<C: ClientContext> Client<C> {
                     .map(|t| t.ptr())
                     .unwrap_or_else(NativeTopic::null),
                 &mut metadata_ptr as *mut *const RDKafkaMetadata,
-                timeout_to_ms(timeout),
+                timeout.into().as_millis(),
             )
         };
         trace!("Metadata fetch completed");
@@ -213,8 +347,26 @@ impl<C: ClientContext> Client<C> {
         Ok(unsafe { Metadata::from_ptr(metadata_ptr) })
     }
 
-    /// Returns high and low watermark for the specified topic and partition.
-    pub fn fetch_watermarks<T: Into<Option<Duration>>>(
+    /// Returns an owned snapshot of the metadata of the specified topic.
+    pub fn fetch_metadata_for_topic<T: Into<Timeout>>(
+        &self,
+        topic: &str,
+        timeout: T,
+    ) -> KafkaResult<OwnedMetadata> {
+        self.fetch_metadata(Some(topic), timeout)
+            .map(|metadata| metadata.detach())
+    }
+
+    /// Returns an owned snapshot of the metadata of all the topics in the cluster.
+    pub fn fetch_all_metadata<T: Into<Timeout>>(&self, timeout: T) -> KafkaResult<OwnedMetadata> {
+        self.fetch_metadata(None, timeout)
+            .map(|metadata| metadata.detach())
+    }
+
+    /// Returns high and low watermark for the specified topic and partition, querying the
+    /// broker. This method blocks until the broker replies or the timeout expires. See
+    /// `get_watermark_offsets` for a cheaper, local alternative.
+    pub fn fetch_watermarks<T: Into<Timeout>>(
         &self,
         topic: &str,
         partition: i32,
@@ -239,9 +391,38 @@ impl<C: ClientContext> Client<C> {
         Ok((low, high))
     }
 
+    /// Returns the low and high watermark for the specified topic and partition, as last cached
+    /// by librdkafka. The broker is not contacted: the high watermark is updated every time a
+    /// message set is fetched for the partition, while the low watermark is only updated
+    /// periodically if `statistics.interval.ms` is set, and is `-1001` (`Offset::Invalid`)
+    /// otherwise. Only meaningful for consumers.
+    ///
+    /// Returns a `State` error if no message set has been fetched for the partition yet.
+    pub fn get_watermark_offsets(&self, topic: &str, partition: i32) -> KafkaResult<(i64, i64)> {
+        let mut low = -1;
+        let mut high = -1;
+        let topic_c = CString::new(topic.to_string())?;
+        let ret = unsafe {
+            rdsys::rd_kafka_get_watermark_offsets(
+                self.native_ptr(),
+                topic_c.as_ptr(),
+                partition,
+                &mut low as *mut i64,
+                &mut high as *mut i64,
+            )
+        };
+        if ret.is_error() {
+            return Err(KafkaError::MetadataFetch(ret.into()));
+        }
+        if high == i64::from(rdsys::RD_KAFKA_OFFSET_INVALID) {
+            return Err(KafkaError::MetadataFetch(RDKafkaErrorCode::State));
+        }
+        Ok((low, high))
+    }
+
     /// Returns the group membership information for the given group. If no group is
     /// specified, all groups will be returned.
-    pub fn fetch_group_list<T: Into<Option<Duration>>>(
+    pub fn fetch_group_list<T: Into<Timeout>>(
         &self,
         group: Option<&str>,
         timeout: T,
@@ -275,13 +456,15 @@ impl<C: ClientContext> Client<C> {
     /// it was generated from.
     pub(crate) fn native_topic(&self, topic: &str) -> KafkaResult<NativeTopic> {
         let topic_c = CString::new(topic.to_string())?;
-        Ok(unsafe {
-            NativeTopic::from_ptr(rdsys::rd_kafka_topic_new(
-                self.native_ptr(),
-                topic_c.as_ptr(),
-                ptr::null_mut(),
-            ))
-        })
+        let topic_ptr = unsafe {
+            rdsys::rd_kafka_topic_new(self.native_ptr(), topic_c.as_ptr(), ptr::null_mut())
+        };
+        if topic_ptr.is_null() {
+            return Err(KafkaError::Global(
+                unsafe { rdsys::rd_kafka_last_error() }.into(),
+            ));
+        }
+        Ok(unsafe { NativeTopic::from_ptr(topic_ptr) })
     }
 
     /// Returns a NativeQueue from the current client. The NativeQueue shouldn't
@@ -289,6 +472,35 @@ impl<C: ClientContext> Client<C> {
     pub(crate) fn new_native_queue(&self) -> NativeQueue {
         unsafe { NativeQueue::from_ptr(rdsys::rd_kafka_queue_new(self.native_ptr())) }
     }
+
+    /// Returns the NativeQueue of the specified partition, or `None` if the partition is not known
+    /// to the client. The NativeQueue shouldn't outlive the client it was generated from.
+    pub(crate) fn native_partition_queue(
+        &self,
+        topic: &str,
+        partition: i32,
+    ) -> KafkaResult<Option<NativeQueue>> {
+        let topic_c = CString::new(topic.to_string())?;
+        let queue_ptr = unsafe {
+            rdsys::rd_kafka_queue_get_partition(self.native_ptr(), topic_c.as_ptr(), partition)
+        };
+        if queue_ptr.is_null() {
+            Ok(None)
+        } else {
+            Ok(Some(unsafe { NativeQueue::from_ptr(queue_ptr) }))
+        }
+    }
+
+    /// Returns the consumer NativeQueue of the client, or `None` if the client is not part of a
+    /// consumer group. The NativeQueue shouldn't outlive the client it was generated from.
+    pub(crate) fn native_consumer_queue(&self) -> Option<NativeQueue> {
+        let queue_ptr = unsafe { rdsys::rd_kafka_queue_get_consumer(self.native_ptr()) };
+        if queue_ptr.is_null() {
+            None
+        } else {
+            Some(unsafe { NativeQueue::from_ptr(queue_ptr) })
+        }
+    }
 }
 
 pub(crate) struct NativeTopic {
@@ -345,9 +557,31 @@ impl NativeQueue {
         self.ptr
     }
 
-    pub fn poll<T: Into<Option<Duration>>>(&self, t: T) -> *mut RDKafkaEvent {
+    pub fn poll<T: Into<Timeout>>(&self, t: T) -> *mut RDKafkaEvent {
         unsafe { rdsys::rd_kafka_queue_poll(self.ptr, timeout_to_ms(t)) }
     }
+
+    /// Consumes a message from the queue, waiting at most `timeout_ms`.
+    pub fn consume_message(&self, timeout_ms: i32) -> Option<*mut RDKafkaMessage> {
+        let message_ptr = unsafe { rdsys::rd_kafka_consume_queue(self.ptr, timeout_ms) };
+        if message_ptr.is_null() {
+            None
+        } else {
+            Some(message_ptr)
+        }
+    }
+
+    /// Forwards the events of the queue to `destination`, or stops forwarding them if
+    /// `destination` is `None`.
+    pub fn forward(&self, destination: Option<&NativeQueue>) {
+        let destination_ptr = destination.map_or(ptr::null_mut(), |queue| queue.ptr);
+        unsafe { rdsys::rd_kafka_queue_forward(self.ptr, destination_ptr) }
+    }
+
+    /// Returns the number of events waiting in the queue.
+    pub fn event_count(&self) -> usize {
+        unsafe { rdsys::rd_kafka_queue_length(self.ptr) }
+    }
 }
 
 impl Drop for NativeQueue {
@@ -386,16 +620,7 @@ pub(crate) unsafe extern "C" fn native_stats_cb<C: ClientContext>(
 ) -> i32 {
     let context = Box::from_raw(opaque as *mut C);
 
-    let mut bytes_vec = Vec::new();
-    bytes_vec.extend_from_slice(slice::from_raw_parts(json as *mut u8, json_len));
-    let json_string = CString::from_vec_unchecked(bytes_vec).into_string();
-    match json_string {
-        Ok(json) => match serde_json::from_str(&json) {
-            Ok(stats) => (*context).stats(stats),
-            Err(e) => error!("Could not parse statistics JSON: {}", e),
-        },
-        Err(e) => error!("Statistics JSON string is not UTF-8: {:?}", e),
-    }
+    (*context).stats_raw(slice::from_raw_parts(json as *mut u8, json_len));
 
     mem::forget(context); // Do not free the context
 
@@ -418,6 +643,63 @@ pub(crate) unsafe extern "C" fn native_error_cb<C: ClientContext>(
     mem::forget(context); // Do not free the context
 }
 
+pub(crate) unsafe extern "C" fn native_oauth_refresh_cb<C: ClientContext>(
+    client: *mut RDKafka,
+    oauthbearer_config: *const c_char,
+    opaque: *mut c_void,
+) {
+    let oauthbearer_config = if oauthbearer_config.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(oauthbearer_config).to_string_lossy())
+    };
+
+    let context = Box::from_raw(opaque as *mut C);
+    let result = (*context).generate_oauth_token(oauthbearer_config.as_deref());
+    mem::forget(context); // Do not free the context
+
+    if let Err(e) = result.and_then(|token| set_oauth_token(client, &token)) {
+        let error = match e.replace('\0', "") {
+            ref e if e.is_empty() => CString::new("OAuth token generation failed").unwrap(),
+            e => CString::new(e).unwrap(),
+        };
+        rdsys::rd_kafka_oauthbearer_set_token_failure(client, error.as_ptr());
+    }
+}
+
+/// Passes the token to librdkafka, returning the reason why it was rejected, if any.
+unsafe fn set_oauth_token(client: *mut RDKafka, token: &OAuthToken) -> Result<(), String> {
+    // The values are secret, so the errors only name the field.
+    let to_cstring = |value: &str, field: &str| {
+        CString::new(value).map_err(|_| format!("The OAuth {} contains a nul byte", field))
+    };
+    let value = to_cstring(&token.token, "token")?;
+    let principal_name = to_cstring(&token.principal_name, "principal name")?;
+    let mut extensions = Vec::with_capacity(token.extensions.len() * 2);
+    for (key, value) in &token.extensions {
+        extensions.push(to_cstring(key, "extension name")?);
+        extensions.push(to_cstring(value, "extension value")?);
+    }
+    let mut extension_ptrs = extensions.iter().map(|e| e.as_ptr()).collect::<Vec<_>>();
+
+    let mut err_buf = ErrBuf::new();
+    let ret = rdsys::rd_kafka_oauthbearer_set_token(
+        client,
+        value.as_ptr(),
+        token.lifetime_ms,
+        principal_name.as_ptr(),
+        extension_ptrs.as_mut_ptr(),
+        extension_ptrs.len(),
+        err_buf.as_mut_ptr(),
+        err_buf.len(),
+    );
+    if ret.is_error() {
+        Err(err_buf.to_string())
+    } else {
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     // Just call everything to test there no panics by default, behavior
@@ -439,4 +721,232 @@ mod tests {
         .unwrap();
         assert!(!client.native_ptr().is_null());
     }
+
+    #[test]
+    fn test_client_name() {
+        let create = |name: &str| {
+            let mut config = ClientConfig::new();
+            config.set_name(name);
+            let native_config = config.create_native_config().unwrap();
+            Client::new(
+                &config,
+                native_config,
+                RDKafkaType::RD_KAFKA_PRODUCER,
+                DefaultClientContext,
+            )
+            .unwrap()
+        };
+        let client_a = create("client-a");
+        let client_b = create("client-b");
+        assert!(client_a.name().starts_with("client-a#producer-"));
+        assert!(client_b.name().starts_with("client-b#producer-"));
+        assert_ne!(client_a.name(), client_b.name());
+    }
+
+    // The error should name the invalid field without revealing the secret value.
+    #[test]
+    fn test_oauth_token_nul_byte() {
+        let token = OAuthToken {
+            token: "sec\0ret".to_owned(),
+            lifetime_ms: 0,
+            principal_name: "principal".to_owned(),
+            extensions: HashMap::new(),
+        };
+        let error = unsafe { set_oauth_token(ptr::null_mut(), &token) }.unwrap_err();
+        assert_eq!(error, "The OAuth token contains a nul byte");
+    }
+
+    // OAUTHBEARER is only available if librdkafka is built with SSL support.
+    #[cfg(feature = "ssl")]
+    mod oauth {
+        use super::*;
+
+        struct OAuthContext {
+            token: Result<OAuthToken, String>,
+            configs: std::sync::Mutex<Vec<Option<String>>>,
+            errors: std::sync::Mutex<Vec<KafkaError>>,
+        }
+
+        impl ClientContext for OAuthContext {
+            const ENABLE_REFRESH_OAUTH_TOKEN: bool = true;
+
+            fn error(&self, error: KafkaError, _reason: &str) {
+                self.errors.lock().unwrap().push(error);
+            }
+
+            fn generate_oauth_token(
+                &self,
+                oauthbearer_config: Option<&str>,
+            ) -> Result<OAuthToken, String> {
+                self.configs
+                    .lock()
+                    .unwrap()
+                    .push(oauthbearer_config.map(str::to_owned));
+                self.token.clone()
+            }
+        }
+
+        fn create_oauth_client(token: Result<OAuthToken, String>) -> Client<OAuthContext> {
+            let mut config = ClientConfig::new();
+            config
+                .set("bootstrap.servers", "localhost:1")
+                .set("security.protocol", "SASL_PLAINTEXT")
+                .set("sasl.mechanisms", "OAUTHBEARER")
+                .set("sasl.oauthbearer.config", "scope=test");
+            let native_config = config.create_native_config().unwrap();
+            let context = OAuthContext {
+                token,
+                configs: std::sync::Mutex::new(Vec::new()),
+                errors: std::sync::Mutex::new(Vec::new()),
+            };
+            let client = Client::new(
+                &config,
+                native_config,
+                RDKafkaType::RD_KAFKA_PRODUCER,
+                context,
+            )
+            .unwrap();
+            // The token refresh is triggered when the client is created, and served by poll.
+            unsafe { rdsys::rd_kafka_poll(client.native_ptr(), 100) };
+            client
+        }
+
+        #[test]
+        fn test_oauth_token_refresh() {
+            let mut extensions = HashMap::new();
+            extensions.insert("traceid".to_owned(), "123".to_owned());
+            let token = OAuthToken {
+                token: "token".to_owned(),
+                lifetime_ms: crate::util::current_time_millis() + 3_600_000,
+                principal_name: "principal".to_owned(),
+                extensions,
+            };
+            let client = create_oauth_client(Ok(token));
+            unsafe { rdsys::rd_kafka_poll(client.native_ptr(), 100) };
+
+            let context = client.context();
+            assert_eq!(
+                *context.configs.lock().unwrap(),
+                vec![Some("scope=test".to_owned())]
+            );
+            // The broker is not reachable, but the token must have been accepted.
+            assert!(!context
+                .errors
+                .lock()
+                .unwrap()
+                .contains(&KafkaError::Global(RDKafkaErrorCode::Authentication)));
+        }
+
+        #[test]
+        fn test_oauth_token_refresh_failure() {
+            let client = create_oauth_client(Err("no token available".to_owned()));
+            unsafe { rdsys::rd_kafka_poll(client.native_ptr(), 100) };
+
+            let context = client.context();
+            assert_eq!(context.configs.lock().unwrap().len(), 1);
+            assert!(context
+                .errors
+                .lock()
+                .unwrap()
+                .contains(&KafkaError::Global(RDKafkaErrorCode::Authentication)));
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_log_tracing() {
+        use std::fmt;
+        use std::sync::{Arc, Mutex};
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Level, Metadata, Subscriber};
+
+        type CapturedEvent = (Level, String, Vec<(String, String)>);
+
+        struct FieldsVisitor(Vec<(String, String)>);
+
+        impl Visit for FieldsVisitor {
+            fn record_str(&mut self, field: &Field, value: &str) {
+                self.0.push((field.name().to_owned(), value.to_owned()));
+            }
+
+            fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+                self.0
+                    .push((field.name().to_owned(), format!("{:?}", value)));
+            }
+        }
+
+        struct CapturingSubscriber(Arc<Mutex<Vec<CapturedEvent>>>);
+
+        impl Subscriber for CapturingSubscriber {
+            fn enabled(&self, _metadata: &Metadata) -> bool {
+                true
+            }
+
+            fn new_span(&self, _span: &Attributes) -> Id {
+                Id::from_u64(1)
+            }
+
+            fn record(&self, _span: &Id, _values: &Record) {}
+
+            fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+            fn event(&self, event: &Event) {
+                let mut visitor = FieldsVisitor(Vec::new());
+                event.record(&mut visitor);
+                self.0.lock().unwrap().push((
+                    *event.metadata().level(),
+                    event.metadata().target().to_owned(),
+                    visitor.0,
+                ));
+            }
+
+            fn enter(&self, _span: &Id) {}
+
+            fn exit(&self, _span: &Id) {}
+        }
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let levels = vec![
+            RDKafkaLogLevel::Emerg,
+            RDKafkaLogLevel::Alert,
+            RDKafkaLogLevel::Critical,
+            RDKafkaLogLevel::Error,
+            RDKafkaLogLevel::Warning,
+            RDKafkaLogLevel::Notice,
+            RDKafkaLogLevel::Info,
+            RDKafkaLogLevel::Debug,
+        ];
+        tracing::subscriber::with_default(CapturingSubscriber(events.clone()), || {
+            for level in levels {
+                DefaultClientContext.log(level, "FAC", "log message");
+            }
+        });
+
+        let events = events.lock().unwrap();
+        let captured_levels = events.iter().map(|e| e.0).collect::<Vec<_>>();
+        assert_eq!(
+            captured_levels,
+            vec![
+                Level::ERROR,
+                Level::ERROR,
+                Level::ERROR,
+                Level::ERROR,
+                Level::WARN,
+                Level::INFO,
+                Level::INFO,
+                Level::DEBUG,
+            ]
+        );
+        for (_, target, fields) in events.iter() {
+            assert_eq!(target, "librdkafka");
+            assert_eq!(
+                fields,
+                &vec![
+                    ("facility".to_owned(), "FAC".to_owned()),
+                    ("message".to_owned(), "log message".to_owned()),
+                ]
+            );
+        }
+    }
 }
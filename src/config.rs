@@ -23,12 +23,21 @@ use crate::rdsys::types::*;
 use log::Level;
 
 use crate::client::ClientContext;
-use crate::error::{IsError, KafkaError, KafkaResult};
-use crate::util::ErrBuf;
+use crate::consumer::CommitMode;
+use crate::error::{InvalidConfigParam, IsError, KafkaError, KafkaResult};
+use crate::util::{duration_to_millis, ErrBuf};
 
 use std::collections::HashMap;
+use std::error;
 use std::ffi::CString;
+use std::fmt;
+use std::fs;
+use std::io;
 use std::mem;
+use std::os::raw::c_void;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
 
 /// The log levels supported by librdkafka.
 #[derive(Copy, Clone, Debug)]
@@ -103,12 +112,135 @@ impl Drop for NativeClientConfig {
     }
 }
 
+/// A native rdkafka-sys topic config.
+pub(crate) struct NativeTopicConfig {
+    ptr: *mut RDKafkaTopicConf,
+}
+
+impl NativeTopicConfig {
+    /// Returns the pointer to the librdkafka RDKafkaTopicConf structure.
+    pub(crate) fn ptr(&self) -> *mut RDKafkaTopicConf {
+        self.ptr
+    }
+
+    /// Returns the pointer to the librdkafka RDKafkaTopicConf structure. The destructor won't be
+    /// executed automatically; the caller should take care of deallocating the resource when no
+    /// longer needed.
+    pub(crate) fn ptr_move(self) -> *mut RDKafkaTopicConf {
+        let ptr = self.ptr;
+        mem::forget(self);
+        ptr
+    }
+}
+
+impl Drop for NativeTopicConfig {
+    fn drop(&mut self) {
+        trace!("Drop NativeTopicConfig {:p}", self.ptr());
+        unsafe { rdsys::rd_kafka_topic_conf_destroy(self.ptr) }
+    }
+}
+
+/// The type of an SSL certificate or key set with `ClientConfig::set_ssl_cert`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CertType {
+    /// The client's public key.
+    PublicKey,
+    /// The client's private key.
+    PrivateKey,
+    /// The CA certificate, used to verify the broker's key.
+    Ca,
+}
+
+impl CertType {
+    /// Returns the name of the certificate type, as used by librdkafka.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CertType::PublicKey => "public-key",
+            CertType::PrivateKey => "private-key",
+            CertType::Ca => "CA",
+        }
+    }
+
+    fn to_native(self) -> RDKafkaCertType {
+        match self {
+            CertType::PublicKey => RDKafkaCertType::RD_KAFKA_CERT_PUBLIC_KEY,
+            CertType::PrivateKey => RDKafkaCertType::RD_KAFKA_CERT_PRIVATE_KEY,
+            CertType::Ca => RDKafkaCertType::RD_KAFKA_CERT_CA,
+        }
+    }
+}
+
+/// The encoding of an SSL certificate or key set with `ClientConfig::set_ssl_cert`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CertEncoding {
+    /// PKCS#12 encoding.
+    Pkcs12,
+    /// DER (binary X.509 ASN.1) encoding.
+    Der,
+    /// PEM encoding.
+    Pem,
+}
+
+impl CertEncoding {
+    /// Returns the name of the encoding, as used by librdkafka.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CertEncoding::Pkcs12 => "PKCS#12",
+            CertEncoding::Der => "DER",
+            CertEncoding::Pem => "PEM",
+        }
+    }
+
+    fn to_native(self) -> RDKafkaCertEnc {
+        match self {
+            CertEncoding::Pkcs12 => RDKafkaCertEnc::RD_KAFKA_CERT_ENC_PKCS12,
+            CertEncoding::Der => RDKafkaCertEnc::RD_KAFKA_CERT_ENC_DER,
+            CertEncoding::Pem => RDKafkaCertEnc::RD_KAFKA_CERT_ENC_PEM,
+        }
+    }
+}
+
+/// An SSL certificate or key, applied to the native configuration when the client is created.
+#[derive(Clone)]
+struct SslCert {
+    cert_type: CertType,
+    encoding: CertEncoding,
+    buffer: Vec<u8>,
+}
+
+/// Sets an SSL certificate or key on a native configuration.
+unsafe fn set_native_ssl_cert(conf: *mut RDKafkaConf, cert: &SslCert) -> KafkaResult<()> {
+    let mut err_buf = ErrBuf::new();
+    let ret = rdsys::rd_kafka_conf_set_ssl_cert(
+        conf,
+        cert.cert_type.to_native(),
+        cert.encoding.to_native(),
+        cert.buffer.as_ptr() as *const c_void,
+        cert.buffer.len(),
+        err_buf.as_mut_ptr(),
+        err_buf.len(),
+    );
+    if ret.is_error() {
+        Err(KafkaError::ClientConfig(
+            ret,
+            err_buf.to_string(),
+            cert.cert_type.as_str().to_owned(),
+            cert.encoding.as_str().to_owned(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
 /// Client configuration.
 #[derive(Clone)]
 pub struct ClientConfig {
     conf_map: HashMap<String, String>,
+    ssl_certs: Vec<SslCert>,
     /// The librdkafka logging level. Refer to `RDKafkaLogLevel` for the list of available levels.
     pub log_level: RDKafkaLogLevel,
+    pub(crate) commit_on_revoke: Option<CommitMode>,
+    pub(crate) skip_unchanged_commits: bool,
 }
 
 impl Default for ClientConfig {
@@ -122,8 +254,45 @@ impl ClientConfig {
     pub fn new() -> ClientConfig {
         ClientConfig {
             conf_map: HashMap::new(),
+            ssl_certs: Vec::new(),
             log_level: log_level_from_global_config(),
+            commit_on_revoke: None,
+            skip_unchanged_commits: false,
+        }
+    }
+
+    /// Creates a new configuration from a properties file, such as the `client.properties` files
+    /// used by the Java client.
+    ///
+    /// Each line of the file should contain a `key=value` pair. The line is split on the first
+    /// `=` only, so that values can contain `=` signs, and whitespace around both the key and the
+    /// value is trimmed. Blank lines and lines starting with `#` or `!` are ignored.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<ClientConfig> {
+        let contents = fs::read_to_string(path)?;
+        ClientConfig::from_properties(&contents)
+    }
+
+    fn from_properties(contents: &str) -> io::Result<ClientConfig> {
+        let mut config = ClientConfig::new();
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if !key.trim().is_empty() => {
+                    config.set(key.trim(), value.trim());
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid property at line {}: {}", line_number + 1, line),
+                    ));
+                }
+            }
         }
+        Ok(config)
     }
 
     /// Sets a new parameter in the configuration.
@@ -132,6 +301,87 @@ impl ClientConfig {
         self
     }
 
+    /// Sets the name of the client (`client.id`), which is also the prefix of the instance name
+    /// used by librdkafka in its logs. See `Client::name`.
+    pub fn set_name(&mut self, name: &str) -> &mut ClientConfig {
+        self.set("client.id", name)
+    }
+
+    /// Sets the action to take when there is no initial offset in the offset store, or the
+    /// desired offset is out of range (`auto.offset.reset`).
+    pub fn set_auto_offset_reset(&mut self, reset: OffsetReset) -> &mut ClientConfig {
+        self.set("auto.offset.reset", reset.as_str())
+    }
+
+    /// Returns the value of a parameter in the configuration, or `None` if the parameter has not
+    /// been set.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.conf_map.get(key).map(|value| value.as_str())
+    }
+
+    /// Returns the value of a parameter in the configuration, or `default` if the parameter has
+    /// not been set.
+    pub fn get_or<'a>(&'a self, key: &str, default: &'a str) -> &'a str {
+        self.get(key).unwrap_or(default)
+    }
+
+    /// Sets an SSL certificate or key from memory, for example to use certificates injected as
+    /// environment variables. Setting a certificate of the same type again replaces it.
+    ///
+    /// The certificate is decoded immediately, and an error is returned if it can't be decoded
+    /// with the specified encoding, or if librdkafka was not built with SSL support. An encrypted
+    /// private key requires the `ssl.key.password` parameter to be set first, and an error is
+    /// also returned if librdkafka rejects the password.
+    pub fn set_ssl_cert(
+        &mut self,
+        cert_type: CertType,
+        encoding: CertEncoding,
+        buffer: &[u8],
+    ) -> KafkaResult<&mut ClientConfig> {
+        let cert = SslCert {
+            cert_type,
+            encoding,
+            buffer: buffer.to_vec(),
+        };
+        let conf = unsafe { NativeClientConfig::from_ptr(rdsys::rd_kafka_conf_new()) };
+        if let Some(password) = self.get("ssl.key.password") {
+            // The password is secret, so the errors only name the parameter.
+            let rejected = |result, description| {
+                KafkaError::ClientConfig(
+                    result,
+                    description,
+                    "ssl.key.password".to_string(),
+                    String::new(),
+                )
+            };
+            let key_c = CString::new("ssl.key.password")?;
+            let value_c = CString::new(password).map_err(|_| {
+                rejected(
+                    RDKafkaConfRes::RD_KAFKA_CONF_INVALID,
+                    "The key password contains a nul byte".to_string(),
+                )
+            })?;
+            let mut err_buf = ErrBuf::new();
+            let ret = unsafe {
+                rdsys::rd_kafka_conf_set(
+                    conf.ptr(),
+                    key_c.as_ptr(),
+                    value_c.as_ptr(),
+                    err_buf.as_mut_ptr(),
+                    err_buf.len(),
+                )
+            };
+            if ret.is_error() {
+                return Err(rejected(ret, err_buf.to_string()));
+            }
+        }
+        unsafe { set_native_ssl_cert(conf.ptr(), &cert)? };
+        self.ssl_certs
+            .retain(|current| current.cert_type != cert_type);
+        self.ssl_certs.push(cert);
+        Ok(self)
+    }
+
     /// Sets the log level of the client. If not specified, the log level will be calculated based
     /// on the global log level of the log crate.
     pub fn set_log_level(&mut self, log_level: RDKafkaLogLevel) -> &mut ClientConfig {
@@ -139,16 +389,38 @@ impl ClientConfig {
         self
     }
 
+    /// Makes the consumers commit their stored offsets when their partitions are revoked, before
+    /// the partitions are released, so that the offsets of the messages processed since the last
+    /// commit are not lost. The commit is performed by the default `ConsumerContext::rebalance`
+    /// implementation, after `ConsumerContext::pre_rebalance`, where more offsets can still be
+    /// stored. A synchronous commit blocks the rebalance until the result of the commit is
+    /// received. Disabled by default.
+    pub fn set_commit_on_revoke(&mut self, mode: Option<CommitMode>) -> &mut ClientConfig {
+        self.commit_on_revoke = mode;
+        self
+    }
+
+    /// Makes the consumers skip the partitions of `Consumer::commit` whose offset is equal to the
+    /// last offset committed for the partition, to avoid sending redundant commits for the
+    /// partitions that don't receive messages. Only the commits acknowledged by the group
+    /// coordinator count, so an asynchronous commit of the same offsets sent before the previous
+    /// one is acknowledged is not skipped. The commits of the consumer state and of a message
+    /// are never skipped. Disabled by default.
+    pub fn set_skip_unchanged_commits(&mut self, skip: bool) -> &mut ClientConfig {
+        self.skip_unchanged_commits = skip;
+        self
+    }
+
     /// Returns the native rdkafka-sys configuration.
     pub fn create_native_config(&self) -> KafkaResult<NativeClientConfig> {
-        let conf = unsafe { rdsys::rd_kafka_conf_new() };
+        let conf = unsafe { NativeClientConfig::from_ptr(rdsys::rd_kafka_conf_new()) };
         let mut err_buf = ErrBuf::new();
         for (key, value) in &self.conf_map {
             let key_c = CString::new(key.to_string())?;
             let value_c = CString::new(value.to_string())?;
             let ret = unsafe {
                 rdsys::rd_kafka_conf_set(
-                    conf,
+                    conf.ptr(),
                     key_c.as_ptr(),
                     value_c.as_ptr(),
                     err_buf.as_mut_ptr(),
@@ -164,7 +436,106 @@ impl ClientConfig {
                 ));
             }
         }
-        Ok(unsafe { NativeClientConfig::from_ptr(conf) })
+        for cert in &self.ssl_certs {
+            unsafe { set_native_ssl_cert(conf.ptr(), cert)? };
+        }
+        Ok(conf)
+    }
+
+    /// Checks the configuration without creating a client. Every parameter is applied to a new
+    /// native configuration, and all the parameters rejected by librdkafka are reported in a
+    /// single `KafkaError::ClientConfigValidation` error, sorted by key. Keys and values containing
+    /// a nul byte are reported as invalid. Some errors, like conflicting parameters, are only
+    /// detected when the client is created.
+    pub fn validate(&self) -> KafkaResult<()> {
+        let conf = unsafe { NativeClientConfig::from_ptr(rdsys::rd_kafka_conf_new()) };
+        let mut err_buf = ErrBuf::new();
+        let mut keys = self.conf_map.keys().collect::<Vec<_>>();
+        keys.sort();
+        let mut invalid_params = Vec::new();
+        for key in keys {
+            let value = &self.conf_map[key];
+            let (key_c, value_c) = match (
+                CString::new(key.to_string()),
+                CString::new(value.to_string()),
+            ) {
+                (Ok(key_c), Ok(value_c)) => (key_c, value_c),
+                _ => {
+                    invalid_params.push(InvalidConfigParam {
+                        result: RDKafkaConfRes::RD_KAFKA_CONF_INVALID,
+                        description: "Configuration contains a nul byte".to_string(),
+                        key: key.to_string(),
+                        value: value.to_string(),
+                    });
+                    continue;
+                }
+            };
+            let ret = unsafe {
+                rdsys::rd_kafka_conf_set(
+                    conf.ptr(),
+                    key_c.as_ptr(),
+                    value_c.as_ptr(),
+                    err_buf.as_mut_ptr(),
+                    err_buf.len(),
+                )
+            };
+            if ret.is_error() {
+                invalid_params.push(InvalidConfigParam {
+                    result: ret,
+                    description: err_buf.to_string(),
+                    key: key.to_string(),
+                    value: value.to_string(),
+                });
+            }
+        }
+        for cert in &self.ssl_certs {
+            if let Err(KafkaError::ClientConfig(result, description, key, value)) =
+                unsafe { set_native_ssl_cert(conf.ptr(), cert) }
+            {
+                invalid_params.push(InvalidConfigParam {
+                    result,
+                    description,
+                    key,
+                    value,
+                });
+            }
+        }
+        if invalid_params.is_empty() {
+            Ok(())
+        } else {
+            Err(KafkaError::ClientConfigValidation(invalid_params))
+        }
+    }
+
+    /// Returns a native rdkafka-sys topic configuration containing the topic-level parameters of
+    /// the configuration. Parameters that don't apply to topics are skipped.
+    pub(crate) fn create_native_topic_config(&self) -> KafkaResult<NativeTopicConfig> {
+        let conf = NativeTopicConfig {
+            ptr: unsafe { rdsys::rd_kafka_topic_conf_new() },
+        };
+        let mut err_buf = ErrBuf::new();
+        for (key, value) in &self.conf_map {
+            let key_c = CString::new(key.to_string())?;
+            let value_c = CString::new(value.to_string())?;
+            let ret = unsafe {
+                rdsys::rd_kafka_topic_conf_set(
+                    conf.ptr(),
+                    key_c.as_ptr(),
+                    value_c.as_ptr(),
+                    err_buf.as_mut_ptr(),
+                    err_buf.len(),
+                )
+            };
+            if ret.is_error() && ret != RDKafkaConfRes::RD_KAFKA_CONF_UNKNOWN {
+                return Err(KafkaError::ClientConfig(
+                    ret,
+                    err_buf.to_string(),
+                    key.to_string(),
+                    value.to_string(),
+                ));
+            }
+        }
+        Ok(conf)
     }
 
     /// Uses the current configuration to create a new Consumer or Producer.
@@ -182,6 +553,209 @@ impl ClientConfig {
     }
 }
 
+/// The action to take when there is no initial offset in the offset store, or the desired offset
+/// is out of range. Used for the `auto.offset.reset` parameter.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OffsetReset {
+    /// Automatically reset the offset to the smallest offset.
+    Earliest,
+    /// Automatically reset the offset to the largest offset.
+    Latest,
+    /// Trigger an error, which can be retrieved by consuming messages.
+    Error,
+}
+
+impl OffsetReset {
+    /// Returns the configuration value corresponding to the offset reset policy.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OffsetReset::Earliest => "earliest",
+            OffsetReset::Latest => "latest",
+            OffsetReset::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for OffsetReset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for OffsetReset {
+    type Err = ParseOffsetResetError;
+
+    /// Parses an `auto.offset.reset` value. The legacy values accepted by librdkafka,
+    /// `smallest`, `beginning`, `largest` and `end`, are parsed as well.
+    fn from_str(value: &str) -> Result<OffsetReset, ParseOffsetResetError> {
+        match value {
+            "earliest" | "smallest" | "beginning" => Ok(OffsetReset::Earliest),
+            "latest" | "largest" | "end" => Ok(OffsetReset::Latest),
+            "error" => Ok(OffsetReset::Error),
+            _ => Err(ParseOffsetResetError {
+                value: value.to_owned(),
+            }),
+        }
+    }
+}
+
+/// The error returned when parsing an invalid `auto.offset.reset` value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseOffsetResetError {
+    /// The value that could not be parsed.
+    pub value: String,
+}
+
+impl fmt::Display for ParseOffsetResetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid auto.offset.reset value: {}", self.value)
+    }
+}
+
+impl error::Error for ParseOffsetResetError {}
+
+/// A builder for `ClientConfig`, providing typed setters for the most frequently used parameters.
+///
+/// Parameters without a dedicated setter can still be configured using `set`.
+///
+/// # Example
+///
+/// ```rust
+/// use rdkafka::config::{ConfigBuilder, OffsetReset};
+/// use std::time::Duration;
+///
+/// let config = ConfigBuilder::new()
+///     .bootstrap_servers("localhost:9092")
+///     .group_id("my_group")
+///     .session_timeout(Duration::from_secs(6))
+///     .auto_offset_reset(OffsetReset::Earliest)
+///     .set("fetch.wait.max.ms", "50")
+///     .build();
+/// ```
+#[derive(Clone)]
+pub struct ConfigBuilder {
+    config: ClientConfig,
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConfigBuilder {
+    /// Creates a new builder with an empty configuration.
+    pub fn new() -> ConfigBuilder {
+        ConfigBuilder {
+            config: ClientConfig::new(),
+        }
+    }
+
+    /// Sets a parameter in the configuration. Can be used for parameters that don't have a
+    /// dedicated setter.
+    pub fn set(mut self, key: &str, value: &str) -> Self {
+        self.config.set(key, value);
+        self
+    }
+
+    /// Sets the initial list of brokers (`bootstrap.servers`), as a CSV list of broker host or
+    /// host:port.
+    pub fn bootstrap_servers(self, servers: &str) -> Self {
+        self.set("bootstrap.servers", servers)
+    }
+
+    /// Sets the client identifier (`client.id`).
+    pub fn client_id(self, client_id: &str) -> Self {
+        self.set("client.id", client_id)
+    }
+
+    /// Sets the consumer group id (`group.id`).
+    pub fn group_id(self, group_id: &str) -> Self {
+        self.set("group.id", group_id)
+    }
+
+    /// Enables or disables the automatic commit of offsets (`enable.auto.commit`).
+    pub fn enable_auto_commit(self, enable: bool) -> Self {
+        self.set("enable.auto.commit", bool_to_str(enable))
+    }
+
+    /// Sets how often offsets are committed automatically (`auto.commit.interval.ms`).
+    pub fn auto_commit_interval(self, interval: Duration) -> Self {
+        self.set_duration("auto.commit.interval.ms", interval)
+    }
+
+    /// Enables or disables the automatic storage of the offset of the messages returned to the
+    /// application (`enable.auto.offset.store`).
+    pub fn enable_auto_offset_store(self, enable: bool) -> Self {
+        self.set("enable.auto.offset.store", bool_to_str(enable))
+    }
+
+    /// Sets the action to take when there is no initial offset in the offset store, or the
+    /// desired offset is out of range (`auto.offset.reset`).
+    pub fn auto_offset_reset(self, reset: OffsetReset) -> Self {
+        self.set("auto.offset.reset", reset.as_str())
+    }
+
+    /// Sets the client group session and failure detection timeout (`session.timeout.ms`).
+    pub fn session_timeout(self, timeout: Duration) -> Self {
+        self.set_duration("session.timeout.ms", timeout)
+    }
+
+    /// Enables or disables the `PartitionEOF` notification when the end of a partition is reached
+    /// (`enable.partition.eof`).
+    pub fn enable_partition_eof(self, enable: bool) -> Self {
+        self.set("enable.partition.eof", bool_to_str(enable))
+    }
+
+    /// Sets the maximum time a produced message can wait to be delivered
+    /// (`message.timeout.ms`).
+    pub fn message_timeout(self, timeout: Duration) -> Self {
+        self.set_duration("message.timeout.ms", timeout)
+    }
+
+    /// Sets how often statistics are passed to the client context (`statistics.interval.ms`).
+    pub fn statistics_interval(self, interval: Duration) -> Self {
+        self.set_duration("statistics.interval.ms", interval)
+    }
+
+    /// Makes the consumer commit its stored offsets when its partitions are revoked. See
+    /// `ClientConfig::set_commit_on_revoke`.
+    pub fn commit_on_revoke(mut self, mode: Option<CommitMode>) -> Self {
+        self.config.set_commit_on_revoke(mode);
+        self
+    }
+
+    /// Makes the consumer skip the commits of unchanged offsets. See
+    /// `ClientConfig::set_skip_unchanged_commits`.
+    pub fn skip_unchanged_commits(mut self, skip: bool) -> Self {
+        self.config.set_skip_unchanged_commits(skip);
+        self
+    }
+
+    /// Sets the log level of the client. See `ClientConfig::set_log_level`.
+    pub fn log_level(mut self, log_level: RDKafkaLogLevel) -> Self {
+        self.config.set_log_level(log_level);
+        self
+    }
+
+    /// Returns the `ClientConfig`.
+    pub fn build(self) -> ClientConfig {
+        self.config
+    }
+
+    fn set_duration(self, key: &str, duration: Duration) -> Self {
+        self.set(key, &duration_to_millis(duration).to_string())
+    }
+}
+
+fn bool_to_str(value: bool) -> &'static str {
+    if value {
+        "true"
+    } else {
+        "false"
+    }
+}
+
 /// Return the log level
 fn log_level_from_global_config() -> RDKafkaLogLevel {
     if log_enabled!(target: "librdkafka", Level::Debug) {
@@ -206,3 +780,241 @@ pub trait FromClientConfigAndContext<C: ClientContext>: Sized {
     /// Create a client from client configuration and a client context.
     fn from_config_and_context(_: &ClientConfig, _: C) -> KafkaResult<Self>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_config_get() {
+        let mut config = ClientConfig::new();
+        config.set("group.id", "group1").set("group.id", "group2");
+
+        assert_eq!(config.get("group.id"), Some("group2"));
+        assert_eq!(config.get("client.id"), None);
+        assert_eq!(config.get_or("group.id", "default"), "group2");
+        assert_eq!(config.get_or("client.id", "default"), "default");
+    }
+
+    #[test]
+    fn test_client_config_from_file() {
+        let path = std::env::temp_dir().join(format!(
+            "rdkafka_test_{}_client.properties",
+            std::process::id()
+        ));
+        let properties = "# Kafka client configuration\n\
+                          bootstrap.servers=localhost:9092\n\
+                          \n\
+                          ! Another comment\n\
+                          sasl.jaas.config = username=user password=pwd  \n\
+                          group.id =group\n";
+        fs::write(&path, properties).unwrap();
+        let config = ClientConfig::from_file(&path);
+        fs::remove_file(&path).unwrap();
+        let config = config.unwrap();
+
+        let mut expected = HashMap::new();
+        expected.insert(
+            "bootstrap.servers".to_string(),
+            "localhost:9092".to_string(),
+        );
+        expected.insert(
+            "sasl.jaas.config".to_string(),
+            "username=user password=pwd".to_string(),
+        );
+        expected.insert("group.id".to_string(), "group".to_string());
+        assert_eq!(config.conf_map, expected);
+    }
+
+    #[test]
+    fn test_client_config_from_file_invalid() {
+        let err = match ClientConfig::from_properties("group.id=group\nbootstrap.servers\n") {
+            Ok(_) => panic!("Invalid properties should not be parsed"),
+            Err(err) => err,
+        };
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "invalid property at line 2: bootstrap.servers"
+        );
+    }
+
+    #[test]
+    fn test_offset_reset() {
+        for &reset in &[
+            OffsetReset::Earliest,
+            OffsetReset::Latest,
+            OffsetReset::Error,
+        ] {
+            assert_eq!(reset.to_string(), reset.as_str());
+            assert_eq!(reset.as_str().parse(), Ok(reset));
+            let mut config = ClientConfig::new();
+            config.set_auto_offset_reset(reset);
+            assert_eq!(config.get("auto.offset.reset"), Some(reset.as_str()));
+        }
+        assert_eq!("smallest".parse(), Ok(OffsetReset::Earliest));
+        assert_eq!("beginning".parse(), Ok(OffsetReset::Earliest));
+        assert_eq!("largest".parse(), Ok(OffsetReset::Latest));
+        assert_eq!("end".parse(), Ok(OffsetReset::Latest));
+
+        let err = "Earliest".parse::<OffsetReset>().unwrap_err();
+        assert_eq!(
+            err,
+            ParseOffsetResetError {
+                value: "Earliest".to_owned()
+            }
+        );
+        assert_eq!(err.to_string(), "Invalid auto.offset.reset value: Earliest");
+    }
+
+    #[test]
+    fn test_config_builder() {
+        let config = ConfigBuilder::new()
+            .bootstrap_servers("localhost:9092")
+            .group_id("group")
+            .enable_auto_commit(false)
+            .session_timeout(Duration::from_secs(6))
+            .auto_commit_interval(Duration::from_millis(1500))
+            .auto_offset_reset(OffsetReset::Earliest)
+            .set("fetch.wait.max.ms", "50")
+            .build();
+
+        let mut expected = HashMap::new();
+        expected.insert(
+            "bootstrap.servers".to_string(),
+            "localhost:9092".to_string(),
+        );
+        expected.insert("group.id".to_string(), "group".to_string());
+        expected.insert("enable.auto.commit".to_string(), "false".to_string());
+        expected.insert("session.timeout.ms".to_string(), "6000".to_string());
+        expected.insert("auto.commit.interval.ms".to_string(), "1500".to_string());
+        expected.insert("auto.offset.reset".to_string(), "earliest".to_string());
+        expected.insert("fetch.wait.max.ms".to_string(), "50".to_string());
+        assert_eq!(config.conf_map, expected);
+        assert!(config.create_native_config().is_ok());
+    }
+
+    // A self-signed certificate, used as CA certificate.
+    #[cfg(feature = "ssl")]
+    const CA_CERT_PEM: &str = "-----BEGIN CERTIFICATE-----
+MIIBhjCCASugAwIBAgIUc5321ynkVoB5UxP1ZeMQcK31iK4wCgYIKoZIzj0EAwIw
+FzEVMBMGA1UEAwwMcmRrYWZrYS10ZXN0MCAXDTI2MTAxNDEyMjM1MFoYDzIxMjYw
+OTIwMTIyMzUwWjAXMRUwEwYDVQQDDAxyZGthZmthLXRlc3QwWTATBgcqhkjOPQIB
+BggqhkjOPQMBBwNCAAQZgaNTepeALYWMb6jq6U92W8LiWj17SgUFPmqqNXW2MIUf
+yYY4THsCX651gGpfEaCW2ogOY5Eq6BJxBbOA0C8/o1MwUTAdBgNVHQ4EFgQUuiuO
+rnpZ4rwyR3owg9nedMrTSOkwHwYDVR0jBBgwFoAUuiuOrnpZ4rwyR3owg9nedMrT
+SOkwDwYDVR0TAQH/BAUwAwEB/zAKBggqhkjOPQQDAgNJADBGAiEAwdbAvncvbPvb
+v788YyQIY3BsZplvZgfU3dtgWGlgfi8CIQDBuImSkI5BEdxWoSI8YeoPLw3s0HK6
+/PZW2ByYjeuX2Q==
+-----END CERTIFICATE-----
+";
+
+    // Setting certificates requires librdkafka to be built with SSL support.
+    #[cfg(feature = "ssl")]
+    #[test]
+    fn test_client_config_set_ssl_cert() {
+        let mut config = ClientConfig::new();
+        config
+            .set_ssl_cert(CertType::Ca, CertEncoding::Pem, CA_CERT_PEM.as_bytes())
+            .unwrap()
+            .set("security.protocol", "ssl");
+        assert_eq!(config.ssl_certs.len(), 1);
+        assert!(config.validate().is_ok());
+        assert!(config.create_native_config().is_ok());
+
+        // A PEM certificate can't be decoded as PKCS#12 or DER, nor as a private key.
+        for &(cert_type, encoding) in &[
+            (CertType::Ca, CertEncoding::Pkcs12),
+            (CertType::Ca, CertEncoding::Der),
+            (CertType::PrivateKey, CertEncoding::Pem),
+        ] {
+            match config.set_ssl_cert(cert_type, encoding, CA_CERT_PEM.as_bytes()) {
+                Err(KafkaError::ClientConfig(result, _, key, value)) => {
+                    assert_eq!(result, RDKafkaConfRes::RD_KAFKA_CONF_INVALID);
+                    assert_eq!(key, cert_type.as_str());
+                    assert_eq!(value, encoding.as_str());
+                }
+                _ => panic!("The certificate should be rejected"),
+            }
+        }
+        assert_eq!(config.ssl_certs.len(), 1);
+    }
+
+    #[cfg(not(feature = "ssl"))]
+    #[test]
+    fn test_client_config_set_ssl_cert_without_ssl() {
+        let mut config = ClientConfig::new();
+        match config.set_ssl_cert(CertType::Ca, CertEncoding::Pem, b"certificate") {
+            Err(KafkaError::ClientConfig(result, description, key, value)) => {
+                assert_eq!(result, RDKafkaConfRes::RD_KAFKA_CONF_INVALID);
+                assert_eq!(description, "librdkafka not built with OpenSSL support");
+                assert_eq!(key, CertType::Ca.as_str());
+                assert_eq!(value, CertEncoding::Pem.as_str());
+            }
+            _ => panic!("The certificate should be rejected"),
+        }
+
+        // The SSL parameters, including the key password, are not supported either. The error
+        // names the parameter without revealing the password.
+        for password in &["secret", "sec\0ret"] {
+            config.set("ssl.key.password", password);
+            let error = match config.set_ssl_cert(CertType::Ca, CertEncoding::Pem, b"certificate") {
+                Err(error) => error,
+                Ok(_) => panic!("The key password should be rejected"),
+            };
+            assert!(!format!("{} {:?}", error, error).contains("sec"));
+            match error {
+                KafkaError::ClientConfig(result, description, key, value) => {
+                    assert!(result.is_error());
+                    assert!(!description.is_empty());
+                    assert_eq!(key, "ssl.key.password");
+                    assert_eq!(value, "");
+                }
+                other => panic!("Unexpected error: {:?}", other),
+            }
+        }
+        assert!(config.ssl_certs.is_empty());
+    }
+
+    #[test]
+    fn test_client_config_validate() {
+        let mut config = ClientConfig::new();
+        config
+            .set("bootstrap.servers", "localhost:9092")
+            .set("session.timeout.ms", "not a number")
+            .set("unknown.param", "value");
+        let params = match config.validate() {
+            Err(KafkaError::ClientConfigValidation(params)) => params,
+            other => panic!("Unexpected validation result: {:?}", other),
+        };
+        assert_eq!(params.len(), 2);
+        assert_eq!(params[0].key, "session.timeout.ms");
+        assert_eq!(params[0].value, "not a number");
+        assert_eq!(params[0].result, RDKafkaConfRes::RD_KAFKA_CONF_INVALID);
+        assert_eq!(params[1].key, "unknown.param");
+        assert_eq!(params[1].result, RDKafkaConfRes::RD_KAFKA_CONF_UNKNOWN);
+
+        config.set("session.timeout.ms", "6000");
+        let params = match config.validate() {
+            Err(KafkaError::ClientConfigValidation(params)) => params,
+            other => panic!("Unexpected validation result: {:?}", other),
+        };
+        assert_eq!(params.len(), 1);
+        assert_eq!(params[0].key, "unknown.param");
+
+        config.set("client.id", "nul\0byte");
+        let params = match config.validate() {
+            Err(KafkaError::ClientConfigValidation(params)) => params,
+            other => panic!("Unexpected validation result: {:?}", other),
+        };
+        assert_eq!(params.len(), 2);
+        assert_eq!(params[0].key, "client.id");
+        assert_eq!(params[0].value, "nul\0byte");
+        assert_eq!(params[0].result, RDKafkaConfRes::RD_KAFKA_CONF_INVALID);
+        assert_eq!(params[1].key, "unknown.param");
+
+        let mut config = ClientConfig::new();
+        config.set("bootstrap.servers", "localhost:9092");
+        assert!(config.validate().is_ok());
+    }
+}
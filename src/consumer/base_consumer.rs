@@ -2,20 +2,28 @@
 use crate::rdsys;
 use crate::rdsys::types::*;
 
-use crate::client::{Client, NativeClient};
-use crate::config::{ClientConfig, FromClientConfig, FromClientConfigAndContext};
-use crate::consumer::{CommitMode, Consumer, ConsumerContext, DefaultConsumerContext};
-use crate::error::{IsError, KafkaError, KafkaResult};
+use crate::client::{Client, ClientContext, NativeClient, NativeQueue, OAuthToken};
+use crate::config::{
+    ClientConfig, FromClientConfig, FromClientConfigAndContext, OffsetReset, RDKafkaLogLevel,
+};
+use crate::consumer::{
+    CommitMode, Consumer, ConsumerContext, DefaultConsumerContext, PartitionLag,
+};
+use crate::error::{IsError, KafkaError, KafkaResult, RDKafkaErrorCode};
 use crate::groups::GroupList;
 use crate::message::{BorrowedMessage, Message};
-use crate::metadata::Metadata;
+use crate::metadata::{Metadata, OwnedMetadata};
+use crate::statistics::Statistics;
 use crate::topic_partition_list::{Offset, TopicPartitionList};
-use crate::util::{cstr_to_owned, timeout_to_ms};
+use crate::util::{cstr_to_owned, timeout_to_ms, Timeout};
 
+use std::collections::{HashMap, HashSet};
 use std::mem;
 use std::os::raw::c_void;
 use std::ptr;
 use std::str;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 use std::time::Duration;
 
 pub(crate) unsafe extern "C" fn native_commit_cb<C: ConsumerContext>(
@@ -24,18 +32,303 @@ pub(crate) unsafe extern "C" fn native_commit_cb<C: ConsumerContext>(
     offsets: *mut RDKafkaTopicPartitionList,
     opaque_ptr: *mut c_void,
 ) {
-    let context = Box::from_raw(opaque_ptr as *mut C);
+    let context = Box::from_raw(opaque_ptr as *mut BaseConsumerContext<C>);
 
     let commit_error = if err.is_error() {
         Err(KafkaError::ConsumerCommit(err.into()))
     } else {
+        if !offsets.is_null() {
+            let committed = TopicPartitionList::from_ptr(offsets);
+            context.state.record_commit(&committed);
+            committed.leak(); // Do not free the offsets, owned by librdkafka
+        }
         Ok(())
     };
-    (*context).commit_callback(commit_error, offsets);
+    context
+        .wrapped_context
+        .commit_callback(commit_error, offsets);
 
     mem::forget(context); // Do not free the context
 }
 
+/// Native callback of the synchronous commits. It stores a copy of the committed offsets, with
+/// the error of each partition, in the `Option<TopicPartitionList>` pointed by `opaque_ptr`.
+unsafe extern "C" fn native_sync_commit_cb(
+    _client: *mut RDKafka,
+    _err: RDKafkaRespErr,
+    offsets: *mut RDKafkaTopicPartitionList,
+    opaque_ptr: *mut c_void,
+) {
+    let committed = &mut *(opaque_ptr as *mut Option<TopicPartitionList>);
+    *committed = Some(if offsets.is_null() {
+        TopicPartitionList::new()
+    } else {
+        TopicPartitionList::from_ptr(rdsys::rd_kafka_topic_partition_list_copy(offsets))
+    });
+}
+
+/// A function observing the rebalances of a consumer, called before the `rebalance` method of
+/// the context, whichever thread serves the rebalance.
+pub(crate) type RebalanceHook = Box<dyn Fn(RDKafkaRespErr, &TopicPartitionList) + Send + Sync>;
+
+/// An offset reset requested with `Consumer::subscribe_with_reset`, applied to the next
+/// assignment of the consumer.
+struct PendingOffsetReset {
+    topics: Vec<String>,
+    reset: OffsetReset,
+}
+
+/// The timeout of the fetch of the committed offsets, when applying an offset reset, or when
+/// seeking asynchronously to the stored offset.
+const COMMITTED_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The state of a `BaseConsumer` that is used by the native callbacks.
+#[derive(Default)]
+pub(crate) struct ConsumerState {
+    pending_reset: Mutex<Option<PendingOffsetReset>>,
+    offset_seeds: Mutex<Vec<OffsetSeed>>,
+    commit_on_revoke: Option<CommitMode>,
+    skip_unchanged_commits: bool,
+    // The last offsets acknowledged by the group coordinator, if unchanged offsets are skipped.
+    committed: Mutex<HashMap<(String, i32), i64>>,
+    rebalance_hook: Mutex<Option<RebalanceHook>>,
+    // The partitions paused by the application. librdkafka keeps a partition paused until it's
+    // resumed, even if it's revoked and assigned again in the meantime.
+    paused: Mutex<HashSet<(String, i32)>>,
+}
+
+impl ConsumerState {
+    /// Records the pause, or the resumption, of the partitions of `partitions` that didn't
+    /// fail.
+    pub(crate) fn record_pause(&self, partitions: &TopicPartitionList, pause: bool) {
+        let mut paused = self.paused.lock().unwrap();
+        for elem in partitions.iter() {
+            if elem.error_code().is_error() {
+                continue;
+            }
+            let key = (elem.topic().to_owned(), elem.partition());
+            if pause {
+                paused.insert(key);
+            } else {
+                paused.remove(&key);
+            }
+        }
+    }
+
+    /// Records the offsets of `offsets` that were committed without errors, if unchanged offsets
+    /// are skipped.
+    fn record_commit(&self, offsets: &TopicPartitionList) {
+        if !self.skip_unchanged_commits {
+            return;
+        }
+        let mut committed = self.committed.lock().unwrap();
+        for elem in offsets.iter() {
+            if elem.error_code().is_error() {
+                continue;
+            }
+            if let Offset::Offset(offset) = elem.offset() {
+                committed.insert((elem.topic().to_owned(), elem.partition()), offset);
+            }
+        }
+    }
+
+    /// Returns the partitions of `offsets` whose offset is not the last committed one.
+    fn changed_offsets(&self, offsets: &TopicPartitionList) -> TopicPartitionList {
+        let committed = self.committed.lock().unwrap();
+        let mut changed = TopicPartitionList::with_capacity(offsets.count());
+        for elem in offsets.iter() {
+            let unchanged = match elem.offset() {
+                Offset::Offset(offset) => {
+                    let key = (elem.topic().to_owned(), elem.partition());
+                    committed.get(&key) == Some(&offset)
+                }
+                _ => false,
+            };
+            if !unchanged {
+                changed.add_partition_offset(elem.topic(), elem.partition(), elem.offset());
+            }
+        }
+        changed
+    }
+}
+
+/// The context of the native client of a `BaseConsumer`. It wraps the context of the user with
+/// the state of the consumer, so the native callbacks have access to both through the opaque.
+pub(crate) struct BaseConsumerContext<C: ConsumerContext> {
+    wrapped_context: C,
+    state: ConsumerState,
+}
+
+// Delegates all the methods calls to the wrapped context.
+impl<C: ConsumerContext> ClientContext for BaseConsumerContext<C> {
+    fn log(&self, level: RDKafkaLogLevel, fac: &str, log_message: &str) {
+        self.wrapped_context.log(level, fac, log_message);
+    }
+
+    fn stats(&self, statistics: Statistics) {
+        self.wrapped_context.stats(statistics);
+    }
+
+    fn stats_raw(&self, statistics: &[u8]) {
+        self.wrapped_context.stats_raw(statistics);
+    }
+
+    fn error(&self, error: KafkaError, reason: &str) {
+        self.wrapped_context.error(error, reason);
+    }
+
+    const ENABLE_REFRESH_OAUTH_TOKEN: bool = C::ENABLE_REFRESH_OAUTH_TOKEN;
+
+    fn generate_oauth_token(&self, oauthbearer_config: Option<&str>) -> Result<OAuthToken, String> {
+        self.wrapped_context
+            .generate_oauth_token(oauthbearer_config)
+    }
+}
+
+/// Sets the offsets of the partitions of `assignment` that belong to the topics of `reset`, and
+/// that don't have a committed offset in `committed`, to the position requested by `reset`.
+fn apply_offset_reset(
+    assignment: &mut TopicPartitionList,
+    committed: &TopicPartitionList,
+    reset: &PendingOffsetReset,
+) {
+    let offset = match reset.reset {
+        OffsetReset::Earliest => Offset::Beginning,
+        OffsetReset::Latest => Offset::End,
+        // Rejected by subscribe_with_reset.
+        OffsetReset::Error => return,
+    };
+    for elem in assignment.iter() {
+        if !reset.topics.iter().any(|topic| topic == elem.topic()) {
+            continue;
+        }
+        let has_committed_offset = match committed.find_partition(elem.topic(), elem.partition()) {
+            Some(committed) => committed.offset() != Offset::Invalid,
+            None => false,
+        };
+        if !has_committed_offset {
+            elem.set_offset(offset);
+        }
+    }
+}
+
+/// Fetches the committed offsets of a new assignment and applies an offset reset to it.
+unsafe fn reset_assignment_offsets(
+    rk: *mut RDKafka,
+    assignment: &mut TopicPartitionList,
+    reset: &PendingOffsetReset,
+) {
+    let mut committed = TopicPartitionList::new();
+    for elem in assignment.iter() {
+        if reset.topics.iter().any(|topic| topic == elem.topic()) {
+            committed.add_partition(elem.topic(), elem.partition());
+        }
+    }
+    if committed.count() == 0 {
+        return;
+    }
+    let ret_code =
+        rdsys::rd_kafka_committed(rk, committed.ptr(), timeout_to_ms(COMMITTED_FETCH_TIMEOUT));
+    if ret_code.is_error() {
+        warn!(
+            "Failed to fetch the committed offsets, the offset reset is ignored: {}",
+            KafkaError::MetadataFetch(ret_code.into())
+        );
+        return;
+    }
+    apply_offset_reset(assignment, &committed, reset);
+}
+
+/// The topic, partition and offset of an offset seed, requested with
+/// `Consumer::subscribe_and_seed`.
+type OffsetSeed = (String, i32, Offset);
+
+/// Sets the offsets of the partitions of `assignment` that have a seed to the seeded offset, and
+/// removes the applied seeds, so that each seed is only applied to the first assignment
+/// containing its partition.
+fn apply_offset_seeds(seeds: &mut Vec<OffsetSeed>, assignment: &mut TopicPartitionList) {
+    for elem in assignment.iter() {
+        let seed = seeds.iter().position(|&(ref topic, partition, _)| {
+            topic == elem.topic() && partition == elem.partition()
+        });
+        if let Some(seed) = seed {
+            let (_, _, offset) = seeds.remove(seed);
+            trace!(
+                "Seeding {} [{}] at offset {:?}",
+                elem.topic(),
+                elem.partition(),
+                offset
+            );
+            elem.set_offset(offset);
+        }
+    }
+}
+
+/// Commits the stored offsets of a consumer whose partitions are about to be revoked, if it was
+/// configured with `ClientConfig::set_commit_on_revoke`.
+pub(crate) fn commit_on_revoke<C: ConsumerContext + ?Sized>(context: &C, client: &NativeClient) {
+    let mode = client
+        .consumer_state()
+        .and_then(|state| state.commit_on_revoke);
+    let mode = match mode {
+        Some(mode) => mode,
+        None => return,
+    };
+    trace!(
+        "Committing the stored offsets before the revoke ({:?})",
+        mode
+    );
+    match unsafe { commit_native(context, client.ptr(), ptr::null(), mode) } {
+        Ok(_) => {}
+        Err(KafkaError::ConsumerCommit(RDKafkaErrorCode::NoOffset)) => {
+            trace!("No stored offsets to commit before the revoke")
+        }
+        Err(e) => warn!("Failed to commit the offsets before the revoke: {}", e),
+    }
+}
+
+/// Commits `offsets`, or the stored offsets if null. Synchronous commits wait for the result on
+/// a temporary queue, rather than on the queue of the consumer, so they can be performed from
+/// the rebalance callback, and pass it to the commit callback of the context.
+unsafe fn commit_native<C: ConsumerContext + ?Sized>(
+    context: &C,
+    rk: *mut RDKafka,
+    offsets: *const RDKafkaTopicPartitionList,
+    mode: CommitMode,
+) -> KafkaResult<TopicPartitionList> {
+    if let CommitMode::Async = mode {
+        let error = rdsys::rd_kafka_commit(rk, offsets, 1);
+        return if error.is_error() {
+            Err(KafkaError::ConsumerCommit(error.into()))
+        } else {
+            Ok(TopicPartitionList::new())
+        };
+    }
+
+    // Without a queue, librdkafka waits for the result of the commit and passes it to the
+    // callback, instead of the commit callback of the configuration.
+    let mut committed: Option<TopicPartitionList> = None;
+    let error = rdsys::rd_kafka_commit_queue(
+        rk,
+        offsets,
+        ptr::null_mut(),
+        Some(native_sync_commit_cb),
+        &mut committed as *mut Option<TopicPartitionList> as *mut c_void,
+    );
+    let result = if error.is_error() {
+        Err(KafkaError::ConsumerCommit(error.into()))
+    } else {
+        Ok(())
+    };
+    match committed {
+        Some(committed) => {
+            context.commit_callback(result.clone(), committed.ptr());
+            result.map(|()| committed)
+        }
+        None => result.map(|()| TopicPartitionList::new()),
+    }
+}
+
 /// Native rebalance callback. This callback will run on every rebalance, and it will call the
 /// rebalance method defined in the current `Context`.
 unsafe extern "C" fn native_rebalance_cb<C: ConsumerContext>(
@@ -44,22 +337,45 @@ unsafe extern "C" fn native_rebalance_cb<C: ConsumerContext>(
     native_tpl: *mut RDKafkaTopicPartitionList,
     opaque_ptr: *mut c_void,
 ) {
-    // let context: &C = &*(opaque_ptr as *const C);
-    let context = Box::from_raw(opaque_ptr as *mut C);
-    let native_client = NativeClient::from_ptr(rk);
+    let context = Box::from_raw(opaque_ptr as *mut BaseConsumerContext<C>);
+    let native_client = NativeClient::from_consumer_ptr(rk, &context.state);
     let mut tpl = TopicPartitionList::from_ptr(native_tpl);
 
-    context.rebalance(&native_client, err, &mut tpl);
+    if err == RDKafkaRespErr::RD_KAFKA_RESP_ERR__ASSIGN_PARTITIONS {
+        let reset = context.state.pending_reset.lock().unwrap().take();
+        if let Some(reset) = reset {
+            reset_assignment_offsets(rk, &mut tpl, &reset);
+        }
+        apply_offset_seeds(&mut context.state.offset_seeds.lock().unwrap(), &mut tpl);
+    }
+    if let Some(ref hook) = *context.state.rebalance_hook.lock().unwrap() {
+        hook(err, &tpl);
+    }
+    context
+        .wrapped_context
+        .rebalance(&native_client, err, &mut tpl);
 
     mem::forget(context); // Do not free the context
     mem::forget(native_client); // Do not free native client
     tpl.leak() // Do not free native topic partition list
 }
 
+/// Runs a rebalance of the consumer through the native rebalance callback, as librdkafka.
+#[cfg(test)]
+pub(crate) fn run_native_rebalance<C: ConsumerContext>(
+    consumer: &BaseConsumer<C>,
+    err: RDKafkaRespErr,
+    tpl: &TopicPartitionList,
+) {
+    let rk = consumer.client.native_ptr();
+    unsafe { native_rebalance_cb::<C>(rk, err, tpl.ptr(), rdsys::rd_kafka_opaque(rk)) };
+}
+
 /// Low level wrapper around the librdkafka consumer. This consumer requires to be periodically polled
 /// to make progress on rebalance, callbacks and to receive messages.
 pub struct BaseConsumer<C: ConsumerContext = DefaultConsumerContext> {
-    client: Client<C>,
+    client: Client<BaseConsumerContext<C>>,
+    closed: AtomicBool,
 }
 
 impl FromClientConfig for BaseConsumer {
@@ -82,6 +398,14 @@ impl<C: ConsumerContext> FromClientConfigAndContext<C> for BaseConsumer<C> {
                 Some(native_commit_cb::<C>),
             );
         }
+        let context = BaseConsumerContext {
+            wrapped_context: context,
+            state: ConsumerState {
+                commit_on_revoke: config.commit_on_revoke,
+                skip_unchanged_commits: config.skip_unchanged_commits,
+                ..ConsumerState::default()
+            },
+        };
         let client = Client::new(
             config,
             native_config,
@@ -89,11 +413,43 @@ impl<C: ConsumerContext> FromClientConfigAndContext<C> for BaseConsumer<C> {
             context,
         )?;
         unsafe { rdsys::rd_kafka_poll_set_consumer(client.native_ptr()) };
-        Ok(BaseConsumer { client })
+        Ok(BaseConsumer {
+            client,
+            closed: AtomicBool::new(false),
+        })
     }
 }
 
 impl<C: ConsumerContext> BaseConsumer<C> {
+    /// Closes the consumer, blocking until it has left the consumer group. The final offset
+    /// commit is performed if automatic commit is enabled, and the rebalance callbacks for the
+    /// revocation of the assigned partitions are run before this method returns.
+    ///
+    /// Dropping the consumer closes it as well, but doesn't report errors.
+    pub fn close(self) -> KafkaResult<()> {
+        self.close_native()
+    }
+
+    /// Closes the native consumer, if it wasn't closed already.
+    pub(crate) fn close_native(&self) -> KafkaResult<()> {
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        trace!("Closing consumer: {:?}", self.client.native_ptr());
+        let ret = unsafe { rdsys::rd_kafka_consumer_close(self.client.native_ptr()) };
+        if ret.is_error() {
+            Err(KafkaError::ConsumerClose(ret.into()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sets the hook observing the rebalances of this consumer, replacing the previous one. The
+    /// hook must not set the hook again, as it's called with the hook locked.
+    pub(crate) fn set_rebalance_hook(&self, hook: Option<RebalanceHook>) {
+        *self.client.context().state.rebalance_hook.lock().unwrap() = hook;
+    }
+
     /// Polls the consumer for messages and returns a pointer to the native rdkafka-sys struct.
     /// This method is for internal use only. Use poll instead.
     pub(crate) fn poll_raw(&self, timeout_ms: i32) -> Option<*mut RDKafkaMessage> {
@@ -106,11 +462,37 @@ impl<C: ConsumerContext> BaseConsumer<C> {
         }
     }
 
+    /// Returns the queue of the specified partition, or `None` if the partition is not known to
+    /// the consumer. This method is for internal use only.
+    pub(crate) fn partition_queue(
+        &self,
+        topic: &str,
+        partition: i32,
+    ) -> KafkaResult<Option<NativeQueue>> {
+        self.client.native_partition_queue(topic, partition)
+    }
+
+    /// Returns the consumer queue, or `None` if the consumer is not part of a consumer group. This
+    /// method is for internal use only.
+    pub(crate) fn consumer_queue(&self) -> Option<NativeQueue> {
+        self.client.native_consumer_queue()
+    }
+
     /// Polls the consumer for new messages.
     ///
     /// It won't block for more than the specified timeout. Use zero `Duration` for non-blocking
     /// call. With no timeout it blocks until an event is received.
     ///
+    /// The result is:
+    ///
+    /// * `None` if no message was received before the timeout expired;
+    /// * `Some(Ok(message))` if a message was received;
+    /// * `Some(Err(KafkaError::PartitionEOF { .. }))` if the end of a partition was reached,
+    ///   with the topic, the partition and the offset at which it was reached. This is only
+    ///   reported if `enable.partition.eof` is set, and it is not a real error: polling can
+    ///   continue as usual;
+    /// * `Some(Err(error))` for any other consumer error.
+    ///
     /// This method should be called at regular intervals, even if no message is expected,
     /// to serve any queued callbacks waiting to be called. This is especially important for
     /// automatic consumer rebalance, as the rebalance function will be executed by the thread
@@ -119,11 +501,8 @@ impl<C: ConsumerContext> BaseConsumer<C> {
     /// # Lifetime
     ///
     /// The returned message lives in the memory of the consumer and cannot outlive it.
-    pub fn poll<T: Into<Option<Duration>>>(
-        &self,
-        timeout: T,
-    ) -> Option<KafkaResult<BorrowedMessage>> {
-        self.poll_raw(timeout_to_ms(timeout))
+    pub fn poll<T: Into<Timeout>>(&self, timeout: T) -> Option<KafkaResult<BorrowedMessage>> {
+        self.poll_raw(timeout.into().as_millis())
             .map(|ptr| unsafe { BorrowedMessage::from_consumer(ptr, self) })
     }
 
@@ -173,6 +552,26 @@ impl<C: ConsumerContext> BaseConsumer<C> {
     pub fn iter(&self) -> Iter<C> {
         Iter(self)
     }
+
+    /// Returns the partitions currently assigned to the consumer that are paused, sorted by
+    /// topic and partition. This includes the partitions paused with `Consumer::pause` and the
+    /// ones selected by `ConsumerContext::partitions_to_pause`. A partition that is revoked while
+    /// paused is reported again if it's assigned again, as librdkafka keeps it paused.
+    pub fn paused_partitions(&self) -> KafkaResult<TopicPartitionList> {
+        let assignment = self.assignment()?;
+        let paused = self.client.context().state.paused.lock().unwrap();
+        let mut keys = assignment
+            .iter()
+            .map(|elem| (elem.topic().to_owned(), elem.partition()))
+            .filter(|key| paused.contains(key))
+            .collect::<Vec<_>>();
+        keys.sort();
+        let mut tpl = TopicPartitionList::with_capacity(keys.len());
+        for (topic, partition) in keys {
+            tpl.add_partition(&topic, partition);
+        }
+        Ok(tpl)
+    }
 }
 
 impl<C: ConsumerContext> Consumer<C> for BaseConsumer<C> {
@@ -193,6 +592,48 @@ impl<C: ConsumerContext> Consumer<C> for BaseConsumer<C> {
         Ok(())
     }
 
+    fn subscribe_with_reset(&self, topics: &[&str], reset: OffsetReset) -> KafkaResult<()> {
+        if reset == OffsetReset::Error {
+            return Err(KafkaError::Subscription(
+                "the error offset reset is not supported".to_owned(),
+            ));
+        }
+        // The reset is installed first, since the assignment can be received as soon as the
+        // consumer is subscribed.
+        let pending_reset = &self.client.context().state.pending_reset;
+        *pending_reset.lock().unwrap() = Some(PendingOffsetReset {
+            topics: topics.iter().map(|topic| (*topic).to_owned()).collect(),
+            reset,
+        });
+        let result = self.subscribe(topics);
+        if result.is_err() {
+            *pending_reset.lock().unwrap() = None;
+        }
+        result
+    }
+
+    fn subscribe_and_seed(&self, offsets: &TopicPartitionList) -> KafkaResult<()> {
+        let mut topics = Vec::new();
+        let mut seeds = Vec::new();
+        for elem in offsets.elements() {
+            if !topics.iter().any(|topic| topic == elem.topic()) {
+                topics.push(elem.topic().to_owned());
+            }
+            if elem.offset() != Offset::Invalid {
+                seeds.push((elem.topic().to_owned(), elem.partition(), elem.offset()));
+            }
+        }
+        // As for subscribe_with_reset, the seeds are installed before subscribing.
+        let offset_seeds = &self.client.context().state.offset_seeds;
+        *offset_seeds.lock().unwrap() = seeds;
+        let topics = topics.iter().map(String::as_str).collect::<Vec<_>>();
+        let result = self.subscribe(&topics);
+        if result.is_err() {
+            offset_seeds.lock().unwrap().clear();
+        }
+        result
+    }
+
     fn unsubscribe(&self) {
         unsafe { rdsys::rd_kafka_unsubscribe(self.client.native_ptr()) };
     }
@@ -209,8 +650,21 @@ impl<C: ConsumerContext> Consumer<C> for BaseConsumer<C> {
 
     fn seek<T>(&self, topic: &str, partition: i32, offset: Offset, timeout: T) -> KafkaResult<()>
     where
-        T: Into<Option<Duration>>,
+        T: Into<Timeout>,
     {
+        let timeout = timeout.into();
+        let mut offset = offset;
+        if offset == Offset::Stored {
+            offset = Offset::Offset(self.resolve_stored_offset(topic, partition, timeout)?);
+        }
+        if let Offset::OffsetTail(count) = offset {
+            if Offset::from_raw(offset.to_raw()) == Offset::Invalid {
+                return Err(KafkaError::Seek(format!(
+                    "invalid tail of {} messages for partition {} of topic {}",
+                    count, partition, topic
+                )));
+            }
+        }
         let topic = self.client.native_topic(topic)?;
         let ret_code = unsafe {
             rdsys::rd_kafka_seek(
@@ -227,34 +681,77 @@ impl<C: ConsumerContext> Consumer<C> for BaseConsumer<C> {
         Ok(())
     }
 
+    fn seek_partitions<T>(
+        &self,
+        partitions: &TopicPartitionList,
+        timeout: T,
+    ) -> KafkaResult<TopicPartitionList>
+    where
+        T: Into<Timeout>,
+    {
+        if let Some(elem) = partitions
+            .elements()
+            .iter()
+            .find(|elem| elem.offset() == Offset::Invalid)
+        {
+            return Err(KafkaError::Seek(format!(
+                "invalid offset for partition {} of topic {}",
+                elem.partition(),
+                elem.topic()
+            )));
+        }
+        let timeout = timeout.into();
+        let timeout_ms = timeout_to_ms(timeout);
+        let mut result = partitions.clone();
+        let no_stored_offset = self.resolve_stored_offsets(&mut result, timeout)?;
+        for elem in result.elements() {
+            if no_stored_offset.contains(&(elem.topic().to_owned(), elem.partition())) {
+                elem.set_error(RDKafkaRespErr::RD_KAFKA_RESP_ERR__NO_OFFSET);
+                continue;
+            }
+            // The handle can't be created for invalid topic names, which only fails this element.
+            let topic = match self.client.native_topic(elem.topic()) {
+                Ok(topic) => topic,
+                Err(e) => {
+                    let error = e
+                        .rdkafka_error_code()
+                        .and_then(|code| rdsys::primitive_to_rd_kafka_resp_err_t(code as i32))
+                        .unwrap_or(RDKafkaRespErr::RD_KAFKA_RESP_ERR__INVALID_ARG);
+                    elem.set_error(error);
+                    continue;
+                }
+            };
+            let ret_code = unsafe {
+                rdsys::rd_kafka_seek(
+                    topic.ptr(),
+                    elem.partition(),
+                    elem.offset().to_raw(),
+                    timeout_ms,
+                )
+            };
+            elem.set_error(ret_code);
+        }
+        Ok(result)
+    }
+
     fn commit(
         &self,
         topic_partition_list: &TopicPartitionList,
         mode: CommitMode,
-    ) -> KafkaResult<()> {
-        let error = unsafe {
-            rdsys::rd_kafka_commit(
-                self.client.native_ptr(),
-                topic_partition_list.ptr(),
-                mode as i32,
-            )
-        };
-        if error.is_error() {
-            Err(KafkaError::ConsumerCommit(error.into()))
-        } else {
-            Ok(())
+    ) -> KafkaResult<TopicPartitionList> {
+        let state = &self.client.context().state;
+        if !state.skip_unchanged_commits {
+            return self.commit_offsets(topic_partition_list.ptr(), mode);
+        }
+        let changed = state.changed_offsets(topic_partition_list);
+        if changed.count() == 0 {
+            return Ok(TopicPartitionList::new());
         }
+        self.commit_offsets(changed.ptr(), mode)
     }
 
-    fn commit_consumer_state(&self, mode: CommitMode) -> KafkaResult<()> {
-        let error = unsafe {
-            rdsys::rd_kafka_commit(self.client.native_ptr(), ptr::null_mut(), mode as i32)
-        };
-        if error.is_error() {
-            Err(KafkaError::ConsumerCommit(error.into()))
-        } else {
-            Ok(())
-        }
+    fn commit_consumer_state(&self, mode: CommitMode) -> KafkaResult<TopicPartitionList> {
+        self.commit_offsets(ptr::null(), mode)
     }
 
     fn commit_message(&self, message: &BorrowedMessage, mode: CommitMode) -> KafkaResult<()> {
@@ -288,6 +785,21 @@ impl<C: ConsumerContext> Consumer<C> for BaseConsumer<C> {
         }
     }
 
+    fn store_partition_offset(
+        &self,
+        topic: &str,
+        partition: i32,
+        offset: Offset,
+    ) -> KafkaResult<()> {
+        let mut tpl = TopicPartitionList::with_capacity(1);
+        tpl.add_partition_offset(topic, partition, offset);
+        self.store_offsets(&tpl)?;
+        match tpl.iter().find(|elem| elem.error_code().is_error()) {
+            Some(elem) => Err(KafkaError::StoreOffset(elem.error_code().into())),
+            None => Ok(()),
+        }
+    }
+
     fn subscription(&self) -> KafkaResult<TopicPartitionList> {
         let mut tpl_ptr = ptr::null_mut();
         let error = unsafe { rdsys::rd_kafka_subscription(self.client.native_ptr(), &mut tpl_ptr) };
@@ -310,7 +822,7 @@ impl<C: ConsumerContext> Consumer<C> for BaseConsumer<C> {
         }
     }
 
-    fn committed<T: Into<Option<Duration>>>(&self, timeout: T) -> KafkaResult<TopicPartitionList> {
+    fn committed<T: Into<Timeout>>(&self, timeout: T) -> KafkaResult<TopicPartitionList> {
         let mut tpl_ptr = ptr::null_mut();
         let assignment_error =
             unsafe { rdsys::rd_kafka_assignment(self.client.native_ptr(), &mut tpl_ptr) };
@@ -321,7 +833,7 @@ impl<C: ConsumerContext> Consumer<C> for BaseConsumer<C> {
         self.committed_offsets(unsafe { TopicPartitionList::from_ptr(tpl_ptr) }, timeout)
     }
 
-    fn committed_offsets<T: Into<Option<Duration>>>(
+    fn committed_offsets<T: Into<Timeout>>(
         &self,
         tpl: TopicPartitionList,
         timeout: T,
@@ -337,7 +849,7 @@ impl<C: ConsumerContext> Consumer<C> for BaseConsumer<C> {
         }
     }
 
-    fn offsets_for_timestamp<T: Into<Option<Duration>>>(
+    fn offsets_for_timestamp<T: Into<Timeout>>(
         &self,
         timestamp: i64,
         timeout: T,
@@ -348,18 +860,19 @@ impl<C: ConsumerContext> Consumer<C> for BaseConsumer<C> {
         if assignment_error.is_error() {
             return Err(KafkaError::MetadataFetch(assignment_error.into()));
         }
-        let mut tpl = unsafe { TopicPartitionList::from_ptr(tpl_ptr) };
+        let tpl = unsafe { TopicPartitionList::from_ptr(tpl_ptr) };
 
         // Set the timestamp we want in the offset field for every partition as librdkafka expects.
-        tpl.set_all_offsets(Offset::Offset(timestamp));
+        // The timestamp is not validated as an offset, since -1 and -2 are valid timestamps.
+        for elem in tpl.iter() {
+            elem.set_offset(Offset::Offset(timestamp));
+        }
 
         self.offsets_for_times(tpl, timeout)
     }
 
-    /**
-     * `timestamps` is a `TopicPartitionList` with timestamps instead of offsets.
-     */
-    fn offsets_for_times<T: Into<Option<Duration>>>(
+    // `timestamps` is a `TopicPartitionList` with timestamps instead of offsets.
+    fn offsets_for_times<T: Into<Timeout>>(
         &self,
         timestamps: TopicPartitionList,
         timeout: T,
@@ -395,7 +908,29 @@ impl<C: ConsumerContext> Consumer<C> for BaseConsumer<C> {
         }
     }
 
-    fn fetch_metadata<T: Into<Option<Duration>>>(
+    fn lag<T: Into<Timeout>>(&self, timeout: T) -> KafkaResult<HashMap<(String, i32), i64>> {
+        let timeout = timeout.into();
+        let positions = self.position()?;
+        compute_lag(&positions, |topic, partition| {
+            self.client
+                .fetch_watermarks(topic, partition, timeout)
+                .map(|(_low, high)| high)
+        })
+    }
+
+    fn assignment_lag<T: Into<Timeout>>(&self, timeout: T) -> KafkaResult<Vec<PartitionLag>> {
+        let timeout = timeout.into();
+        let assignment = self.assignment()?;
+        let committed = self.committed(timeout)?;
+        let positions = self.position()?;
+        compute_assignment_lag(&assignment, &committed, &positions, |topic, partition| {
+            self.client
+                .fetch_watermarks(topic, partition, timeout)
+                .map(|(_low, high)| high)
+        })
+    }
+
+    fn fetch_metadata<T: Into<Timeout>>(
         &self,
         topic: Option<&str>,
         timeout: T,
@@ -403,7 +938,19 @@ impl<C: ConsumerContext> Consumer<C> for BaseConsumer<C> {
         self.client.fetch_metadata(topic, timeout)
     }
 
-    fn fetch_watermarks<T: Into<Option<Duration>>>(
+    fn fetch_metadata_for_topic<T: Into<Timeout>>(
+        &self,
+        topic: &str,
+        timeout: T,
+    ) -> KafkaResult<OwnedMetadata> {
+        self.client.fetch_metadata_for_topic(topic, timeout)
+    }
+
+    fn fetch_all_metadata<T: Into<Timeout>>(&self, timeout: T) -> KafkaResult<OwnedMetadata> {
+        self.client.fetch_all_metadata(timeout)
+    }
+
+    fn fetch_watermarks<T: Into<Timeout>>(
         &self,
         topic: &str,
         partition: i32,
@@ -412,7 +959,29 @@ impl<C: ConsumerContext> Consumer<C> for BaseConsumer<C> {
         self.client.fetch_watermarks(topic, partition, timeout)
     }
 
-    fn fetch_group_list<T: Into<Option<Duration>>>(
+    fn get_watermark_offsets(&self, topic: &str, partition: i32) -> KafkaResult<(i64, i64)> {
+        self.client.get_watermark_offsets(topic, partition)
+    }
+
+    fn name(&self) -> String {
+        self.client.name()
+    }
+
+    fn member_id(&self) -> Option<String> {
+        let member_id_ptr = unsafe { rdsys::rd_kafka_memberid(self.client.native_ptr()) };
+        if member_id_ptr.is_null() {
+            return None;
+        }
+        let member_id = unsafe { cstr_to_owned(member_id_ptr) };
+        unsafe { rdsys::rd_kafka_mem_free(self.client.native_ptr(), member_id_ptr as *mut c_void) };
+        if member_id.is_empty() {
+            None
+        } else {
+            Some(member_id)
+        }
+    }
+
+    fn fetch_group_list<T: Into<Timeout>>(
         &self,
         group: Option<&str>,
         timeout: T,
@@ -421,31 +990,223 @@ impl<C: ConsumerContext> Consumer<C> for BaseConsumer<C> {
     }
 
     fn pause(&self, partitions: &TopicPartitionList) -> KafkaResult<()> {
-        let ret_code =
-            unsafe { rdsys::rd_kafka_pause_partitions(self.client.native_ptr(), partitions.ptr()) };
-        if ret_code.is_error() {
-            let error = unsafe { cstr_to_owned(rdsys::rd_kafka_err2str(ret_code)) };
-            return Err(KafkaError::PauseResume(error));
-        };
-        Ok(())
+        self.pause_resume(partitions, true)
     }
 
     fn resume(&self, partitions: &TopicPartitionList) -> KafkaResult<()> {
+        self.pause_resume(partitions, false)
+    }
+}
+
+impl<C: ConsumerContext> BaseConsumer<C> {
+    /// Commits `offsets`, or the current state of the consumer if `offsets` is null. Synchronous
+    /// commits return the offsets acknowledged by the group coordinator, and call the commit
+    /// callback of the context before returning. Asynchronous commits return an empty list.
+    fn commit_offsets(
+        &self,
+        offsets: *const RDKafkaTopicPartitionList,
+        mode: CommitMode,
+    ) -> KafkaResult<TopicPartitionList> {
+        let committed = unsafe {
+            commit_native(
+                &self.client.context().wrapped_context,
+                self.client.native_ptr(),
+                offsets,
+                mode,
+            )
+        }?;
+        // The asynchronous commits are recorded by the commit callback.
+        self.client.context().state.record_commit(&committed);
+        Ok(committed)
+    }
+
+    /// Returns the committed offset of the partition, to seek to `Offset::Stored`. Fails with a
+    /// seek error if the partition doesn't have a committed offset.
+    pub(crate) fn resolve_stored_offset(
+        &self,
+        topic: &str,
+        partition: i32,
+        timeout: Timeout,
+    ) -> KafkaResult<i64> {
+        let mut tpl = TopicPartitionList::with_capacity(1);
+        tpl.add_partition_offset(topic, partition, Offset::Stored);
+        self.resolve_stored_offsets(&mut tpl, timeout)?;
+        match tpl
+            .find_partition(topic, partition)
+            .map(|elem| elem.offset())
+        {
+            Some(Offset::Offset(offset)) => Ok(offset),
+            _ => {
+                let error = unsafe {
+                    cstr_to_owned(rdsys::rd_kafka_err2str(
+                        RDKafkaRespErr::RD_KAFKA_RESP_ERR__NO_OFFSET,
+                    ))
+                };
+                Err(KafkaError::Seek(error))
+            }
+        }
+    }
+
+    /// Replaces the `Offset::Stored` offsets of `tpl` with the committed offsets of the
+    /// partitions, as librdkafka can't seek to the stored offset. Returns the partitions that
+    /// don't have a committed offset, whose offset is left unchanged. The committed offsets are
+    /// fetched with the seek timeout, unless the seek is asynchronous: the fetch can't be, so it
+    /// blocks for up to `COMMITTED_FETCH_TIMEOUT` instead.
+    fn resolve_stored_offsets(
+        &self,
+        tpl: &mut TopicPartitionList,
+        timeout: Timeout,
+    ) -> KafkaResult<Vec<(String, i32)>> {
+        let mut stored = TopicPartitionList::new();
+        for elem in tpl.iter() {
+            if elem.offset() == Offset::Stored {
+                stored.add_partition(elem.topic(), elem.partition());
+            }
+        }
+        if stored.count() == 0 {
+            return Ok(Vec::new());
+        }
+        let timeout = match timeout {
+            Timeout::After(timeout) if timeout == Duration::from_millis(0) => {
+                Timeout::After(COMMITTED_FETCH_TIMEOUT)
+            }
+            timeout => timeout,
+        };
+        let committed = self.committed_offsets(stored, timeout)?;
+        let mut no_stored_offset = Vec::new();
+        for elem in committed.iter() {
+            match elem.offset() {
+                Offset::Offset(offset) => tpl.set_partition_offset(
+                    elem.topic(),
+                    elem.partition(),
+                    Offset::Offset(offset),
+                )?,
+                _ => no_stored_offset.push((elem.topic().to_owned(), elem.partition())),
+            }
+        }
+        Ok(no_stored_offset)
+    }
+
+    /// Pauses or resumes the specified partitions, depending on `pause`. The partitions must be
+    /// assigned to the consumer. If the operation fails for some of the partitions, the returned
+    /// error lists all of them.
+    fn pause_resume(&self, partitions: &TopicPartitionList, pause: bool) -> KafkaResult<()> {
+        let assignment = self.assignment()?;
+        let unassigned = partitions
+            .iter()
+            .filter(|elem| {
+                assignment
+                    .find_partition(elem.topic(), elem.partition())
+                    .is_none()
+            })
+            .map(|elem| format!("{} [{}]", elem.topic(), elem.partition()))
+            .collect::<Vec<_>>();
+        if !unassigned.is_empty() {
+            return Err(KafkaError::PauseResume(format!(
+                "partitions not assigned: {}",
+                unassigned.join(", ")
+            )));
+        }
+
+        // librdkafka reports the errors of each partition in the list.
+        let tpl = partitions.clone();
         let ret_code = unsafe {
-            rdsys::rd_kafka_resume_partitions(self.client.native_ptr(), partitions.ptr())
+            if pause {
+                rdsys::rd_kafka_pause_partitions(self.client.native_ptr(), tpl.ptr())
+            } else {
+                rdsys::rd_kafka_resume_partitions(self.client.native_ptr(), tpl.ptr())
+            }
         };
         if ret_code.is_error() {
             let error = unsafe { cstr_to_owned(rdsys::rd_kafka_err2str(ret_code)) };
             return Err(KafkaError::PauseResume(error));
         };
+        self.client.context().state.record_pause(&tpl, pause);
+        let failed = tpl
+            .iter()
+            .filter(|elem| elem.error_code().is_error())
+            .map(|elem| {
+                let error = unsafe { cstr_to_owned(rdsys::rd_kafka_err2str(elem.error_code())) };
+                format!("{} [{}]: {}", elem.topic(), elem.partition(), error)
+            })
+            .collect::<Vec<_>>();
+        if !failed.is_empty() {
+            return Err(KafkaError::PauseResume(failed.join(", ")));
+        }
         Ok(())
     }
 }
 
+/// Computes the lag of each partition in `positions`, using `high_watermark` to retrieve the high
+/// watermark of each partition. Partitions with an unknown position or a negative lag are skipped.
+fn compute_lag<F>(
+    positions: &TopicPartitionList,
+    mut high_watermark: F,
+) -> KafkaResult<HashMap<(String, i32), i64>>
+where
+    F: FnMut(&str, i32) -> KafkaResult<i64>,
+{
+    let mut lags = HashMap::new();
+    for elem in positions.iter() {
+        let position = match elem.offset() {
+            Offset::Offset(position) => position,
+            _ => continue,
+        };
+        let lag = high_watermark(elem.topic(), elem.partition())? - position;
+        if lag >= 0 {
+            lags.insert((elem.topic().to_owned(), elem.partition()), lag);
+        }
+    }
+    Ok(lags)
+}
+
+/// Combines the committed offsets and the positions of the partitions in `assignment`, using
+/// `high_watermark` to retrieve the high watermark of each partition. Partitions missing from
+/// `committed` or `positions`, or without a valid offset, get a `None` offset.
+fn compute_assignment_lag<F>(
+    assignment: &TopicPartitionList,
+    committed: &TopicPartitionList,
+    positions: &TopicPartitionList,
+    mut high_watermark: F,
+) -> KafkaResult<Vec<PartitionLag>>
+where
+    F: FnMut(&str, i32) -> KafkaResult<i64>,
+{
+    let known_offset = |tpl: &TopicPartitionList, topic: &str, partition: i32| match tpl
+        .find_partition(topic, partition)
+        .map(|elem| elem.offset())
+    {
+        Some(Offset::Offset(offset)) => Some(offset),
+        _ => None,
+    };
+    let mut lags = Vec::with_capacity(assignment.count());
+    for elem in assignment.iter() {
+        let (topic, partition) = (elem.topic(), elem.partition());
+        let committed = known_offset(committed, topic, partition);
+        let position = known_offset(positions, topic, partition);
+        let high_watermark = high_watermark(topic, partition)?;
+        let lag = position
+            .or(committed)
+            .map(|offset| high_watermark - offset)
+            .filter(|lag| *lag >= 0);
+        lags.push(PartitionLag {
+            topic: topic.to_owned(),
+            partition,
+            committed,
+            position,
+            high_watermark,
+            lag,
+        });
+    }
+    Ok(lags)
+}
+
 impl<C: ConsumerContext> Drop for BaseConsumer<C> {
     fn drop(&mut self) {
         trace!("Destroying consumer: {:?}", self.client.native_ptr()); // TODO: fix me (multiple executions ?)
-        unsafe { rdsys::rd_kafka_consumer_close(self.client.native_ptr()) };
+        if let Err(e) = self.close_native() {
+            warn!("Failure while closing consumer: {}", e);
+        }
         trace!("Consumer destroyed: {:?}", self.client.native_ptr());
     }
 }
@@ -473,3 +1234,763 @@ impl<'a, C: ConsumerContext + 'a> IntoIterator for &'a BaseConsumer<C> {
         self.iter()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    // Paths that don't require a broker are tested here, the rest of the behavior is tested in
+    // the integration tests.
+    use super::*;
+    use crate::consumer::pause_assigned_partitions;
+    use crate::error::RDKafkaErrorCode;
+
+    use std::sync::Arc;
+    use std::thread;
+
+    fn create_consumer() -> BaseConsumer {
+        ClientConfig::new()
+            .set("group.id", "rdkafka_unit_test_group")
+            .create()
+            .unwrap()
+    }
+
+    // Without an assignment there is nothing to commit, and the commit callback should be called
+    // before the synchronous commit returns.
+    #[test]
+    fn test_commit_consumer_state_no_offset() {
+        #[derive(Default)]
+        struct CommitContext {
+            results: std::sync::Mutex<Vec<KafkaResult<()>>>,
+        }
+
+        impl crate::client::ClientContext for CommitContext {}
+        impl ConsumerContext for CommitContext {
+            fn commit_callback(&self, result: KafkaResult<()>, _: *mut RDKafkaTopicPartitionList) {
+                self.results.lock().unwrap().push(result);
+            }
+        }
+
+        let consumer: BaseConsumer<CommitContext> = ClientConfig::new()
+            .set("group.id", "rdkafka_unit_test_group")
+            .create_with_context(CommitContext::default())
+            .unwrap();
+        let expected = Err(KafkaError::ConsumerCommit(RDKafkaErrorCode::NoOffset));
+        assert_eq!(
+            consumer.commit_consumer_state(CommitMode::Sync),
+            expected.clone().map(|()| TopicPartitionList::new())
+        );
+        let results = consumer
+            .get_base_consumer()
+            .client
+            .context()
+            .wrapped_context
+            .results
+            .lock()
+            .unwrap();
+        assert_eq!(*results, vec![expected]);
+    }
+
+    #[test]
+    fn test_rebalance_hook() {
+        let consumer = Arc::new(create_consumer());
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let hook_calls = calls.clone();
+        consumer.set_rebalance_hook(Some(Box::new(move |err, tpl| {
+            hook_calls.lock().unwrap().push((err, tpl.count()));
+        })));
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition("topic1", 0);
+        let revoke = RDKafkaRespErr::RD_KAFKA_RESP_ERR__REVOKE_PARTITIONS;
+        // The hook belongs to the consumer, so it runs whichever thread serves the rebalance.
+        let polling_consumer = consumer.clone();
+        let polling_tpl = tpl.clone();
+        thread::spawn(move || run_native_rebalance(&polling_consumer, revoke, &polling_tpl))
+            .join()
+            .unwrap();
+        consumer.set_rebalance_hook(None);
+        run_native_rebalance(&consumer, revoke, &tpl);
+        assert_eq!(*calls.lock().unwrap(), vec![(revoke, 1)]);
+    }
+
+    #[test]
+    fn test_apply_offset_reset() {
+        let mut assignment = TopicPartitionList::new();
+        assignment.add_partition("topic1", 0);
+        assignment.add_partition("topic1", 1);
+        assignment.add_partition("topic2", 0);
+        let mut committed = TopicPartitionList::new();
+        committed.add_partition_offset("topic1", 0, Offset::Invalid);
+        committed.add_partition_offset("topic1", 1, Offset::Offset(5));
+        let reset = PendingOffsetReset {
+            topics: vec!["topic1".to_owned()],
+            reset: OffsetReset::Earliest,
+        };
+
+        apply_offset_reset(&mut assignment, &committed, &reset);
+
+        // The committed offset and the topics that were not requested are left untouched.
+        let mut expected = TopicPartitionList::new();
+        expected.add_partition_offset("topic1", 0, Offset::Beginning);
+        expected.add_partition_offset("topic1", 1, Offset::Invalid);
+        expected.add_partition_offset("topic2", 0, Offset::Invalid);
+        assert_eq!(assignment, expected);
+    }
+
+    #[test]
+    fn test_pending_offset_reset() {
+        let consumer: BaseConsumer = ClientConfig::new()
+            .set("group.id", "test_pending_offset_reset")
+            .create()
+            .unwrap();
+        let pending_reset = &consumer.client.context().state.pending_reset;
+
+        consumer
+            .subscribe_with_reset(&["topic1"], OffsetReset::Latest)
+            .unwrap();
+        let reset = pending_reset.lock().unwrap().take().unwrap();
+        assert_eq!(reset.topics, vec!["topic1".to_owned()]);
+        assert_eq!(reset.reset, OffsetReset::Latest);
+
+        match consumer.subscribe_with_reset(&["topic1"], OffsetReset::Error) {
+            Err(KafkaError::Subscription(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(pending_reset.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_offset_seeds() {
+        let consumer: BaseConsumer = ClientConfig::new()
+            .set("group.id", "test_offset_seeds")
+            .create()
+            .unwrap();
+        let offset_seeds = &consumer.client.context().state.offset_seeds;
+
+        let mut offsets = TopicPartitionList::new();
+        offsets.add_partition_offset("topic1", 0, Offset::Offset(5));
+        offsets.add_partition_offset("topic1", 1, Offset::Offset(7));
+        offsets.add_partition("topic2", 0);
+        consumer.subscribe_and_seed(&offsets).unwrap();
+        let mut subscription = TopicPartitionList::new();
+        subscription.add_topic_unassigned("topic1");
+        subscription.add_topic_unassigned("topic2");
+        assert_eq!(
+            consumer.subscription().unwrap().to_topic_map(),
+            subscription.to_topic_map()
+        );
+
+        // The partitions without a seed are left untouched.
+        let mut assignment = TopicPartitionList::new();
+        assignment.add_partition("topic1", 0);
+        assignment.add_partition("topic2", 0);
+        apply_offset_seeds(&mut offset_seeds.lock().unwrap(), &mut assignment);
+        let mut expected = TopicPartitionList::new();
+        expected.add_partition_offset("topic1", 0, Offset::Offset(5));
+        expected.add_partition_offset("topic2", 0, Offset::Invalid);
+        assert_eq!(assignment, expected);
+
+        // Each seed is only applied once.
+        let mut assignment = TopicPartitionList::new();
+        assignment.add_partition("topic1", 0);
+        assignment.add_partition("topic1", 1);
+        apply_offset_seeds(&mut offset_seeds.lock().unwrap(), &mut assignment);
+        let mut expected = TopicPartitionList::new();
+        expected.add_partition_offset("topic1", 0, Offset::Invalid);
+        expected.add_partition_offset("topic1", 1, Offset::Offset(7));
+        assert_eq!(assignment, expected);
+        assert!(offset_seeds.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_lowest_priority_partitions() {
+        use crate::consumer::lowest_priority_partitions;
+
+        let mut assignment = TopicPartitionList::new();
+        for partition in 0..4 {
+            assignment.add_partition("topic1", partition);
+        }
+        let lags = [100, 5, 50, 5];
+
+        let lowest =
+            lowest_priority_partitions(&assignment, 2, |_, partition| lags[partition as usize]);
+        let mut expected = TopicPartitionList::new();
+        expected.add_partition("topic1", 1);
+        expected.add_partition("topic1", 3);
+        assert_eq!(lowest, expected);
+        assert_eq!(
+            lowest_priority_partitions(&assignment, 10, |_, _| 0).count(),
+            4
+        );
+    }
+
+    struct PausingContext {
+        assignments: Mutex<Vec<HashMap<(String, i32), Offset>>>,
+    }
+
+    impl crate::client::ClientContext for PausingContext {}
+
+    impl ConsumerContext for PausingContext {
+        fn partitions_to_pause(
+            &self,
+            assignment: &TopicPartitionList,
+        ) -> Option<TopicPartitionList> {
+            self.assignments
+                .lock()
+                .unwrap()
+                .push(assignment.to_topic_map());
+            let mut paused = TopicPartitionList::new();
+            paused.add_partition("topic1", 1);
+            Some(paused)
+        }
+    }
+
+    // The paused partitions should be tracked through pauses, resumptions and changes of the
+    // assignment, whether they are manual or caused by a rebalance.
+    #[test]
+    fn test_paused_partitions() {
+        let consumer = create_consumer();
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition("topic1", 0);
+        tpl.add_partition("topic1", 1);
+        tpl.add_partition("topic2", 0);
+        consumer.assign(&tpl).unwrap();
+
+        let mut to_pause = TopicPartitionList::new();
+        to_pause.add_partition("topic2", 0);
+        to_pause.add_partition("topic1", 1);
+        consumer.pause(&to_pause).unwrap();
+        let mut paused = TopicPartitionList::new();
+        paused.add_partition("topic1", 1);
+        paused.add_partition("topic2", 0);
+        assert_eq!(consumer.paused_partitions().unwrap(), paused);
+
+        // A partition dropped from the assignment is not reported anymore, but it is still
+        // paused by librdkafka if it's assigned again.
+        let mut reduced = TopicPartitionList::new();
+        reduced.add_partition("topic1", 0);
+        reduced.add_partition("topic2", 0);
+        consumer.assign(&reduced).unwrap();
+        assert_eq!(consumer.paused_partitions().unwrap().count(), 1);
+        consumer.assign(&tpl).unwrap();
+        assert_eq!(consumer.paused_partitions().unwrap(), paused);
+
+        let mut to_resume = TopicPartitionList::new();
+        to_resume.add_partition("topic2", 0);
+        consumer.resume(&to_resume).unwrap();
+        assert_eq!(consumer.paused_partitions().unwrap().count(), 1);
+
+        run_native_rebalance(
+            &consumer,
+            RDKafkaRespErr::RD_KAFKA_RESP_ERR__REVOKE_PARTITIONS,
+            &tpl,
+        );
+        assert_eq!(consumer.paused_partitions().unwrap().count(), 0);
+    }
+
+    // The default rebalance should assign the whole list, and then ask the context for the
+    // partitions to pause.
+    #[test]
+    fn test_rebalance_partitions_to_pause() {
+        let consumer: BaseConsumer<PausingContext> = ClientConfig::new()
+            .set("group.id", "rdkafka_unit_test_group")
+            .create_with_context(PausingContext {
+                assignments: Mutex::new(Vec::new()),
+            })
+            .unwrap();
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition("topic1", 0);
+        tpl.add_partition("topic1", 1);
+
+        run_native_rebalance(
+            &consumer,
+            RDKafkaRespErr::RD_KAFKA_RESP_ERR__ASSIGN_PARTITIONS,
+            &tpl,
+        );
+
+        assert_eq!(
+            *consumer
+                .client
+                .context()
+                .wrapped_context
+                .assignments
+                .lock()
+                .unwrap(),
+            vec![tpl.to_topic_map()]
+        );
+        assert_eq!(consumer.assignment().unwrap(), tpl);
+        let mut paused = TopicPartitionList::new();
+        paused.add_partition("topic1", 1);
+        assert_eq!(consumer.paused_partitions().unwrap(), paused);
+
+        // Partitions that librdkafka doesn't know about can't be paused.
+        let mut unknown = TopicPartitionList::new();
+        unknown.add_partition("topic3", 0);
+        match pause_assigned_partitions(consumer.client.native_client(), &unknown) {
+            Err(KafkaError::PauseResume(error)) => assert!(error.starts_with("topic3 [0]: ")),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(consumer.paused_partitions().unwrap(), paused);
+    }
+
+    struct RevokeContext {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl crate::client::ClientContext for RevokeContext {}
+
+    impl ConsumerContext for RevokeContext {
+        fn post_rebalance(&self, rebalance: &crate::consumer::Rebalance) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("post_rebalance {:?}", rebalance));
+        }
+
+        fn commit_callback(&self, _: KafkaResult<()>, offsets: *mut RDKafkaTopicPartitionList) {
+            let offsets = unsafe { TopicPartitionList::from_ptr(offsets) };
+            let elem = offsets.find_partition("topic1", 0).unwrap();
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("commit {:?}", elem.offset()));
+            unsafe { offsets.leak() };
+        }
+    }
+
+    // With commit on revoke, the stored offsets should be committed while the partitions are
+    // still assigned. The null offsets list passed to librdkafka stands for the current
+    // assignment, so the stored offset is only part of the commit if it happens before the
+    // unassign. Without a broker the commit fails, but it still reaches the commit callback.
+    #[test]
+    fn test_rebalance_commit_on_revoke() {
+        let consumer: BaseConsumer<RevokeContext> = ClientConfig::new()
+            .set("group.id", "rdkafka_unit_test_group")
+            .set("enable.auto.commit", "false")
+            .set("enable.auto.offset.store", "false")
+            .set("session.timeout.ms", "100")
+            .set_commit_on_revoke(Some(CommitMode::Sync))
+            .create_with_context(RevokeContext {
+                events: Mutex::new(Vec::new()),
+            })
+            .unwrap();
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition("topic1", 0);
+        consumer.assign(&tpl).unwrap();
+        consumer
+            .store_partition_offset("topic1", 0, Offset::Offset(5))
+            .unwrap();
+
+        run_native_rebalance(
+            &consumer,
+            RDKafkaRespErr::RD_KAFKA_RESP_ERR__REVOKE_PARTITIONS,
+            &tpl,
+        );
+
+        assert_eq!(
+            *consumer
+                .client
+                .context()
+                .wrapped_context
+                .events
+                .lock()
+                .unwrap(),
+            vec!["commit Offset(5)", "post_rebalance Revoke"]
+        );
+        assert_eq!(consumer.assignment().unwrap().count(), 0);
+    }
+
+    // The offsets equal to the last acknowledged commit of their partition should be skipped,
+    // and nothing should be sent if they are all unchanged. Without a broker no commit is
+    // acknowledged, so the acknowledgment is recorded directly.
+    #[test]
+    fn test_skip_unchanged_commits() {
+        let consumer: BaseConsumer<RevokeContext> = ClientConfig::new()
+            .set("group.id", "rdkafka_unit_test_group")
+            .set("enable.auto.commit", "false")
+            .set("session.timeout.ms", "100")
+            .set_skip_unchanged_commits(true)
+            .create_with_context(RevokeContext {
+                events: Mutex::new(Vec::new()),
+            })
+            .unwrap();
+        let state = &consumer.client.context().state;
+        let mut committed = TopicPartitionList::new();
+        committed.add_partition_offset("topic1", 0, Offset::Offset(5));
+        committed.add_partition_offset("topic1", 1, Offset::Offset(3));
+        state.record_commit(&committed);
+
+        assert_eq!(
+            consumer.commit(&committed, CommitMode::Sync),
+            Ok(TopicPartitionList::new())
+        );
+        assert_eq!(
+            *consumer
+                .client
+                .context()
+                .wrapped_context
+                .events
+                .lock()
+                .unwrap(),
+            Vec::<String>::new()
+        );
+
+        let mut offsets = TopicPartitionList::new();
+        offsets.add_partition_offset("topic1", 0, Offset::Offset(6));
+        offsets.add_partition_offset("topic1", 1, Offset::Offset(3));
+        offsets.add_partition_offset("topic2", 0, Offset::Offset(3));
+        offsets.add_partition_offset("topic2", 1, Offset::End);
+        let mut changed = TopicPartitionList::new();
+        changed.add_partition_offset("topic1", 0, Offset::Offset(6));
+        changed.add_partition_offset("topic2", 0, Offset::Offset(3));
+        changed.add_partition_offset("topic2", 1, Offset::End);
+        assert_eq!(state.changed_offsets(&offsets), changed);
+
+        // The changed offsets are sent, and fail without a broker.
+        assert!(consumer.commit(&offsets, CommitMode::Sync).is_err());
+        assert_eq!(
+            *consumer
+                .client
+                .context()
+                .wrapped_context
+                .events
+                .lock()
+                .unwrap(),
+            vec!["commit Offset(6)"]
+        );
+    }
+
+    #[test]
+    fn test_seek_partitions_invalid_offset() {
+        let consumer = create_consumer();
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition_offset("topic1", 0, Offset::Offset(10));
+        tpl.add_partition_offset("topic1", 1, Offset::Invalid);
+
+        match consumer.seek_partitions(&tpl, Duration::from_secs(0)) {
+            Err(KafkaError::Seek(msg)) => {
+                assert_eq!(msg, "invalid offset for partition 1 of topic topic1")
+            }
+            res => panic!("Unexpected seek result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_seek_partitions_unassigned() {
+        let consumer = create_consumer();
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition_offset("topic1", 0, Offset::Offset(10));
+        tpl.add_partition_offset("topic1", 1, Offset::Beginning);
+
+        let res = consumer
+            .seek_partitions(&tpl, Duration::from_secs(0))
+            .unwrap();
+        assert_eq!(res, tpl);
+        for elem in res.elements() {
+            assert!(elem.error().is_err());
+        }
+    }
+
+    // A topic that can't be looked up should only fail its own partitions.
+    #[test]
+    fn test_seek_partitions_invalid_topic() {
+        let consumer = create_consumer();
+        let invalid_topic = "t".repeat(600);
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition_offset(&invalid_topic, 0, Offset::Offset(10));
+        tpl.add_partition_offset("topic1", 0, Offset::Offset(10));
+
+        let res = consumer
+            .seek_partitions(&tpl, Duration::from_secs(0))
+            .unwrap();
+        let errors = res
+            .elements()
+            .iter()
+            .map(|elem| (elem.topic().to_owned(), elem.error_code()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    invalid_topic,
+                    RDKafkaRespErr::RD_KAFKA_RESP_ERR__INVALID_ARG
+                ),
+                (
+                    "topic1".to_owned(),
+                    RDKafkaRespErr::RD_KAFKA_RESP_ERR__UNKNOWN_PARTITION
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_seek_and_wait_invalid() {
+        let consumer = create_consumer();
+        match consumer.seek_and_wait("topic1", 0, Offset::Invalid, Duration::from_secs(1)) {
+            Err(KafkaError::Seek(msg)) => {
+                assert_eq!(msg, "invalid offset for partition 0 of topic topic1")
+            }
+            res => panic!("Unexpected seek result: {:?}", res),
+        }
+        // The partition is not assigned, so the synchronous seek itself fails.
+        match consumer.seek_and_wait("topic1", 0, Offset::Offset(10), Duration::from_secs(1)) {
+            Err(KafkaError::Seek(_)) => {}
+            res => panic!("Unexpected seek result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_seek_to_beginning_unassigned() {
+        let consumer = create_consumer();
+        assert_eq!(
+            consumer.seek_to_beginning(Duration::from_secs(0)),
+            Ok(TopicPartitionList::new())
+        );
+
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition_offset("topic1", 0, Offset::Offset(10));
+        let res = consumer
+            .seek_partitions_to_end(&tpl, Duration::from_secs(0))
+            .unwrap();
+        let elem = res.find_partition("topic1", 0).unwrap();
+        assert_eq!(elem.offset(), Offset::End);
+        assert!(elem.error().is_err());
+    }
+
+    // Without a broker, the committed offsets can't be fetched, so seeking to the stored offset
+    // fails before any seek is requested.
+    #[test]
+    fn test_seek_stored_without_broker() {
+        let consumer = create_consumer();
+        match consumer.seek("topic1", 0, Offset::Stored, Duration::from_millis(100)) {
+            Err(KafkaError::MetadataFetch(_)) => {}
+            other => panic!("Unexpected seek result: {:?}", other),
+        }
+
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition_offset("topic1", 0, Offset::Stored);
+        match consumer.seek_partitions(&tpl, Duration::from_millis(100)) {
+            Err(KafkaError::MetadataFetch(_)) => {}
+            other => panic!("Unexpected seek result: {:?}", other.map(|tpl| tpl.count())),
+        }
+    }
+
+    #[test]
+    fn test_compute_lag() {
+        let mut positions = TopicPartitionList::new();
+        positions.add_partition_offset("topic1", 0, Offset::Offset(10));
+        positions.add_partition_offset("topic1", 1, Offset::Offset(50));
+        positions.add_partition_offset("topic1", 2, Offset::Invalid);
+        positions.add_partition_offset("topic2", 0, Offset::Offset(120));
+
+        let watermarks: HashMap<(&str, i32), i64> = vec![
+            (("topic1", 0), 15),
+            (("topic1", 1), 50),
+            (("topic2", 0), 100),
+        ]
+        .into_iter()
+        .collect();
+        let mut lookups = Vec::new();
+        let lags = compute_lag(&positions, |topic, partition| {
+            lookups.push((topic.to_owned(), partition));
+            Ok(watermarks[&(topic, partition)])
+        })
+        .unwrap();
+
+        // No lookup for the partition without a position.
+        assert_eq!(lookups.len(), 3);
+        assert_eq!(lags.len(), 2);
+        assert_eq!(lags[&("topic1".to_owned(), 0)], 5);
+        assert_eq!(lags[&("topic1".to_owned(), 1)], 0);
+        // Negative lag.
+        assert!(!lags.contains_key(&("topic2".to_owned(), 0)));
+    }
+
+    #[test]
+    fn test_compute_lag_error() {
+        let mut positions = TopicPartitionList::new();
+        positions.add_partition_offset("topic1", 0, Offset::Offset(10));
+
+        let res = compute_lag(&positions, |_, _| {
+            Err(KafkaError::MetadataFetch(
+                RDKafkaErrorCode::OperationTimedOut,
+            ))
+        });
+        assert_eq!(
+            res,
+            Err(KafkaError::MetadataFetch(
+                RDKafkaErrorCode::OperationTimedOut
+            ))
+        );
+    }
+
+    #[test]
+    fn test_compute_assignment_lag() {
+        let mut assignment = TopicPartitionList::new();
+        assignment.add_partition("topic1", 0);
+        assignment.add_partition("topic1", 1);
+        assignment.add_partition("topic1", 2);
+        // No committed offset yet for partition 1, and partition 2 is missing.
+        let mut committed = TopicPartitionList::new();
+        committed.add_partition_offset("topic1", 0, Offset::Offset(8));
+        committed.add_partition_offset("topic1", 1, Offset::Invalid);
+        // Partition 2 hasn't been consumed yet.
+        let mut positions = TopicPartitionList::new();
+        positions.add_partition_offset("topic1", 0, Offset::Offset(10));
+        positions.add_partition_offset("topic1", 1, Offset::Offset(3));
+        positions.add_partition_offset("topic1", 2, Offset::Invalid);
+
+        let lags = compute_assignment_lag(&assignment, &committed, &positions, |_, partition| {
+            Ok(i64::from(partition) + 20)
+        })
+        .unwrap();
+
+        let lag = |partition, committed, position, high_watermark, lag| PartitionLag {
+            topic: "topic1".to_owned(),
+            partition,
+            committed,
+            position,
+            high_watermark,
+            lag,
+        };
+        assert_eq!(
+            lags,
+            vec![
+                lag(0, Some(8), Some(10), 20, Some(10)),
+                lag(1, None, Some(3), 21, Some(18)),
+                lag(2, None, None, 22, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pause_resume_unassigned() {
+        let consumer = create_consumer();
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition("topic1", 0);
+        tpl.add_partition("topic1", 1);
+
+        let expected = Err(KafkaError::PauseResume(
+            "partitions not assigned: topic1 [0], topic1 [1]".to_owned(),
+        ));
+        assert_eq!(consumer.pause(&tpl), expected);
+        assert_eq!(consumer.resume(&tpl), expected);
+    }
+
+    #[test]
+    fn test_get_watermark_offsets_not_cached() {
+        // Without a broker nothing will ever be fetched, so the cache is never populated.
+        let consumer = create_consumer();
+        assert_eq!(
+            consumer.get_watermark_offsets("topic1", 0),
+            Err(KafkaError::MetadataFetch(RDKafkaErrorCode::State))
+        );
+    }
+
+    #[test]
+    fn test_store_partition_offset_unassigned() {
+        let consumer: BaseConsumer = ClientConfig::new()
+            .set("group.id", "rdkafka_unit_test_group")
+            .set("enable.auto.offset.store", "false")
+            .create()
+            .unwrap();
+
+        assert_eq!(
+            consumer.store_partition_offset("topic1", 0, Offset::Offset(10)),
+            Err(KafkaError::StoreOffset(RDKafkaErrorCode::UnknownPartition))
+        );
+
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition_offset("topic1", 0, Offset::Offset(10));
+        tpl.add_partition_offset("topic2", 0, Offset::Offset(20));
+        assert_eq!(
+            consumer.store_offsets(&tpl),
+            Err(KafkaError::StoreOffset(RDKafkaErrorCode::UnknownPartition))
+        );
+        for elem in tpl.iter() {
+            assert_eq!(
+                elem.error(),
+                Err(KafkaError::OffsetFetch(RDKafkaErrorCode::UnknownPartition))
+            );
+        }
+    }
+
+    #[test]
+    fn test_member_id_not_joined() {
+        let consumer = create_consumer();
+        assert_eq!(consumer.member_id(), None);
+    }
+
+    // The subscription lists the subscribed topics, even if no partition is assigned yet.
+    #[test]
+    fn test_subscription() {
+        let consumer = create_consumer();
+        consumer.subscribe(&["topic1", "topic2"]).unwrap();
+        let mut expected = TopicPartitionList::new();
+        expected.add_topic_unassigned("topic1");
+        expected.add_topic_unassigned("topic2");
+        assert_eq!(
+            consumer.subscription().unwrap().to_topic_map(),
+            expected.to_topic_map()
+        );
+        assert_eq!(consumer.assignment().unwrap().count(), 0);
+
+        consumer.unsubscribe();
+        assert_eq!(consumer.subscription().unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_subscribe_regex() {
+        let consumer = create_consumer();
+        consumer.subscribe_regex("rdkafka_test_.*").unwrap();
+        let subscription = consumer.subscription().unwrap();
+        let topics = subscription
+            .iter()
+            .map(|elem| elem.topic().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(topics, vec!["^rdkafka_test_.*"]);
+    }
+
+    #[test]
+    fn test_subscribe_regex_invalid() {
+        let consumer = create_consumer();
+        for pattern in &["rdkafka_test_(", "", "^rdkafka_test_.*"] {
+            match consumer.subscribe_regex(pattern) {
+                Err(KafkaError::Subscription(_)) => (),
+                res => panic!("Unexpected result for {:?}: {:?}", pattern, res),
+            }
+        }
+        // The invalid patterns are rejected by librdkafka.
+        match consumer.subscribe_regex("rdkafka_test_[") {
+            Err(KafkaError::Subscription(error)) => {
+                assert!(error.starts_with("invalid topic pattern rdkafka_test_[: "))
+            }
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        assert_eq!(consumer.subscription().unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_subscribe_topics() {
+        let consumer = create_consumer();
+        match consumer.subscribe_topics(&["topic1", "^topic2"]) {
+            Err(KafkaError::Subscription(_)) => (),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        consumer.subscribe_topics(&["topic1", "topic2"]).unwrap();
+        assert_eq!(consumer.subscription().unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_poll_timeout() {
+        let consumer = create_consumer();
+        assert!(consumer.poll(Duration::from_millis(0)).is_none());
+        assert!(consumer.poll(Duration::from_millis(100)).is_none());
+    }
+
+    #[test]
+    fn test_close() {
+        let consumer = create_consumer();
+        assert_eq!(consumer.close_native(), Ok(()));
+        // Closing again is a no-op.
+        assert_eq!(consumer.close(), Ok(()));
+    }
+}
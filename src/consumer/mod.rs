@@ -4,27 +4,38 @@ pub mod stream_consumer;
 
 // Re-export
 pub use self::base_consumer::BaseConsumer;
-pub use self::stream_consumer::{MessageStream, StreamConsumer};
+pub use self::stream_consumer::{
+    AssignmentStream, AutoCommitStream, ConsumerEvent, MessageStream, PartitionStream,
+    PartitionStreams, Recv, RecvEvent, StreamConfig, StreamConsumer,
+};
 
 use crate::rdsys;
 use crate::rdsys::types::*;
 
 use crate::client::{ClientContext, NativeClient};
-use crate::error::KafkaResult;
+use crate::config::OffsetReset;
+use crate::error::{IsError, KafkaError, KafkaResult, RDKafkaErrorCode};
 use crate::groups::GroupList;
 use crate::message::BorrowedMessage;
-use crate::metadata::Metadata;
-use crate::util::cstr_to_owned;
+use crate::metadata::{Metadata, OwnedMetadata};
+use crate::util::{cstr_to_owned, Timeout};
 
+use std::cmp;
+use std::collections::HashMap;
 use std::ptr;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crate::topic_partition_list::{Offset, TopicPartitionList};
 
 /// Rebalance information.
+///
+/// The rebalances of librdkafka 1.2 always follow the eager protocol: every rebalance revokes
+/// the whole assignment, and then assigns the new one. The group generation id is not exposed by
+/// librdkafka; the member id of the consumer can be read with `Consumer::member_id`.
 #[derive(Clone, Debug)]
 pub enum Rebalance<'a> {
-    /// A new partition assignment is received.
+    /// A new partition assignment is received. The offsets of the list can be modified in
+    /// `ConsumerContext::pre_rebalance`.
     Assign(&'a TopicPartitionList),
     /// All partitions are revoked.
     Revoke,
@@ -32,12 +43,23 @@ pub enum Rebalance<'a> {
     Error(String),
 }
 
+/// The kind of a rebalance event, as reported by `StreamConsumer::assignment_stream`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RebalanceKind {
+    /// The partitions are assigned to the consumer.
+    Assign,
+    /// The partitions are revoked from the consumer.
+    Revoke,
+}
+
 /// Consumer specific Context. This user-defined object can be used to provide custom callbacks to
 /// consumer events. Refer to the list of methods to check which callbacks can be specified.
 pub trait ConsumerContext: ClientContext {
     /// Implements the default rebalancing strategy and calls the `pre_rebalance` and
     /// `post_rebalance` methods. If this method is overridden, it will be responsibility
-    /// of the user to call them if needed.
+    /// of the user to call them if needed. When the partitions are revoked, the stored offsets
+    /// are committed before the partitions are released, if enabled with
+    /// `ClientConfig::set_commit_on_revoke`.
     fn rebalance(
         &self,
         native_client: &NativeClient,
@@ -63,9 +85,17 @@ pub trait ConsumerContext: ClientContext {
             match err {
                 RDKafkaRespErr::RD_KAFKA_RESP_ERR__ASSIGN_PARTITIONS => {
                     rdsys::rd_kafka_assign(native_client.ptr(), tpl.ptr());
+                    if let Some(paused) = self.partitions_to_pause(tpl) {
+                        if let Err(e) = pause_assigned_partitions(native_client, &paused) {
+                            error!("Failed to pause the assigned partitions: {}", e);
+                        }
+                    }
                 }
                 _ => {
                     // Also for RD_KAFKA_RESP_ERR__REVOKE_PARTITIONS
+                    if err == RDKafkaRespErr::RD_KAFKA_RESP_ERR__REVOKE_PARTITIONS {
+                        base_consumer::commit_on_revoke(self, native_client);
+                    }
                     rdsys::rd_kafka_assign(native_client.ptr(), ptr::null());
                 }
             }
@@ -76,6 +106,12 @@ pub trait ConsumerContext: ClientContext {
 
     /// Pre-rebalance callback. This method will run before the rebalance and should
     /// terminate its execution quickly.
+    ///
+    /// The offsets of a `Rebalance::Assign` list can be changed here with
+    /// `TopicPartitionListElem::set_offset`: the default `rebalance` implementation assigns the
+    /// list after this method returns, so the consumer will start reading each partition from
+    /// the offset set here, for example an offset stored outside of Kafka. Partitions left at
+    /// `Offset::Invalid` resume from the committed offset.
     #[allow(unused_variables)]
     fn pre_rebalance<'a>(&self, rebalance: &Rebalance<'a>) {}
 
@@ -84,9 +120,24 @@ pub trait ConsumerContext: ClientContext {
     #[allow(unused_variables)]
     fn post_rebalance<'a>(&self, rebalance: &Rebalance<'a>) {}
 
+    /// Returns the partitions of a new assignment that should be paused as soon as they are
+    /// assigned, for example the ones with the lowest priority according to their lag, as
+    /// selected by `lowest_priority_partitions`. Called by the default `rebalance`
+    /// implementation, after the assignment and before `post_rebalance`. The default
+    /// implementation doesn't pause any partition.
+    ///
+    /// This only changes which of the assigned partitions the consumer processes first: the
+    /// partitions assigned by the group coordinator stay assigned to the consumer, and the paused
+    /// ones can be resumed later with `Consumer::resume`.
+    #[allow(unused_variables)]
+    fn partitions_to_pause(&self, assignment: &TopicPartitionList) -> Option<TopicPartitionList> {
+        None
+    }
+
     // TODO: convert pointer to structure
     /// Post commit callback. This method will run after a group of offsets was committed to the
-    /// offset store.
+    /// offset store. For synchronous commits it runs on the committing thread, before the commit
+    /// method returns.
     #[allow(unused_variables)]
     fn commit_callback(&self, result: KafkaResult<()>, offsets: *mut RDKafkaTopicPartitionList) {}
 }
@@ -98,8 +149,24 @@ pub struct DefaultConsumerContext;
 impl ClientContext for DefaultConsumerContext {}
 impl ConsumerContext for DefaultConsumerContext {}
 
+/// Returns the librdkafka subscription corresponding to a regular expression. The regular
+/// expression itself is validated by librdkafka when subscribing.
+fn regex_subscription(pattern: &str) -> KafkaResult<String> {
+    if pattern.is_empty() {
+        return Err(KafkaError::Subscription("empty topic pattern".to_string()));
+    }
+    if pattern.starts_with('^') {
+        return Err(KafkaError::Subscription(format!(
+            "topic pattern {} must not start with ^, it is added automatically",
+            pattern
+        )));
+    }
+    Ok(format!("^{}", pattern))
+}
+
 /// Specifies if the commit should be performed synchronously
 /// or asynchronously.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CommitMode {
     /// Synchronous commit.
     Sync = 0,
@@ -107,6 +174,82 @@ pub enum CommitMode {
     Async = 1,
 }
 
+/// Offsets and lag of a partition assigned to the consumer, as returned by
+/// [`Consumer::assignment_lag`](trait.Consumer.html#method.assignment_lag).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartitionLag {
+    /// The topic of the partition.
+    pub topic: String,
+    /// The id of the partition.
+    pub partition: i32,
+    /// The committed offset of the consumer group, if any.
+    pub committed: Option<i64>,
+    /// The current position of the consumer, if known.
+    pub position: Option<i64>,
+    /// The high watermark of the partition.
+    pub high_watermark: i64,
+    /// The difference between the high watermark and the position, or the committed offset if
+    /// the position is not known. `None` if neither is known.
+    pub lag: Option<i64>,
+}
+
+/// Returns the `count` partitions of `assignment` with the lowest priority, as computed by the
+/// `priority` function, for example from the lag of each partition. Partitions with the same
+/// priority keep their order in the list. Meant to implement
+/// `ConsumerContext::partitions_to_pause`.
+pub fn lowest_priority_partitions<F>(
+    assignment: &TopicPartitionList,
+    count: usize,
+    mut priority: F,
+) -> TopicPartitionList
+where
+    F: FnMut(&str, i32) -> i64,
+{
+    let mut partitions = assignment
+        .iter()
+        .map(|elem| {
+            let priority = priority(elem.topic(), elem.partition());
+            (priority, elem.topic().to_owned(), elem.partition())
+        })
+        .collect::<Vec<_>>();
+    partitions.sort_by_key(|&(priority, _, _)| priority);
+    let mut lowest = TopicPartitionList::with_capacity(count.min(partitions.len()));
+    for (_, topic, partition) in partitions.into_iter().take(count) {
+        lowest.add_partition(&topic, partition);
+    }
+    lowest
+}
+
+/// Pauses the partitions selected by `ConsumerContext::partitions_to_pause`. The error lists the
+/// partitions that couldn't be paused.
+pub(crate) fn pause_assigned_partitions(
+    native_client: &NativeClient,
+    partitions: &TopicPartitionList,
+) -> KafkaResult<()> {
+    let ret_code =
+        unsafe { rdsys::rd_kafka_pause_partitions(native_client.ptr(), partitions.ptr()) };
+    if ret_code.is_error() {
+        return Err(KafkaError::PauseResume(
+            RDKafkaErrorCode::from(ret_code).to_string(),
+        ));
+    }
+    if let Some(state) = native_client.consumer_state() {
+        state.record_pause(partitions, true);
+    }
+    let failed = partitions
+        .iter()
+        .filter(|elem| elem.error_code().is_error())
+        .map(|elem| {
+            let error = RDKafkaErrorCode::from(elem.error_code());
+            format!("{} [{}]: {}", elem.topic(), elem.partition(), error)
+        })
+        .collect::<Vec<_>>();
+    if !failed.is_empty() {
+        return Err(KafkaError::PauseResume(failed.join(", ")));
+    }
+    Ok(())
+}
+
 /// Common trait for all consumers.
 ///
 /// # Note about object safety
@@ -128,6 +271,64 @@ pub trait Consumer<C: ConsumerContext = DefaultConsumerContext> {
         self.get_base_consumer().subscribe(topics)
     }
 
+    /// Subscribe the consumer to a list of topics, each interpreted literally. Unlike
+    /// `subscribe`, topic names starting with `^` are rejected instead of being interpreted as
+    /// regular expressions; use `subscribe_regex` to subscribe to a pattern.
+    fn subscribe_topics(&self, topics: &[&str]) -> KafkaResult<()> {
+        if let Some(topic) = topics.iter().find(|topic| topic.starts_with('^')) {
+            return Err(KafkaError::Subscription(format!(
+                "topic name {} would be interpreted as a regular expression",
+                topic
+            )));
+        }
+        self.get_base_consumer().subscribe(topics)
+    }
+
+    /// Subscribe the consumer to all the topics matching the regular expression `pattern`,
+    /// including the topics created after the subscription. The pattern is prefixed with `^`,
+    /// which is how librdkafka distinguishes patterns from topic names, and validated by
+    /// librdkafka, using its own regular expression engine. The pattern must not start with `^`
+    /// itself, as the prefix is added automatically. Note that librdkafka matches the pattern
+    /// against the whole topic name only if it ends with `$`.
+    fn subscribe_regex(&self, pattern: &str) -> KafkaResult<()> {
+        let subscription = regex_subscription(pattern)?;
+        self.get_base_consumer()
+            .subscribe(&[&subscription])
+            .map_err(|e| match e {
+                KafkaError::Subscription(error) => KafkaError::Subscription(format!(
+                    "invalid topic pattern {}: {}",
+                    pattern, error
+                )),
+                e => e,
+            })
+    }
+
+    /// Subscribe the consumer to a list of topics, like `subscribe`, but start reading the
+    /// assigned partitions that have no committed offset according to `reset`, instead of the
+    /// `auto.offset.reset` configuration. The reset only applies to the first assignment received
+    /// after the call, and the committed offsets of the partitions that have one are never
+    /// overridden. If the committed offsets can't be fetched during the rebalance, the consumer
+    /// falls back to `auto.offset.reset`. `OffsetReset::Error` is not supported, and is rejected
+    /// with `KafkaError::Subscription`.
+    ///
+    /// The committed offsets are fetched by the rebalance callback, which blocks the thread
+    /// polling the consumer until the group coordinator answers, for up to 10 seconds.
+    fn subscribe_with_reset(&self, topics: &[&str], reset: OffsetReset) -> KafkaResult<()> {
+        self.get_base_consumer().subscribe_with_reset(topics, reset)
+    }
+
+    /// Subscribe the consumer to the topics of `offsets`, and start reading the partitions of
+    /// `offsets` from the offsets they contain once they are assigned, e.g. to resume from
+    /// offsets stored outside of Kafka. Each offset is applied to the first assignment received
+    /// after the call that contains its partition, overriding the committed offset. The assigned
+    /// partitions without an offset in `offsets`, or with `Offset::Invalid`, start from their
+    /// committed offset, or according to `auto.offset.reset`. The offsets are applied before the
+    /// rebalance method of the context is called, so they also apply to custom rebalances that
+    /// assign the list they receive.
+    fn subscribe_and_seed(&self, offsets: &TopicPartitionList) -> KafkaResult<()> {
+        self.get_base_consumer().subscribe_and_seed(offsets)
+    }
+
     /// Unsubscribe the current subscription list.
     fn unsubscribe(&self) {
         self.get_base_consumer().unsubscribe();
@@ -142,22 +343,173 @@ pub trait Consumer<C: ConsumerContext = DefaultConsumerContext> {
     /// Seek to `offset` for the specified `topic` and `partition`. After a
     /// successful call to `seek`, the next poll of the consumer will return the
     /// message with `offset`.
+    ///
+    /// Seeking to `Offset::Stored` seeks to the offset committed by the consumer group, which
+    /// is fetched first. An error is returned if the partition has no committed offset. The
+    /// committed offset is fetched synchronously even if the seek is asynchronous (with a zero
+    /// timeout), in which case the fetch blocks for up to 10 seconds.
     fn seek<T>(&self, topic: &str, partition: i32, offset: Offset, timeout: T) -> KafkaResult<()>
     where
-        T: Into<Option<Duration>>,
+        T: Into<Timeout>,
     {
         self.get_base_consumer()
             .seek(topic, partition, offset, timeout)
     }
 
+    /// Same as `seek`, but the seek is performed synchronously, to an absolute offset, which is
+    /// returned. Logical offsets are resolved first: `Offset::Beginning`, `Offset::End` and
+    /// `Offset::OffsetTail` with the watermarks of the partition, and `Offset::Stored` with the
+    /// committed offset. Once this method returns, the messages fetched before the seek are
+    /// discarded, and the next message returned for the partition is the one at the returned
+    /// offset, if it still exists. The timeout covers both the resolution and the seek and must
+    /// not be zero.
+    ///
+    /// librdkafka only updates `position` when a message is consumed, so it can't be used to
+    /// check that the seek took effect.
+    fn seek_and_wait<T>(
+        &self,
+        topic: &str,
+        partition: i32,
+        offset: Offset,
+        timeout: T,
+    ) -> KafkaResult<Offset>
+    where
+        T: Into<Timeout>,
+        Self: Sized,
+    {
+        let deadline = match timeout.into() {
+            Timeout::After(timeout) => Some(Instant::now() + timeout),
+            Timeout::Never => None,
+        };
+        // A timeout rounded down to zero milliseconds would make the seek asynchronous.
+        let min_timeout = Duration::from_millis(1);
+        let remaining = || {
+            deadline.map(|deadline| {
+                let now = Instant::now();
+                if now + min_timeout < deadline {
+                    deadline - now
+                } else {
+                    min_timeout
+                }
+            })
+        };
+        let resolved = match offset {
+            Offset::Offset(offset) => offset,
+            Offset::OffsetTail(count) if count < 0 => {
+                return Err(KafkaError::Seek(format!(
+                    "invalid offset for partition {} of topic {}",
+                    partition, topic
+                )))
+            }
+            Offset::Beginning | Offset::End | Offset::OffsetTail(_) => {
+                let (low, high) = self.fetch_watermarks(topic, partition, remaining())?;
+                match offset {
+                    Offset::Beginning => low,
+                    Offset::OffsetTail(count) => cmp::max(high - count, low),
+                    _ => high,
+                }
+            }
+            Offset::Stored => self.get_base_consumer().resolve_stored_offset(
+                topic,
+                partition,
+                remaining().into(),
+            )?,
+            Offset::Invalid => {
+                return Err(KafkaError::Seek(format!(
+                    "invalid offset for partition {} of topic {}",
+                    partition, topic
+                )))
+            }
+        };
+        let resolved = Offset::Offset(resolved);
+        self.seek(topic, partition, resolved, remaining())?;
+        Ok(resolved)
+    }
+
+    /// Seek every partition in `partitions` to the offset specified for it in the list. The
+    /// failures of the individual seeks, including the partitions whose topic can't be looked
+    /// up, are reported in the returned list and can be inspected with
+    /// [`TopicPartitionListElem::error`], and don't prevent the other seeks. Partitions with an
+    /// [`Offset::Invalid`] offset are rejected before any seek is performed. The timeout
+    /// applies to each partition individually. As in `seek`, `Offset::Stored` seeks to the
+    /// committed offset, fetched first even if the seek is asynchronous; partitions without a
+    /// committed offset are reported with a `RDKafkaErrorCode::NoOffset` error.
+    ///
+    /// [`TopicPartitionListElem::error`]: ../topic_partition_list/struct.TopicPartitionListElem.html#method.error
+    /// [`Offset::Invalid`]: ../topic_partition_list/enum.Offset.html#variant.Invalid
+    fn seek_partitions<T>(
+        &self,
+        partitions: &TopicPartitionList,
+        timeout: T,
+    ) -> KafkaResult<TopicPartitionList>
+    where
+        T: Into<Timeout>,
+    {
+        self.get_base_consumer()
+            .seek_partitions(partitions, timeout)
+    }
+
+    /// Seek every partition in `partitions` to its beginning, ignoring the offsets of the list.
+    /// The result is reported as in `seek_partitions`.
+    fn seek_partitions_to_beginning<T>(
+        &self,
+        partitions: &TopicPartitionList,
+        timeout: T,
+    ) -> KafkaResult<TopicPartitionList>
+    where
+        T: Into<Timeout>,
+    {
+        let mut partitions = partitions.clone();
+        partitions.set_all_offsets(Offset::Beginning)?;
+        self.seek_partitions(&partitions, timeout)
+    }
+
+    /// Seek every partition in `partitions` to its end, ignoring the offsets of the list. The
+    /// result is reported as in `seek_partitions`.
+    fn seek_partitions_to_end<T>(
+        &self,
+        partitions: &TopicPartitionList,
+        timeout: T,
+    ) -> KafkaResult<TopicPartitionList>
+    where
+        T: Into<Timeout>,
+    {
+        let mut partitions = partitions.clone();
+        partitions.set_all_offsets(Offset::End)?;
+        self.seek_partitions(&partitions, timeout)
+    }
+
+    /// Seek every partition of the current assignment to its beginning, to replay all the
+    /// available messages. The result is reported as in `seek_partitions`.
+    fn seek_to_beginning<T>(&self, timeout: T) -> KafkaResult<TopicPartitionList>
+    where
+        T: Into<Timeout>,
+    {
+        self.seek_partitions_to_beginning(&self.assignment()?, timeout)
+    }
+
+    /// Seek every partition of the current assignment to its end, to skip all the messages
+    /// received so far. The result is reported as in `seek_partitions`.
+    fn seek_to_end<T>(&self, timeout: T) -> KafkaResult<TopicPartitionList>
+    where
+        T: Into<Timeout>,
+    {
+        self.seek_partitions_to_end(&self.assignment()?, timeout)
+    }
+
     /// Commits the offset of the specified message. The commit can be sync (blocking), or async.
     /// Notice that when a specific offset is committed, all the previous offsets are considered
     /// committed as well. Use this method only if you are processing messages in order.
+    ///
+    /// A synchronous commit returns the offsets acknowledged by the group coordinator. If the
+    /// commit failed only for some of the partitions, the method succeeds and the error of each
+    /// partition is available through `TopicPartitionListElem::error`. An asynchronous commit
+    /// returns an empty list, as the result is only passed to `ConsumerContext::commit_callback`.
     fn commit(
         &self,
         topic_partition_list: &TopicPartitionList,
         mode: CommitMode,
-    ) -> KafkaResult<()> {
+    ) -> KafkaResult<TopicPartitionList> {
         self.get_base_consumer().commit(topic_partition_list, mode)
     }
 
@@ -165,7 +517,9 @@ pub trait Consumer<C: ConsumerContext = DefaultConsumerContext> {
     /// has been received, but before the message has been processed by the user code,
     /// this might lead to data loss. Check the "at-least-once delivery" section in the readme
     /// for more information.
-    fn commit_consumer_state(&self, mode: CommitMode) -> KafkaResult<()> {
+    ///
+    /// The returned offsets are the ones that were committed, as described in `commit`.
+    fn commit_consumer_state(&self, mode: CommitMode) -> KafkaResult<TopicPartitionList> {
         self.get_base_consumer().commit_consumer_state(mode)
     }
 
@@ -183,11 +537,34 @@ pub trait Consumer<C: ConsumerContext = DefaultConsumerContext> {
 
     /// Store offsets to be used on the next (auto)commit.
     /// When using this `enable.auto.offset.store` should be set to `false` in the config.
+    ///
+    /// The offset of each element must be the offset of the next message to consume, i.e. the
+    /// offset of the last processed message plus one. An error is only returned if none of the
+    /// offsets could be stored; the errors of the individual partitions, such as an unknown
+    /// partition, are reported by the `error` method of the corresponding elements of `tpl`.
     fn store_offsets(&self, tpl: &TopicPartitionList) -> KafkaResult<()> {
         self.get_base_consumer().store_offsets(tpl)
     }
 
-    /// Returns the current topic subscription.
+    /// Store the offset of the specified partition to be used on the next (auto)commit.
+    /// When using this `enable.auto.offset.store` should be set to `false` in the config.
+    ///
+    /// Like in `store_offsets`, the offset must be the offset of the next message to consume.
+    fn store_partition_offset(
+        &self,
+        topic: &str,
+        partition: i32,
+        offset: Offset,
+    ) -> KafkaResult<()> {
+        self.get_base_consumer()
+            .store_partition_offset(topic, partition, offset)
+    }
+
+    /// Returns the current topic subscription, as set by `subscribe` or `subscribe_regex`. Each
+    /// topic, or regular expression, is listed once with the unassigned partition, as added by
+    /// `TopicPartitionList::add_topic_unassigned`. Unlike `assignment`, the subscription doesn't
+    /// depend on the partitions assigned by the group coordinator, and it is empty when the
+    /// partitions are assigned manually with `assign`.
     fn subscription(&self) -> KafkaResult<TopicPartitionList> {
         self.get_base_consumer().subscription()
     }
@@ -200,20 +577,24 @@ pub trait Consumer<C: ConsumerContext = DefaultConsumerContext> {
     /// Retrieve committed offsets for topics and partitions.
     fn committed<T>(&self, timeout: T) -> KafkaResult<TopicPartitionList>
     where
-        T: Into<Option<Duration>>,
+        T: Into<Timeout>,
         Self: Sized,
     {
         self.get_base_consumer().committed(timeout)
     }
 
-    /// Retrieve committed offsets for specified topics and partitions.
+    /// Retrieve committed offsets for specified topics and partitions. The partitions don't need
+    /// to be assigned to the consumer, so the offsets committed by its group can be fetched for
+    /// any partition, for example to monitor the lag of the group. Partitions without a
+    /// committed offset get `Offset::Invalid`, and the error of each partition whose offset
+    /// could not be fetched is available through `TopicPartitionListElem::error`.
     fn committed_offsets<T>(
         &self,
         tpl: TopicPartitionList,
         timeout: T,
     ) -> KafkaResult<TopicPartitionList>
     where
-        T: Into<Option<Duration>>,
+        T: Into<Timeout>,
     {
         self.get_base_consumer().committed_offsets(tpl, timeout)
     }
@@ -225,7 +606,7 @@ pub trait Consumer<C: ConsumerContext = DefaultConsumerContext> {
         timeout: T,
     ) -> KafkaResult<TopicPartitionList>
     where
-        T: Into<Option<Duration>>,
+        T: Into<Timeout>,
         Self: Sized,
     {
         self.get_base_consumer()
@@ -233,13 +614,20 @@ pub trait Consumer<C: ConsumerContext = DefaultConsumerContext> {
     }
 
     /// Look up the offsets for the specified partitions by timestamp.
+    ///
+    /// The offset field of each element of `timestamps` must contain the timestamp to look up,
+    /// in milliseconds since the Unix epoch, e.g. `Offset::Offset(timestamp_ms)`. In the returned
+    /// list, the offset of each element is the earliest offset whose timestamp is greater than or
+    /// equal to the requested timestamp, or `Offset::End` if there is no such message. Errors
+    /// affecting a single partition, such as an unknown partition, are reported by the `error`
+    /// method of the corresponding element.
     fn offsets_for_times<T>(
         &self,
         timestamps: TopicPartitionList,
         timeout: T,
     ) -> KafkaResult<TopicPartitionList>
     where
-        T: Into<Option<Duration>>,
+        T: Into<Timeout>,
         Self: Sized,
     {
         self.get_base_consumer()
@@ -251,17 +639,60 @@ pub trait Consumer<C: ConsumerContext = DefaultConsumerContext> {
         self.get_base_consumer().position()
     }
 
+    /// Returns the lag of each assigned partition, computed as the difference between the high
+    /// watermark of the partition and the current position of the consumer. Partitions whose
+    /// position is not known yet, or whose lag would be negative, are not included. The timeout
+    /// applies to each watermark lookup.
+    fn lag<T>(&self, timeout: T) -> KafkaResult<HashMap<(String, i32), i64>>
+    where
+        T: Into<Timeout>,
+        Self: Sized,
+    {
+        self.get_base_consumer().lag(timeout)
+    }
+
+    /// Returns the committed offset, the current position, the high watermark and the lag of each
+    /// assigned partition. Offsets that are not known yet are reported as `None`. The timeout
+    /// applies to the committed offsets lookup and to each watermark lookup.
+    fn assignment_lag<T>(&self, timeout: T) -> KafkaResult<Vec<PartitionLag>>
+    where
+        T: Into<Timeout>,
+        Self: Sized,
+    {
+        self.get_base_consumer().assignment_lag(timeout)
+    }
+
     /// Returns the metadata information for the specified topic, or for all topics in the cluster
     /// if no topic is specified.
     fn fetch_metadata<T>(&self, topic: Option<&str>, timeout: T) -> KafkaResult<Metadata>
     where
-        T: Into<Option<Duration>>,
+        T: Into<Timeout>,
         Self: Sized,
     {
         self.get_base_consumer().fetch_metadata(topic, timeout)
     }
 
-    /// Returns the metadata information for all the topics in the cluster.
+    /// Returns an owned snapshot of the metadata of the specified topic.
+    fn fetch_metadata_for_topic<T>(&self, topic: &str, timeout: T) -> KafkaResult<OwnedMetadata>
+    where
+        T: Into<Timeout>,
+        Self: Sized,
+    {
+        self.get_base_consumer()
+            .fetch_metadata_for_topic(topic, timeout)
+    }
+
+    /// Returns an owned snapshot of the metadata of all the topics in the cluster.
+    fn fetch_all_metadata<T>(&self, timeout: T) -> KafkaResult<OwnedMetadata>
+    where
+        T: Into<Timeout>,
+        Self: Sized,
+    {
+        self.get_base_consumer().fetch_all_metadata(timeout)
+    }
+
+    /// Returns the low and high watermark for the specified topic and partition, querying the
+    /// broker.
     fn fetch_watermarks<T>(
         &self,
         topic: &str,
@@ -269,30 +700,66 @@ pub trait Consumer<C: ConsumerContext = DefaultConsumerContext> {
         timeout: T,
     ) -> KafkaResult<(i64, i64)>
     where
-        T: Into<Option<Duration>>,
+        T: Into<Timeout>,
         Self: Sized,
     {
         self.get_base_consumer()
             .fetch_watermarks(topic, partition, timeout)
     }
 
+    /// Returns the low and high watermark for the specified topic and partition, as last cached
+    /// by the consumer, without querying the broker. See `Client::get_watermark_offsets`.
+    fn get_watermark_offsets(&self, topic: &str, partition: i32) -> KafkaResult<(i64, i64)> {
+        self.get_base_consumer()
+            .get_watermark_offsets(topic, partition)
+    }
+
+    /// Returns the member id assigned to the consumer by the group coordinator, or `None` if the
+    /// consumer is not part of a group or hasn't joined it yet.
+    fn member_id(&self) -> Option<String> {
+        self.get_base_consumer().member_id()
+    }
+
+    /// Returns the name of the client instance. See `Client::name`.
+    fn name(&self) -> String {
+        self.get_base_consumer().name()
+    }
+
     /// Returns the group membership information for the given group. If no group is
     /// specified, all groups will be returned.
     fn fetch_group_list<T>(&self, group: Option<&str>, timeout: T) -> KafkaResult<GroupList>
     where
-        T: Into<Option<Duration>>,
+        T: Into<Timeout>,
         Self: Sized,
     {
         self.get_base_consumer().fetch_group_list(group, timeout)
     }
 
-    /// Pause consumption for the provided list of partitions.
+    /// Pause consumption for the provided list of partitions. All the partitions must be assigned
+    /// to the consumer, otherwise an error is returned and no partition is paused. If pausing
+    /// fails for some of the partitions, the error lists each of them.
     fn pause(&self, partitions: &TopicPartitionList) -> KafkaResult<()> {
         self.get_base_consumer().pause(partitions)
     }
 
-    /// Resume consumption for the provided list of partitions.
+    /// Resume consumption for the provided list of partitions. As for `pause`, all the partitions
+    /// must be assigned to the consumer.
     fn resume(&self, partitions: &TopicPartitionList) -> KafkaResult<()> {
         self.get_base_consumer().resume(partitions)
     }
+
+    /// Pauses consumption for all the partitions currently assigned to the consumer. The
+    /// assignment is read again on every call, so the partitions assigned by a rebalance since
+    /// the previous call are paused as well. Partitions assigned after this call are not paused.
+    fn pause_all(&self) -> KafkaResult<()> {
+        let assignment = self.assignment()?;
+        self.pause(&assignment)
+    }
+
+    /// Resumes consumption for all the partitions currently assigned to the consumer. See
+    /// `pause_all`.
+    fn resume_all(&self) -> KafkaResult<()> {
+        let assignment = self.assignment()?;
+        self.resume(&assignment)
+    }
 }
@@ -2,26 +2,35 @@
 use crate::rdsys;
 use crate::rdsys::types::*;
 use futures::sync::mpsc;
-use futures::{Future, Poll, Sink, Stream};
+use futures::{Async, Future, Poll, Sink, Stream};
 
+use crate::client::NativeQueue;
 use crate::config::{ClientConfig, FromClientConfig, FromClientConfigAndContext};
 use crate::consumer::base_consumer::BaseConsumer;
-use crate::consumer::{Consumer, ConsumerContext, DefaultConsumerContext};
+use crate::consumer::{
+    CommitMode, Consumer, ConsumerContext, DefaultConsumerContext, RebalanceKind,
+};
 use crate::error::{KafkaError, KafkaResult};
-use crate::message::BorrowedMessage;
+use crate::message::{BorrowedMessage, Message, MessagePool};
+use crate::topic_partition_list::{Offset, TopicPartitionList};
 use crate::util::duration_to_millis;
 
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::CStr;
 use std::ptr;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 /// Default channel size for the stream consumer. The number of context switches
 /// seems to decrease exponentially as the channel size is increased, and it stabilizes when
 /// the channel size reaches 10 or so.
 const CONSUMER_CHANNEL_SIZE: usize = 10;
 
+/// How often a partition polling thread checks if it can start consuming from its partition queue.
+const SPLIT_READY_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
 /// A small wrapper for a message pointer. This wrapper is only used to
 /// pass a message between the polling thread and the thread consuming the stream,
 /// and to transform it from pointer to `BorrowedMessage` with a lifetime that derives from the
@@ -31,13 +40,17 @@ const CONSUMER_CHANNEL_SIZE: usize = 10;
 /// are safe to be used for their entire lifetime.
 struct PolledMessagePtr {
     message_ptr: *mut RDKafkaMessage,
+    generation: u64,
 }
 
 impl PolledMessagePtr {
     /// Creates a new PolledPtr from a message pointer. It takes the ownership of the message.
     fn new(message_ptr: *mut RDKafkaMessage) -> PolledMessagePtr {
         trace!("New polled ptr {:?}", message_ptr);
-        PolledMessagePtr { message_ptr }
+        PolledMessagePtr {
+            message_ptr,
+            generation: 0,
+        }
     }
 
     /// Transforms the `PolledMessagePtr` into a message whose lifetime will be bound to the
@@ -51,6 +64,39 @@ impl PolledMessagePtr {
         self.message_ptr = ptr::null_mut();
         msg
     }
+
+    /// Returns the topic and partition of the message, if available, without copying the topic.
+    fn topic_partition(&self) -> Option<(&str, i32)> {
+        unsafe {
+            let topic_ptr = (*self.message_ptr).rkt;
+            if topic_ptr.is_null() {
+                return None;
+            }
+            let topic = CStr::from_ptr(rdsys::rd_kafka_topic_name(topic_ptr))
+                .to_str()
+                .ok()?;
+            Some((topic, (*self.message_ptr).partition))
+        }
+    }
+
+    /// Returns the topic and partition of the message, if available.
+    fn partition_key(&self) -> Option<PartitionKey> {
+        self.topic_partition()
+            .map(|(topic, partition)| (topic.to_owned(), partition))
+    }
+
+    /// Returns true if the partition of the message has been revoked after the message was
+    /// polled, and the message should be dropped. Nothing is dropped if `revoked` is None.
+    fn is_revoked(&self, revoked: Option<&Arc<RevokedPartitions>>) -> bool {
+        let revoked = match revoked {
+            Some(revoked) => revoked,
+            None => return false,
+        };
+        match self.topic_partition() {
+            Some((topic, partition)) => revoked.is_revoked(topic, partition, self.generation),
+            None => false,
+        }
+    }
 }
 
 impl Drop for PolledMessagePtr {
@@ -67,6 +113,200 @@ impl Drop for PolledMessagePtr {
 /// Allow message pointer to be moved across threads.
 unsafe impl Send for PolledMessagePtr {}
 
+/// Counts the messages that have been polled by the polling thread but not yet yielded by the
+/// stream. It behaves like a semaphore: the polling thread acquires a slot before polling, and
+/// the stream releases it once the message is handed to the user.
+struct InflightCounter {
+    max_inflight: usize,
+    count: Mutex<usize>,
+    condvar: Condvar,
+}
+
+impl InflightCounter {
+    fn new(max_inflight: usize) -> InflightCounter {
+        InflightCounter {
+            max_inflight,
+            count: Mutex::new(0),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Acquires a slot, waiting at most `timeout` for one to become available. Returns false if
+    /// no slot could be acquired within the timeout.
+    fn acquire(&self, timeout: Duration) -> bool {
+        let mut count = self.count.lock().unwrap();
+        let start_time = Instant::now();
+        while *count >= self.max_inflight {
+            let elapsed = start_time.elapsed();
+            if elapsed >= timeout {
+                return false;
+            }
+            count = self
+                .condvar
+                .wait_timeout(count, timeout - elapsed)
+                .unwrap()
+                .0;
+        }
+        *count += 1;
+        true
+    }
+
+    /// Releases a slot previously acquired.
+    fn release(&self) {
+        let mut count = self.count.lock().unwrap();
+        *count = count.saturating_sub(1);
+        self.condvar.notify_one();
+    }
+
+    #[cfg(test)]
+    fn count(&self) -> usize {
+        *self.count.lock().unwrap()
+    }
+}
+
+/// Tracks the partitions revoked from the consumer, so that the messages of a revoked partition
+/// that are still buffered for the stream can be dropped. Polled messages are tagged with the
+/// current generation, which is incremented every time partitions are revoked, so messages
+/// polled since the last revocation are checked without locking.
+#[derive(Default)]
+struct RevokedPartitions {
+    generation: AtomicU64,
+    // The generation of the last revocation of every partition, by topic.
+    revoked: Mutex<HashMap<String, HashMap<i32, u64>>>,
+}
+
+impl RevokedPartitions {
+    /// Returns the current generation.
+    fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Records the revocation of the partitions in `tpl`, starting a new generation.
+    fn revoke(&self, tpl: &TopicPartitionList) {
+        let mut revoked = self.revoked.lock().unwrap();
+        let generation = self.generation.load(Ordering::Relaxed) + 1;
+        for elem in tpl.iter() {
+            revoked
+                .entry(elem.topic().to_owned())
+                .or_default()
+                .insert(elem.partition(), generation);
+        }
+        self.generation.store(generation, Ordering::Release);
+    }
+
+    /// Returns true if the partition has been revoked after a message of the specified
+    /// generation was polled.
+    fn is_revoked(&self, topic: &str, partition: i32, generation: u64) -> bool {
+        if self.generation() == generation {
+            return false;
+        }
+        match self
+            .revoked
+            .lock()
+            .unwrap()
+            .get(topic)
+            .and_then(|partitions| partitions.get(&partition))
+        {
+            Some(&revoked_generation) => revoked_generation > generation,
+            None => false,
+        }
+    }
+}
+
+/// Configuration of the stream returned by `StreamConsumer::start_with_config`.
+#[derive(Clone, Debug)]
+pub struct StreamConfig {
+    poll_interval: Duration,
+    no_message_error: bool,
+    idle_timeout: Option<Duration>,
+    max_inflight: Option<usize>,
+    message_pool: Option<Arc<MessagePool>>,
+    drain_on_revoke: bool,
+}
+
+impl Default for StreamConfig {
+    fn default() -> StreamConfig {
+        StreamConfig::new()
+    }
+}
+
+impl StreamConfig {
+    /// Creates a new stream configuration, with a 100ms polling interval, no `NoMessageReceived`
+    /// notifications and no bound on the number of in-flight messages.
+    pub fn new() -> StreamConfig {
+        StreamConfig {
+            poll_interval: Duration::from_millis(100),
+            no_message_error: false,
+            idle_timeout: None,
+            max_inflight: None,
+            message_pool: None,
+            drain_on_revoke: false,
+        }
+    }
+
+    /// Sets the polling interval of the internal polling thread.
+    pub fn poll_interval(mut self, poll_interval: Duration) -> StreamConfig {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// If set to true, the stream will return an error of type `KafkaError::NoMessageReceived`
+    /// every time the poll interval is reached and no message has been received.
+    pub fn no_message_error(mut self, no_message_error: bool) -> StreamConfig {
+        self.no_message_error = no_message_error;
+        self
+    }
+
+    /// If set, the stream will return an error of type `KafkaError::NoMessageReceived`, which
+    /// `MessageStream::recv_event` reports as `ConsumerEvent::Idle`, every time no message has
+    /// been received for `idle_timeout`, so that the consuming loop can wake up periodically,
+    /// e.g. to commit or to run health checks. The timeout is checked every time a poll of the
+    /// polling thread times out, so the event can be delayed by up to one poll interval. Polls
+    /// block for the poll interval, so an idle consumer doesn't use CPU between the events.
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> StreamConfig {
+        self.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    /// Sets the maximum number of messages that can be polled from the consumer without having
+    /// been yielded by the stream yet. Once the limit is reached the polling thread stops
+    /// polling the consumer until the stream is drained. A limit of 0 is rounded up to 1, as the
+    /// polling thread would otherwise never poll the consumer.
+    ///
+    /// Note that this only bounds the messages buffered between the polling thread and the
+    /// stream: librdkafka will still pre-fetch messages into its own queue, up to the limits
+    /// set by `queued.min.messages` and `queued.max.messages.kbytes`. Also, since the consumer
+    /// is not polled while the limit is reached, rebalance callbacks will be delayed until the
+    /// stream is drained, and the consumer might leave the group if it is not drained
+    /// within `max.poll.interval.ms`.
+    pub fn max_inflight(mut self, max_inflight: usize) -> StreamConfig {
+        self.max_inflight = Some(max_inflight.max(1));
+        self
+    }
+
+    /// Sets the pool used to allocate the key and the payload of the messages of the stream when
+    /// they are detached with `BorrowedMessage::detach`. The buffers are returned to the pool
+    /// when the `OwnedMessage`s are dropped, so that consumers detaching every message don't
+    /// need to allocate new buffers. By default no pool is used.
+    pub fn message_pool(mut self, message_pool: Arc<MessagePool>) -> StreamConfig {
+        self.message_pool = Some(message_pool);
+        self
+    }
+
+    /// If set to true, the messages of a partition that are still buffered between the polling
+    /// thread and the stream when the partition is revoked are dropped, instead of being yielded
+    /// after the revocation. The buffered messages are discarded as soon as the revocation
+    /// starts, before `ConsumerContext::pre_rebalance` is called, whichever call serves the
+    /// rebalance, so that the application doesn't process or commit messages of partitions it
+    /// doesn't own anymore. This applies to the streams returned by
+    /// `StreamConsumer::split_partition_queues` as well. Messages already yielded by the streams
+    /// are not affected. Defaults to false.
+    pub fn drain_on_revoke(mut self, drain_on_revoke: bool) -> StreamConfig {
+        self.drain_on_revoke = drain_on_revoke;
+        self
+    }
+}
+
 /// A Kafka consumer implementing Stream.
 ///
 /// It can be used to receive messages as they are consumed from Kafka. Note: there might be
@@ -77,14 +317,55 @@ unsafe impl Send for PolledMessagePtr {}
 pub struct MessageStream<'a, C: ConsumerContext + 'static> {
     consumer: &'a StreamConsumer<C>,
     receiver: mpsc::Receiver<Option<PolledMessagePtr>>,
+    inflight: Option<Arc<InflightCounter>>,
+    message_pool: Option<Arc<MessagePool>>,
+    revoked: Option<Arc<RevokedPartitions>>,
 }
 
 impl<'a, C: ConsumerContext + 'static> MessageStream<'a, C> {
     fn new(
         consumer: &'a StreamConsumer<C>,
         receiver: mpsc::Receiver<Option<PolledMessagePtr>>,
+        inflight: Option<Arc<InflightCounter>>,
+        message_pool: Option<Arc<MessagePool>>,
+        revoked: Option<Arc<RevokedPartitions>>,
     ) -> MessageStream<'a, C> {
-        MessageStream { consumer, receiver }
+        MessageStream {
+            consumer,
+            receiver,
+            inflight,
+            message_pool,
+            revoked,
+        }
+    }
+
+    /// Returns a future that resolves to the next message of the stream. Messages are not
+    /// copied: the `BorrowedMessage` points to the memory allocated by librdkafka, and it can't
+    /// outlive the consumer. Use `BorrowedMessage::detach` to get an `OwnedMessage` if the
+    /// message needs to be retained. The future fails with `KafkaError::Canceled` if the stream
+    /// terminated, because the consumer was stopped.
+    pub fn recv(&mut self) -> Recv<'_, 'a, C> {
+        Recv { stream: self }
+    }
+
+    /// Same as `recv`, but the future resolves to a `ConsumerEvent`, so that reaching the end of
+    /// a partition, or going without messages, can be told apart from the other errors. The end
+    /// of a partition is only reported if `enable.partition.eof` is set, and idleness if
+    /// `StreamConfig::idle_timeout` or `StreamConfig::no_message_error` is set.
+    pub fn recv_event(&mut self) -> RecvEvent<'_, 'a, C> {
+        RecvEvent { stream: self }
+    }
+
+    /// Wraps the stream into an `AutoCommitStream`, that commits the offsets of the messages it
+    /// yields, so that the consumer doesn't need to commit them explicitly.
+    pub fn auto_committing(self, interval: Duration, mode: CommitMode) -> AutoCommitStream<'a, C> {
+        AutoCommitStream {
+            stream: self,
+            mode,
+            offsets: AutoCommitOffsets::new(interval, Instant::now()),
+            commit_error: None,
+            terminated: false,
+        }
     }
 }
 
@@ -93,41 +374,822 @@ impl<'a, C: ConsumerContext + 'a> Stream for MessageStream<'a, C> {
     type Error = ();
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            let polled_ptr = match self.receiver.poll()? {
+                Async::Ready(Some(Some(polled_ptr))) => polled_ptr,
+                Async::Ready(Some(None)) => {
+                    return Ok(Async::Ready(Some(Err(KafkaError::NoMessageReceived))))
+                }
+                Async::Ready(None) => return Ok(Async::Ready(None)),
+                Async::NotReady => return Ok(Async::NotReady),
+            };
+            if let Some(ref inflight) = self.inflight {
+                inflight.release();
+            }
+            if polled_ptr.is_revoked(self.revoked.as_ref()) {
+                trace!("Dropping buffered message of a revoked partition");
+                continue;
+            }
+            let message = polled_ptr
+                .into_message_of(self.consumer)
+                .map(|message| message.with_pool(self.message_pool.clone()));
+            return Ok(Async::Ready(Some(message)));
+        }
+    }
+}
+
+/// The future returned by `MessageStream::recv`.
+#[must_use = "Futures do nothing unless polled"]
+pub struct Recv<'s, 'a, C: ConsumerContext + 'static> {
+    stream: &'s mut MessageStream<'a, C>,
+}
+
+impl<'s, 'a, C: ConsumerContext + 'a> Future for Recv<'s, 'a, C> {
+    type Item = BorrowedMessage<'a>;
+    type Error = KafkaError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.stream.poll() {
+            Ok(Async::Ready(Some(Ok(message)))) => Ok(Async::Ready(message)),
+            Ok(Async::Ready(Some(Err(e)))) => Err(e),
+            Ok(Async::Ready(None)) | Err(()) => Err(KafkaError::Canceled),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+        }
+    }
+}
+
+/// An item of a `MessageStream`, with the end of a partition distinguished from the errors.
+/// Items of the stream can be converted with `ConsumerEvent::from`, e.g. with
+/// `stream.map(ConsumerEvent::from)`.
+#[derive(Debug)]
+pub enum ConsumerEvent<'a> {
+    /// A message was consumed.
+    Message(BorrowedMessage<'a>),
+    /// The end of a partition was reached. `offset` is the offset of the next message that will
+    /// be produced to the partition.
+    Eof {
+        /// The topic of the partition.
+        topic: String,
+        /// The partition.
+        partition: i32,
+        /// The offset reached.
+        offset: i64,
+    },
+    /// No message was received for the idle timeout of the stream, or for the poll interval if
+    /// `StreamConfig::no_message_error` is set.
+    Idle,
+    /// An error occurred.
+    Error(KafkaError),
+}
+
+impl<'a> From<KafkaResult<BorrowedMessage<'a>>> for ConsumerEvent<'a> {
+    fn from(result: KafkaResult<BorrowedMessage<'a>>) -> ConsumerEvent<'a> {
+        match result {
+            Ok(message) => ConsumerEvent::Message(message),
+            Err(KafkaError::PartitionEOF {
+                topic,
+                partition,
+                offset,
+            }) => ConsumerEvent::Eof {
+                topic,
+                partition,
+                offset,
+            },
+            Err(KafkaError::NoMessageReceived) => ConsumerEvent::Idle,
+            Err(e) => ConsumerEvent::Error(e),
+        }
+    }
+}
+
+/// The future returned by `MessageStream::recv_event`.
+#[must_use = "Futures do nothing unless polled"]
+pub struct RecvEvent<'s, 'a, C: ConsumerContext + 'static> {
+    stream: &'s mut MessageStream<'a, C>,
+}
+
+impl<'s, 'a, C: ConsumerContext + 'a> Future for RecvEvent<'s, 'a, C> {
+    type Item = ConsumerEvent<'a>;
+    type Error = KafkaError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.stream.poll() {
+            Ok(Async::Ready(Some(result))) => Ok(Async::Ready(ConsumerEvent::from(result))),
+            Ok(Async::Ready(None)) | Err(()) => Err(KafkaError::Canceled),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+        }
+    }
+}
+
+/// A topic name and partition pair.
+type PartitionKey = (String, i32);
+
+/// A stream of messages that commits the offsets of the messages it yields, returned by
+/// `MessageStream::auto_committing`.
+///
+/// Every time a message is yielded, its offset plus one, which is the offset of the next message
+/// to read, is registered for its partition. When a message is yielded, the registered offsets
+/// of the partitions whose commit interval has elapsed since their last commit are committed
+/// together, and all the registered offsets are committed when the stream terminates or is
+/// dropped. The commit interval of a partition can be overridden with `set_partition_interval`.
+/// A failed commit is yielded as an error, and its offsets are retried at the next commit. If
+/// the final commit fails, its error is yielded once before the stream terminates. With
+/// `set_skip_unchanged`, the offsets that are already committed, e.g. because messages were
+/// consumed again after a rebalance, are not committed again.
+///
+/// Offsets are committed when the messages are yielded, not once they are processed, so the
+/// stream provides at-most-once delivery: the messages that were yielded but not processed yet
+/// when the application crashes are not consumed again after a restart.
+pub struct AutoCommitStream<'a, C: ConsumerContext + 'static> {
+    stream: MessageStream<'a, C>,
+    mode: CommitMode,
+    offsets: AutoCommitOffsets,
+    commit_error: Option<KafkaError>,
+    terminated: bool,
+}
+
+impl<'a, C: ConsumerContext + 'static> AutoCommitStream<'a, C> {
+    /// Sets the commit interval of a partition, overriding the interval of the stream.
+    pub fn set_partition_interval(&mut self, topic: &str, partition: i32, interval: Duration) {
+        self.offsets
+            .intervals
+            .insert((topic.to_owned(), partition), interval);
+    }
+
+    /// Sets whether the registered offsets that are equal to the last offsets committed by the
+    /// stream for their partition are skipped, to avoid sending redundant commits. Disabled by
+    /// default.
+    pub fn set_skip_unchanged(&mut self, skip_unchanged: bool) {
+        self.offsets.skip_unchanged = skip_unchanged;
+    }
+
+    /// Commits the offsets registered since the last commit, if any.
+    pub fn commit(&mut self) -> KafkaResult<()> {
+        self.commit_offsets(None)
+    }
+
+    /// Commits the registered offsets of the partitions whose commit interval has elapsed.
+    fn commit_due(&mut self) -> KafkaResult<()> {
+        self.commit_offsets(Some(Instant::now()))
+    }
+
+    fn commit_offsets(&mut self, due_at: Option<Instant>) -> KafkaResult<()> {
+        let tpl = self.offsets.to_commit(due_at);
+        if tpl.count() == 0 {
+            return Ok(());
+        }
+        self.stream.consumer.commit(&tpl, self.mode)?;
+        self.offsets.committed(&tpl, Instant::now());
+        Ok(())
+    }
+}
+
+impl<'a, C: ConsumerContext + 'a> Stream for AutoCommitStream<'a, C> {
+    type Item = KafkaResult<BorrowedMessage<'a>>;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if let Some(e) = self.commit_error.take() {
+            return Ok(Async::Ready(Some(Err(e))));
+        }
+        if self.terminated {
+            return Ok(Async::Ready(None));
+        }
+        match self.stream.poll()? {
+            Async::Ready(Some(Ok(message))) => {
+                self.offsets.register(&message);
+                if let Err(e) = self.commit_due() {
+                    self.commit_error = Some(e);
+                }
+                Ok(Async::Ready(Some(Ok(message))))
+            }
+            Async::Ready(None) => {
+                self.terminated = true;
+                match self.commit() {
+                    Ok(()) => Ok(Async::Ready(None)),
+                    Err(e) => Ok(Async::Ready(Some(Err(e)))),
+                }
+            }
+            other => Ok(other),
+        }
+    }
+}
+
+impl<'a, C: ConsumerContext + 'static> Drop for AutoCommitStream<'a, C> {
+    fn drop(&mut self) {
+        if let Err(e) = self.commit() {
+            warn!(
+                "Failed to commit the offsets of the auto-commit stream: {}",
+                e
+            );
+        }
+    }
+}
+
+/// The offsets registered by an `AutoCommitStream`, with the time and the offset of the last
+/// commit of each partition.
+struct AutoCommitOffsets {
+    interval: Duration,
+    intervals: HashMap<PartitionKey, Duration>,
+    created: Instant,
+    last_commits: HashMap<PartitionKey, Instant>,
+    committed_offsets: HashMap<PartitionKey, i64>,
+    skip_unchanged: bool,
+    pending: HashMap<PartitionKey, i64>,
+}
+
+impl AutoCommitOffsets {
+    fn new(interval: Duration, created: Instant) -> AutoCommitOffsets {
+        AutoCommitOffsets {
+            interval,
+            intervals: HashMap::new(),
+            created,
+            last_commits: HashMap::new(),
+            committed_offsets: HashMap::new(),
+            skip_unchanged: false,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Registers the offset of the next message to read after `message`. If unchanged offsets
+    /// are skipped and the offset is the last committed one, nothing is left to commit for the
+    /// partition.
+    fn register<M: Message>(&mut self, message: &M) {
+        let key = (message.topic().to_owned(), message.partition());
+        let offset = message.offset() + 1;
+        if self.skip_unchanged && self.committed_offsets.get(&key) == Some(&offset) {
+            self.pending.remove(&key);
+        } else {
+            self.pending.insert(key, offset);
+        }
+    }
+
+    /// Returns whether the commit interval of a partition has elapsed at `now`. Partitions that
+    /// were never committed count from the creation of the stream.
+    fn is_due(&self, key: &PartitionKey, now: Instant) -> bool {
+        let interval = self.intervals.get(key).cloned().unwrap_or(self.interval);
+        let last_commit = self.last_commits.get(key).cloned().unwrap_or(self.created);
+        now.duration_since(last_commit) >= interval
+    }
+
+    /// Builds the list of registered offsets to commit, restricted to the partitions due at
+    /// `due_at` if set.
+    fn to_commit(&self, due_at: Option<Instant>) -> TopicPartitionList {
+        let mut tpl = TopicPartitionList::new();
+        for (key, offset) in &self.pending {
+            let due = match due_at {
+                Some(now) => self.is_due(key, now),
+                None => true,
+            };
+            if due {
+                tpl.add_partition_offset(&key.0, key.1, Offset::Offset(*offset));
+            }
+        }
+        tpl
+    }
+
+    /// Marks the partitions of `tpl` as committed at `now`.
+    fn committed(&mut self, tpl: &TopicPartitionList, now: Instant) {
+        for elem in tpl.iter() {
+            let key = (elem.topic().to_owned(), elem.partition());
+            self.pending.remove(&key);
+            if let Offset::Offset(offset) = elem.offset() {
+                self.committed_offsets.insert(key.clone(), offset);
+            }
+            self.last_commits.insert(key, now);
+        }
+    }
+}
+
+/// A stream of the messages of a single partition, split from the main stream of a
+/// `StreamConsumer` with `StreamConsumer::split_partition_queues`. Messages are yielded in the
+/// same order they are stored in the partition. The stream terminates when the partition is
+/// revoked from the consumer.
+pub struct PartitionStream<'a, C: ConsumerContext + 'static> {
+    consumer: &'a StreamConsumer<C>,
+    topic: String,
+    partition: i32,
+    receiver: mpsc::Receiver<PolledMessagePtr>,
+    revoked: Option<Arc<RevokedPartitions>>,
+}
+
+impl<'a, C: ConsumerContext + 'static> PartitionStream<'a, C> {
+    /// Returns the topic of the partition.
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// Returns the partition.
+    pub fn partition(&self) -> i32 {
+        self.partition
+    }
+}
+
+impl<'a, C: ConsumerContext + 'a> Stream for PartitionStream<'a, C> {
+    type Item = KafkaResult<BorrowedMessage<'a>>;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            let polled_ptr = match self.receiver.poll()? {
+                Async::Ready(Some(polled_ptr)) => polled_ptr,
+                Async::Ready(None) => return Ok(Async::Ready(None)),
+                Async::NotReady => return Ok(Async::NotReady),
+            };
+            if polled_ptr.is_revoked(self.revoked.as_ref()) {
+                trace!("Dropping buffered message of a revoked partition");
+                continue;
+            }
+            return Ok(Async::Ready(Some(
+                polled_ptr.into_message_of(self.consumer),
+            )));
+        }
+    }
+}
+
+/// A stream of `PartitionStream`s, yielding a new `PartitionStream` every time a partition is
+/// assigned to the consumer. See `StreamConsumer::split_partition_queues`.
+pub struct PartitionStreams<'a, C: ConsumerContext + 'static> {
+    consumer: &'a StreamConsumer<C>,
+    receiver: mpsc::UnboundedReceiver<SplitPartition>,
+}
+
+impl<'a, C: ConsumerContext + 'a> Stream for PartitionStreams<'a, C> {
+    type Item = PartitionStream<'a, C>;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let consumer = self.consumer;
         self.receiver.poll().map(|ready| {
             ready.map(|option| {
-                option.map(|polled_ptr_opt| {
-                    polled_ptr_opt.map_or(Err(KafkaError::NoMessageReceived), |polled_ptr| {
-                        polled_ptr.into_message_of(self.consumer)
-                    })
+                option.map(|split| PartitionStream {
+                    consumer,
+                    topic: split.topic,
+                    partition: split.partition,
+                    receiver: split.receiver,
+                    revoked: split.revoked,
                 })
             })
         })
     }
 }
 
-/// Internal consumer loop. This is the main body of the thread that will drive the stream consumer.
-/// If `send_none` is true, the loop will send a None into the sender every time the poll times out.
-fn poll_loop<C: ConsumerContext>(
+/// The sender of the rebalance events to the `AssignmentStream`, if any.
+type AssignmentAnnouncer =
+    Mutex<Option<mpsc::UnboundedSender<(RebalanceKind, TopicPartitionList)>>>;
+
+/// Sends a rebalance event to the `AssignmentStream`, if any. The sender is removed once the
+/// stream is dropped.
+fn announce_rebalance(
+    announcer: &AssignmentAnnouncer,
+    err: RDKafkaRespErr,
+    tpl: &TopicPartitionList,
+) {
+    let kind = match err {
+        RDKafkaRespErr::RD_KAFKA_RESP_ERR__ASSIGN_PARTITIONS => RebalanceKind::Assign,
+        RDKafkaRespErr::RD_KAFKA_RESP_ERR__REVOKE_PARTITIONS => RebalanceKind::Revoke,
+        _ => return,
+    };
+    let mut announcer = announcer.lock().unwrap();
+    let closed = match *announcer {
+        Some(ref sender) => sender.unbounded_send((kind, tpl.clone())).is_err(),
+        None => false,
+    };
+    if closed {
+        *announcer = None;
+    }
+}
+
+/// A stream of the rebalance events of the consumer, with the partitions assigned or revoked.
+/// See `StreamConsumer::assignment_stream`.
+pub struct AssignmentStream {
+    receiver: mpsc::UnboundedReceiver<(RebalanceKind, TopicPartitionList)>,
+}
+
+impl Stream for AssignmentStream {
+    type Item = (RebalanceKind, TopicPartitionList);
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.receiver.poll()
+    }
+}
+
+/// A partition split from the main stream, sent by the polling thread to `PartitionStreams`.
+struct SplitPartition {
+    topic: String,
+    partition: i32,
+    receiver: mpsc::Receiver<PolledMessagePtr>,
+    // Set if the buffered messages of the partition are dropped once it's revoked.
+    revoked: Option<Arc<RevokedPartitions>>,
+}
+
+/// The polling thread side of a partition split from the main stream. The messages of the
+/// partition polled from the consumer queue wait in `pending` until the partition stream has
+/// room for them, so that the polling thread never blocks on a slow partition stream.
+struct SplitPartitionHandle {
+    sender: mpsc::Sender<PolledMessagePtr>,
+    pending: VecDeque<PolledMessagePtr>,
+    ready: Arc<AtomicBool>,
+    should_stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl SplitPartitionHandle {
+    /// Sends the pending messages that fit in the partition stream, without blocking. Returns
+    /// false if the partition stream has been dropped.
+    fn flush(&mut self) -> bool {
+        while let Some(message) = self.pending.pop_front() {
+            match self.sender.try_send(message) {
+                Ok(()) => {}
+                Err(e) => {
+                    let disconnected = e.is_disconnected();
+                    self.pending.push_front(e.into_inner());
+                    return !disconnected;
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Body of the thread driving a single partition stream. Messages are only consumed from the
+/// partition queue once `ready` is set, meaning that the messages of the partition that had already
+/// been forwarded to the consumer queue have been routed to the partition stream, so that their
+/// order is preserved. While the partition stream is full, `should_stop` is checked every
+/// `SPLIT_READY_CHECK_INTERVAL`, so that the thread terminates promptly once the partition is
+/// revoked. The messages are tagged with the current generation of `revoked`.
+#[allow(clippy::too_many_arguments)]
+fn partition_poll_loop<C: ConsumerContext>(
     consumer: &BaseConsumer<C>,
-    sender: mpsc::Sender<Option<PolledMessagePtr>>,
+    queue: &NativeQueue,
+    mut sender: mpsc::Sender<PolledMessagePtr>,
+    main_sender: mpsc::Sender<Option<PolledMessagePtr>>,
+    ready: &AtomicBool,
     should_stop: &AtomicBool,
     poll_interval: Duration,
-    send_none: bool,
+    revoked: &RevokedPartitions,
+) {
+    trace!("Partition polling thread loop started");
+    let poll_interval_ms = duration_to_millis(poll_interval) as i32;
+    'poll: while !should_stop.load(Ordering::Relaxed) {
+        if !ready.load(Ordering::Relaxed) {
+            thread::sleep(SPLIT_READY_CHECK_INTERVAL);
+            continue;
+        }
+        let mut message = match queue.consume_message(poll_interval_ms) {
+            Some(message_ptr) => PolledMessagePtr::new(message_ptr),
+            None => continue,
+        };
+        message.generation = revoked.generation();
+        loop {
+            match sender.try_send(message) {
+                Ok(()) => break,
+                Err(e) if e.is_disconnected() => {
+                    // The partition stream has been dropped, so the partition is handed back to
+                    // the main stream.
+                    debug!("Partition stream not available, resuming delivery on the main stream");
+                    let _ = main_sender.send(Some(e.into_inner())).wait();
+                    queue.forward(consumer.consumer_queue().as_ref());
+                    break 'poll;
+                }
+                Err(e) => {
+                    if should_stop.load(Ordering::Relaxed) {
+                        break 'poll;
+                    }
+                    message = e.into_inner();
+                    thread::sleep(SPLIT_READY_CHECK_INTERVAL);
+                }
+            }
+        }
+    }
+    trace!("Partition polling thread loop terminated");
+}
+
+/// Splits the partitions assigned to the consumer from the main stream, once requested with
+/// `StreamConsumer::split_partition_queues`. It is driven by the main polling thread.
+struct PartitionSplitter<C: ConsumerContext + 'static> {
+    consumer: Arc<BaseConsumer<C>>,
+    consumer_queue: Option<NativeQueue>,
+    poll_interval: Duration,
+    last_update: Option<Instant>,
+    assigned: HashSet<PartitionKey>,
+    partitions: HashMap<PartitionKey, SplitPartitionHandle>,
+    // The messages to deliver on the main stream, after their partition stream was dropped.
+    returned: VecDeque<PolledMessagePtr>,
+    revoked: Arc<RevokedPartitions>,
+    drain_on_revoke: bool,
+}
+
+impl<C: ConsumerContext + 'static> PartitionSplitter<C> {
+    fn new(
+        consumer: Arc<BaseConsumer<C>>,
+        poll_interval: Duration,
+        revoked: Arc<RevokedPartitions>,
+        drain_on_revoke: bool,
+    ) -> PartitionSplitter<C> {
+        let consumer_queue = consumer.consumer_queue();
+        PartitionSplitter {
+            consumer,
+            consumer_queue,
+            poll_interval,
+            last_update: None,
+            assigned: HashSet::new(),
+            partitions: HashMap::new(),
+            returned: VecDeque::new(),
+            revoked,
+            drain_on_revoke,
+        }
+    }
+
+    /// Sends the pending messages to the partition streams, and lets the partition polling
+    /// threads start consuming from their partition queues, once no message fetched before the
+    /// split is left in the consumer queue or pending.
+    fn mark_ready(&mut self) {
+        let consumer_queue_empty = match self.consumer_queue {
+            Some(ref consumer_queue) => consumer_queue.event_count() == 0,
+            None => return,
+        };
+        for split in self.partitions.values_mut() {
+            if !split.flush() {
+                self.returned.extend(split.pending.drain(..));
+            }
+            if consumer_queue_empty && split.pending.is_empty() {
+                split.ready.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Returns the next message to deliver on the main stream because its partition stream was
+    /// dropped, if any.
+    fn take_returned(&mut self) -> Option<PolledMessagePtr> {
+        self.returned.pop_front()
+    }
+
+    /// Updates the split partitions to match the current assignment of the consumer. Unless
+    /// `force` is set, the assignment is checked at most once per poll interval.
+    fn update(
+        &mut self,
+        announcer: &mpsc::UnboundedSender<SplitPartition>,
+        main_sender: &mpsc::Sender<Option<PolledMessagePtr>>,
+        force: bool,
+    ) {
+        match self.last_update {
+            Some(last_update) if !force && last_update.elapsed() < self.poll_interval => return,
+            _ => {}
+        }
+        self.last_update = Some(Instant::now());
+        if self.consumer_queue.is_none() {
+            return;
+        }
+        let assignment = match self.consumer.assignment() {
+            Ok(assignment) => assignment,
+            Err(e) => {
+                warn!("Failed to fetch assignment: {}", e);
+                return;
+            }
+        };
+        let mut assigned = assignment
+            .elements()
+            .iter()
+            .map(|elem| (elem.topic().to_owned(), elem.partition()))
+            .collect::<HashSet<_>>();
+
+        let revoked = self
+            .partitions
+            .keys()
+            .filter(|key| !assigned.contains(*key))
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in revoked {
+            if let Some(mut split) = self.partitions.remove(&key) {
+                trace!("Closing partition stream for {:?}", key);
+                split.should_stop.store(true, Ordering::Relaxed);
+                // Unless they are drained, the pending messages are still delivered: on the
+                // partition stream before it ends if they fit, or on the main stream.
+                if !self.drain_on_revoke {
+                    split.flush();
+                    self.returned.extend(split.pending.drain(..));
+                }
+                if let Err(e) = split.handle.join() {
+                    warn!("Failure while terminating partition thread: {:?}", e);
+                }
+            }
+        }
+
+        let new_partitions = assigned
+            .difference(&self.assigned)
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in new_partitions {
+            if !self.split(key.clone(), announcer, main_sender) {
+                // Try again at the next update.
+                assigned.remove(&key);
+            }
+        }
+        self.assigned = assigned;
+    }
+
+    /// Splits the specified partition from the main stream. Returns false if the partition queue
+    /// is not available yet.
+    fn split(
+        &mut self,
+        key: PartitionKey,
+        announcer: &mpsc::UnboundedSender<SplitPartition>,
+        main_sender: &mpsc::Sender<Option<PolledMessagePtr>>,
+    ) -> bool {
+        let queue = match self.consumer.partition_queue(&key.0, key.1) {
+            Ok(Some(queue)) => queue,
+            Ok(None) => return false,
+            Err(e) => {
+                warn!("Failed to get queue of partition {:?}: {}", key, e);
+                return true;
+            }
+        };
+        let (sender, receiver) = mpsc::channel(CONSUMER_CHANNEL_SIZE);
+        let split_partition = SplitPartition {
+            topic: key.0.clone(),
+            partition: key.1,
+            receiver,
+            revoked: if self.drain_on_revoke {
+                Some(self.revoked.clone())
+            } else {
+                None
+            },
+        };
+        if announcer.unbounded_send(split_partition).is_err() {
+            // Partition streams are not requested anymore. The partition might have been split
+            // before, so make sure its messages are delivered to the main stream.
+            queue.forward(self.consumer_queue.as_ref());
+            return true;
+        }
+        trace!("Splitting partition {:?} from the main stream", key);
+        queue.forward(None);
+
+        let ready = Arc::new(AtomicBool::new(false));
+        let should_stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let consumer = self.consumer.clone();
+            let sender = sender.clone();
+            let main_sender = main_sender.clone();
+            let ready = ready.clone();
+            let should_stop = should_stop.clone();
+            let poll_interval = self.poll_interval;
+            let revoked = self.revoked.clone();
+            thread::Builder::new()
+                .name("partition-poll".to_string())
+                .spawn(move || {
+                    partition_poll_loop(
+                        consumer.as_ref(),
+                        &queue,
+                        sender,
+                        main_sender,
+                        ready.as_ref(),
+                        should_stop.as_ref(),
+                        poll_interval,
+                        revoked.as_ref(),
+                    );
+                })
+                .expect("Failed to start partition polling thread")
+        };
+        self.partitions.insert(
+            key,
+            SplitPartitionHandle {
+                sender,
+                pending: VecDeque::new(),
+                ready,
+                should_stop,
+                handle,
+            },
+        );
+        true
+    }
+
+    /// Routes a message polled from the consumer queue to its partition stream, if the partition
+    /// has been split. Returns the message back if it should be delivered on the main stream. The
+    /// message is queued without blocking if the partition stream is full.
+    fn route(
+        &mut self,
+        message: PolledMessagePtr,
+        announcer: Option<&mpsc::UnboundedSender<SplitPartition>>,
+        main_sender: &mpsc::Sender<Option<PolledMessagePtr>>,
+    ) -> Option<PolledMessagePtr> {
+        let announcer = match announcer {
+            Some(announcer) => announcer,
+            None => return Some(message),
+        };
+        let key = match message.partition_key() {
+            Some(key) => key,
+            None => return Some(message),
+        };
+        if !self.assigned.contains(&key) {
+            // The assignment changed since the last update.
+            self.update(announcer, main_sender, true);
+        }
+        let split = match self.partitions.get_mut(&key) {
+            Some(split) => split,
+            None => return Some(message),
+        };
+        split.pending.push_back(message);
+        if !split.flush() {
+            self.returned.extend(split.pending.drain(..));
+        }
+        None
+    }
+
+    /// Stops all the partition polling threads, blocking until they are terminated.
+    fn stop(&mut self) {
+        for split in self.partitions.values() {
+            split.should_stop.store(true, Ordering::Relaxed);
+        }
+        for (_, split) in self.partitions.drain() {
+            if let Err(e) = split.handle.join() {
+                warn!("Failure while terminating partition thread: {:?}", e);
+            }
+        }
+    }
+}
+
+/// Internal consumer loop. This is the main body of the thread that will drive the stream consumer,
+/// polling it every `config.poll_interval`. If `config.no_message_error` is true, the loop will
+/// send a None into the sender every time the poll times out, and if `config.idle_timeout` is
+/// set, every time a poll times out once no message was received for the idle timeout.
+/// If an `inflight` counter is provided, the loop will only poll the consumer when a slot is
+/// available. Once partition streams are requested through `split_announcer`, the messages of
+/// the split partitions are routed to their own streams. The messages are tagged with the
+/// current generation of `revoked`.
+fn poll_loop<C: ConsumerContext + 'static>(
+    consumer: &Arc<BaseConsumer<C>>,
+    sender: mpsc::Sender<Option<PolledMessagePtr>>,
+    should_stop: &AtomicBool,
+    config: &StreamConfig,
+    inflight: Option<&InflightCounter>,
+    split_announcer: &Mutex<Option<mpsc::UnboundedSender<SplitPartition>>>,
+    revoked: &Arc<RevokedPartitions>,
 ) {
     trace!("Polling thread loop started");
+    let poll_interval = config.poll_interval;
     let mut curr_sender = sender;
+    let mut splitter = PartitionSplitter::new(
+        consumer.clone(),
+        poll_interval,
+        revoked.clone(),
+        config.drain_on_revoke,
+    );
     let poll_interval_ms = duration_to_millis(poll_interval) as i32;
+    let mut last_activity = Instant::now();
     while !should_stop.load(Ordering::Relaxed) {
+        let announcer = split_announcer.lock().unwrap().clone();
+        if let Some(ref announcer) = announcer {
+            splitter.mark_ready();
+            splitter.update(announcer, &curr_sender, false);
+        }
+        if let Some(inflight) = inflight {
+            if !inflight.acquire(poll_interval) {
+                trace!("Maximum number of in-flight messages reached");
+                continue;
+            }
+        }
+        if let Some(message) = splitter.take_returned() {
+            match curr_sender.send(Some(message)).wait() {
+                Ok(new_sender) => curr_sender = new_sender,
+                Err(e) => {
+                    debug!("Sender not available: {:?}", e);
+                    break;
+                }
+            };
+            continue;
+        }
         trace!("Polling base consumer");
         let future_sender = match consumer.poll_raw(poll_interval_ms) {
             None => {
-                if send_none {
+                if let Some(inflight) = inflight {
+                    inflight.release();
+                }
+                let idle = match config.idle_timeout {
+                    Some(idle_timeout) => last_activity.elapsed() >= idle_timeout,
+                    None => false,
+                };
+                if config.no_message_error || idle {
+                    last_activity = Instant::now();
                     curr_sender.send(None)
                 } else {
                     continue; // TODO: check stream closed
                 }
             }
-            Some(m_ptr) => curr_sender.send(Some(PolledMessagePtr::new(m_ptr))),
+            Some(m_ptr) => {
+                last_activity = Instant::now();
+                let mut message = PolledMessagePtr::new(m_ptr);
+                message.generation = revoked.generation();
+                match splitter.route(message, announcer.as_ref(), &curr_sender) {
+                    Some(message) => curr_sender.send(Some(message)),
+                    None => {
+                        if let Some(inflight) = inflight {
+                            inflight.release();
+                        }
+                        continue;
+                    }
+                }
+            }
         };
         match future_sender.wait() {
             Ok(new_sender) => curr_sender = new_sender,
@@ -137,6 +1199,7 @@ fn poll_loop<C: ConsumerContext>(
             }
         };
     }
+    splitter.stop();
     trace!("Polling thread loop terminated");
 }
 
@@ -152,6 +1215,9 @@ pub struct StreamConsumer<C: ConsumerContext + 'static = DefaultConsumerContext>
     consumer: Arc<BaseConsumer<C>>,
     should_stop: Arc<AtomicBool>,
     handle: Mutex<Option<JoinHandle<()>>>,
+    split_announcer: Arc<Mutex<Option<mpsc::UnboundedSender<SplitPartition>>>>,
+    revoked: Arc<RevokedPartitions>,
+    assignment_announcer: Arc<AssignmentAnnouncer>,
 }
 
 impl<C: ConsumerContext> Consumer<C> for StreamConsumer<C> {
@@ -176,7 +1242,22 @@ impl<C: ConsumerContext> FromClientConfigAndContext<C> for StreamConsumer<C> {
             consumer: Arc::new(BaseConsumer::from_config_and_context(config, context)?),
             should_stop: Arc::new(AtomicBool::new(false)),
             handle: Mutex::new(None),
+            split_announcer: Arc::new(Mutex::new(None)),
+            revoked: Arc::new(RevokedPartitions::default()),
+            assignment_announcer: Arc::new(Mutex::new(None)),
         };
+        // The hook runs for every rebalance, whether it's served by the polling thread, by
+        // `try_recv` or while closing the consumer.
+        let revoked = stream_consumer.revoked.clone();
+        let assignment_announcer = stream_consumer.assignment_announcer.clone();
+        stream_consumer
+            .consumer
+            .set_rebalance_hook(Some(Box::new(move |err, tpl| {
+                announce_rebalance(&assignment_announcer, err, tpl);
+                if err == RDKafkaRespErr::RD_KAFKA_RESP_ERR__REVOKE_PARTITIONS {
+                    revoked.revoke(tpl);
+                }
+            })));
         Ok(stream_consumer)
     }
 }
@@ -193,24 +1274,93 @@ impl<C: ConsumerContext> StreamConsumer<C> {
     /// `KafkaError::NoMessageReceived` every time the poll interval is reached and no message has
     /// been received.
     pub fn start_with(&self, poll_interval: Duration, no_message_error: bool) -> MessageStream<C> {
+        self.start_with_config(
+            StreamConfig::new()
+                .poll_interval(poll_interval)
+                .no_message_error(no_message_error),
+        )
+    }
+
+    /// Starts the StreamConsumer with the specified `StreamConfig`.
+    pub fn start_with_config(&self, config: StreamConfig) -> MessageStream<'_, C> {
         // TODO: verify called once
         let (sender, receiver) = mpsc::channel(CONSUMER_CHANNEL_SIZE);
         let consumer = self.consumer.clone();
         let should_stop = self.should_stop.clone();
+        let inflight = config
+            .max_inflight
+            .map(|max_inflight| Arc::new(InflightCounter::new(max_inflight)));
+        let thread_inflight = inflight.clone();
+        let message_pool = config.message_pool.clone();
+        let revoked = if config.drain_on_revoke {
+            Some(self.revoked.clone())
+        } else {
+            None
+        };
+        let thread_revoked = self.revoked.clone();
+        let split_announcer = self.split_announcer.clone();
         let handle = thread::Builder::new()
             .name("poll".to_string())
             .spawn(move || {
                 poll_loop(
-                    consumer.as_ref(),
+                    &consumer,
                     sender,
                     should_stop.as_ref(),
-                    poll_interval,
-                    no_message_error,
+                    &config,
+                    thread_inflight.as_ref().map(Arc::as_ref),
+                    split_announcer.as_ref(),
+                    &thread_revoked,
                 );
             })
             .expect("Failed to start polling thread");
         *self.handle.lock().unwrap() = Some(handle);
-        MessageStream::new(self, receiver)
+        MessageStream::new(self, receiver, inflight, message_pool, revoked)
+    }
+
+    /// Splits the partitions assigned to the consumer from the main message stream, returning a
+    /// stream of `PartitionStream`s. A new `PartitionStream` is yielded every time a partition is
+    /// assigned to the consumer, and it terminates when the partition is revoked. Each partition
+    /// stream only yields the messages of its own partition, in order, and the main stream stops
+    /// yielding them.
+    ///
+    /// Every partition stream is fed by a dedicated thread, so a slow partition doesn't prevent
+    /// the others from making progress. The main stream still needs to be started and polled, as
+    /// its polling thread serves the rebalance callbacks and detects assignment changes.
+    /// Dropping a `PartitionStream` while its partition is still assigned hands the partition
+    /// back to the main stream. The consumer must be part of a consumer group.
+    pub fn split_partition_queues(&self) -> PartitionStreams<'_, C> {
+        let (sender, receiver) = mpsc::unbounded();
+        *self.split_announcer.lock().unwrap() = Some(sender);
+        PartitionStreams {
+            consumer: self,
+            receiver,
+        }
+    }
+
+    /// Returns the next message available in the consumer, or `None` if no message is
+    /// available, without blocking. This is meant for event loops that consume the messages
+    /// without running the stream: every call polls the consumer with no timeout, serving the
+    /// rebalances and the other callbacks. If the stream is running, the messages are split
+    /// between the stream and the callers of `try_recv`.
+    pub fn try_recv(&self) -> Option<KafkaResult<BorrowedMessage<'_>>> {
+        self.consumer.poll(Duration::from_millis(0))
+    }
+
+    /// Returns the partitions currently assigned to the consumer that are paused. See
+    /// `BaseConsumer::paused_partitions`.
+    pub fn paused_partitions(&self) -> KafkaResult<TopicPartitionList> {
+        self.consumer.paused_partitions()
+    }
+
+    /// Returns a stream of the rebalance events of the consumer, yielding the partitions of every
+    /// assignment and revocation. Unlike `ConsumerContext::rebalance`, the events can be handled
+    /// in the task of the application, without blocking the polling thread. The events are
+    /// reported whichever call serves the rebalance, including `try_recv` and `close`. Calling
+    /// this method again replaces the previous stream, which then terminates.
+    pub fn assignment_stream(&self) -> AssignmentStream {
+        let (sender, receiver) = mpsc::unbounded();
+        *self.assignment_announcer.lock().unwrap() = Some(sender);
+        AssignmentStream { receiver }
     }
 
     /// Stops the StreamConsumer, blocking the caller until the internal consumer has been stopped.
@@ -225,6 +1375,13 @@ impl<C: ConsumerContext> StreamConsumer<C> {
             };
         }
     }
+
+    /// Stops the StreamConsumer and closes the underlying consumer, blocking until it has left
+    /// the consumer group. See `BaseConsumer::close`.
+    pub fn close(self) -> KafkaResult<()> {
+        self.stop();
+        self.consumer.close_native()
+    }
 }
 
 impl<C: ConsumerContext> Drop for StreamConsumer<C> {
@@ -235,3 +1392,396 @@ impl<C: ConsumerContext> Drop for StreamConsumer<C> {
         self.stop();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consumer::base_consumer;
+    use crate::message::{OwnedMessage, Timestamp};
+
+    #[test]
+    fn test_inflight_counter() {
+        let counter = Arc::new(InflightCounter::new(2));
+        assert!(counter.acquire(Duration::from_millis(0)));
+        assert!(counter.acquire(Duration::from_millis(0)));
+        assert!(!counter.acquire(Duration::from_millis(10)));
+        assert_eq!(counter.count(), 2);
+
+        let releaser = counter.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            releaser.release();
+        });
+        assert!(counter.acquire(Duration::from_secs(10)));
+        assert_eq!(counter.count(), 2);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_stream_config_max_inflight() {
+        assert_eq!(StreamConfig::new().max_inflight(0).max_inflight, Some(1));
+        assert_eq!(StreamConfig::new().max_inflight(5).max_inflight, Some(5));
+    }
+
+    fn test_message(partition: i32, offset: i64) -> OwnedMessage {
+        OwnedMessage::new(
+            None,
+            None,
+            "topic1".to_owned(),
+            Timestamp::NotAvailable,
+            partition,
+            offset,
+            None,
+        )
+    }
+
+    // The error of the final commit should be yielded once, and then the stream should
+    // terminate. Without a group, the commit fails immediately.
+    #[test]
+    fn test_auto_commit_stream_final_commit_error() {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", "localhost:1")
+            .create()
+            .unwrap();
+        let mut stream = consumer
+            .start()
+            .auto_committing(Duration::from_secs(1), CommitMode::Sync);
+        stream.offsets.pending.insert(("topic1".to_owned(), 0), 5);
+        consumer.stop();
+
+        let results = stream.wait().collect::<Vec<_>>();
+        assert_eq!(results.len(), 1);
+        match results[0] {
+            Ok(Err(KafkaError::ConsumerCommit(_))) => {}
+            ref result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_auto_commit_offsets() {
+        let start = Instant::now();
+        let mut offsets = AutoCommitOffsets::new(Duration::from_secs(1), start);
+        offsets.register(&test_message(0, 10));
+        offsets.register(&test_message(0, 11));
+        offsets.register(&test_message(1, 3));
+
+        // The committed offsets are the ones of the next messages to read.
+        let tpl = offsets.to_commit(None);
+        assert_eq!(tpl.count(), 2);
+        assert_eq!(
+            tpl.find_partition("topic1", 0).map(|elem| elem.offset()),
+            Some(Offset::Offset(12))
+        );
+        assert_eq!(
+            tpl.find_partition("topic1", 1).map(|elem| elem.offset()),
+            Some(Offset::Offset(4))
+        );
+        // No partition is due before the interval elapses.
+        assert_eq!(offsets.to_commit(Some(start)).count(), 0);
+    }
+
+    #[test]
+    fn test_auto_commit_offsets_partition_interval() {
+        let start = Instant::now();
+        let mut offsets = AutoCommitOffsets::new(Duration::from_secs(1), start);
+        offsets
+            .intervals
+            .insert(("topic1".to_owned(), 1), Duration::from_secs(10));
+        offsets.register(&test_message(0, 10));
+        offsets.register(&test_message(1, 3));
+
+        // Only the partition with the default interval is due.
+        let tick = start + Duration::from_secs(2);
+        let mut expected = TopicPartitionList::new();
+        expected.add_partition_offset("topic1", 0, Offset::Offset(11));
+        let tpl = offsets.to_commit(Some(tick));
+        assert_eq!(tpl, expected);
+        offsets.committed(&tpl, tick);
+
+        // The committed partition waits for its next interval.
+        offsets.register(&test_message(0, 11));
+        assert_eq!(offsets.to_commit(Some(tick)).count(), 0);
+
+        let tpl = offsets.to_commit(Some(start + Duration::from_secs(10)));
+        assert_eq!(tpl.count(), 2);
+        assert_eq!(
+            tpl.find_partition("topic1", 0).map(|elem| elem.offset()),
+            Some(Offset::Offset(12))
+        );
+        assert_eq!(
+            tpl.find_partition("topic1", 1).map(|elem| elem.offset()),
+            Some(Offset::Offset(4))
+        );
+    }
+
+    #[test]
+    fn test_auto_commit_offsets_skip_unchanged() {
+        let start = Instant::now();
+        let commit_twice = |offsets: &mut AutoCommitOffsets| {
+            offsets.register(&test_message(0, 10));
+            let tpl = offsets.to_commit(None);
+            offsets.committed(&tpl, start);
+            // The message is consumed again, e.g. after a rebalance.
+            offsets.register(&test_message(0, 10));
+            offsets.to_commit(None)
+        };
+
+        let mut offsets = AutoCommitOffsets::new(Duration::from_secs(1), start);
+        assert_eq!(commit_twice(&mut offsets).count(), 1);
+
+        let mut offsets = AutoCommitOffsets::new(Duration::from_secs(1), start);
+        offsets.skip_unchanged = true;
+        assert_eq!(commit_twice(&mut offsets).count(), 0);
+
+        // Offsets that changed are still committed, even if they went backwards.
+        offsets.register(&test_message(0, 11));
+        offsets.register(&test_message(1, 3));
+        assert_eq!(offsets.to_commit(None).count(), 2);
+        offsets.register(&test_message(0, 5));
+        assert_eq!(
+            offsets
+                .to_commit(None)
+                .find_partition("topic1", 0)
+                .map(|elem| elem.offset()),
+            Some(Offset::Offset(6))
+        );
+    }
+
+    // The messages of a revoked partition that were not sent to its stream yet should still be
+    // delivered, unless they are drained.
+    #[test]
+    fn test_split_partition_revoke_pending() {
+        for &drain_on_revoke in &[false, true] {
+            let consumer: BaseConsumer = ClientConfig::new()
+                .set("bootstrap.servers", "localhost:1")
+                .set("group.id", "test_split_partition_revoke_pending")
+                .create()
+                .unwrap();
+            let mut splitter = PartitionSplitter::new(
+                Arc::new(consumer),
+                Duration::from_millis(100),
+                Arc::new(RevokedPartitions::default()),
+                drain_on_revoke,
+            );
+            // The partition stream only has room for one of the messages.
+            let (sender, receiver) = mpsc::channel(0);
+            let pending = (0..3)
+                .map(|_| PolledMessagePtr::new(ptr::null_mut()))
+                .collect();
+            splitter.partitions.insert(
+                ("topic1".to_owned(), 0),
+                SplitPartitionHandle {
+                    sender,
+                    pending,
+                    ready: Arc::new(AtomicBool::new(false)),
+                    should_stop: Arc::new(AtomicBool::new(false)),
+                    handle: thread::spawn(|| ()),
+                },
+            );
+            let (announcer, _) = mpsc::unbounded();
+            let (main_sender, _main_receiver) = mpsc::channel(1);
+
+            // The partition is not assigned anymore.
+            splitter.update(&announcer, &main_sender, true);
+
+            assert!(splitter.partitions.is_empty());
+            let streamed = receiver.wait().count();
+            if drain_on_revoke {
+                assert_eq!((streamed, splitter.returned.len()), (0, 0));
+            } else {
+                assert_eq!((streamed, splitter.returned.len()), (1, 2));
+            }
+        }
+    }
+
+    #[test]
+    fn test_revoked_partitions() {
+        let revoked = RevokedPartitions::default();
+        let generation = revoked.generation();
+        assert!(!revoked.is_revoked("topic1", 0, generation));
+
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition("topic1", 0);
+        revoked.revoke(&tpl);
+        // Messages polled before the revocation are dropped, the later ones are kept.
+        assert!(revoked.is_revoked("topic1", 0, generation));
+        assert!(!revoked.is_revoked("topic1", 1, generation));
+        assert!(!revoked.is_revoked("topic2", 0, generation));
+        assert_eq!(revoked.generation(), generation + 1);
+        assert!(!revoked.is_revoked("topic1", 0, revoked.generation()));
+    }
+
+    // Rebalances should be observed even if they are not served by the polling thread, e.g.
+    // while the application uses `try_recv`.
+    #[test]
+    fn test_rebalance_without_stream() {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", "localhost:1")
+            .set("group.id", "test_rebalance_without_stream")
+            .create()
+            .unwrap();
+        let assignment_stream = consumer.assignment_stream();
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition("topic1", 0);
+        consumer.assign(&tpl).unwrap();
+        consumer.pause(&tpl).unwrap();
+        let generation = consumer.revoked.generation();
+
+        base_consumer::run_native_rebalance(
+            consumer.get_base_consumer(),
+            RDKafkaRespErr::RD_KAFKA_RESP_ERR__REVOKE_PARTITIONS,
+            &tpl,
+        );
+
+        assert!(consumer.revoked.is_revoked("topic1", 0, generation));
+        assert_eq!(consumer.paused_partitions().unwrap().count(), 0);
+        let (kind, revoked) = assignment_stream.wait().next().unwrap().unwrap();
+        assert_eq!(kind, RebalanceKind::Revoke);
+        assert!(revoked.find_partition("topic1", 0).is_some());
+    }
+
+    #[test]
+    fn test_try_recv_empty() {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", "localhost:1")
+            .set("group.id", "test_try_recv_empty")
+            .create()
+            .unwrap();
+        consumer.subscribe(&["topic1"]).unwrap();
+        let start = Instant::now();
+        assert!(consumer.try_recv().is_none());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_consumer_event_from_error() {
+        let eof = KafkaError::PartitionEOF {
+            topic: "topic1".to_owned(),
+            partition: 2,
+            offset: 10,
+        };
+        match ConsumerEvent::from(Err(eof)) {
+            ConsumerEvent::Eof {
+                topic,
+                partition,
+                offset,
+            } => assert_eq!((topic.as_str(), partition, offset), ("topic1", 2, 10)),
+            event => panic!("Unexpected event: {:?}", event),
+        }
+        match ConsumerEvent::from(Err(KafkaError::NoMessageReceived)) {
+            ConsumerEvent::Idle => {}
+            event => panic!("Unexpected event: {:?}", event),
+        }
+        match ConsumerEvent::from(Err(KafkaError::Canceled)) {
+            ConsumerEvent::Error(KafkaError::Canceled) => {}
+            event => panic!("Unexpected event: {:?}", event),
+        }
+    }
+
+    // Without messages, the idle event should be received after the idle timeout, and not
+    // after every poll interval.
+    #[test]
+    fn test_stream_idle_timeout() {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", "localhost:1")
+            .set("group.id", "test_stream_idle_timeout")
+            .create()
+            .unwrap();
+        let mut stream = consumer.start_with_config(
+            StreamConfig::new()
+                .poll_interval(Duration::from_millis(50))
+                .idle_timeout(Duration::from_millis(300)),
+        );
+
+        for _ in 0..2 {
+            let start = Instant::now();
+            match stream.recv_event().wait() {
+                Ok(ConsumerEvent::Idle) => {}
+                Ok(event) => panic!("Unexpected event: {:?}", event),
+                Err(e) => panic!("Unexpected error: {:?}", e),
+            }
+            let elapsed = start.elapsed();
+            assert!(elapsed >= Duration::from_millis(250), "{:?}", elapsed);
+            assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+        }
+    }
+
+    #[test]
+    fn test_announce_rebalance() {
+        let announcer = Mutex::new(None);
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition("topic1", 0);
+        // Events are dropped if there is no stream.
+        announce_rebalance(
+            &announcer,
+            RDKafkaRespErr::RD_KAFKA_RESP_ERR__ASSIGN_PARTITIONS,
+            &tpl,
+        );
+
+        let (sender, receiver) = mpsc::unbounded();
+        *announcer.lock().unwrap() = Some(sender);
+        let stream = AssignmentStream { receiver };
+        announce_rebalance(
+            &announcer,
+            RDKafkaRespErr::RD_KAFKA_RESP_ERR__ASSIGN_PARTITIONS,
+            &tpl,
+        );
+        announce_rebalance(&announcer, RDKafkaRespErr::RD_KAFKA_RESP_ERR__FAIL, &tpl);
+        announce_rebalance(
+            &announcer,
+            RDKafkaRespErr::RD_KAFKA_RESP_ERR__REVOKE_PARTITIONS,
+            &tpl,
+        );
+        let events = stream
+            .take(2)
+            .wait()
+            .map(|event| {
+                let (kind, tpl) = event.unwrap();
+                (kind, tpl.to_topic_map())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                (RebalanceKind::Assign, tpl.to_topic_map()),
+                (RebalanceKind::Revoke, tpl.to_topic_map()),
+            ]
+        );
+
+        // The sender is removed once the stream is dropped.
+        announce_rebalance(
+            &announcer,
+            RDKafkaRespErr::RD_KAFKA_RESP_ERR__ASSIGN_PARTITIONS,
+            &tpl,
+        );
+        assert!(announcer.lock().unwrap().is_none());
+    }
+
+    // Each call of pause_all and resume_all should apply to the assignment at the time of the
+    // call.
+    #[test]
+    fn test_pause_all_resume_all() {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", "localhost:1")
+            .set("group.id", "test_pause_all_resume_all")
+            .create()
+            .unwrap();
+        consumer.pause_all().unwrap();
+        assert_eq!(consumer.paused_partitions().unwrap().count(), 0);
+
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition("topic1", 0);
+        tpl.add_partition("topic1", 1);
+        consumer.assign(&tpl).unwrap();
+        consumer.pause_all().unwrap();
+        assert_eq!(consumer.paused_partitions().unwrap(), tpl);
+
+        tpl.add_partition("topic2", 0);
+        consumer.assign(&tpl).unwrap();
+        consumer.pause_all().unwrap();
+        assert_eq!(consumer.paused_partitions().unwrap(), tpl);
+
+        consumer.resume_all().unwrap();
+        assert_eq!(consumer.paused_partitions().unwrap().count(), 0);
+    }
+}
@@ -4,7 +4,7 @@ use crate::rdsys::types::*;
 use std::{error, ffi, fmt};
 
 // Re-export rdkafka error
-pub use crate::rdsys::types::RDKafkaError;
+pub use crate::rdsys::types::{RDKafkaError, RDKafkaErrorCode};
 
 /// Kafka result.
 pub type KafkaResult<T> = Result<T, KafkaError>;
@@ -27,49 +27,96 @@ impl IsError for RDKafkaConfRes {
     }
 }
 
+/// A configuration parameter rejected by librdkafka, as reported by `ClientConfig::validate`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidConfigParam {
+    /// The result of the configuration of the parameter.
+    pub result: RDKafkaConfRes,
+    /// The description of the error.
+    pub description: String,
+    /// The key of the parameter.
+    pub key: String,
+    /// The value of the parameter.
+    pub value: String,
+}
+
+impl fmt::Display for InvalidConfigParam {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.description, self.key, self.value)
+    }
+}
+
+/// Formats a list of invalid configuration parameters, separated by semicolons.
+fn fmt_invalid_params(params: &[InvalidConfigParam], f: &mut fmt::Formatter) -> fmt::Result {
+    for (idx, param) in params.iter().enumerate() {
+        if idx > 0 {
+            write!(f, "; ")?;
+        }
+        write!(f, "{}", param)?;
+    }
+    Ok(())
+}
+
 // TODO: consider using macro
 
-/// Represents all Kafka errors. Check the underlying `RDKafkaError` to get details.
+/// Represents all Kafka errors. Check the underlying `RDKafkaErrorCode` to get details.
 #[derive(Clone, PartialEq, Eq)]
 pub enum KafkaError {
     /// Creation of admin operation failed.
     AdminOpCreation(String),
-    /// The admin operation itself failed.
-    AdminOp(RDKafkaError),
+    /// The admin operation itself failed. The detailed error string reported by librdkafka is
+    /// included, if it says more than the error code.
+    AdminOp(RDKafkaErrorCode, Option<String>),
     /// The client was dropped before the operation completed.
     Canceled,
     /// Invalid client configuration.
     ClientConfig(RDKafkaConfRes, String, String, String),
+    /// Invalid client configuration, listing all the invalid parameters.
+    ClientConfigValidation(Vec<InvalidConfigParam>),
     /// Client creation failed.
     ClientCreation(String),
+    /// Consumer close failed.
+    ConsumerClose(RDKafkaErrorCode),
     /// Consumer commit failed.
-    ConsumerCommit(RDKafkaError),
+    ConsumerCommit(RDKafkaErrorCode),
+    /// Flushing failed.
+    Flush(RDKafkaErrorCode),
     /// Global error.
-    Global(RDKafkaError),
+    Global(RDKafkaErrorCode),
     /// Group list fetch failed.
-    GroupListFetch(RDKafkaError),
+    GroupListFetch(RDKafkaErrorCode),
     /// Message consumption failed.
-    MessageConsumption(RDKafkaError),
+    MessageConsumption(RDKafkaErrorCode),
     /// Message production error.
-    MessageProduction(RDKafkaError),
+    MessageProduction(RDKafkaErrorCode),
     /// Metadata fetch error.
-    MetadataFetch(RDKafkaError),
+    MetadataFetch(RDKafkaErrorCode),
     /// No message was received.
     NoMessageReceived,
     /// Unexpected null pointer
     Nul(ffi::NulError),
     /// Offset fetch failed.
-    OffsetFetch(RDKafkaError),
-    /// End of partition reached.
-    PartitionEOF(i32),
+    OffsetFetch(RDKafkaErrorCode),
+    /// End of partition reached. Only reported if `enable.partition.eof` is set.
+    PartitionEOF {
+        /// The topic of the partition.
+        topic: String,
+        /// The partition whose end has been reached.
+        partition: i32,
+        /// The offset at which the end was reached, i.e. the offset of the next message that
+        /// will be produced to the partition.
+        offset: i64,
+    },
     /// Pause/Resume failed.
     PauseResume(String),
+    /// Purging the producer failed.
+    Purge(RDKafkaErrorCode),
     /// Seeking a partition failed.
     Seek(String),
     /// Setting partition offset failed.
-    SetPartitionOffset(RDKafkaError),
+    SetPartitionOffset(RDKafkaErrorCode),
     /// Offset store failed.
-    StoreOffset(RDKafkaError),
+    StoreOffset(RDKafkaErrorCode),
     /// Subscription creation failed.
     Subscription(String),
 }
@@ -77,7 +124,12 @@ pub enum KafkaError {
 impl fmt::Debug for KafkaError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            KafkaError::AdminOp(err) => write!(f, "KafkaError (Admin operation error: {})", err),
+            KafkaError::AdminOp(err, None) => {
+                write!(f, "KafkaError (Admin operation error: {})", err)
+            }
+            KafkaError::AdminOp(err, Some(ref detail)) => {
+                write!(f, "KafkaError (Admin operation error: {}: {})", err, detail)
+            }
             KafkaError::AdminOpCreation(ref err) => {
                 write!(f, "KafkaError (Admin operation creation error: {})", err)
             }
@@ -87,12 +139,21 @@ impl fmt::Debug for KafkaError {
                 "KafkaError (Client config error: {} {} {})",
                 desc, key, value
             ),
+            KafkaError::ClientConfigValidation(ref params) => {
+                write!(f, "KafkaError (Client config validation error: ")?;
+                fmt_invalid_params(params, f)?;
+                write!(f, ")")
+            }
             KafkaError::ClientCreation(ref err) => {
                 write!(f, "KafkaError (Client creation error: {})", err)
             }
+            KafkaError::ConsumerClose(err) => {
+                write!(f, "KafkaError (Consumer close error: {})", err)
+            }
             KafkaError::ConsumerCommit(err) => {
                 write!(f, "KafkaError (Consumer commit error: {})", err)
             }
+            KafkaError::Flush(err) => write!(f, "KafkaError (Flush error: {})", err),
             KafkaError::Global(err) => write!(f, "KafkaError (Global error: {})", err),
             KafkaError::GroupListFetch(err) => {
                 write!(f, "KafkaError (Group list fetch error: {})", err)
@@ -111,10 +172,19 @@ impl fmt::Debug for KafkaError {
             }
             KafkaError::Nul(_) => write!(f, "FFI null error"),
             KafkaError::OffsetFetch(err) => write!(f, "KafkaError (Offset fetch error: {})", err),
-            KafkaError::PartitionEOF(part_n) => write!(f, "KafkaError (Partition EOF: {})", part_n),
+            KafkaError::PartitionEOF {
+                ref topic,
+                partition,
+                offset,
+            } => write!(
+                f,
+                "KafkaError (Partition EOF: {} [{}] at offset {})",
+                topic, partition, offset
+            ),
             KafkaError::PauseResume(ref err) => {
                 write!(f, "KafkaError (Pause/resume error: {})", err)
             }
+            KafkaError::Purge(err) => write!(f, "KafkaError (Purge error: {})", err),
             KafkaError::Seek(ref err) => {
                 write!(f, "KafkaError (Seek error: {})", err)
             }
@@ -132,7 +202,10 @@ impl fmt::Debug for KafkaError {
 impl fmt::Display for KafkaError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            KafkaError::AdminOp(err) => write!(f, "Admin operation error: {}", err),
+            KafkaError::AdminOp(err, None) => write!(f, "Admin operation error: {}", err),
+            KafkaError::AdminOp(err, Some(ref detail)) => {
+                write!(f, "Admin operation error: {}: {}", err, detail)
+            }
             KafkaError::AdminOpCreation(ref err) => {
                 write!(f, "Admin operation creation error: {}", err)
             }
@@ -140,8 +213,14 @@ impl fmt::Display for KafkaError {
             KafkaError::ClientConfig(_, ref desc, ref key, ref value) => {
                 write!(f, "Client config error: {} {} {}", desc, key, value)
             }
+            KafkaError::ClientConfigValidation(ref params) => {
+                write!(f, "Client config validation error: ")?;
+                fmt_invalid_params(params, f)
+            }
             KafkaError::ClientCreation(ref err) => write!(f, "Client creation error: {}", err),
+            KafkaError::ConsumerClose(err) => write!(f, "Consumer close error: {}", err),
             KafkaError::ConsumerCommit(err) => write!(f, "Consumer commit error: {}", err),
+            KafkaError::Flush(err) => write!(f, "Flush error: {}", err),
             KafkaError::Global(err) => write!(f, "Global error: {}", err),
             KafkaError::GroupListFetch(err) => write!(f, "Group list fetch error: {}", err),
             KafkaError::MessageConsumption(err) => write!(f, "Message consumption error: {}", err),
@@ -152,8 +231,17 @@ impl fmt::Display for KafkaError {
             }
             KafkaError::Nul(_) => write!(f, "FFI nul error"),
             KafkaError::OffsetFetch(err) => write!(f, "Offset fetch error: {}", err),
-            KafkaError::PartitionEOF(part_n) => write!(f, "Partition EOF: {}", part_n),
+            KafkaError::PartitionEOF {
+                ref topic,
+                partition,
+                offset,
+            } => write!(
+                f,
+                "Partition EOF: {} [{}] at offset {}",
+                topic, partition, offset
+            ),
             KafkaError::PauseResume(ref err) => write!(f, "Pause/resume error: {}", err),
+            KafkaError::Purge(err) => write!(f, "Purge error: {}", err),
             KafkaError::Seek(ref err) => write!(f, "Seek error: {}", err),
             KafkaError::SetPartitionOffset(err) => write!(f, "Set partition offset error: {}", err),
             KafkaError::StoreOffset(err) => write!(f, "Store offset error: {}", err),
@@ -165,12 +253,15 @@ impl fmt::Display for KafkaError {
 impl error::Error for KafkaError {
     fn description(&self) -> &str {
         match *self {
-            KafkaError::AdminOp(_) => "Admin operation error",
+            KafkaError::AdminOp(_, _) => "Admin operation error",
             KafkaError::AdminOpCreation(_) => "Admin operation creation error",
             KafkaError::Canceled => "Client dropped",
             KafkaError::ClientConfig(_, _, _, _) => "Client config error",
+            KafkaError::ClientConfigValidation(_) => "Client config validation error",
             KafkaError::ClientCreation(_) => "Client creation error",
+            KafkaError::ConsumerClose(_) => "Consumer close error",
             KafkaError::ConsumerCommit(_) => "Consumer commit error",
+            KafkaError::Flush(_) => "Flush error",
             KafkaError::Global(_) => "Global error",
             KafkaError::GroupListFetch(_) => "Group list fetch error",
             KafkaError::MessageConsumption(_) => "Message consumption error",
@@ -179,8 +270,9 @@ impl error::Error for KafkaError {
             KafkaError::NoMessageReceived => "No message received within the given poll interval",
             KafkaError::Nul(_) => "FFI nul error",
             KafkaError::OffsetFetch(_) => "Offset fetch error",
-            KafkaError::PartitionEOF(_) => "Partition EOF error",
+            KafkaError::PartitionEOF { .. } => "Partition EOF error",
             KafkaError::PauseResume(_) => "Pause/resume error",
+            KafkaError::Purge(_) => "Purge error",
             KafkaError::Seek(_) => "Seek error",
             KafkaError::SetPartitionOffset(_) => "Set partition offset error",
             KafkaError::StoreOffset(_) => "Store offset error",
@@ -191,12 +283,15 @@ impl error::Error for KafkaError {
     #[allow(clippy::match_same_arms)]
     fn cause(&self) -> Option<&dyn error::Error> {
         match *self {
-            KafkaError::AdminOp(_) => None,
+            KafkaError::AdminOp(_, _) => None,
             KafkaError::AdminOpCreation(_) => None,
             KafkaError::Canceled => None,
             KafkaError::ClientConfig(_, _, _, _) => None,
+            KafkaError::ClientConfigValidation(_) => None,
             KafkaError::ClientCreation(_) => None,
+            KafkaError::ConsumerClose(ref err) => Some(err),
             KafkaError::ConsumerCommit(ref err) => Some(err),
+            KafkaError::Flush(ref err) => Some(err),
             KafkaError::Global(ref err) => Some(err),
             KafkaError::GroupListFetch(ref err) => Some(err),
             KafkaError::MessageConsumption(ref err) => Some(err),
@@ -205,8 +300,9 @@ impl error::Error for KafkaError {
             KafkaError::NoMessageReceived => None,
             KafkaError::Nul(_) => None,
             KafkaError::OffsetFetch(ref err) => Some(err),
-            KafkaError::PartitionEOF(_) => None,
+            KafkaError::PartitionEOF { .. } => None,
             KafkaError::PauseResume(_) => None,
+            KafkaError::Purge(ref err) => Some(err),
             KafkaError::Seek(_) => None,
             KafkaError::SetPartitionOffset(ref err) => Some(err),
             KafkaError::StoreOffset(ref err) => Some(err),
@@ -215,8 +311,132 @@ impl error::Error for KafkaError {
     }
 }
 
+impl KafkaError {
+    /// Returns the librdkafka error code carried by the error, if any.
+    pub fn rdkafka_error_code(&self) -> Option<RDKafkaErrorCode> {
+        match *self {
+            KafkaError::AdminOp(err, _)
+            | KafkaError::ConsumerClose(err)
+            | KafkaError::ConsumerCommit(err)
+            | KafkaError::Flush(err)
+            | KafkaError::Global(err)
+            | KafkaError::GroupListFetch(err)
+            | KafkaError::MessageConsumption(err)
+            | KafkaError::MessageProduction(err)
+            | KafkaError::MetadataFetch(err)
+            | KafkaError::OffsetFetch(err)
+            | KafkaError::Purge(err)
+            | KafkaError::SetPartitionOffset(err)
+            | KafkaError::StoreOffset(err) => Some(err),
+            KafkaError::PartitionEOF { .. } => Some(RDKafkaErrorCode::PartitionEOF),
+            _ => None,
+        }
+    }
+
+    /// Returns the detailed error string reported by librdkafka, if any. Only the errors of admin
+    /// operations carry one.
+    pub fn detail(&self) -> Option<&str> {
+        match *self {
+            KafkaError::AdminOp(_, Some(ref detail)) => Some(detail),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the operation that caused the error may succeed if retried, e.g. because
+    /// the error was caused by a timeout or by a leader change. Errors that don't carry a
+    /// librdkafka error code are never retriable.
+    pub fn is_retriable(&self) -> bool {
+        match self.rdkafka_error_code() {
+            Some(code) => RETRIABLE_CODES.contains(&code),
+            None => false,
+        }
+    }
+
+    /// Returns true if the error is fatal, meaning that the client can't be used anymore and
+    /// should be recreated.
+    pub fn is_fatal(&self) -> bool {
+        self.rdkafka_error_code() == Some(RDKafkaErrorCode::Fatal)
+    }
+}
+
+/// librdkafka and broker error codes that are considered retriable.
+const RETRIABLE_CODES: &[RDKafkaErrorCode] = &[
+    RDKafkaErrorCode::BrokerTransportFailure,
+    RDKafkaErrorCode::MessageTimedOut,
+    RDKafkaErrorCode::AllBrokersDown,
+    RDKafkaErrorCode::OperationTimedOut,
+    RDKafkaErrorCode::QueueFull,
+    RDKafkaErrorCode::WaitingForCoordinator,
+    RDKafkaErrorCode::TimedOutQueue,
+    RDKafkaErrorCode::Retry,
+    RDKafkaErrorCode::UnknownTopicOrPartition,
+    RDKafkaErrorCode::LeaderNotAvailable,
+    RDKafkaErrorCode::NotLeaderForPartition,
+    RDKafkaErrorCode::RequestTimedOut,
+    RDKafkaErrorCode::BrokerNotAvailable,
+    RDKafkaErrorCode::ReplicaNotAvailable,
+    RDKafkaErrorCode::NetworkException,
+    RDKafkaErrorCode::GroupLoadInProgress,
+    RDKafkaErrorCode::GroupCoordinatorNotAvailable,
+    RDKafkaErrorCode::NotCoordinatorForGroup,
+    RDKafkaErrorCode::NotEnoughReplicas,
+    RDKafkaErrorCode::NotEnoughReplicasAfterAppend,
+    RDKafkaErrorCode::RebalanceInProgress,
+    RDKafkaErrorCode::NotController,
+    RDKafkaErrorCode::ConcurrentTransactions,
+    RDKafkaErrorCode::KafkaStorageError,
+    RDKafkaErrorCode::FetchSessionIdNotFound,
+    RDKafkaErrorCode::InvalidFetchSessionEpoch,
+    RDKafkaErrorCode::FencedLeaderEpoch,
+    RDKafkaErrorCode::UnknownLeaderEpoch,
+    RDKafkaErrorCode::OffsetNotAvailable,
+    RDKafkaErrorCode::PreferredLeaderNotAvailable,
+];
+
 impl From<ffi::NulError> for KafkaError {
     fn from(err: ffi::NulError) -> KafkaError {
         KafkaError::Nul(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_classification() {
+        let timed_out = KafkaError::MessageProduction(RDKafkaErrorCode::MessageTimedOut);
+        assert_eq!(
+            timed_out.rdkafka_error_code(),
+            Some(RDKafkaErrorCode::MessageTimedOut)
+        );
+        assert!(timed_out.is_retriable());
+        assert!(!timed_out.is_fatal());
+
+        let not_leader = KafkaError::ConsumerCommit(RDKafkaErrorCode::NotLeaderForPartition);
+        assert!(not_leader.is_retriable());
+
+        let too_large = KafkaError::MessageProduction(RDKafkaErrorCode::MessageSizeTooLarge);
+        assert!(!too_large.is_retriable());
+        assert!(!too_large.is_fatal());
+
+        let fatal = KafkaError::Global(RDKafkaErrorCode::Fatal);
+        assert!(fatal.is_fatal());
+        assert!(!fatal.is_retriable());
+
+        let subscription = KafkaError::Subscription("topic".to_owned());
+        assert_eq!(subscription.rdkafka_error_code(), None);
+        assert!(!subscription.is_retriable());
+        assert!(!subscription.is_fatal());
+
+        assert_eq!(
+            KafkaError::PartitionEOF {
+                topic: "topic1".to_string(),
+                partition: 0,
+                offset: 10,
+            }
+            .rdkafka_error_code(),
+            Some(RDKafkaErrorCode::PartitionEOF)
+        );
+    }
+}
@@ -66,6 +66,44 @@ impl GroupMemberInfo {
     }
 }
 
+/// The state of a consumer group, as reported by the group coordinator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GroupState {
+    /// The group has no members.
+    Empty,
+    /// The group is stable, and all its members have been assigned their partitions.
+    Stable,
+    /// The group is waiting for its members to join, as part of a rebalance.
+    PreparingRebalance,
+    /// The group is waiting for the leader to assign the partitions, as part of a rebalance.
+    CompletingRebalance,
+    /// The group has been removed from the coordinator.
+    Dead,
+    /// The state reported by the coordinator is not known.
+    Unknown,
+}
+
+impl GroupState {
+    /// Returns true if the group is rebalancing.
+    pub fn is_rebalancing(self) -> bool {
+        self == GroupState::PreparingRebalance || self == GroupState::CompletingRebalance
+    }
+}
+
+impl<'a> From<&'a str> for GroupState {
+    fn from(state: &'a str) -> GroupState {
+        match state {
+            "Empty" => GroupState::Empty,
+            "Stable" => GroupState::Stable,
+            "PreparingRebalance" => GroupState::PreparingRebalance,
+            // Older brokers call the CompletingRebalance state AwaitingSync.
+            "CompletingRebalance" | "AwaitingSync" => GroupState::CompletingRebalance,
+            "Dead" => GroupState::Dead,
+            _ => GroupState::Unknown,
+        }
+    }
+}
+
 /// Group information container.
 pub struct GroupInfo(RDKafkaGroupInfo);
 
@@ -98,6 +136,11 @@ impl GroupInfo {
         }
     }
 
+    /// Returns the state of the group as a `GroupState`.
+    pub fn group_state(&self) -> GroupState {
+        GroupState::from(self.state())
+    }
+
     /// Returns the protocol of the group.
     pub fn protocol(&self) -> &str {
         unsafe {
@@ -149,3 +192,30 @@ impl Drop for GroupList {
         unsafe { rdsys::rd_kafka_group_list_destroy(self.0) };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_state() {
+        assert_eq!(GroupState::from("Empty"), GroupState::Empty);
+        assert_eq!(GroupState::from("Stable"), GroupState::Stable);
+        assert_eq!(
+            GroupState::from("PreparingRebalance"),
+            GroupState::PreparingRebalance
+        );
+        assert_eq!(
+            GroupState::from("CompletingRebalance"),
+            GroupState::CompletingRebalance
+        );
+        assert_eq!(
+            GroupState::from("AwaitingSync"),
+            GroupState::CompletingRebalance
+        );
+        assert_eq!(GroupState::from("Dead"), GroupState::Dead);
+        assert_eq!(GroupState::from(""), GroupState::Unknown);
+        assert!(GroupState::PreparingRebalance.is_rebalancing());
+        assert!(!GroupState::Stable.is_rebalancing());
+    }
+}
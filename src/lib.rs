@@ -206,6 +206,9 @@
 //! To enable debugging in your project, make sure you initialize the logger with
 //! `env_logger::init()` or equivalent.
 //!
+//! If the `tracing` feature is enabled, the logs of librdkafka are emitted as `tracing` events
+//! instead, with the librdkafka facility and message as fields.
+//!
 
 #![warn(missing_docs)]
 
@@ -234,6 +237,8 @@ pub mod error;
 pub mod groups;
 pub mod message;
 pub mod metadata;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod producer;
 pub mod statistics;
 pub mod topic_partition_list;
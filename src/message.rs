@@ -2,21 +2,25 @@
 use crate::rdsys;
 use crate::rdsys::types::*;
 
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::ffi::{CStr, CString};
 use std::fmt;
 use std::marker::PhantomData;
 use std::os::raw::c_void;
 use std::ptr;
 use std::str;
+use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
 
+use crate::producer::{BaseRecord, FutureRecord};
 use crate::util;
 
 use crate::error::{IsError, KafkaError, KafkaResult};
 use crate::util::millis_to_epoch;
 
 /// Timestamp of a message
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub enum Timestamp {
     /// Timestamp not available
     NotAvailable,
@@ -37,6 +41,15 @@ impl Timestamp {
         }
     }
 
+    /// Returns true if the timestamp is a log append time, set by the broker when the message was
+    /// appended to the log, rather than a creation time set by the producer.
+    pub fn is_log_append(&self) -> bool {
+        match *self {
+            Timestamp::LogAppendTime(_) => true,
+            Timestamp::NotAvailable | Timestamp::CreateTime(_) => false,
+        }
+    }
+
     /// Creates a new `Timestamp::CreateTime` representing the current time.
     pub fn now() -> Timestamp {
         Timestamp::from(SystemTime::now())
@@ -68,7 +81,8 @@ impl From<SystemTime> for Timestamp {
 /// alongside every message. Only read-only methods are provided by this trait, as the underlying
 /// storage might not allow modification.
 pub trait Headers {
-    /// Return the number of defined headers.
+    /// Return the number of defined headers. The count is kept by librdkafka, so this doesn't
+    /// iterate over the headers.
     fn count(&self) -> usize;
     /// Get the specified header (the first header corresponds to index 0). If the index is
     /// out of bounds, None is returned.
@@ -80,6 +94,54 @@ pub trait Headers {
         self.get(idx)
             .map(|(name, value)| (name, V::from_bytes(value)))
     }
+
+    /// Same as [Headers::get], but a null-valued header is returned with a `None` value instead
+    /// of an empty one.
+    fn get_nullable(&self, idx: usize) -> Option<(&str, Option<&[u8]>)> {
+        self.get(idx).map(|(name, value)| (name, Some(value)))
+    }
+
+    /// Returns all the headers, grouped by key. The values of each key are kept in the order of
+    /// the headers, and null values are returned as `None`.
+    fn to_map(&self) -> HashMap<String, Vec<Option<Vec<u8>>>> {
+        let mut map: HashMap<String, Vec<Option<Vec<u8>>>> = HashMap::new();
+        for idx in 0..self.count() {
+            if let Some((name, value)) = self.get_nullable(idx) {
+                map.entry(name.to_owned())
+                    .or_default()
+                    .push(value.map(<[u8]>::to_vec));
+            }
+        }
+        map
+    }
+
+    /// Same as [Headers::to_map], but the values are converted to strings. Values that are not
+    /// valid UTF-8 are skipped, so `None` always corresponds to a null value.
+    fn to_str_map(&self) -> HashMap<String, Vec<Option<String>>> {
+        let mut map: HashMap<String, Vec<Option<String>>> = HashMap::new();
+        for idx in 0..self.count() {
+            let (name, value) = match self.get_nullable(idx) {
+                Some((name, Some(value))) => match str::from_utf8(value) {
+                    Ok(value) => (name, Some(value.to_owned())),
+                    Err(_) => continue,
+                },
+                Some((name, None)) => (name, None),
+                None => continue,
+            };
+            map.entry(name.to_owned()).or_default().push(value);
+        }
+        map
+    }
+
+    /// Returns the total size of the keys and the values of the headers, in bytes. Null values
+    /// count as empty values. The size doesn't include the framing added by the protocol, so it
+    /// is a lower bound of the space taken by the headers in a request.
+    fn total_bytes(&self) -> usize {
+        (0..self.count())
+            .filter_map(|idx| self.get(idx))
+            .map(|(name, value)| name.len() + value.len())
+            .sum()
+    }
 }
 
 /// The `Message` trait provides access to the fields of a generic Kafka message.
@@ -117,6 +179,18 @@ pub trait Message {
         self.key().map(K::from_bytes)
     }
 
+    /// Decodes the payload into a value of the specified type. Returns None if there is no
+    /// payload, and an error if the payload can't be decoded.
+    fn payload_as<D: Decoder>(&self) -> Option<Result<D, DecodeError>> {
+        self.payload().map(D::decode)
+    }
+
+    /// Decodes the key into a value of the specified type. Returns None if there is no key, and
+    /// an error if the key can't be decoded.
+    fn key_as<D: Decoder>(&self) -> Option<Result<D, DecodeError>> {
+        self.key().map(D::decode)
+    }
+
     /// Returns the headers of the message, if available.
     fn headers(&self) -> Option<&Self::Headers>;
 }
@@ -152,6 +226,11 @@ impl Headers for BorrowedHeaders {
     }
 
     fn get(&self, idx: usize) -> Option<(&str, &[u8])> {
+        self.get_nullable(idx)
+            .map(|(name, value)| (name, value.unwrap_or(&[][..])))
+    }
+
+    fn get_nullable(&self, idx: usize) -> Option<(&str, Option<&[u8]>)> {
         let mut value_ptr = ptr::null();
         let mut name_ptr = ptr::null();
         let mut value_size = 0;
@@ -168,10 +247,12 @@ impl Headers for BorrowedHeaders {
             None
         } else {
             unsafe {
-                Some((
-                    CStr::from_ptr(name_ptr).to_str().unwrap(),
-                    util::ptr_to_slice(value_ptr, value_size),
-                ))
+                let value = if value_ptr.is_null() {
+                    None
+                } else {
+                    Some(util::ptr_to_slice(value_ptr, value_size))
+                };
+                Some((CStr::from_ptr(name_ptr).to_str().unwrap(), value))
             }
         }
     }
@@ -189,6 +270,7 @@ impl Headers for BorrowedHeaders {
 /// To transform a `BorrowedMessage` into a `OwnedMessage`, use the `detach` method.
 pub struct BorrowedMessage<'a> {
     ptr: *mut RDKafkaMessage,
+    pool: Option<Arc<MessagePool>>,
     _owner: PhantomData<&'a u8>,
 }
 
@@ -209,6 +291,25 @@ impl<'a> fmt::Debug for BorrowedMessage<'a> {
     }
 }
 
+/// Returns the error carried by a native message returned by a consumer.
+unsafe fn consumer_message_error(ptr: *const RDKafkaMessage) -> KafkaError {
+    match (*ptr).err {
+        rdsys::rd_kafka_resp_err_t::RD_KAFKA_RESP_ERR__PARTITION_EOF => {
+            let topic = if (*ptr).rkt.is_null() {
+                String::new()
+            } else {
+                util::cstr_to_owned(rdsys::rd_kafka_topic_name((*ptr).rkt))
+            };
+            KafkaError::PartitionEOF {
+                topic,
+                partition: (*ptr).partition,
+                offset: (*ptr).offset,
+            }
+        }
+        e => KafkaError::MessageConsumption(e.into()),
+    }
+}
+
 impl<'a> BorrowedMessage<'a> {
     /// Creates a new `BorrowedMessage` that wraps the native Kafka message pointer returned by a
     /// consumer. The lifetime of the message will be bound to the lifetime of the consumer passed
@@ -219,17 +320,13 @@ impl<'a> BorrowedMessage<'a> {
         _consumer: &'a C,
     ) -> KafkaResult<BorrowedMessage<'a>> {
         if (*ptr).err.is_error() {
-            let err = match (*ptr).err {
-                rdsys::rd_kafka_resp_err_t::RD_KAFKA_RESP_ERR__PARTITION_EOF => {
-                    KafkaError::PartitionEOF((*ptr).partition)
-                }
-                e => KafkaError::MessageConsumption(e.into()),
-            };
+            let err = consumer_message_error(ptr);
             rdsys::rd_kafka_message_destroy(ptr);
             Err(err)
         } else {
             Ok(BorrowedMessage {
                 ptr,
+                pool: None,
                 _owner: PhantomData,
             })
         }
@@ -245,6 +342,7 @@ impl<'a> BorrowedMessage<'a> {
     ) -> DeliveryResult<'a> {
         let borrowed_message = BorrowedMessage {
             ptr,
+            pool: None,
             _owner: PhantomData,
         };
         if (*ptr).err.is_error() {
@@ -277,17 +375,59 @@ impl<'a> BorrowedMessage<'a> {
         unsafe { (*self.ptr).len }
     }
 
+    /// Returns the time elapsed since a produced message was enqueued in the producer, in
+    /// microseconds. In the delivery report, it is the latency of the production of the message,
+    /// from the enqueue to the acknowledgement by the broker, or to the failure. Returns `None`
+    /// for the messages received by consumers.
+    pub fn latency_us(&self) -> Option<i64> {
+        let latency = unsafe { rdsys::rd_kafka_message_latency(self.ptr) };
+        if latency < 0 {
+            None
+        } else {
+            Some(latency)
+        }
+    }
+
+    /// Sets the pool used by `detach` to allocate the key and the payload of the message.
+    pub(crate) fn with_pool(mut self, pool: Option<Arc<MessagePool>>) -> BorrowedMessage<'a> {
+        self.pool = pool;
+        self
+    }
+
     /// Clones the content of the `BorrowedMessage` and returns an `OwnedMessage`, that can
     /// outlive the consumer. This operation requires memory allocation and can be expensive.
+    /// If the message was received from a stream configured with a `MessagePool`, the key and
+    /// the payload are copied into buffers taken from the pool.
     pub fn detach(&self) -> OwnedMessage {
+        match self.pool {
+            Some(ref pool) => self.detach_with_pool(pool),
+            None => OwnedMessage {
+                key: self.key().map(|k| k.to_vec()),
+                payload: self.payload().map(|p| p.to_vec()),
+                topic: self.topic().to_owned(),
+                timestamp: self.timestamp(),
+                partition: self.partition(),
+                offset: self.offset(),
+                headers: self.headers().map(BorrowedHeaders::detach),
+                latency_us: self.latency_us(),
+                pool: None,
+            },
+        }
+    }
+
+    /// Like `detach`, but copies the key and the payload into buffers taken from the provided
+    /// pool. The buffers are returned to the pool when the `OwnedMessage` is dropped.
+    pub fn detach_with_pool(&self, pool: &Arc<MessagePool>) -> OwnedMessage {
         OwnedMessage {
-            key: self.key().map(|k| k.to_vec()),
-            payload: self.payload().map(|p| p.to_vec()),
+            key: self.key().map(|k| pool.copy(k)),
+            payload: self.payload().map(|p| pool.copy(p)),
             topic: self.topic().to_owned(),
             timestamp: self.timestamp(),
             partition: self.partition(),
             offset: self.offset(),
             headers: self.headers().map(BorrowedHeaders::detach),
+            latency_us: self.latency_us(),
+            pool: Some(Arc::clone(pool)),
         }
     }
 }
@@ -344,10 +484,10 @@ impl<'a> Message for BorrowedMessage<'a> {
         unsafe {
             let err = rdsys::rd_kafka_message_headers(self.ptr, &mut native_headers_ptr);
             match err.into() {
-                RDKafkaError::NoError => {
+                RDKafkaErrorCode::NoError => {
                     Some(BorrowedHeaders::from_native_ptr(self, native_headers_ptr))
                 }
-                RDKafkaError::NoEnt => None,
+                RDKafkaErrorCode::NoEnt => None,
                 _ => None,
             }
         }
@@ -365,6 +505,17 @@ impl<'a> Drop for BorrowedMessage<'a> {
 // ********** OWNED MESSAGE **********
 //
 
+/// A message header, to be added to an [OwnedHeaders] struct with [OwnedHeaders::insert].
+/// A header with no value is sent as a null-valued header, that is read back as an empty value
+/// by [Headers::get] and as `None` by [Headers::get_nullable].
+#[derive(Debug)]
+pub struct Header<'a, V: ?Sized> {
+    /// The key of the header.
+    pub key: &'a str,
+    /// The value of the header, or `None` for a null value.
+    pub value: Option<&'a V>,
+}
+
 /// Owned message headers
 ///
 /// Kafka supports associating an array of key-value pairs to every message, called message headers.
@@ -409,6 +560,29 @@ impl OwnedHeaders {
         self
     }
 
+    /// Add a new header to the structure. Headers are kept in insertion order, and multiple
+    /// headers with the same key are allowed.
+    pub fn insert<V: ToHeaderValue + ?Sized>(self, header: Header<'_, V>) -> OwnedHeaders {
+        let name_cstring = CString::new(header.key.to_owned()).unwrap();
+        let value = header.value.map(ToHeaderValue::to_header_value);
+        let (value_ptr, value_len) = match value {
+            Some(ref bytes) => (bytes.as_ptr() as *mut c_void, bytes.len() as isize),
+            None => (ptr::null_mut(), 0),
+        };
+        let err = unsafe {
+            rdsys::rd_kafka_header_add(
+                self.ptr,
+                name_cstring.as_ptr(),
+                name_cstring.as_bytes().len() as isize,
+                value_ptr,
+                value_len,
+            )
+        };
+        // OwnedHeaders should always represent writable instances of RDKafkaHeaders
+        assert!(!err.is_error());
+        self
+    }
+
     pub(crate) fn ptr(&self) -> *mut RDKafkaHeaders {
         self.ptr
     }
@@ -433,6 +607,10 @@ impl Headers for OwnedHeaders {
     fn get(&self, idx: usize) -> Option<(&str, &[u8])> {
         self.as_borrowed().get(idx)
     }
+
+    fn get_nullable(&self, idx: usize) -> Option<(&str, Option<&[u8]>)> {
+        self.as_borrowed().get_nullable(idx)
+    }
 }
 
 impl Clone for OwnedHeaders {
@@ -461,6 +639,8 @@ pub struct OwnedMessage {
     partition: i32,
     offset: i64,
     headers: Option<OwnedHeaders>,
+    latency_us: Option<i64>,
+    pool: Option<Arc<MessagePool>>,
 }
 
 impl OwnedMessage {
@@ -482,8 +662,161 @@ impl OwnedMessage {
             partition,
             offset,
             headers,
+            latency_us: None,
+            pool: None,
         }
     }
+
+    /// Returns the production latency of the message in microseconds, as reported by
+    /// `BorrowedMessage::latency_us` when the message was detached.
+    pub fn latency_us(&self) -> Option<i64> {
+        self.latency_us
+    }
+
+    /// Creates a record to send a copy of this message to the dead letter topic `topic`, after
+    /// its processing failed with `error`. The record has the key and the payload of the
+    /// message, and its headers followed by:
+    ///
+    /// * `x-original-topic`: the topic of the message;
+    /// * `x-original-partition`: the partition of the message, as a decimal string;
+    /// * `x-original-offset`: the offset of the message, as a decimal string;
+    /// * `x-error`: the description of the error.
+    pub fn to_dead_letter<'a>(
+        &'a self,
+        topic: &'a str,
+        error: &str,
+    ) -> FutureRecord<'a, [u8], [u8]> {
+        let headers = match self.headers {
+            Some(ref headers) => headers.clone(),
+            None => OwnedHeaders::new_with_capacity(4),
+        };
+        let partition = self.partition.to_string();
+        let offset = self.offset.to_string();
+        let headers = headers
+            .insert(Header {
+                key: "x-original-topic",
+                value: Some(self.topic.as_str()),
+            })
+            .insert(Header {
+                key: "x-original-partition",
+                value: Some(partition.as_str()),
+            })
+            .insert(Header {
+                key: "x-original-offset",
+                value: Some(offset.as_str()),
+            })
+            .insert(Header {
+                key: "x-error",
+                value: Some(error),
+            });
+        FutureRecord {
+            topic,
+            partition: None,
+            payload: self.payload(),
+            key: self.key(),
+            timestamp: None,
+            headers: Some(headers),
+        }
+    }
+}
+
+impl Drop for OwnedMessage {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            if let Some(key) = self.key.take() {
+                pool.recycle(key);
+            }
+            if let Some(payload) = self.payload.take() {
+                pool.recycle(payload);
+            }
+        }
+    }
+}
+
+/// A bounded pool of buffers, used to reduce the allocations performed when detaching
+/// messages. The key and the payload of the [OwnedMessage]s detached with the pool are copied
+/// into buffers taken from the pool, and the buffers are given back to the pool when the
+/// messages are dropped. The pool retains at most `max_buffers` buffers, of at most
+/// `max_buffer_capacity` bytes each: additional or larger buffers are freed. The pool can be
+/// shared across threads.
+///
+/// A pool can be used with [BorrowedMessage::detach_with_pool], or set on the stream of a
+/// `StreamConsumer` with `StreamConfig::message_pool`.
+pub struct MessagePool {
+    max_buffers: usize,
+    max_buffer_capacity: usize,
+    buffers: Mutex<Vec<Vec<u8>>>,
+}
+
+/// The default maximum capacity of the buffers retained by a [MessagePool], matching the default
+/// maximum size of a message in librdkafka.
+const DEFAULT_MAX_BUFFER_CAPACITY: usize = 1_000_000;
+
+impl MessagePool {
+    /// Creates a new pool, that will retain at most `max_buffers` buffers.
+    pub fn new(max_buffers: usize) -> MessagePool {
+        MessagePool {
+            max_buffers,
+            max_buffer_capacity: DEFAULT_MAX_BUFFER_CAPACITY,
+            buffers: Mutex::new(Vec::with_capacity(max_buffers)),
+        }
+    }
+
+    /// Sets the maximum capacity of the buffers retained by the pool, in bytes, so that a few
+    /// large messages don't keep their memory allocated once dropped. Defaults to 1000000 bytes,
+    /// the default value of `message.max.bytes`.
+    pub fn max_buffer_capacity(mut self, max_buffer_capacity: usize) -> MessagePool {
+        self.max_buffer_capacity = max_buffer_capacity;
+        self
+    }
+
+    /// Returns the number of buffers currently available in the pool.
+    pub fn len(&self) -> usize {
+        self.buffers.lock().unwrap().len()
+    }
+
+    /// Returns true if no buffer is currently available in the pool.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copies `data` into a buffer taken from the pool, or into a new buffer if the pool is
+    /// empty.
+    fn copy(&self, data: &[u8]) -> Vec<u8> {
+        let buffer = self.buffers.lock().unwrap().pop();
+        match buffer {
+            Some(mut buffer) => {
+                buffer.clear();
+                buffer.extend_from_slice(data);
+                buffer
+            }
+            None => data.to_vec(),
+        }
+    }
+
+    /// Gives a buffer back to the pool. The buffer is freed if the pool is full, or if the buffer
+    /// is too large.
+    fn recycle(&self, buffer: Vec<u8>) {
+        if buffer.capacity() > self.max_buffer_capacity {
+            return;
+        }
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers.len() < self.max_buffers {
+            buffers.push(buffer);
+        }
+    }
+}
+
+impl fmt::Debug for MessagePool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "MessagePool {{ max_buffers: {}, max_buffer_capacity: {}, available: {} }}",
+            self.max_buffers,
+            self.max_buffer_capacity,
+            self.len()
+        )
+    }
 }
 
 impl Message for OwnedMessage {
@@ -524,6 +857,85 @@ impl Message for OwnedMessage {
     }
 }
 
+/// A serializable representation of a message, that can be used to persist or forward messages,
+/// for example to a dead letter store. It can be created from an [OwnedMessage], and converted
+/// back to a [BaseRecord] to produce it again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageRecord {
+    /// The topic of the message.
+    pub topic: String,
+    /// The partition of the message.
+    pub partition: i32,
+    /// The offset of the message.
+    pub offset: i64,
+    /// The timestamp of the message.
+    pub timestamp: Timestamp,
+    /// The key of the message, if any.
+    pub key: Option<Vec<u8>>,
+    /// The payload of the message, if any.
+    pub payload: Option<Vec<u8>>,
+    /// The headers of the message, in order.
+    pub headers: Vec<MessageRecordHeader>,
+}
+
+/// A header of a [MessageRecord].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageRecordHeader {
+    /// The key of the header.
+    pub key: String,
+    /// The value of the header, or `None` for a null value.
+    pub value: Option<Vec<u8>>,
+}
+
+impl MessageRecord {
+    /// Returns a [BaseRecord] with the content of the message, that can be sent with a
+    /// `BaseProducer`. The record is directed to the original topic and partition of the message;
+    /// they can be changed by setting the corresponding fields of the returned record.
+    pub fn to_base_record(&self) -> BaseRecord<'_, [u8], [u8]> {
+        let mut record = BaseRecord::to(&self.topic).partition(self.partition);
+        record.key = self.key.as_deref();
+        record.payload = self.payload.as_deref();
+        record.timestamp = self.timestamp.to_millis();
+        if !self.headers.is_empty() {
+            let headers = self.headers.iter().fold(
+                OwnedHeaders::new_with_capacity(self.headers.len()),
+                |h, header| {
+                    h.insert(Header {
+                        key: &header.key,
+                        value: header.value.as_deref(),
+                    })
+                },
+            );
+            record.headers = Some(headers);
+        }
+        record
+    }
+}
+
+impl<'a> From<&'a OwnedMessage> for MessageRecord {
+    fn from(message: &'a OwnedMessage) -> MessageRecord {
+        let headers = match message.headers() {
+            Some(headers) => (0..headers.count())
+                .filter_map(|idx| headers.get_nullable(idx))
+                .map(|(key, value)| MessageRecordHeader {
+                    key: key.to_owned(),
+                    value: value.map(<[u8]>::to_vec),
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        MessageRecord {
+            topic: message.topic().to_owned(),
+            partition: message.partition(),
+            offset: message.offset(),
+            timestamp: message.timestamp(),
+            key: message.key().map(<[u8]>::to_vec),
+            payload: message.payload().map(<[u8]>::to_vec),
+            headers,
+        }
+    }
+}
+
 /// Given a reference to a byte array, returns a different view of the same data.
 /// No allocation is performed, however the underlying data might be checked for
 /// correctness (for example when converting to `str`).
@@ -548,6 +960,69 @@ impl FromBytes for str {
     }
 }
 
+/// The error returned by a [Decoder] when some bytes can't be decoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodeError(String);
+
+impl DecodeError {
+    /// Creates a new decoding error with the provided description.
+    pub fn new<S: Into<String>>(description: S) -> DecodeError {
+        DecodeError(description.into())
+    }
+
+    /// Returns the description of the error.
+    pub fn description(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Decoding error: {}", self.0)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Decodes a byte array into an owned value, for example using a serialization format such as
+/// JSON, Avro or Protobuf. Unlike [FromBytes], the decoding can allocate.
+pub trait Decoder: Sized {
+    /// Tries to decode the provided byte slice.
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError>;
+}
+
+impl Decoder for Vec<u8> {
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Ok(bytes.to_vec())
+    }
+}
+
+impl Decoder for String {
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        str::from_utf8(bytes)
+            .map(str::to_owned)
+            .map_err(|e| DecodeError::new(e.to_string()))
+    }
+}
+
+/// A value decoded from JSON with serde_json. Available with the `json` feature.
+///
+/// ```rust,ignore
+/// let Json(payload) = message.payload_as::<Json<MyPayload>>().unwrap()?;
+/// ```
+#[cfg(feature = "json")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Json<T>(pub T);
+
+#[cfg(feature = "json")]
+impl<T: serde::de::DeserializeOwned> Decoder for Json<T> {
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        serde_json::from_slice(bytes)
+            .map(Json)
+            .map_err(|e| DecodeError::new(e.to_string()))
+    }
+}
+
 /// Given some data, returns the byte representation of that data.
 /// No copy of the data should be performed.
 pub trait ToBytes {
@@ -591,6 +1066,34 @@ impl ToBytes for () {
     }
 }
 
+/// Converts some data to the value of a message header. Unlike [ToBytes], the conversion can
+/// allocate, which allows integers to be used as header values.
+pub trait ToHeaderValue {
+    /// Convert the provided data to the bytes of a header value.
+    fn to_header_value(&self) -> Cow<'_, [u8]>;
+}
+
+impl<T: ToBytes + ?Sized> ToHeaderValue for T {
+    fn to_header_value(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.to_bytes())
+    }
+}
+
+// Integers are encoded in big-endian order, like the serializers of the Java client do.
+macro_rules! integer_header_value_impls {
+    ($($T:ty)+) => {
+        $(
+            impl ToHeaderValue for $T {
+                fn to_header_value(&self) -> Cow<'_, [u8]> {
+                    Cow::Owned(self.to_be_bytes().to_vec())
+                }
+            }
+         )+
+    }
+}
+
+integer_header_value_impls! { i16 i32 i64 u16 u32 u64 }
+
 // Implement to_bytes for arrays - https://github.com/rust-lang/rfcs/issues/1038
 macro_rules! array_impls {
     ($($N:expr)+) => {
@@ -639,6 +1142,14 @@ mod test {
         assert_eq!(t, Timestamp::CreateTime(100));
     }
 
+    #[test]
+    fn test_timestamp_is_log_append() {
+        assert!(!Timestamp::CreateTime(100).is_log_append());
+        assert!(Timestamp::LogAppendTime(100).is_log_append());
+        assert!(!Timestamp::NotAvailable.is_log_append());
+        assert_eq!(Timestamp::NotAvailable.to_millis(), None);
+    }
+
     #[test]
     fn test_headers() {
         let owned = OwnedHeaders::new()
@@ -650,4 +1161,336 @@ mod test {
         );
         assert_eq!(owned.get_as::<str>(1), Some(("key2", Ok("value2"))));
     }
+
+    #[test]
+    fn test_headers_insert() {
+        let owned = OwnedHeaders::new()
+            .insert(Header {
+                key: "key1",
+                value: Some("value1"),
+            })
+            .insert(Header {
+                key: "key2",
+                value: Some(&1234i64),
+            })
+            .insert(Header {
+                key: "key1",
+                value: Some(&[1u8, 2, 3][..]),
+            })
+            .insert::<str>(Header {
+                key: "key3",
+                value: None,
+            });
+        assert_eq!(owned.count(), 4);
+        assert_eq!(owned.get_as::<str>(0), Some(("key1", Ok("value1"))));
+        assert_eq!(
+            owned.get(1),
+            Some(("key2", &[0, 0, 0, 0, 0, 0, 4, 210][..]))
+        );
+        assert_eq!(owned.get(2), Some(("key1", &[1, 2, 3][..])));
+        assert_eq!(owned.get(3), Some(("key3", &[][..])));
+        assert_eq!(owned.get(4), None);
+    }
+
+    #[test]
+    fn test_headers_total_bytes() {
+        assert_eq!(OwnedHeaders::new().total_bytes(), 0);
+
+        let owned = OwnedHeaders::new()
+            .add("key1", "value1")
+            .insert::<str>(Header {
+                key: "key2",
+                value: None,
+            })
+            .add("k3", &[1u8, 2, 3][..])
+            .add("key4", "");
+        assert_eq!(owned.total_bytes(), (4 + 6) + 4 + (2 + 3) + 4);
+        assert_eq!(owned.as_borrowed().total_bytes(), owned.total_bytes());
+    }
+
+    #[test]
+    fn test_headers_to_map() {
+        let owned = OwnedHeaders::new()
+            .add("key1", "value1")
+            .insert::<str>(Header {
+                key: "key2",
+                value: None,
+            })
+            .add("key1", "")
+            .add("key3", &[0xffu8][..])
+            .add("key1", "value2");
+        assert_eq!(owned.get_nullable(1), Some(("key2", None)));
+        assert_eq!(owned.get_nullable(2), Some(("key1", Some(&[][..]))));
+
+        let map = owned.to_map();
+        assert_eq!(map.len(), 3);
+        assert_eq!(
+            map["key1"],
+            vec![
+                Some(b"value1".to_vec()),
+                Some(Vec::new()),
+                Some(b"value2".to_vec())
+            ]
+        );
+        assert_eq!(map["key2"], vec![None]);
+        assert_eq!(map["key3"], vec![Some(vec![0xff])]);
+
+        let str_map = owned.to_str_map();
+        assert_eq!(str_map.len(), 2);
+        assert_eq!(
+            str_map["key1"],
+            vec![
+                Some("value1".to_owned()),
+                Some(String::new()),
+                Some("value2".to_owned())
+            ]
+        );
+        assert_eq!(str_map["key2"], vec![None]);
+        assert!(!str_map.contains_key("key3"));
+    }
+
+    #[test]
+    fn test_to_dead_letter() {
+        let message = OwnedMessage::new(
+            Some(b"payload".to_vec()),
+            Some(b"key".to_vec()),
+            "source".to_owned(),
+            Timestamp::CreateTime(100),
+            3,
+            42,
+            Some(OwnedHeaders::new().add("trace-id", "abc")),
+        );
+        let record = message.to_dead_letter("dead-letters", "invalid payload");
+
+        assert_eq!(record.topic, "dead-letters");
+        assert_eq!(record.partition, None);
+        assert_eq!(record.key, Some(&b"key"[..]));
+        assert_eq!(record.payload, Some(&b"payload"[..]));
+        let headers = record.headers.as_ref().unwrap();
+        assert_eq!(headers.count(), 5);
+        assert_eq!(headers.get_as::<str>(0), Some(("trace-id", Ok("abc"))));
+        assert_eq!(
+            headers.get_as::<str>(1),
+            Some(("x-original-topic", Ok("source")))
+        );
+        assert_eq!(
+            headers.get_as::<str>(2),
+            Some(("x-original-partition", Ok("3")))
+        );
+        assert_eq!(
+            headers.get_as::<str>(3),
+            Some(("x-original-offset", Ok("42")))
+        );
+        assert_eq!(
+            headers.get_as::<str>(4),
+            Some(("x-error", Ok("invalid payload")))
+        );
+        // The headers of the message are left untouched.
+        assert_eq!(message.headers().unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_message_record_json_round_trip() {
+        let headers = OwnedHeaders::new()
+            .add("key1", "value1")
+            .add("key1", "")
+            .insert::<str>(Header {
+                key: "key2",
+                value: None,
+            });
+        let message = OwnedMessage::new(
+            Some(b"payload".to_vec()),
+            None,
+            "topic1".to_owned(),
+            Timestamp::LogAppendTime(1234),
+            2,
+            10,
+            Some(headers),
+        );
+        let record = MessageRecord::from(&message);
+
+        let json = serde_json::to_string(&record).unwrap();
+        assert!(json.contains(r#""timestamp":{"LogAppendTime":1234}"#));
+        let deserialized: MessageRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, record);
+        assert_eq!(deserialized.topic, "topic1");
+        assert_eq!(deserialized.partition, 2);
+        assert_eq!(deserialized.offset, 10);
+        assert_eq!(deserialized.key, None);
+        assert_eq!(deserialized.payload, Some(b"payload".to_vec()));
+        assert_eq!(deserialized.headers.len(), 3);
+        assert_eq!(deserialized.headers[0].key, "key1");
+        assert_eq!(deserialized.headers[0].value, Some(b"value1".to_vec()));
+        assert_eq!(deserialized.headers[1].value, Some(Vec::new()));
+        assert_eq!(deserialized.headers[2].key, "key2");
+        assert_eq!(deserialized.headers[2].value, None);
+
+        let base_record = deserialized.to_base_record();
+        assert_eq!(base_record.topic, "topic1");
+        assert_eq!(base_record.partition, Some(2));
+        assert_eq!(base_record.payload, Some(&b"payload"[..]));
+        assert_eq!(base_record.key, None);
+        assert_eq!(base_record.timestamp, Some(1234));
+        let headers = base_record.headers.unwrap();
+        assert_eq!(headers.count(), 3);
+        assert_eq!(headers.get_as::<str>(0), Some(("key1", Ok("value1"))));
+        assert_eq!(headers.get_nullable(2), Some(("key2", None)));
+    }
+
+    #[test]
+    fn test_payload_as() {
+        let message = OwnedMessage::new(
+            Some(b"payload".to_vec()),
+            Some(vec![0xff, 0xfe]),
+            "topic1".to_owned(),
+            Timestamp::NotAvailable,
+            0,
+            0,
+            None,
+        );
+        assert_eq!(
+            message.payload_as::<String>(),
+            Some(Ok("payload".to_owned()))
+        );
+        assert_eq!(message.key_as::<Vec<u8>>(), Some(Ok(vec![0xff, 0xfe])));
+        assert!(message.key_as::<String>().unwrap().is_err());
+
+        let message = OwnedMessage::new(
+            None,
+            None,
+            "topic1".to_owned(),
+            Timestamp::NotAvailable,
+            0,
+            0,
+            None,
+        );
+        assert_eq!(message.payload_as::<String>(), None);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_payload_as_json() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Payload {
+            id: u32,
+            name: String,
+        }
+
+        let message = |payload: &[u8]| {
+            OwnedMessage::new(
+                Some(payload.to_vec()),
+                None,
+                "topic1".to_owned(),
+                Timestamp::NotAvailable,
+                0,
+                0,
+                None,
+            )
+        };
+        assert_eq!(
+            message(br#"{"id":1,"name":"one"}"#).payload_as::<Json<Payload>>(),
+            Some(Ok(Json(Payload {
+                id: 1,
+                name: "one".to_owned()
+            })))
+        );
+        let error = message(br#"{"id":"#)
+            .payload_as::<Json<Payload>>()
+            .unwrap()
+            .unwrap_err();
+        assert!(error.description().contains("EOF"));
+    }
+
+    #[test]
+    fn test_timestamp_serialization() {
+        let json = serde_json::to_string(&Timestamp::NotAvailable).unwrap();
+        assert_eq!(json, r#""NotAvailable""#);
+        let timestamp: Timestamp = serde_json::from_str(r#"{"CreateTime":100}"#).unwrap();
+        assert_eq!(timestamp, Timestamp::CreateTime(100));
+    }
+
+    #[test]
+    fn test_message_pool() {
+        let pool = Arc::new(MessagePool::new(2));
+        assert!(pool.is_empty());
+
+        let messages = (0..3)
+            .map(|i| OwnedMessage {
+                key: Some(pool.copy(b"key")),
+                payload: Some(pool.copy(&[i; 100])),
+                topic: "topic".to_owned(),
+                timestamp: Timestamp::NotAvailable,
+                partition: 0,
+                offset: i64::from(i),
+                headers: None,
+                latency_us: None,
+                pool: Some(pool.clone()),
+            })
+            .collect::<Vec<_>>();
+        assert!(pool.is_empty());
+
+        // The pool is bounded.
+        drop(messages);
+        assert_eq!(pool.len(), 2);
+
+        // Recycled buffers are reused, and their previous content is discarded.
+        let payload = pool.copy(b"payload");
+        assert_eq!(payload, b"payload");
+        assert!(payload.capacity() >= 100);
+        assert_eq!(pool.len(), 1);
+
+        // Messages created without a pool don't change it.
+        drop(OwnedMessage::new(
+            Some(vec![1]),
+            None,
+            "topic".to_owned(),
+            Timestamp::NotAvailable,
+            0,
+            0,
+            None,
+        ));
+        assert_eq!(pool.len(), 1);
+
+        // Buffers larger than the maximum capacity are freed.
+        let pool = MessagePool::new(2).max_buffer_capacity(10);
+        pool.recycle(Vec::with_capacity(10));
+        pool.recycle(Vec::with_capacity(11));
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn test_consumer_message_error() {
+        let client = unsafe {
+            let conf = rdsys::rd_kafka_conf_new();
+            rdsys::rd_kafka_new(RDKafkaType::RD_KAFKA_CONSUMER, conf, ptr::null_mut(), 0)
+        };
+        let topic_name = CString::new("topic1").unwrap();
+        let topic =
+            unsafe { rdsys::rd_kafka_topic_new(client, topic_name.as_ptr(), ptr::null_mut()) };
+        let mut message: RDKafkaMessage = unsafe { std::mem::zeroed() };
+        message.rkt = topic;
+        message.partition = 2;
+        message.offset = 42;
+
+        message.err = RDKafkaRespErr::RD_KAFKA_RESP_ERR__PARTITION_EOF;
+        assert_eq!(
+            unsafe { consumer_message_error(&message) },
+            KafkaError::PartitionEOF {
+                topic: "topic1".to_string(),
+                partition: 2,
+                offset: 42,
+            }
+        );
+
+        message.err = RDKafkaRespErr::RD_KAFKA_RESP_ERR__TRANSPORT;
+        assert_eq!(
+            unsafe { consumer_message_error(&message) },
+            KafkaError::MessageConsumption(RDKafkaErrorCode::BrokerTransportFailure)
+        );
+
+        unsafe {
+            rdsys::rd_kafka_topic_destroy(topic);
+            rdsys::rd_kafka_destroy(client);
+        }
+    }
 }
@@ -1,11 +1,14 @@
 //! Cluster metadata.
 use std::ffi::CStr;
 use std::slice;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use crate::rdsys;
 use crate::rdsys::types::*;
 
-use crate::error::IsError;
+use crate::error::{IsError, KafkaResult, RDKafkaErrorCode};
+use crate::util::Timeout;
 
 /// Broker metadata information.
 pub struct MetadataBroker(RDKafkaMetadataBroker);
@@ -29,6 +32,15 @@ impl MetadataBroker {
     pub fn port(&self) -> i32 {
         self.0.port
     }
+
+    /// Clones the content of the `MetadataBroker` and returns an `OwnedMetadataBroker`.
+    pub fn detach(&self) -> OwnedMetadataBroker {
+        OwnedMetadataBroker {
+            id: self.id(),
+            host: self.host().to_owned(),
+            port: self.port(),
+        }
+    }
 }
 
 /// Partition metadata information.
@@ -64,6 +76,17 @@ impl MetadataPartition {
     pub fn isr(&self) -> &[i32] {
         unsafe { slice::from_raw_parts(self.0.isrs, self.0.isr_cnt as usize) }
     }
+
+    /// Clones the content of the `MetadataPartition` and returns an `OwnedMetadataPartition`.
+    pub fn detach(&self) -> OwnedMetadataPartition {
+        OwnedMetadataPartition {
+            id: self.id(),
+            leader: self.leader(),
+            replicas: self.replicas().to_vec(),
+            isr: self.isr().to_vec(),
+            error: self.error().map(RDKafkaErrorCode::from),
+        }
+    }
 }
 
 /// Topic metadata information.
@@ -97,6 +120,19 @@ impl MetadataTopic {
             None
         }
     }
+
+    /// Clones the content of the `MetadataTopic` and returns an `OwnedMetadataTopic`.
+    pub fn detach(&self) -> OwnedMetadataTopic {
+        OwnedMetadataTopic {
+            name: self.name().to_owned(),
+            partitions: self
+                .partitions()
+                .iter()
+                .map(MetadataPartition::detach)
+                .collect(),
+            error: self.error().map(RDKafkaErrorCode::from),
+        }
+    }
 }
 
 /// Metadata container. This structure wraps the metadata pointer returned by rdkafka-sys,
@@ -142,6 +178,17 @@ impl Metadata {
             )
         }
     }
+
+    /// Clones the content of the `Metadata` and returns an `OwnedMetadata`, which doesn't hold
+    /// any native resource.
+    pub fn detach(&self) -> OwnedMetadata {
+        OwnedMetadata {
+            orig_broker_id: self.orig_broker_id(),
+            orig_broker_name: self.orig_broker_name().to_owned(),
+            brokers: self.brokers().iter().map(MetadataBroker::detach).collect(),
+            topics: self.topics().iter().map(MetadataTopic::detach).collect(),
+        }
+    }
 }
 
 impl Drop for Metadata {
@@ -149,3 +196,348 @@ impl Drop for Metadata {
         unsafe { rdsys::rd_kafka_metadata_destroy(self.0) };
     }
 }
+
+/// Owned broker metadata information.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OwnedMetadataBroker {
+    id: i32,
+    host: String,
+    port: i32,
+}
+
+impl OwnedMetadataBroker {
+    /// Returns the id of the broker.
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Returns the host name of the broker.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Returns the port of the broker.
+    pub fn port(&self) -> i32 {
+        self.port
+    }
+}
+
+/// Owned partition metadata information.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OwnedMetadataPartition {
+    id: i32,
+    leader: i32,
+    replicas: Vec<i32>,
+    isr: Vec<i32>,
+    error: Option<RDKafkaErrorCode>,
+}
+
+impl OwnedMetadataPartition {
+    /// Returns the id of the partition.
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Returns the broker id of the leader broker for the partition, or -1 if there is no leader.
+    pub fn leader(&self) -> i32 {
+        self.leader
+    }
+
+    /// Returns the broker ids of the replicas.
+    pub fn replicas(&self) -> &[i32] {
+        &self.replicas
+    }
+
+    /// Returns the broker ids of the in sync replicas.
+    pub fn isr(&self) -> &[i32] {
+        &self.isr
+    }
+
+    /// Returns the metadata error reported by the broker for the partition, or None if there is
+    /// no error.
+    pub fn error(&self) -> Option<RDKafkaErrorCode> {
+        self.error
+    }
+}
+
+/// Owned topic metadata information.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OwnedMetadataTopic {
+    name: String,
+    partitions: Vec<OwnedMetadataPartition>,
+    error: Option<RDKafkaErrorCode>,
+}
+
+impl OwnedMetadataTopic {
+    /// Returns the name of the topic.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the partition metadata information for all the partitions.
+    pub fn partitions(&self) -> &[OwnedMetadataPartition] {
+        &self.partitions
+    }
+
+    /// Returns the metadata error, or None if there was no error.
+    pub fn error(&self) -> Option<RDKafkaErrorCode> {
+        self.error
+    }
+}
+
+/// An owned snapshot of the cluster metadata. Unlike `Metadata`, it can be cloned and it can
+/// outlive the client it was fetched with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OwnedMetadata {
+    orig_broker_id: i32,
+    orig_broker_name: String,
+    brokers: Vec<OwnedMetadataBroker>,
+    topics: Vec<OwnedMetadataTopic>,
+}
+
+impl OwnedMetadata {
+    /// Returns the id of the broker originating this metadata.
+    pub fn orig_broker_id(&self) -> i32 {
+        self.orig_broker_id
+    }
+
+    /// Returns the hostname of the broker originating this metadata.
+    pub fn orig_broker_name(&self) -> &str {
+        &self.orig_broker_name
+    }
+
+    /// Returns the metadata information for all the brokers in the cluster.
+    pub fn brokers(&self) -> &[OwnedMetadataBroker] {
+        &self.brokers
+    }
+
+    /// Returns the metadata information for all the topics in the metadata.
+    pub fn topics(&self) -> &[OwnedMetadataTopic] {
+        &self.topics
+    }
+}
+
+/// A cache of the cluster metadata, for tools that read the metadata frequently, like
+/// dashboards. The metadata is fetched with the provided function, for example
+/// `Consumer::fetch_all_metadata`, only when the cached snapshot is older than the maximum age
+/// requested by the caller.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use rdkafka::consumer::{BaseConsumer, Consumer};
+/// use rdkafka::metadata::CachedMetadata;
+/// use rdkafka::ClientConfig;
+/// use std::time::Duration;
+///
+/// let consumer: BaseConsumer = ClientConfig::new().create().unwrap();
+/// let cache = CachedMetadata::new(|timeout| consumer.fetch_all_metadata(timeout));
+/// let metadata = cache
+///     .metadata(Duration::from_secs(10), Duration::from_secs(5))
+///     .unwrap();
+/// println!("{} brokers", metadata.brokers().len());
+/// ```
+pub struct CachedMetadata<F: Fn(Timeout) -> KafkaResult<OwnedMetadata>> {
+    fetch: F,
+    cached: Mutex<Option<(Instant, Arc<OwnedMetadata>)>>,
+}
+
+impl<F: Fn(Timeout) -> KafkaResult<OwnedMetadata>> CachedMetadata<F> {
+    /// Creates a new empty cache, fetching the metadata with `fetch`.
+    pub fn new(fetch: F) -> CachedMetadata<F> {
+        CachedMetadata {
+            fetch,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Returns the cached metadata if it was fetched less than `max_age` ago, otherwise fetches
+    /// it again with the specified timeout. If the fetch fails, the error is returned and the
+    /// cache is left unchanged.
+    pub fn metadata<T: Into<Timeout>>(
+        &self,
+        max_age: Duration,
+        timeout: T,
+    ) -> KafkaResult<Arc<OwnedMetadata>> {
+        self.metadata_at(Instant::now(), max_age, timeout.into())
+    }
+
+    /// Drops the cached metadata, so that the next call to `metadata` fetches it again.
+    pub fn invalidate(&self) {
+        *self.cached.lock().unwrap() = None;
+    }
+
+    fn metadata_at(
+        &self,
+        now: Instant,
+        max_age: Duration,
+        timeout: Timeout,
+    ) -> KafkaResult<Arc<OwnedMetadata>> {
+        let mut cached = self.cached.lock().unwrap();
+        if let Some((fetched_at, ref metadata)) = *cached {
+            if now.saturating_duration_since(fetched_at) < max_age {
+                return Ok(metadata.clone());
+            }
+        }
+        let metadata = Arc::new((self.fetch)(timeout)?);
+        *cached = Some((now, metadata.clone()));
+        Ok(metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::error::KafkaError;
+
+    use std::cell::Cell;
+    use std::ffi::CString;
+    use std::mem;
+
+    #[test]
+    fn test_detach() {
+        let host = CString::new("broker1").unwrap();
+        let topic_name = CString::new("topic1").unwrap();
+        let mut brokers = [RDKafkaMetadataBroker {
+            id: 1,
+            host: host.as_ptr() as *mut _,
+            port: 9092,
+        }];
+        let mut replicas0 = [1, 2];
+        let mut isrs0 = [2];
+        let mut replicas1 = [2];
+        let mut isrs1: [i32; 0] = [];
+        let mut partitions = [
+            RDKafkaMetadataPartition {
+                id: 0,
+                err: RDKafkaRespErr::RD_KAFKA_RESP_ERR_NO_ERROR,
+                leader: 2,
+                replica_cnt: 2,
+                replicas: replicas0.as_mut_ptr(),
+                isr_cnt: 1,
+                isrs: isrs0.as_mut_ptr(),
+            },
+            RDKafkaMetadataPartition {
+                id: 1,
+                err: RDKafkaRespErr::RD_KAFKA_RESP_ERR_LEADER_NOT_AVAILABLE,
+                leader: -1,
+                replica_cnt: 1,
+                replicas: replicas1.as_mut_ptr(),
+                isr_cnt: 0,
+                isrs: isrs1.as_mut_ptr(),
+            },
+        ];
+        let mut topics = [RDKafkaMetadataTopic {
+            topic: topic_name.as_ptr() as *mut _,
+            partition_cnt: 2,
+            partitions: partitions.as_mut_ptr(),
+            err: RDKafkaRespErr::RD_KAFKA_RESP_ERR_NO_ERROR,
+        }];
+        let native = RDKafkaMetadata {
+            broker_cnt: 1,
+            brokers: brokers.as_mut_ptr(),
+            topic_cnt: 1,
+            topics: topics.as_mut_ptr(),
+            orig_broker_id: 1,
+            orig_broker_name: host.as_ptr() as *mut _,
+        };
+
+        let metadata = unsafe { Metadata::from_ptr(&native) };
+        let owned = metadata.detach();
+        // The native metadata is not allocated by librdkafka.
+        mem::forget(metadata);
+
+        assert_eq!(owned.orig_broker_id(), 1);
+        assert_eq!(owned.orig_broker_name(), "broker1");
+        assert_eq!(owned.brokers().len(), 1);
+        assert_eq!(owned.brokers()[0].id(), 1);
+        assert_eq!(owned.brokers()[0].host(), "broker1");
+        assert_eq!(owned.brokers()[0].port(), 9092);
+
+        let topic = &owned.topics()[0];
+        assert_eq!(topic.name(), "topic1");
+        assert_eq!(topic.error(), None);
+        let partition0 = &topic.partitions()[0];
+        assert_eq!(partition0.id(), 0);
+        assert_eq!(partition0.leader(), 2);
+        assert_eq!(partition0.replicas(), &[1, 2]);
+        assert_eq!(partition0.isr(), &[2]);
+        assert_eq!(partition0.error(), None);
+        let partition1 = &topic.partitions()[1];
+        assert_eq!(partition1.id(), 1);
+        assert_eq!(partition1.leader(), -1);
+        assert_eq!(partition1.replicas(), &[2]);
+        assert!(partition1.isr().is_empty());
+        assert_eq!(
+            partition1.error(),
+            Some(RDKafkaErrorCode::LeaderNotAvailable)
+        );
+
+        assert_eq!(owned.clone(), owned);
+    }
+
+    fn test_metadata(orig_broker_id: i32) -> OwnedMetadata {
+        OwnedMetadata {
+            orig_broker_id,
+            orig_broker_name: "broker1".to_owned(),
+            brokers: Vec::new(),
+            topics: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_cached_metadata() {
+        let fetches = Cell::new(0);
+        let fail = Cell::new(false);
+        let cache = CachedMetadata::new(|timeout| {
+            assert_eq!(timeout, Timeout::After(Duration::from_secs(1)));
+            if fail.get() {
+                return Err(KafkaError::MetadataFetch(
+                    RDKafkaErrorCode::OperationTimedOut,
+                ));
+            }
+            fetches.set(fetches.get() + 1);
+            Ok(test_metadata(fetches.get()))
+        });
+        let max_age = Duration::from_secs(10);
+        let timeout = Timeout::After(Duration::from_secs(1));
+        let start = Instant::now();
+
+        let metadata = cache.metadata_at(start, max_age, timeout).unwrap();
+        assert_eq!(metadata.orig_broker_id(), 1);
+        // A second call within the maximum age doesn't fetch the metadata again.
+        let metadata = cache
+            .metadata_at(start + Duration::from_secs(9), max_age, timeout)
+            .unwrap();
+        assert_eq!(metadata.orig_broker_id(), 1);
+        assert_eq!(fetches.get(), 1);
+
+        let metadata = cache
+            .metadata_at(start + Duration::from_secs(10), max_age, timeout)
+            .unwrap();
+        assert_eq!(metadata.orig_broker_id(), 2);
+
+        cache.invalidate();
+        let metadata = cache
+            .metadata_at(start + Duration::from_secs(11), max_age, timeout)
+            .unwrap();
+        assert_eq!(metadata.orig_broker_id(), 3);
+
+        // A failed fetch keeps the cached metadata.
+        fail.set(true);
+        assert!(cache
+            .metadata_at(
+                start + Duration::from_secs(12),
+                Duration::from_secs(0),
+                timeout
+            )
+            .is_err());
+        fail.set(false);
+        let metadata = cache
+            .metadata_at(start + Duration::from_secs(13), max_age, timeout)
+            .unwrap();
+        assert_eq!(metadata.orig_broker_id(), 3);
+        assert_eq!(fetches.get(), 3);
+    }
+}
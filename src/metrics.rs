@@ -0,0 +1,407 @@
+//! Prometheus metrics exported from the librdkafka statistics.
+//!
+//! This module is only available if the `metrics` feature is enabled. A
+//! [`StatisticsRecorder`](struct.StatisticsRecorder.html) updates a set of Prometheus metrics,
+//! labeled by client, broker, topic and partition, every time it receives new statistics.
+//! Statistics are emitted by librdkafka only if the `statistics.interval.ms` configuration
+//! parameter is set.
+//!
+//! ```rust,no_run
+//! use prometheus::Registry;
+//! use rdkafka::config::ClientConfig;
+//! use rdkafka::consumer::{BaseConsumer, ConsumerContext};
+//! use rdkafka::metrics::StatisticsRecorder;
+//! use rdkafka::{ClientContext, Statistics};
+//!
+//! struct MetricsContext {
+//!     recorder: StatisticsRecorder,
+//! }
+//!
+//! impl ClientContext for MetricsContext {
+//!     fn stats(&self, statistics: Statistics) {
+//!         self.recorder.record(&statistics);
+//!     }
+//! }
+//!
+//! impl ConsumerContext for MetricsContext {}
+//!
+//! let registry = Registry::new();
+//! let recorder = StatisticsRecorder::new().unwrap();
+//! recorder.register(&registry).unwrap();
+//! let consumer: BaseConsumer<MetricsContext> = ClientConfig::new()
+//!     .set("group.id", "my-group")
+//!     .set("statistics.interval.ms", "5000")
+//!     .create_with_context(MetricsContext { recorder })
+//!     .unwrap();
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use prometheus::{GaugeVec, IntCounterVec, IntGaugeVec, Opts, Registry};
+
+use crate::statistics::Statistics;
+
+const CLIENT_LABELS: &[&str] = &["client"];
+const BROKER_LABELS: &[&str] = &["client", "broker"];
+const PARTITION_LABELS: &[&str] = &["client", "topic", "partition"];
+
+/// The brokers and partitions that appeared in the last statistics of a client.
+#[derive(Default)]
+struct KnownLabels {
+    brokers: HashSet<String>,
+    partitions: HashSet<(String, i32)>,
+}
+
+/// Updates Prometheus metrics using the statistics of one or more clients.
+///
+/// The metrics of brokers and partitions that are missing from the latest statistics of a client
+/// are removed, so that metrics don't linger after a partition is revoked or a broker leaves
+/// the cluster.
+pub struct StatisticsRecorder {
+    replyq: IntGaugeVec,
+    msg_cnt: IntGaugeVec,
+    msg_size: IntGaugeVec,
+    broker_outbuf_cnt: IntGaugeVec,
+    broker_waitresp_cnt: IntGaugeVec,
+    broker_rtt_avg: GaugeVec,
+    broker_tx: IntCounterVec,
+    broker_rx: IntCounterVec,
+    broker_txerrs: IntCounterVec,
+    partition_msgq_cnt: IntGaugeVec,
+    partition_fetchq_cnt: IntGaugeVec,
+    partition_consumer_lag: IntGaugeVec,
+    known_labels: Mutex<HashMap<String, KnownLabels>>,
+}
+
+impl StatisticsRecorder {
+    /// Creates a new recorder. The metrics have to be registered with `register` to be exported.
+    pub fn new() -> prometheus::Result<StatisticsRecorder> {
+        Ok(StatisticsRecorder {
+            replyq: int_gauge_vec(
+                "rdkafka_client_replyq",
+                "Number of operations waiting to be served by poll",
+                CLIENT_LABELS,
+            )?,
+            msg_cnt: int_gauge_vec(
+                "rdkafka_client_msg_cnt",
+                "Number of messages in the producer queues",
+                CLIENT_LABELS,
+            )?,
+            msg_size: int_gauge_vec(
+                "rdkafka_client_msg_size",
+                "Size in bytes of the messages in the producer queues",
+                CLIENT_LABELS,
+            )?,
+            broker_outbuf_cnt: int_gauge_vec(
+                "rdkafka_broker_outbuf_cnt",
+                "Number of requests awaiting transmission to the broker",
+                BROKER_LABELS,
+            )?,
+            broker_waitresp_cnt: int_gauge_vec(
+                "rdkafka_broker_waitresp_cnt",
+                "Number of requests in flight to the broker, awaiting a response",
+                BROKER_LABELS,
+            )?,
+            broker_rtt_avg: GaugeVec::new(
+                Opts::new(
+                    "rdkafka_broker_rtt_avg_seconds",
+                    "Average round-trip time to the broker",
+                ),
+                BROKER_LABELS,
+            )?,
+            broker_tx: int_counter_vec(
+                "rdkafka_broker_tx_total",
+                "Total number of requests sent to the broker",
+                BROKER_LABELS,
+            )?,
+            broker_rx: int_counter_vec(
+                "rdkafka_broker_rx_total",
+                "Total number of responses received from the broker",
+                BROKER_LABELS,
+            )?,
+            broker_txerrs: int_counter_vec(
+                "rdkafka_broker_txerrs_total",
+                "Total number of transmission errors",
+                BROKER_LABELS,
+            )?,
+            partition_msgq_cnt: int_gauge_vec(
+                "rdkafka_partition_msgq_cnt",
+                "Number of messages waiting to be produced in the partition queue",
+                PARTITION_LABELS,
+            )?,
+            partition_fetchq_cnt: int_gauge_vec(
+                "rdkafka_partition_fetchq_cnt",
+                "Number of pre-fetched messages in the partition fetch queue",
+                PARTITION_LABELS,
+            )?,
+            partition_consumer_lag: int_gauge_vec(
+                "rdkafka_partition_consumer_lag",
+                "Difference between the high watermark and the committed offset, or -1 if unknown",
+                PARTITION_LABELS,
+            )?,
+            known_labels: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Registers all the metrics of the recorder in the provided registry.
+    pub fn register(&self, registry: &Registry) -> prometheus::Result<()> {
+        registry.register(Box::new(self.replyq.clone()))?;
+        registry.register(Box::new(self.msg_cnt.clone()))?;
+        registry.register(Box::new(self.msg_size.clone()))?;
+        registry.register(Box::new(self.broker_outbuf_cnt.clone()))?;
+        registry.register(Box::new(self.broker_waitresp_cnt.clone()))?;
+        registry.register(Box::new(self.broker_rtt_avg.clone()))?;
+        registry.register(Box::new(self.broker_tx.clone()))?;
+        registry.register(Box::new(self.broker_rx.clone()))?;
+        registry.register(Box::new(self.broker_txerrs.clone()))?;
+        registry.register(Box::new(self.partition_msgq_cnt.clone()))?;
+        registry.register(Box::new(self.partition_fetchq_cnt.clone()))?;
+        registry.register(Box::new(self.partition_consumer_lag.clone()))?;
+        Ok(())
+    }
+
+    /// Updates the metrics using the provided statistics.
+    pub fn record(&self, statistics: &Statistics) {
+        let client = statistics.name.as_str();
+        self.replyq
+            .with_label_values(&[client])
+            .set(statistics.replyq);
+        self.msg_cnt
+            .with_label_values(&[client])
+            .set(statistics.msg_cnt);
+        self.msg_size
+            .with_label_values(&[client])
+            .set(statistics.msg_size);
+
+        let mut labels = KnownLabels::default();
+        for (name, broker) in &statistics.brokers {
+            let values = [client, name.as_str()];
+            self.broker_outbuf_cnt
+                .with_label_values(&values)
+                .set(broker.outbuf_cnt);
+            self.broker_waitresp_cnt
+                .with_label_values(&values)
+                .set(broker.waitresp_cnt);
+            if let Some(ref rtt) = broker.rtt {
+                // librdkafka reports latencies in microseconds.
+                self.broker_rtt_avg
+                    .with_label_values(&values)
+                    .set(rtt.avg as f64 / 1_000_000.0);
+            }
+            update_counter(&self.broker_tx, &values, broker.tx);
+            update_counter(&self.broker_rx, &values, broker.rx);
+            update_counter(&self.broker_txerrs, &values, broker.txerrs);
+            labels.brokers.insert(name.clone());
+        }
+
+        for (topic_name, topic) in &statistics.topics {
+            for partition in topic.partitions.values() {
+                // Skip the internal partition used for messages that are not assigned yet.
+                if partition.partition < 0 {
+                    continue;
+                }
+                let partition_id = partition.partition.to_string();
+                let values = [client, topic_name.as_str(), partition_id.as_str()];
+                self.partition_msgq_cnt
+                    .with_label_values(&values)
+                    .set(partition.msgq_cnt);
+                self.partition_fetchq_cnt
+                    .with_label_values(&values)
+                    .set(partition.fetchq_cnt);
+                self.partition_consumer_lag
+                    .with_label_values(&values)
+                    .set(partition.consumer_lag);
+                labels
+                    .partitions
+                    .insert((topic_name.clone(), partition.partition));
+            }
+        }
+
+        let mut known_labels = self.known_labels.lock().unwrap();
+        if let Some(previous) = known_labels.get(client) {
+            for broker in previous.brokers.difference(&labels.brokers) {
+                self.remove_broker(client, broker);
+            }
+            for &(ref topic, partition) in previous.partitions.difference(&labels.partitions) {
+                self.remove_partition(client, topic, partition);
+            }
+        }
+        known_labels.insert(client.to_owned(), labels);
+    }
+
+    fn remove_broker(&self, client: &str, broker: &str) {
+        let values = [client, broker];
+        // Errors are ignored, as they only mean that the metric was never set.
+        let _ = self.broker_outbuf_cnt.remove_label_values(&values);
+        let _ = self.broker_waitresp_cnt.remove_label_values(&values);
+        let _ = self.broker_rtt_avg.remove_label_values(&values);
+        let _ = self.broker_tx.remove_label_values(&values);
+        let _ = self.broker_rx.remove_label_values(&values);
+        let _ = self.broker_txerrs.remove_label_values(&values);
+    }
+
+    fn remove_partition(&self, client: &str, topic: &str, partition: i32) {
+        let partition_id = partition.to_string();
+        let values = [client, topic, partition_id.as_str()];
+        let _ = self.partition_msgq_cnt.remove_label_values(&values);
+        let _ = self.partition_fetchq_cnt.remove_label_values(&values);
+        let _ = self.partition_consumer_lag.remove_label_values(&values);
+    }
+}
+
+fn int_gauge_vec(name: &str, help: &str, labels: &[&str]) -> prometheus::Result<IntGaugeVec> {
+    IntGaugeVec::new(Opts::new(name, help), labels)
+}
+
+fn int_counter_vec(name: &str, help: &str, labels: &[&str]) -> prometheus::Result<IntCounterVec> {
+    IntCounterVec::new(Opts::new(name, help), labels)
+}
+
+/// Brings the counter up to the total reported by librdkafka. Counters can't decrease, so a lower
+/// total is ignored.
+fn update_counter(counter_vec: &IntCounterVec, values: &[&str], total: i64) {
+    let counter = counter_vec.with_label_values(values);
+    let current = counter.get() as i64;
+    if total > current {
+        counter.inc_by((total - current) as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+
+    fn broker_json(name: &str, tx: i64, rtt_avg: i64) -> String {
+        format!(
+            r#"{{
+                "name": "{}", "nodeid": 1, "state": "UP", "stateage": 0,
+                "outbuf_cnt": 2, "outbuf_msg_cnt": 0, "waitresp_cnt": 1, "waitresp_msg_cnt": 0,
+                "tx": {}, "txbytes": 0, "txerrs": 0, "txretries": 0, "req_timeouts": 0,
+                "rx": 0, "rxbytes": 0, "rxerrs": 0, "rxcorriderrs": 0, "rxpartial": 0,
+                "rtt": {{"min": 0, "max": 0, "avg": {}, "sum": 0, "cnt": 0}},
+                "toppars": {{}}
+            }}"#,
+            name, tx, rtt_avg
+        )
+    }
+
+    fn partition_json(partition: i32, consumer_lag: i64) -> String {
+        format!(
+            r#"{{
+                "partition": {}, "leader": 1, "desired": true, "unknown": false,
+                "msgq_cnt": 0, "msgq_bytes": 0, "xmit_msgq_cnt": 0, "xmit_msgq_bytes": 0,
+                "fetchq_cnt": 3, "fetchq_size": 0, "fetch_state": "active",
+                "query_offset": 0, "next_offset": 0, "app_offset": 0, "stored_offset": 0,
+                "committed_offset": 0, "eof_offset": 0, "lo_offset": 0, "hi_offset": 0,
+                "consumer_lag": {}, "txmsgs": 0, "txbytes": 0, "msgs": 0, "rx_ver_drops": 0
+            }}"#,
+            partition, consumer_lag
+        )
+    }
+
+    fn statistics(brokers: &[(&str, i64, i64)], partitions: &[(i32, i64)]) -> Statistics {
+        let brokers = brokers
+            .iter()
+            .map(|&(name, tx, rtt)| format!(r#""{}": {}"#, name, broker_json(name, tx, rtt)))
+            .collect::<Vec<_>>()
+            .join(",");
+        let partitions = partitions
+            .iter()
+            .map(|&(partition, lag)| {
+                format!(r#""{}": {}"#, partition, partition_json(partition, lag))
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let json = format!(
+            r#"{{
+                "name": "consumer-1", "type": "consumer", "ts": 0, "time": 0, "replyq": 4,
+                "msg_cnt": 0, "msg_size": 0, "msg_max": 0, "msg_size_max": 0,
+                "brokers": {{{}}},
+                "topics": {{
+                    "topic1": {{"topic": "topic1", "metadata_age": 0, "partitions": {{{}}}}}
+                }}
+            }}"#,
+            brokers, partitions
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    fn gauge_values(registry: &Registry, name: &str) -> Vec<(Vec<String>, f64)> {
+        let families = registry.gather();
+        let family = match families.iter().find(|family| family.get_name() == name) {
+            Some(family) => family,
+            None => return Vec::new(),
+        };
+        let mut values = family
+            .get_metric()
+            .iter()
+            .map(|metric| {
+                let labels: Vec<String> = metric
+                    .get_label()
+                    .iter()
+                    .map(|label| label.get_value().to_owned())
+                    .collect();
+                (labels, metric.get_gauge().get_value())
+            })
+            .collect::<Vec<_>>();
+        values.sort_by(|a, b| a.0.cmp(&b.0));
+        values
+    }
+
+    fn labels(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| (*value).to_owned()).collect()
+    }
+
+    #[test]
+    fn test_statistics_recorder() {
+        let registry = Registry::new();
+        let recorder = StatisticsRecorder::new().unwrap();
+        recorder.register(&registry).unwrap();
+
+        recorder.record(&statistics(
+            &[("broker1:9092/1", 10, 2000), ("broker2:9092/2", 5, 1000)],
+            &[(-1, -1), (0, 7), (1, 9)],
+        ));
+        assert_eq!(
+            gauge_values(&registry, "rdkafka_client_replyq"),
+            vec![(labels(&["consumer-1"]), 4.0)]
+        );
+        assert_eq!(
+            gauge_values(&registry, "rdkafka_broker_rtt_avg_seconds"),
+            vec![
+                (labels(&["broker1:9092/1", "consumer-1"]), 0.002),
+                (labels(&["broker2:9092/2", "consumer-1"]), 0.001),
+            ]
+        );
+        // Labels are sorted by name: client, partition, topic.
+        assert_eq!(
+            gauge_values(&registry, "rdkafka_partition_consumer_lag"),
+            vec![
+                (labels(&["consumer-1", "0", "topic1"]), 7.0),
+                (labels(&["consumer-1", "1", "topic1"]), 9.0),
+            ]
+        );
+        let tx = recorder
+            .broker_tx
+            .with_label_values(&["consumer-1", "broker1:9092/1"]);
+        assert_eq!(tx.get(), 10);
+
+        // Partition 1 and broker 2 disappear from the second snapshot.
+        recorder.record(&statistics(&[("broker1:9092/1", 15, 3000)], &[(0, 2)]));
+        assert_eq!(
+            gauge_values(&registry, "rdkafka_broker_rtt_avg_seconds"),
+            vec![(labels(&["broker1:9092/1", "consumer-1"]), 0.003)]
+        );
+        assert_eq!(
+            gauge_values(&registry, "rdkafka_partition_consumer_lag"),
+            vec![(labels(&["consumer-1", "0", "topic1"]), 2.0)]
+        );
+        assert_eq!(
+            gauge_values(&registry, "rdkafka_partition_fetchq_cnt"),
+            vec![(labels(&["consumer-1", "0", "topic1"]), 3.0)]
+        );
+        assert_eq!(tx.get(), 15);
+    }
+}
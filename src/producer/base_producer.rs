@@ -21,7 +21,7 @@
 //! ### Calling poll
 //!
 //! To execute delivery callbacks the `poll` method of the producer should be called regularly.
-//! If `poll` is not called, or not often enough, a `RDKafkaError::QueueFull` error will be returned.
+//! If `poll` is not called, or not often enough, a `RDKafkaErrorCode::QueueFull` error will be returned.
 //!
 //! ## `ThreadedProducer`
 //! The `ThreadedProducer` is a wrapper around the `BaseProducer` which spawns a thread
@@ -29,7 +29,7 @@
 //! have to. The thread is started when the producer is created, and it will be terminated
 //! once the producer goes out of scope.
 //!
-//! A `RDKafkaError::QueueFull` error can still be returned in case the polling thread is not
+//! A `RDKafkaErrorCode::QueueFull` error can still be returned in case the polling thread is not
 //! fast enough or Kafka is not able to receive data and acknowledge messages quickly enough.
 //! If this error is returned, the producer should wait and try again.
 //!
@@ -38,18 +38,27 @@ use crate::rdsys;
 use crate::rdsys::rd_kafka_vtype_t::*;
 use crate::rdsys::types::*;
 
-use crate::client::{Client, ClientContext};
-use crate::config::{ClientConfig, FromClientConfig, FromClientConfigAndContext};
-use crate::error::{IsError, KafkaError, KafkaResult};
-use crate::message::{BorrowedMessage, OwnedHeaders, ToBytes};
-use crate::util::{timeout_to_ms, IntoOpaque};
+use crate::client::{Client, ClientContext, DefaultClientContext, NativeTopic, OAuthToken};
+use crate::config::{ClientConfig, FromClientConfig, FromClientConfigAndContext, RDKafkaLogLevel};
+use crate::error::{IsError, KafkaError, KafkaResult, RDKafkaErrorCode};
+use crate::message::{BorrowedMessage, OwnedHeaders, OwnedMessage, ToBytes};
+use crate::statistics::Statistics;
+use crate::util::{timeout_to_ms, IntoOpaque, Timeout};
 
-use std::ffi::CString;
+use futures::sync::mpsc;
+use futures::{Poll, Stream};
+
+use std::cmp;
+use std::error;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::marker::PhantomData;
 use std::mem;
 use std::os::raw::c_void;
 use std::ptr;
+use std::slice;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread::{self, JoinHandle};
 use std::time::Duration;
 
@@ -65,14 +74,52 @@ pub use crate::message::DeliveryResult;
 pub trait ProducerContext: ClientContext {
     /// A `DeliveryOpaque` is a user-defined structure that will be passed to the producer when
     /// producing a message, and returned to the `delivery` method once the message has been
-    /// delivered, or failed to.
+    /// delivered, or failed to. The opaque of a message that can't be enqueued is returned with
+    /// its record by `send`. The messages purged, including the ones still pending when the last
+    /// clone of the producer is dropped, are reported as failed, so every opaque is eventually
+    /// handed back and dropped.
     type DeliveryOpaque: IntoOpaque;
 
     /// This method will be called once the message has been delivered (or failed to). The
     /// `DeliveryOpaque` will be the one provided by the user when calling send.
     fn delivery(&self, delivery_result: &DeliveryResult, delivery_opaque: Self::DeliveryOpaque);
+
+    /// Returns true if the context provides a custom partitioner, in which case the `partition`
+    /// method will be used to assign a partition to the messages that don't specify one.
+    /// Otherwise (the default), the partitioner configured with the `partitioner` parameter is
+    /// used.
+    fn has_custom_partitioner(&self) -> bool {
+        false
+    }
+
+    /// Returns the partition a message should be produced to, given its topic, its optional key
+    /// and the number of partitions of the topic. Only called if `has_custom_partitioner` returns
+    /// true. The returned partition must be between 0 and `partition_cnt - 1`, otherwise a
+    /// warning is logged and the partition is chosen by the default partitioner of librdkafka
+    /// instead, hashing the key, or picking a random partition for messages without a key.
+    fn partition(&self, _topic: &str, _key: Option<&[u8]>, _partition_cnt: i32) -> i32 {
+        PARTITION_UA
+    }
+
+    /// This method is called with every record sent by the producer, before it is enqueued. The
+    /// context can inspect the record, and add or replace its headers, for example to propagate
+    /// a trace id; the changes to the headers are reflected in the produced message. The default
+    /// implementation does nothing.
+    ///
+    /// The hook is called again every time the record is sent: if the record can't be enqueued,
+    /// it is returned to the caller with its original headers, so a retry doesn't duplicate the
+    /// headers added by the hook. The `FutureProducer` and the `DeliveryStreamContext` only wrap
+    /// a `ClientContext`, so this hook is not available for them.
+    fn on_send(&self, _record: &mut InterceptedRecord) {}
 }
 
+/// The partition value used by librdkafka for unassigned partitions.
+const PARTITION_UA: i32 = -1;
+
+/// The maximum number of times `BaseProducer::send_batch` retries to send a record when the
+/// producer queue is full.
+pub const SEND_BATCH_QUEUE_FULL_RETRIES: usize = 10;
+
 /// Default producer context that can be use when a custom context is not required.
 #[derive(Clone)]
 pub struct DefaultProducerContext;
@@ -84,6 +131,90 @@ impl ProducerContext for DefaultProducerContext {
     fn delivery(&self, _: &DeliveryResult, _: Self::DeliveryOpaque) {}
 }
 
+/// The owned result of the delivery of a message, as reported by the `DeliveryStream`. If the
+/// delivery failed, the error is returned together with the message.
+pub type OwnedDeliveryResult = Result<OwnedMessage, (KafkaError, OwnedMessage)>;
+
+/// A `ProducerContext` that forwards every delivery report, together with its `DeliveryOpaque`,
+/// to a [DeliveryStream]. The stream can be retrieved with
+/// [BaseProducer::delivery_report_stream]. All the other callbacks are delegated to the wrapped
+/// `ClientContext`.
+pub struct DeliveryStreamContext<D: IntoOpaque, C: ClientContext = DefaultClientContext> {
+    wrapped_context: C,
+    sender: mpsc::UnboundedSender<(OwnedDeliveryResult, D)>,
+    receiver: Mutex<Option<mpsc::UnboundedReceiver<(OwnedDeliveryResult, D)>>>,
+}
+
+impl<D: IntoOpaque, C: ClientContext> DeliveryStreamContext<D, C> {
+    /// Creates a new `DeliveryStreamContext` wrapping the provided `ClientContext`.
+    pub fn new(wrapped_context: C) -> DeliveryStreamContext<D, C> {
+        let (sender, receiver) = mpsc::unbounded();
+        DeliveryStreamContext {
+            wrapped_context,
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+        }
+    }
+}
+
+// Delegates all the methods calls to the wrapped context.
+impl<D: IntoOpaque, C: ClientContext> ClientContext for DeliveryStreamContext<D, C> {
+    fn log(&self, level: RDKafkaLogLevel, fac: &str, log_message: &str) {
+        self.wrapped_context.log(level, fac, log_message);
+    }
+
+    fn stats(&self, statistics: Statistics) {
+        self.wrapped_context.stats(statistics);
+    }
+
+    fn stats_raw(&self, statistics: &[u8]) {
+        self.wrapped_context.stats_raw(statistics);
+    }
+
+    fn error(&self, error: KafkaError, reason: &str) {
+        self.wrapped_context.error(error, reason);
+    }
+
+    const ENABLE_REFRESH_OAUTH_TOKEN: bool = C::ENABLE_REFRESH_OAUTH_TOKEN;
+
+    fn generate_oauth_token(&self, oauthbearer_config: Option<&str>) -> Result<OAuthToken, String> {
+        self.wrapped_context
+            .generate_oauth_token(oauthbearer_config)
+    }
+}
+
+impl<D: IntoOpaque, C: ClientContext> ProducerContext for DeliveryStreamContext<D, C> {
+    type DeliveryOpaque = D;
+
+    fn delivery(&self, delivery_result: &DeliveryResult, delivery_opaque: D) {
+        let owned_delivery_result = match *delivery_result {
+            Ok(ref message) => Ok(message.detach()),
+            Err((ref error, ref message)) => Err((error.clone(), message.detach())),
+        };
+        // The stream might have been dropped, in which case the report is discarded.
+        let _ = self
+            .sender
+            .unbounded_send((owned_delivery_result, delivery_opaque));
+    }
+}
+
+/// A stream of the delivery reports of a producer created with a [DeliveryStreamContext]. Each
+/// item contains the result of the delivery and the `DeliveryOpaque` provided with the record.
+/// The stream completes once the producer is dropped.
+#[must_use = "Streams do nothing unless polled"]
+pub struct DeliveryStream<D> {
+    receiver: mpsc::UnboundedReceiver<(OwnedDeliveryResult, D)>,
+}
+
+impl<D> Stream for DeliveryStream<D> {
+    type Item = (OwnedDeliveryResult, D);
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.receiver.poll()
+    }
+}
+
 /// Callback that gets called from librdkafka every time a message succeeds or fails to be
 /// delivered.
 unsafe extern "C" fn delivery_cb<C: ProducerContext>(
@@ -106,6 +237,46 @@ unsafe extern "C" fn delivery_cb<C: ProducerContext>(
     }
 }
 
+/// Callback that gets called from librdkafka to assign a partition to a message, when the context
+/// provides a custom partitioner.
+unsafe extern "C" fn partitioner_cb<C: ProducerContext>(
+    topic: *const RDKafkaTopic,
+    key_data: *const c_void,
+    key_len: usize,
+    partition_cnt: i32,
+    topic_opaque: *mut c_void,
+    msg_opaque: *mut c_void,
+) -> i32 {
+    // librdkafka doesn't call the partitioner before the partitions of the topic are known.
+    if partition_cnt <= 0 {
+        return PARTITION_UA;
+    }
+    let producer_context = &*(topic_opaque as *const C);
+    let topic_name = CStr::from_ptr(rdsys::rd_kafka_topic_name(topic)).to_string_lossy();
+    let key = if key_data.is_null() {
+        None
+    } else {
+        Some(slice::from_raw_parts(key_data as *const u8, key_len))
+    };
+    let partition = producer_context.partition(&topic_name, key, partition_cnt);
+    if partition >= 0 && partition < partition_cnt {
+        partition
+    } else {
+        warn!(
+            "Invalid partition {} for topic {} with {} partitions, using the default partitioner",
+            partition, topic_name, partition_cnt
+        );
+        rdsys::rd_kafka_msg_partitioner_consistent_random(
+            topic,
+            key_data,
+            key_len,
+            partition_cnt,
+            topic_opaque,
+            msg_opaque,
+        )
+    }
+}
+
 //
 // ********** BASE PRODUCER **********
 //
@@ -153,6 +324,9 @@ pub struct BaseRecord<
     pub partition: Option<i32>,
     /// Optional payload
     pub payload: Option<&'a P>,
+    /// Optional payload made of several buffers, which are concatenated. If set, it takes
+    /// precedence over `payload`.
+    pub payload_slices: Option<&'a [&'a [u8]]>,
     /// Optional key
     pub key: Option<&'a K>,
     /// Optional timestamp
@@ -170,6 +344,7 @@ impl<'a, K: ToBytes + ?Sized, P: ToBytes + ?Sized, D: IntoOpaque> BaseRecord<'a,
             topic,
             partition: None,
             payload: None,
+            payload_slices: None,
             key: None,
             timestamp: None,
             headers: None,
@@ -189,13 +364,22 @@ impl<'a, K: ToBytes + ?Sized, P: ToBytes + ?Sized, D: IntoOpaque> BaseRecord<'a,
         self
     }
 
+    /// Set the payload of the record as the concatenation of several buffers. The buffers are
+    /// copied once, directly into the memory handed over to librdkafka, so there is no need to
+    /// assemble them beforehand. It takes precedence over the payload set with `payload`.
+    pub fn payload_slices(mut self, slices: &'a [&'a [u8]]) -> BaseRecord<'a, K, P, D> {
+        self.payload_slices = Some(slices);
+        self
+    }
+
     /// Set the key of the record.
     pub fn key(mut self, key: &'a K) -> BaseRecord<'a, K, P, D> {
         self.key = Some(key);
         self
     }
 
-    /// Set the timestamp of the record.
+    /// Set the timestamp of the record, in milliseconds since the epoch. The timestamp is sent
+    /// as the `CreateTime` of the message. If not set, librdkafka uses the current time.
     pub fn timestamp(mut self, timestamp: i64) -> BaseRecord<'a, K, P, D> {
         self.timestamp = Some(timestamp);
         self
@@ -208,6 +392,61 @@ impl<'a, K: ToBytes + ?Sized, P: ToBytes + ?Sized, D: IntoOpaque> BaseRecord<'a,
     }
 }
 
+/// A record about to be sent, as seen by `ProducerContext::on_send`. Only its headers can be
+/// modified.
+#[derive(Debug)]
+pub struct InterceptedRecord<'a> {
+    topic: &'a str,
+    partition: Option<i32>,
+    key: Option<&'a [u8]>,
+    payload: Option<&'a [u8]>,
+    timestamp: Option<i64>,
+    headers: Option<OwnedHeaders>,
+}
+
+impl<'a> InterceptedRecord<'a> {
+    /// Returns the destination topic of the record.
+    pub fn topic(&self) -> &str {
+        self.topic
+    }
+
+    /// Returns the destination partition of the record, if set.
+    pub fn partition(&self) -> Option<i32> {
+        self.partition
+    }
+
+    /// Returns the key of the record, if any.
+    pub fn key(&self) -> Option<&[u8]> {
+        self.key
+    }
+
+    /// Returns the payload of the record, if any.
+    pub fn payload(&self) -> Option<&[u8]> {
+        self.payload
+    }
+
+    /// Returns the timestamp of the record, if set.
+    pub fn timestamp(&self) -> Option<i64> {
+        self.timestamp
+    }
+
+    /// Returns the headers of the record, if any.
+    pub fn headers(&self) -> Option<&OwnedHeaders> {
+        self.headers.as_ref()
+    }
+
+    /// Removes the headers of the record and returns them, so that they can be extended and set
+    /// again with `set_headers`.
+    pub fn take_headers(&mut self) -> Option<OwnedHeaders> {
+        self.headers.take()
+    }
+
+    /// Sets the headers of the record, replacing the previous ones.
+    pub fn set_headers(&mut self, headers: OwnedHeaders) {
+        self.headers = Some(headers);
+    }
+}
+
 impl<'a, K: ToBytes + ?Sized, P: ToBytes + ?Sized> BaseRecord<'a, K, P, ()> {
     /// Create a new record with the specified topic name.
     pub fn to(topic: &'a str) -> BaseRecord<'a, K, P, ()> {
@@ -215,6 +454,7 @@ impl<'a, K: ToBytes + ?Sized, P: ToBytes + ?Sized> BaseRecord<'a, K, P, ()> {
             topic,
             partition: None,
             payload: None,
+            payload_slices: None,
             key: None,
             timestamp: None,
             headers: None,
@@ -223,6 +463,68 @@ impl<'a, K: ToBytes + ?Sized, P: ToBytes + ?Sized> BaseRecord<'a, K, P, ()> {
     }
 }
 
+/// A handle to a topic of a `BaseProducer`, returned by `BaseProducer::topic`. It can't outlive
+/// the producer that created it.
+pub struct Topic<'p> {
+    native: NativeTopic,
+    name: String,
+    client_ptr: *mut RDKafka,
+    _producer: PhantomData<&'p ()>,
+}
+
+// The client pointer is only used to check that the handle belongs to the producer.
+unsafe impl<'p> Send for Topic<'p> {}
+unsafe impl<'p> Sync for Topic<'p> {}
+
+impl<'p> Topic<'p> {
+    /// Returns the name of the topic.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<'p> fmt::Debug for Topic<'p> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Topic {{ name: {:?} }}", self.name)
+    }
+}
+
+/// A payload assembled from several buffers, in memory allocated with `malloc` so that its
+/// ownership can be passed to librdkafka. It's freed on drop.
+struct NativePayload {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl NativePayload {
+    fn assemble(slices: &[&[u8]]) -> NativePayload {
+        let len = slices.iter().map(|s| s.len()).sum();
+        // Allocate at least one byte, as malloc may return a null pointer for empty allocations.
+        let ptr = unsafe { libc::malloc(cmp::max(len, 1)) as *mut u8 };
+        assert!(
+            !ptr.is_null(),
+            "Failed to allocate {} bytes for the payload",
+            len
+        );
+        let mut offset = 0;
+        for s in slices {
+            unsafe { ptr::copy_nonoverlapping(s.as_ptr(), ptr.add(offset), s.len()) };
+            offset += s.len();
+        }
+        NativePayload { ptr, len }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for NativePayload {
+    fn drop(&mut self) {
+        unsafe { libc::free(self.ptr as *mut c_void) };
+    }
+}
+
 impl FromClientConfig for BaseProducer<DefaultProducerContext> {
     /// Creates a new `BaseProducer` starting from a configuration.
     fn from_config(config: &ClientConfig) -> KafkaResult<BaseProducer<DefaultProducerContext>> {
@@ -235,11 +537,31 @@ impl<C: ProducerContext> FromClientConfigAndContext<C> for BaseProducer<C> {
     fn from_config_and_context(config: &ClientConfig, context: C) -> KafkaResult<BaseProducer<C>> {
         let native_config = config.create_native_config()?;
         unsafe { rdsys::rd_kafka_conf_set_dr_msg_cb(native_config.ptr(), Some(delivery_cb::<C>)) };
-        let client = Client::new(
+        let mut boxed_context = Box::new(context);
+        if boxed_context.has_custom_partitioner() {
+            // The default topic configuration is replaced, so it has to contain all the
+            // topic-level parameters of the client configuration.
+            let topic_config = config.create_native_topic_config()?;
+            unsafe {
+                rdsys::rd_kafka_topic_conf_set_opaque(
+                    topic_config.ptr(),
+                    (&mut *boxed_context) as *mut C as *mut c_void,
+                );
+                rdsys::rd_kafka_topic_conf_set_partitioner_cb(
+                    topic_config.ptr(),
+                    Some(partitioner_cb::<C>),
+                );
+                rdsys::rd_kafka_conf_set_default_topic_conf(
+                    native_config.ptr(),
+                    topic_config.ptr_move(),
+                );
+            }
+        }
+        let client = Client::new_with_boxed_context(
             config,
             native_config,
             RDKafkaType::RD_KAFKA_PRODUCER,
-            context,
+            boxed_context,
         )?;
         Ok(BaseProducer::from_client(client))
     }
@@ -281,26 +603,56 @@ impl<C: ProducerContext> FromClientConfigAndContext<C> for BaseProducer<C> {
 /// producer.flush(Duration::from_secs(1));
 /// ```
 pub struct BaseProducer<C: ProducerContext = DefaultProducerContext> {
-    client_arc: Arc<Client<C>>,
+    client_arc: Arc<ProducerClient<C>>,
+}
+
+/// The client of a `BaseProducer`, shared by all its clones.
+struct ProducerClient<C: ProducerContext> {
+    client: Client<C>,
+}
+
+impl<C: ProducerContext> Drop for ProducerClient<C> {
+    fn drop(&mut self) {
+        // librdkafka doesn't serve the delivery callbacks of the messages still pending when the
+        // client is destroyed, so their delivery opaques would never be dropped. They are purged
+        // instead, and their delivery callbacks are served with a purge error. This runs once,
+        // when the last clone of the producer is dropped.
+        let native_ptr = self.client.native_ptr();
+        let pending = unsafe { rdsys::rd_kafka_outq_len(native_ptr) };
+        if pending == 0 {
+            return;
+        }
+        trace!("Purging {} pending messages", pending);
+        let flags = PurgeConfig::new().queue().inflight().flags;
+        let ret = unsafe { rdsys::rd_kafka_purge(native_ptr, flags) };
+        if ret.is_error() {
+            warn!(
+                "Failure while purging the producer: {}",
+                KafkaError::Purge(ret.into())
+            );
+            return;
+        }
+        while unsafe { rdsys::rd_kafka_poll(native_ptr, 0) } > 0 {}
+    }
 }
 
 impl<C: ProducerContext> BaseProducer<C> {
     /// Creates a base producer starting from a Client.
     fn from_client(client: Client<C>) -> BaseProducer<C> {
         BaseProducer {
-            client_arc: Arc::new(client),
+            client_arc: Arc::new(ProducerClient { client }),
         }
     }
 
     /// Polls the producer. Regular calls to `poll` are required to process the events
     /// and execute the message delivery callbacks. Returns the number of events served.
-    pub fn poll<T: Into<Option<Duration>>>(&self, timeout: T) -> i32 {
+    pub fn poll<T: Into<Timeout>>(&self, timeout: T) -> i32 {
         unsafe { rdsys::rd_kafka_poll(self.native_ptr(), timeout_to_ms(timeout)) }
     }
 
     /// Returns a pointer to the native Kafka client.
     fn native_ptr(&self) -> *mut RDKafka {
-        self.client_arc.native_ptr()
+        self.client_arc.client.native_ptr()
     }
 
     /// Produce a message to Kafka. Message fields such as key, payload, partition, timestamp etc.
@@ -315,6 +667,11 @@ impl<C: ProducerContext> BaseProducer<C> {
     /// hash of the key. If no key is specified, a random partition will be used. To correctly
     /// handle errors, the delivery callback should be implemented.
     ///
+    /// Records larger than `message.max.bytes` fail to enqueue with
+    /// `RDKafkaErrorCode::MessageSizeTooLarge`, without a round trip to the broker. The size
+    /// checked is the size of the record on the wire, including the key, the headers and the
+    /// protocol overhead.
+    ///
     /// Note that this method will never block.
     // Simplifying the return type requires generic associated types, which are
     // unstable.
@@ -327,24 +684,109 @@ impl<C: ProducerContext> BaseProducer<C> {
         K: ToBytes + ?Sized,
         P: ToBytes + ?Sized,
     {
-        let (payload_ptr, payload_len) = match record.payload.map(P::to_bytes) {
+        self.produce(None, record)
+    }
+
+    /// Returns a handle to a topic, that can be used to send records to the topic with
+    /// `send_to`, without looking it up by name for every record.
+    pub fn topic(&self, name: &str) -> KafkaResult<Topic<'_>> {
+        Ok(Topic {
+            native: self.client_arc.client.native_topic(name)?,
+            name: name.to_owned(),
+            client_ptr: self.native_ptr(),
+            _producer: PhantomData,
+        })
+    }
+
+    /// Same as `send`, but the record is sent to the topic of the handle returned by `topic`, and
+    /// the `topic` field of the record is ignored. Fails with
+    /// `RDKafkaErrorCode::InvalidArgument` if the handle was created by another producer.
+    // The record is returned with the error so that it can be sent again, which makes the error
+    // large.
+    #[allow(clippy::type_complexity, clippy::result_large_err)]
+    pub fn send_to<'a, K, P>(
+        &self,
+        topic: &Topic<'_>,
+        record: BaseRecord<'a, K, P, C::DeliveryOpaque>,
+    ) -> Result<(), (KafkaError, BaseRecord<'a, K, P, C::DeliveryOpaque>)>
+    where
+        K: ToBytes + ?Sized,
+        P: ToBytes + ?Sized,
+    {
+        if topic.client_ptr != self.native_ptr() {
+            let err = KafkaError::MessageProduction(RDKafkaErrorCode::InvalidArgument);
+            return Err((err, record));
+        }
+        self.produce(Some(topic), record)
+    }
+
+    /// Sends a record to the topic of the handle if set, or to the topic named in the record.
+    #[allow(clippy::type_complexity, clippy::result_large_err)]
+    fn produce<'a, K, P>(
+        &self,
+        topic: Option<&Topic<'_>>,
+        record: BaseRecord<'a, K, P, C::DeliveryOpaque>,
+    ) -> Result<(), (KafkaError, BaseRecord<'a, K, P, C::DeliveryOpaque>)>
+    where
+        K: ToBytes + ?Sized,
+        P: ToBytes + ?Sized,
+    {
+        let assembled_payload = record.payload_slices.map(NativePayload::assemble);
+        let payload = match assembled_payload {
+            Some(ref assembled) => Some(assembled.as_slice()),
+            None => record.payload.map(P::to_bytes),
+        };
+        // The hook works on a copy of the headers, so the original ones can be returned with
+        // the record if it can't be enqueued.
+        let mut intercepted = InterceptedRecord {
+            topic: match topic {
+                Some(topic) => &topic.name,
+                None => record.topic,
+            },
+            partition: record.partition,
+            key: record.key.map(K::to_bytes),
+            payload,
+            timestamp: record.timestamp,
+            headers: record.headers.clone(),
+        };
+        self.client_arc.client.context().on_send(&mut intercepted);
+        let headers = intercepted.headers;
+
+        let (payload_ptr, payload_len) = match payload {
             None => (ptr::null_mut(), 0),
             Some(p) => (p.as_ptr() as *mut c_void, p.len()),
         };
+        // An assembled payload is handed over to librdkafka, which frees it once delivered.
+        let msg_flags = if assembled_payload.is_some() {
+            rdsys::RD_KAFKA_MSG_F_FREE
+        } else {
+            rdsys::RD_KAFKA_MSG_F_COPY
+        };
         let (key_ptr, key_len) = match record.key.map(K::to_bytes) {
             None => (ptr::null_mut(), 0),
             Some(k) => (k.as_ptr() as *mut c_void, k.len()),
         };
-        let topic_cstring = CString::new(record.topic.to_owned()).unwrap();
+        // Both the topic name and the topic handle are passed as pointers.
+        let topic_cstring;
+        let (topic_vtype, topic_ptr) = match topic {
+            Some(topic) => (RD_KAFKA_VTYPE_RKT, topic.native.ptr() as *const c_void),
+            None => {
+                topic_cstring = CString::new(record.topic.to_owned()).unwrap();
+                (
+                    RD_KAFKA_VTYPE_TOPIC,
+                    topic_cstring.as_ptr() as *const c_void,
+                )
+            }
+        };
         let produce_error = unsafe {
             rdsys::rd_kafka_producev(
                 self.native_ptr(),
-                RD_KAFKA_VTYPE_TOPIC,
-                topic_cstring.as_ptr(),
+                topic_vtype,
+                topic_ptr,
                 RD_KAFKA_VTYPE_PARTITION,
                 record.partition.unwrap_or(-1),
                 RD_KAFKA_VTYPE_MSGFLAGS,
-                rdsys::RD_KAFKA_MSG_F_COPY as i32,
+                msg_flags as i32,
                 RD_KAFKA_VTYPE_VALUE,
                 payload_ptr,
                 payload_len,
@@ -356,33 +798,124 @@ impl<C: ProducerContext> BaseProducer<C> {
                 RD_KAFKA_VTYPE_TIMESTAMP,
                 record.timestamp.unwrap_or(0),
                 RD_KAFKA_VTYPE_HEADERS,
-                record
-                    .headers
-                    .as_ref()
-                    .map_or(ptr::null_mut(), OwnedHeaders::ptr),
+                headers.as_ref().map_or(ptr::null_mut(), OwnedHeaders::ptr),
                 RD_KAFKA_VTYPE_END,
             )
         };
         if produce_error.is_error() {
             Err((KafkaError::MessageProduction(produce_error.into()), record))
         } else {
-            // The kafka producer now owns the delivery opaque and the headers
+            // The kafka producer now owns the delivery opaque, the headers and the assembled
+            // payload
             mem::forget(record.delivery_opaque);
-            mem::forget(record.headers);
+            mem::forget(headers);
+            mem::forget(assembled_payload);
             Ok(())
         }
     }
 
+    /// Sends a batch of records, returning the index and the error of each record that could not
+    /// be enqueued. When the producer queue is full, the producer is polled to serve the delivery
+    /// callbacks and make room in the queue, and the record is retried up to
+    /// `SEND_BATCH_QUEUE_FULL_RETRIES` times before being reported as failed. A failure doesn't
+    /// prevent the following records from being sent.
+    pub fn send_batch<'a, K, P, I>(&self, records: I) -> Vec<(usize, KafkaError)>
+    where
+        K: ToBytes + ?Sized + 'a,
+        P: ToBytes + ?Sized + 'a,
+        I: IntoIterator<Item = BaseRecord<'a, K, P, C::DeliveryOpaque>>,
+    {
+        let mut failures = Vec::new();
+        for (idx, mut record) in records.into_iter().enumerate() {
+            let mut retries = 0;
+            loop {
+                match self.send(record) {
+                    Ok(()) => break,
+                    Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), r))
+                        if retries < SEND_BATCH_QUEUE_FULL_RETRIES =>
+                    {
+                        retries += 1;
+                        record = r;
+                        self.poll(Duration::from_millis(100));
+                    }
+                    Err((e, _)) => {
+                        failures.push((idx, e));
+                        break;
+                    }
+                }
+            }
+        }
+        failures
+    }
+
     /// Flushes the producer. Should be called before termination. This method will call `poll()`
-    /// internally.
-    pub fn flush<T: Into<Option<Duration>>>(&self, timeout: T) {
+    /// internally. Returns an error if some messages are still waiting to be delivered when the
+    /// timeout expires.
+    pub fn flush<T: Into<Timeout>>(&self, timeout: T) -> KafkaResult<()> {
+        let ret = unsafe { rdsys::rd_kafka_flush(self.native_ptr(), timeout_to_ms(timeout)) };
+        if ret.is_error() {
+            Err(KafkaError::Flush(ret.into()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Same as `flush`, but the error reports the number of messages that are still waiting to
+    /// be delivered when the timeout expires, for example to log how many messages would be lost.
+    pub fn flush_with_report<T: Into<Timeout>>(&self, timeout: T) -> Result<(), FlushError> {
         unsafe { rdsys::rd_kafka_flush(self.native_ptr(), timeout_to_ms(timeout)) };
+        let remaining = self.in_flight_count();
+        if remaining > 0 {
+            Err(FlushError { remaining })
+        } else {
+            Ok(())
+        }
     }
 
     /// Returns the number of messages waiting to be sent, or sent but not acknowledged yet.
     pub fn in_flight_count(&self) -> i32 {
         unsafe { rdsys::rd_kafka_outq_len(self.native_ptr()) }
     }
+
+    /// Returns the fatal error raised by the producer, if any. See `Client::fatal_error`.
+    pub fn fatal_error(&self) -> Option<(RDKafkaErrorCode, String)> {
+        self.client_arc.client.fatal_error()
+    }
+
+    /// Returns the name of the client instance. See `Client::name`.
+    pub fn name(&self) -> String {
+        self.client_arc.client.name()
+    }
+
+    /// Purges the messages of the producer selected by the `PurgeConfig`, without waiting for
+    /// them to be delivered. The delivery callback of each purged message is called with a
+    /// `RDKafkaErrorCode::PurgeQueue` or `RDKafkaErrorCode::PurgeInflight` error the next time
+    /// the producer is polled. Messages that have already been transmitted to the broker might
+    /// still be written to the log.
+    pub fn purge(&self, config: PurgeConfig) -> KafkaResult<()> {
+        let ret = unsafe { rdsys::rd_kafka_purge(self.native_ptr(), config.flags) };
+        if ret.is_error() {
+            Err(KafkaError::Purge(ret.into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<D: IntoOpaque, C: ClientContext> BaseProducer<DeliveryStreamContext<D, C>> {
+    /// Returns the stream of the delivery reports of the producer. The producer still has to be
+    /// polled for the delivery reports to be served. There is only one stream per producer, so
+    /// `None` is returned if the stream has already been taken.
+    pub fn delivery_report_stream(&self) -> Option<DeliveryStream<D>> {
+        self.client_arc
+            .client
+            .context()
+            .receiver
+            .lock()
+            .unwrap()
+            .take()
+            .map(|receiver| DeliveryStream { receiver })
+    }
 }
 
 impl<C: ProducerContext> Clone for BaseProducer<C> {
@@ -397,16 +930,115 @@ impl<C: ProducerContext> Clone for BaseProducer<C> {
 // ********** THREADED PRODUCER **********
 //
 
+/// Configuration of the polling thread of a `ThreadedProducer` or of a `FutureProducer`.
+///
+/// The polling thread blocks in `poll` until an event, such as a delivery report, is available,
+/// so events are served as soon as they arrive regardless of the poll interval. The poll
+/// interval bounds how long each call to `poll` can block, and thus how long it takes for the
+/// thread to stop once the producer is dropped: a longer interval means fewer wake-ups of an
+/// idle producer, a shorter one a quicker shutdown.
+#[derive(Clone, Debug)]
+pub struct PollingConfig {
+    poll_interval: Duration,
+}
+
+impl Default for PollingConfig {
+    fn default() -> PollingConfig {
+        PollingConfig::new()
+    }
+}
+
+impl PollingConfig {
+    /// Creates a new polling configuration, with a 100ms poll interval.
+    pub fn new() -> PollingConfig {
+        PollingConfig {
+            poll_interval: Duration::from_millis(100),
+        }
+    }
+
+    /// Sets the maximum time each call to `poll` blocks waiting for events. Intervals shorter
+    /// than 1ms are rounded up to 1ms, so that the polling thread never busy-loops.
+    pub fn poll_interval(mut self, poll_interval: Duration) -> PollingConfig {
+        self.poll_interval = poll_interval.max(Duration::from_millis(1));
+        self
+    }
+}
+
+/// The error returned by `BaseProducer::flush_with_report` when some messages are not delivered
+/// before the timeout expires.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlushError {
+    /// The number of messages still waiting to be delivered when the flush returned.
+    pub remaining: i32,
+}
+
+impl fmt::Display for FlushError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Flush timed out with {} messages not delivered",
+            self.remaining
+        )
+    }
+}
+
+impl error::Error for FlushError {
+    fn description(&self) -> &str {
+        "Flush timed out"
+    }
+}
+
+/// Selects the messages purged by `BaseProducer::purge`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PurgeConfig {
+    flags: i32,
+}
+
+impl PurgeConfig {
+    /// Creates a new purge configuration, that doesn't select any message.
+    pub fn new() -> PurgeConfig {
+        PurgeConfig { flags: 0 }
+    }
+
+    /// Selects the messages waiting in the producer queue, that haven't been sent to the broker
+    /// yet.
+    pub fn queue(mut self) -> PurgeConfig {
+        self.flags |= rdsys::RD_KAFKA_PURGE_F_QUEUE as i32;
+        self
+    }
+
+    /// Selects the messages that have been sent to the broker, and are waiting for an
+    /// acknowledgement.
+    pub fn inflight(mut self) -> PurgeConfig {
+        self.flags |= rdsys::RD_KAFKA_PURGE_F_INFLIGHT as i32;
+        self
+    }
+
+    /// Makes `purge` return without waiting for the internal threads of librdkafka to purge
+    /// their queues.
+    pub fn non_blocking(mut self) -> PurgeConfig {
+        self.flags |= rdsys::RD_KAFKA_PURGE_F_NON_BLOCKING as i32;
+        self
+    }
+}
+
 /// A producer with a separate thread for event handling.
 ///
 /// The `ThreadedProducer` is a `BaseProducer` with a separate thread dedicated to calling `poll` at
 /// regular intervals in order to execute any queued event, such as delivery notifications. The
-/// thread will be automatically stopped when the producer is dropped.
+/// thread will be automatically stopped when the producer is dropped. The behavior of the thread
+/// can be configured with a [PollingConfig], using [ThreadedProducer::with_polling_config].
+///
+/// When the producer is dropped, the messages that haven't been delivered yet are purged before
+/// stopping the thread. The producer can instead flush them first, blocking the drop for up to a
+/// given time, if enabled with [ThreadedProducer::set_drop_flush_timeout].
 #[must_use = "The threaded producer will stop immediately if unused"]
 pub struct ThreadedProducer<C: ProducerContext + 'static> {
     producer: BaseProducer<C>,
     should_stop: Arc<AtomicBool>,
     handle: RwLock<Option<JoinHandle<()>>>,
+    polling_config: PollingConfig,
+    drop_flush_timeout: RwLock<Option<Duration>>,
 }
 
 impl FromClientConfig for ThreadedProducer<DefaultProducerContext> {
@@ -419,29 +1051,42 @@ impl<C: ProducerContext + 'static> FromClientConfigAndContext<C> for ThreadedPro
     fn from_config_and_context(
         config: &ClientConfig,
         context: C,
+    ) -> KafkaResult<ThreadedProducer<C>> {
+        ThreadedProducer::with_polling_config(config, context, PollingConfig::new())
+    }
+}
+
+impl<C: ProducerContext + 'static> ThreadedProducer<C> {
+    /// Creates a new `ThreadedProducer`, whose polling thread is configured with the provided
+    /// [PollingConfig].
+    pub fn with_polling_config(
+        config: &ClientConfig,
+        context: C,
+        polling_config: PollingConfig,
     ) -> KafkaResult<ThreadedProducer<C>> {
         let threaded_producer = ThreadedProducer {
             producer: BaseProducer::from_config_and_context(config, context)?,
             should_stop: Arc::new(AtomicBool::new(false)),
             handle: RwLock::new(None),
+            polling_config,
+            drop_flush_timeout: RwLock::new(None),
         };
         threaded_producer.start();
         Ok(threaded_producer)
     }
-}
 
-impl<C: ProducerContext + 'static> ThreadedProducer<C> {
     /// Starts the polling thread that will drive the producer. The thread is already started by
     /// default.
     fn start(&self) {
         let producer_clone = self.producer.clone();
         let should_stop = self.should_stop.clone();
+        let poll_interval = self.polling_config.poll_interval;
         let handle = thread::Builder::new()
             .name("producer polling thread".to_string())
             .spawn(move || {
                 trace!("Polling thread loop started");
                 loop {
-                    let n = producer_clone.poll(Duration::from_millis(100));
+                    let n = producer_clone.poll(poll_interval);
                     if n == 0 {
                         if should_stop.load(Ordering::Relaxed) {
                             // We received nothing and the thread should
@@ -459,6 +1104,36 @@ impl<C: ProducerContext + 'static> ThreadedProducer<C> {
         *handle_store = Some(handle);
     }
 
+    /// Sets the maximum time the producer waits for the pending messages to be delivered when it
+    /// is dropped, blocking the drop. `None`, the default, disables the flush, purging the
+    /// pending messages immediately.
+    pub fn set_drop_flush_timeout<T: Into<Option<Duration>>>(&self, timeout: T) {
+        *self.drop_flush_timeout.write().expect("poison error") = timeout.into();
+    }
+
+    /// Flushes the producer with the drop flush timeout, if enabled, and purges the messages
+    /// that are still pending, so that their delivery callbacks are called by the polling thread
+    /// before it stops. A warning is logged if some messages are purged.
+    fn flush_on_drop(&self) {
+        let timeout = *self.drop_flush_timeout.read().expect("poison error");
+        if let Some(timeout) = timeout {
+            if self.producer.flush(timeout).is_ok() {
+                return;
+            }
+        }
+        let pending = self.producer.in_flight_count();
+        if pending == 0 {
+            return;
+        }
+        warn!(
+            "Purging {} messages not delivered when the producer was dropped",
+            pending
+        );
+        if let Err(e) = self.producer.purge(PurgeConfig::new().queue().inflight()) {
+            warn!("Failure while purging the producer: {}", e);
+        }
+    }
+
     /// Stops the polling thread. This method will be called during destruction.
     fn stop(&self) {
         let mut handle_store = self.handle.write().expect("poison error");
@@ -490,24 +1165,48 @@ impl<C: ProducerContext + 'static> ThreadedProducer<C> {
 
     /// Polls the internal producer. This is not normally required since the `ThreadedProducer` had
     /// a thread dedicated to calling `poll` regularly.
-    pub fn poll<T: Into<Option<Duration>>>(&self, timeout: T) {
+    pub fn poll<T: Into<Timeout>>(&self, timeout: T) {
         self.producer.poll(timeout);
     }
 
-    /// Flushes the producer. Should be called before termination.
-    pub fn flush<T: Into<Option<Duration>>>(&self, timeout: T) {
-        self.producer.flush(timeout);
+    /// Flushes the producer. Should be called before termination. Returns an error if some
+    /// messages are still waiting to be delivered when the timeout expires.
+    pub fn flush<T: Into<Timeout>>(&self, timeout: T) -> KafkaResult<()> {
+        self.producer.flush(timeout)
+    }
+
+    /// Same as `flush`, but the error reports the number of messages that are still waiting to
+    /// be delivered. See `BaseProducer::flush_with_report`.
+    pub fn flush_with_report<T: Into<Timeout>>(&self, timeout: T) -> Result<(), FlushError> {
+        self.producer.flush_with_report(timeout)
     }
 
     /// Returns the number of messages waiting to be sent, or send but not acknowledged yet.
     pub fn in_flight_count(&self) -> i32 {
         self.producer.in_flight_count()
     }
+
+    /// Returns the fatal error raised by the producer, if any. See `Client::fatal_error`.
+    pub fn fatal_error(&self) -> Option<(RDKafkaErrorCode, String)> {
+        self.producer.fatal_error()
+    }
+
+    /// Returns the name of the client instance. See `Client::name`.
+    pub fn name(&self) -> String {
+        self.producer.name()
+    }
+
+    /// Purges the messages of the producer selected by the `PurgeConfig`. The delivery callbacks
+    /// of the purged messages are called by the polling thread. See `BaseProducer::purge`.
+    pub fn purge(&self, config: PurgeConfig) -> KafkaResult<()> {
+        self.producer.purge(config)
+    }
 }
 
 impl<C: ProducerContext + 'static> Drop for ThreadedProducer<C> {
     fn drop(&mut self) {
         trace!("Destroy ThreadedProducer");
+        self.flush_on_drop();
         self.stop();
         trace!("ThreadedProducer destroyed");
     }
@@ -519,6 +1218,9 @@ mod tests {
     // traits (Clone, Send, Sync etc.). Behavior is tested in the integrations tests.
     use super::*;
     use crate::config::ClientConfig;
+    use crate::message::{Headers, Message};
+
+    use futures::Future;
 
     // Verify that the producer is clone, according to documentation.
     #[test]
@@ -526,4 +1228,489 @@ mod tests {
         let producer = ClientConfig::new().create::<BaseProducer<_>>().unwrap();
         let _producer_clone = producer.clone();
     }
+
+    #[test]
+    fn test_polling_config() {
+        assert_eq!(
+            PollingConfig::new().poll_interval,
+            Duration::from_millis(100)
+        );
+        assert_eq!(
+            PollingConfig::new()
+                .poll_interval(Duration::from_secs(0))
+                .poll_interval,
+            Duration::from_millis(1)
+        );
+    }
+
+    #[test]
+    fn test_base_producer_flush_with_report() {
+        let producer: BaseProducer = ClientConfig::new()
+            .set("bootstrap.servers", "localhost:1")
+            .create()
+            .unwrap();
+        assert_eq!(
+            producer.flush_with_report(Duration::from_millis(10)),
+            Ok(())
+        );
+
+        for _ in 0..3 {
+            producer
+                .send(BaseRecord::<(), str>::to("topic1").payload("payload"))
+                .unwrap();
+        }
+        let err = producer
+            .flush_with_report(Duration::from_millis(100))
+            .unwrap_err();
+        assert_eq!(err, FlushError { remaining: 3 });
+        assert_eq!(
+            err.to_string(),
+            "Flush timed out with 3 messages not delivered"
+        );
+    }
+
+    // Without a broker, the messages are reported as failed once they time out.
+    #[test]
+    fn test_base_producer_delivery_report_stream() {
+        let producer: BaseProducer<DeliveryStreamContext<usize>> = ClientConfig::new()
+            .set("message.timeout.ms", "100")
+            .create_with_context(DeliveryStreamContext::new(DefaultClientContext))
+            .unwrap();
+        let stream = producer.delivery_report_stream().unwrap();
+        assert!(producer.delivery_report_stream().is_none());
+
+        for i in 0..3 {
+            producer
+                .send(
+                    BaseRecord::with_opaque_to("topic", i)
+                        .payload("payload")
+                        .key(&format!("key{}", i))
+                        .partition(0),
+                )
+                .unwrap();
+        }
+        assert_eq!(
+            producer.flush(Duration::from_secs(5)),
+            Ok(()),
+            "Messages not reported in time"
+        );
+        drop(producer);
+
+        // The stream completes once the producer is dropped.
+        let reports = stream.collect().wait().unwrap();
+        assert_eq!(reports.len(), 3);
+        for (i, (result, opaque)) in reports.into_iter().enumerate() {
+            assert_eq!(opaque, i);
+            match result {
+                Err((
+                    KafkaError::MessageProduction(RDKafkaErrorCode::MessageTimedOut),
+                    message,
+                )) => {
+                    assert_eq!(
+                        message.key_view::<str>(),
+                        Some(Ok(format!("key{}", i).as_str()))
+                    );
+                    assert_eq!(message.partition(), 0);
+                }
+                res => panic!("Unexpected delivery result: {:?}", res),
+            }
+        }
+    }
+
+    #[test]
+    fn test_base_producer_fatal_error() {
+        let producer: BaseProducer = ClientConfig::new()
+            .set("enable.idempotence", "true")
+            .create()
+            .unwrap();
+        assert_eq!(producer.fatal_error(), None);
+
+        // The return value of rd_kafka_test_fatal_error is inverted in librdkafka 1.2, so the
+        // error is checked with fatal_error only.
+        let reason = CString::new("simulated").unwrap();
+        unsafe {
+            rdsys::rd_kafka_test_fatal_error(
+                producer.native_ptr(),
+                RDKafkaRespErr::RD_KAFKA_RESP_ERR_OUT_OF_ORDER_SEQUENCE_NUMBER,
+                reason.as_ptr(),
+            )
+        };
+        assert_eq!(
+            producer.fatal_error(),
+            Some((
+                RDKafkaErrorCode::OutOfOrderSequenceNumber,
+                "test_fatal_error: simulated".to_owned()
+            ))
+        );
+    }
+
+    // The payload assembled from the slices should be reported with the delivery, even if the
+    // message could not be delivered.
+    #[test]
+    fn test_base_producer_payload_slices() {
+        let producer: BaseProducer<DeliveryStreamContext<usize>> = ClientConfig::new()
+            .set("message.timeout.ms", "100")
+            .create_with_context(DeliveryStreamContext::new(DefaultClientContext))
+            .unwrap();
+        let stream = producer.delivery_report_stream().unwrap();
+
+        let (first, second) = (b"first".to_vec(), b"second".to_vec());
+        let slices: &[&[u8]] = &[&first, &second];
+        producer
+            .send(BaseRecord::<(), str, _>::with_opaque_to("topic", 0).payload_slices(slices))
+            .unwrap();
+        drop((first, second));
+        producer
+            .send(BaseRecord::<(), str, _>::with_opaque_to("topic", 1).payload_slices(&[]))
+            .unwrap();
+        producer
+            .send(BaseRecord::<(), str, _>::with_opaque_to("topic", 2).payload("single"))
+            .unwrap();
+        assert_eq!(producer.flush(Duration::from_secs(5)), Ok(()));
+        drop(producer);
+
+        let payloads = stream
+            .map(|(result, opaque)| {
+                let message = match result {
+                    Ok(message) => message,
+                    Err((_, message)) => message,
+                };
+                (opaque, message.payload().map(<[u8]>::to_vec))
+            })
+            .collect()
+            .wait()
+            .unwrap();
+        assert_eq!(
+            payloads,
+            vec![
+                (0, Some(b"firstsecond".to_vec())),
+                (1, Some(Vec::new())),
+                (2, Some(b"single".to_vec())),
+            ]
+        );
+    }
+
+    // Records sent to a topic handle go to the topic of the handle, whatever their own topic is,
+    // and handles of other producers are rejected.
+    #[test]
+    fn test_base_producer_send_to() {
+        fn assert_send<T: Send>(_: &T) {}
+
+        let producer: BaseProducer<DeliveryStreamContext<usize>> = ClientConfig::new()
+            .set("message.timeout.ms", "100")
+            .create_with_context(DeliveryStreamContext::new(DefaultClientContext))
+            .unwrap();
+        let stream = producer.delivery_report_stream().unwrap();
+        let topic = producer.topic("cached").unwrap();
+        assert_send(&topic);
+        assert_eq!(topic.name(), "cached");
+
+        for i in 0..100 {
+            producer
+                .send_to(
+                    &topic,
+                    BaseRecord::<(), str, _>::with_opaque_to("ignored", i).payload("p"),
+                )
+                .unwrap();
+        }
+        producer
+            .send(BaseRecord::<(), str, _>::with_opaque_to("named", 100).payload("p"))
+            .unwrap();
+        assert_eq!(producer.in_flight_count(), 101);
+
+        let other: BaseProducer<DeliveryStreamContext<usize>> = ClientConfig::new()
+            .create_with_context(DeliveryStreamContext::new(DefaultClientContext))
+            .unwrap();
+        let (err, record) = other
+            .send_to(
+                &topic,
+                BaseRecord::<(), str, _>::with_opaque_to("ignored", 101),
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            KafkaError::MessageProduction(RDKafkaErrorCode::InvalidArgument)
+        );
+        assert_eq!(record.delivery_opaque, 101);
+
+        assert_eq!(producer.flush(Duration::from_secs(5)), Ok(()));
+        drop(topic);
+        drop(producer);
+        let topics: Vec<(usize, String)> = stream
+            .map(|(result, opaque)| {
+                let message = match result {
+                    Ok(message) => message,
+                    Err((_, message)) => message,
+                };
+                (opaque, message.topic().to_owned())
+            })
+            .collect()
+            .wait()
+            .unwrap();
+        assert_eq!(topics.len(), 101);
+        assert!(topics[..100].iter().all(|(_, topic)| topic == "cached"));
+        assert_eq!(topics[100], (100, "named".to_owned()));
+    }
+
+    struct TracingContext {
+        delivered_headers: Mutex<Vec<Vec<(String, String)>>>,
+    }
+
+    impl ClientContext for TracingContext {}
+    impl ProducerContext for TracingContext {
+        type DeliveryOpaque = ();
+
+        fn delivery(&self, delivery_result: &DeliveryResult, _: Self::DeliveryOpaque) {
+            let message = match *delivery_result {
+                Ok(ref message) => message,
+                Err((_, ref message)) => message,
+            };
+            let headers = message.headers().map_or_else(Vec::new, |headers| {
+                (0..headers.count())
+                    .map(|i| {
+                        let (name, value) = headers.get_as::<str>(i).unwrap();
+                        (name.to_owned(), value.unwrap().to_owned())
+                    })
+                    .collect()
+            });
+            self.delivered_headers.lock().unwrap().push(headers);
+        }
+
+        fn on_send(&self, record: &mut InterceptedRecord) {
+            assert_eq!(record.topic(), "topic");
+            let headers = record
+                .take_headers()
+                .unwrap_or_default()
+                .add("trace-id", "abc");
+            record.set_headers(headers);
+        }
+    }
+
+    // The headers injected by the context should be attached to the message, and reported with
+    // its delivery.
+    #[test]
+    fn test_base_producer_on_send() {
+        let producer: BaseProducer<TracingContext> = ClientConfig::new()
+            .set("message.timeout.ms", "100")
+            .create_with_context(TracingContext {
+                delivered_headers: Mutex::new(Vec::new()),
+            })
+            .unwrap();
+        producer
+            .send(BaseRecord::<(), str>::to("topic").payload("payload"))
+            .unwrap();
+        producer
+            .send(
+                BaseRecord::<(), str>::to("topic")
+                    .payload("payload")
+                    .headers(OwnedHeaders::new().add("user", "1")),
+            )
+            .unwrap();
+        assert_eq!(producer.flush(Duration::from_secs(5)), Ok(()));
+
+        let delivered_headers = producer
+            .client_arc
+            .client
+            .context()
+            .delivered_headers
+            .lock()
+            .unwrap()
+            .clone();
+        let header = |name: &str, value: &str| (name.to_owned(), value.to_owned());
+        assert_eq!(
+            delivered_headers,
+            vec![
+                vec![header("trace-id", "abc")],
+                vec![header("user", "1"), header("trace-id", "abc")],
+            ]
+        );
+    }
+
+    // A record that can't be enqueued should be returned with the headers it was sent with, so
+    // that the headers added by the context are not duplicated when it is sent again.
+    #[test]
+    fn test_base_producer_on_send_queue_full() {
+        let producer: BaseProducer<TracingContext> = ClientConfig::new()
+            .set("queue.buffering.max.messages", "1")
+            .create_with_context(TracingContext {
+                delivered_headers: Mutex::new(Vec::new()),
+            })
+            .unwrap();
+        producer
+            .send(BaseRecord::<(), str>::to("topic").payload("payload"))
+            .unwrap();
+        let mut record = BaseRecord::<(), str>::to("topic")
+            .payload("payload")
+            .headers(OwnedHeaders::new().add("user", "1"));
+        for _ in 0..2 {
+            record = match producer.send(record) {
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), record)) => record,
+                other => panic!("unexpected result: {:?}", other.map_err(|(e, _)| e)),
+            };
+            let headers = record.headers.as_ref().unwrap();
+            assert_eq!(headers.count(), 1);
+            assert_eq!(headers.get_as::<str>(0), Some(("user", Ok("1"))));
+        }
+        producer.purge(PurgeConfig::new().queue()).unwrap();
+    }
+
+    struct InvalidPartitionerContext;
+
+    impl ClientContext for InvalidPartitionerContext {}
+    impl ProducerContext for InvalidPartitionerContext {
+        type DeliveryOpaque = ();
+
+        fn delivery(&self, _: &DeliveryResult, _: Self::DeliveryOpaque) {}
+
+        fn has_custom_partitioner(&self) -> bool {
+            true
+        }
+
+        fn partition(&self, _topic: &str, _key: Option<&[u8]>, partition_cnt: i32) -> i32 {
+            partition_cnt
+        }
+    }
+
+    // An invalid partition returned by the context should be replaced by the partition chosen
+    // by the default partitioner of librdkafka.
+    #[test]
+    fn test_partitioner_fallback() {
+        let producer: BaseProducer<InvalidPartitionerContext> = ClientConfig::new()
+            .create_with_context(InvalidPartitionerContext)
+            .unwrap();
+        let topic = producer.topic("topic").unwrap();
+        let opaque = producer.client_arc.client.context() as *const _ as *mut c_void;
+        let key = b"key";
+        unsafe {
+            let partition = partitioner_cb::<InvalidPartitionerContext>(
+                topic.native.ptr(),
+                key.as_ptr() as *const c_void,
+                key.len(),
+                3,
+                opaque,
+                ptr::null_mut(),
+            );
+            let expected = rdsys::rd_kafka_msg_partitioner_consistent(
+                topic.native.ptr(),
+                key.as_ptr() as *const c_void,
+                key.len(),
+                3,
+                opaque,
+                ptr::null_mut(),
+            );
+            assert_eq!(partition, expected);
+            for _ in 0..10 {
+                let partition = partitioner_cb::<InvalidPartitionerContext>(
+                    topic.native.ptr(),
+                    ptr::null(),
+                    0,
+                    3,
+                    opaque,
+                    ptr::null_mut(),
+                );
+                assert!((0..3).contains(&partition));
+            }
+        }
+    }
+
+    type OpaqueDelivery = (usize, Option<RDKafkaErrorCode>);
+
+    #[derive(Clone, Default)]
+    struct OpaqueContext {
+        deliveries: Arc<Mutex<Vec<OpaqueDelivery>>>,
+    }
+
+    impl ClientContext for OpaqueContext {}
+    impl ProducerContext for OpaqueContext {
+        type DeliveryOpaque = usize;
+
+        fn delivery(&self, delivery_result: &DeliveryResult, delivery_opaque: usize) {
+            let error = match *delivery_result {
+                Ok(_) => None,
+                Err((ref error, _)) => error.rdkafka_error_code(),
+            };
+            self.deliveries
+                .lock()
+                .unwrap()
+                .push((delivery_opaque, error));
+        }
+    }
+
+    // The typed opaque of each message should be handed back intact when its delivery fails
+    // because it is purged, explicitly or when the producer is dropped.
+    #[test]
+    #[allow(clippy::legacy_numeric_constants)]
+    fn test_base_producer_opaque_purge() {
+        let context = OpaqueContext::default();
+        let producer: BaseProducer<OpaqueContext> = ClientConfig::new()
+            .set("message.timeout.ms", "10000")
+            .create_with_context(context.clone())
+            .unwrap();
+        for &opaque in &[1usize, std::usize::MAX] {
+            producer
+                .send(BaseRecord::<(), str, usize>::with_opaque_to("topic", opaque).payload("A"))
+                .unwrap();
+        }
+        producer.purge(PurgeConfig::new().queue()).unwrap();
+        producer.poll(Duration::from_millis(0));
+        assert_eq!(
+            *context.deliveries.lock().unwrap(),
+            vec![
+                (1, Some(RDKafkaErrorCode::PurgeQueue)),
+                (std::usize::MAX, Some(RDKafkaErrorCode::PurgeQueue)),
+            ]
+        );
+
+        producer
+            .send(BaseRecord::<(), str, usize>::with_opaque_to("topic", 42).payload("A"))
+            .unwrap();
+        // Only the last clone purges the pending messages.
+        drop(producer.clone());
+        assert_eq!(context.deliveries.lock().unwrap().len(), 2);
+        drop(producer);
+        assert_eq!(
+            context.deliveries.lock().unwrap().last(),
+            Some(&(42, Some(RDKafkaErrorCode::PurgeQueue)))
+        );
+    }
+
+    // Oversized records are rejected before being enqueued, whether the size comes from the
+    // payload or from the headers.
+    #[test]
+    fn test_base_producer_message_too_large() {
+        let producer: BaseProducer = ClientConfig::new()
+            .set("message.max.bytes", "1000")
+            .create()
+            .unwrap();
+        let payload = vec![0u8; 2000];
+        let small = vec![0u8; 500];
+
+        let (err, record) = producer
+            .send(BaseRecord::<(), _>::to("topic").payload(&payload))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            KafkaError::MessageProduction(RDKafkaErrorCode::MessageSizeTooLarge)
+        );
+        assert_eq!(record.payload.map(Vec::len), Some(2000));
+
+        let headers = OwnedHeaders::new().add("big", &small).add("bigger", &small);
+        let (err, record) = producer
+            .send(
+                BaseRecord::<(), _>::to("topic")
+                    .payload(&small)
+                    .headers(headers),
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            KafkaError::MessageProduction(RDKafkaErrorCode::MessageSizeTooLarge)
+        );
+        assert_eq!(record.headers.map(|h| h.count()), Some(2));
+
+        producer
+            .send(BaseRecord::<(), _>::to("topic").payload(&small))
+            .unwrap();
+        producer.purge(PurgeConfig::new().queue()).unwrap();
+    }
 }
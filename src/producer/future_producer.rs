@@ -3,17 +3,26 @@
 //! A high level producer that returns a Future for every produced message.
 // TODO: extend docs
 
-use crate::client::{ClientContext, DefaultClientContext};
+use crate::client::{ClientContext, DefaultClientContext, OAuthToken};
 use crate::config::{ClientConfig, FromClientConfig, FromClientConfigAndContext, RDKafkaLogLevel};
-use crate::error::{KafkaError, KafkaResult, RDKafkaError};
-use crate::message::{Message, OwnedHeaders, OwnedMessage, Timestamp, ToBytes};
-use crate::producer::{BaseRecord, DeliveryResult, ProducerContext, ThreadedProducer};
+use crate::error::{KafkaError, KafkaResult, RDKafkaErrorCode};
+use crate::message::{
+    Header, Message, OwnedHeaders, OwnedMessage, Timestamp, ToBytes, ToHeaderValue,
+};
+use crate::producer::{
+    BaseRecord, DeliveryResult, FlushError, PollingConfig, ProducerContext, PurgeConfig,
+    ThreadedProducer,
+};
 use crate::statistics::Statistics;
-use crate::util::IntoOpaque;
+use crate::util::{IntoOpaque, Timeout};
 
-use futures::{self, Canceled, Complete, Future, Oneshot, Poll};
+use futures::executor::{self, Notify, NotifyHandle};
+use futures::task::{self, Task};
+use futures::{self, Async, Canceled, Complete, Future, Oneshot, Poll};
 
-use std::sync::Arc;
+use std::cmp;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, Thread};
 use std::time::{Duration, Instant};
 
 //
@@ -82,7 +91,8 @@ impl<'a, K: ToBytes + ?Sized, P: ToBytes + ?Sized> FutureRecord<'a, K, P> {
         self
     }
 
-    /// Set the destination timestamp of the record.
+    /// Set the timestamp of the record, in milliseconds since the epoch. The timestamp is sent
+    /// as the `CreateTime` of the message. If not set, librdkafka uses the current time.
     pub fn timestamp(mut self, timestamp: i64) -> FutureRecord<'a, K, P> {
         self.timestamp = Some(timestamp);
         self
@@ -94,12 +104,37 @@ impl<'a, K: ToBytes + ?Sized, P: ToBytes + ?Sized> FutureRecord<'a, K, P> {
         self
     }
 
+    /// Add a header to the record, after the headers already set. A `None` value adds a header
+    /// with a null value.
+    pub fn header<V: ToHeaderValue + ?Sized>(
+        mut self,
+        key: &str,
+        value: Option<&V>,
+    ) -> FutureRecord<'a, K, P> {
+        let headers = self.headers.take().unwrap_or_default();
+        self.headers = Some(headers.insert(Header { key, value }));
+        self
+    }
+
+    /// Returns a copy of the record, to send it again.
+    fn duplicate(&self) -> FutureRecord<'a, K, P> {
+        FutureRecord {
+            topic: self.topic,
+            partition: self.partition,
+            payload: self.payload,
+            key: self.key,
+            timestamp: self.timestamp,
+            headers: self.headers.clone(),
+        }
+    }
+
     fn into_base_record<D: IntoOpaque>(self, delivery_opaque: D) -> BaseRecord<'a, K, P, D> {
         BaseRecord {
             topic: self.topic,
             partition: self.partition,
             key: self.key,
             payload: self.payload,
+            payload_slices: None,
             timestamp: self.timestamp,
             headers: self.headers,
             delivery_opaque,
@@ -107,11 +142,32 @@ impl<'a, K: ToBytes + ?Sized, P: ToBytes + ?Sized> FutureRecord<'a, K, P> {
     }
 }
 
+/// The tasks waiting for space in the producer queue. They are notified every time a delivery
+/// report is received, since each delivery report frees up a slot in the queue.
+#[derive(Default)]
+struct QueueSpaceWaiters {
+    tasks: Mutex<Vec<Task>>,
+}
+
+impl QueueSpaceWaiters {
+    /// Registers the current task, to be notified on the next delivery report.
+    fn register_current(&self) {
+        self.tasks.lock().unwrap().push(task::current());
+    }
+
+    fn notify_all(&self) {
+        for task in self.tasks.lock().unwrap().drain(..) {
+            task.notify();
+        }
+    }
+}
+
 /// The `ProducerContext` used by the `FutureProducer`. This context will use a Future as its
 /// `DeliveryOpaque` and will complete the future when the message is delivered (or failed to).
 #[derive(Clone)]
 struct FutureProducerContext<C: ClientContext + 'static> {
     wrapped_context: C,
+    queue_space_waiters: Arc<QueueSpaceWaiters>,
 }
 
 /// Represents the result of message production as performed from the `FutureProducer`.
@@ -121,6 +177,9 @@ struct FutureProducerContext<C: ClientContext + 'static> {
 /// an owned copy of the original message.
 type OwnedDeliveryResult = Result<(i32, i64), (KafkaError, OwnedMessage)>;
 
+/// The delivery result sent to the `DeliveryFuture`, with the latency of the production.
+type DeliveryReport = (OwnedDeliveryResult, Option<i64>);
+
 // Delegates all the methods calls to the wrapped context.
 impl<C: ClientContext + 'static> ClientContext for FutureProducerContext<C> {
     fn log(&self, level: RDKafkaLogLevel, fac: &str, log_message: &str) {
@@ -131,20 +190,39 @@ impl<C: ClientContext + 'static> ClientContext for FutureProducerContext<C> {
         self.wrapped_context.stats(statistics);
     }
 
+    fn stats_raw(&self, statistics: &[u8]) {
+        self.wrapped_context.stats_raw(statistics);
+    }
+
     fn error(&self, error: KafkaError, reason: &str) {
         self.wrapped_context.error(error, reason);
     }
+
+    const ENABLE_REFRESH_OAUTH_TOKEN: bool = C::ENABLE_REFRESH_OAUTH_TOKEN;
+
+    fn generate_oauth_token(&self, oauthbearer_config: Option<&str>) -> Result<OAuthToken, String> {
+        self.wrapped_context
+            .generate_oauth_token(oauthbearer_config)
+    }
 }
 
 impl<C: ClientContext + 'static> ProducerContext for FutureProducerContext<C> {
-    type DeliveryOpaque = Box<Complete<OwnedDeliveryResult>>;
+    type DeliveryOpaque = Box<Complete<DeliveryReport>>;
 
-    fn delivery(&self, delivery_result: &DeliveryResult, tx: Box<Complete<OwnedDeliveryResult>>) {
-        let owned_delivery_result = match *delivery_result {
-            Ok(ref message) => Ok((message.partition(), message.offset())),
-            Err((ref error, ref message)) => Err((error.clone(), message.detach())),
+    fn delivery(&self, delivery_result: &DeliveryResult, tx: Box<Complete<DeliveryReport>>) {
+        let report = match *delivery_result {
+            Ok(ref message) => (
+                Ok((message.partition(), message.offset())),
+                message.latency_us(),
+            ),
+            Err((ref error, ref message)) => {
+                let message = message.detach();
+                let latency = message.latency_us();
+                (Err((error.clone(), message)), latency)
+            }
         };
-        let _ = tx.send(owned_delivery_result); // TODO: handle error
+        let _ = tx.send(report); // TODO: handle error
+        self.queue_space_waiters.notify_all();
     }
 }
 
@@ -159,12 +237,14 @@ impl<C: ClientContext + 'static> ProducerContext for FutureProducerContext<C> {
 #[must_use = "Producer polling thread will stop immediately if unused"]
 pub struct FutureProducer<C: ClientContext + 'static = DefaultClientContext> {
     producer: Arc<ThreadedProducer<FutureProducerContext<C>>>,
+    queue_space_waiters: Arc<QueueSpaceWaiters>,
 }
 
 impl<C: ClientContext + 'static> Clone for FutureProducer<C> {
     fn clone(&self) -> FutureProducer<C> {
         FutureProducer {
             producer: self.producer.clone(),
+            queue_space_waiters: self.queue_space_waiters.clone(),
         }
     }
 }
@@ -180,12 +260,28 @@ impl<C: ClientContext + 'static> FromClientConfigAndContext<C> for FutureProduce
         config: &ClientConfig,
         context: C,
     ) -> KafkaResult<FutureProducer<C>> {
+        FutureProducer::with_polling_config(config, context, PollingConfig::new())
+    }
+}
+
+impl<C: ClientContext + 'static> FutureProducer<C> {
+    /// Creates a new `FutureProducer`, whose internal polling thread is configured with the
+    /// provided [PollingConfig].
+    pub fn with_polling_config(
+        config: &ClientConfig,
+        context: C,
+        polling_config: PollingConfig,
+    ) -> KafkaResult<FutureProducer<C>> {
+        let queue_space_waiters = Arc::new(QueueSpaceWaiters::default());
         let future_context = FutureProducerContext {
             wrapped_context: context,
+            queue_space_waiters: queue_space_waiters.clone(),
         };
-        let threaded_producer = ThreadedProducer::from_config_and_context(config, future_context)?;
+        let threaded_producer =
+            ThreadedProducer::with_polling_config(config, future_context, polling_config)?;
         Ok(FutureProducer {
             producer: Arc::new(threaded_producer),
+            queue_space_waiters,
         })
     }
 }
@@ -195,24 +291,267 @@ impl<C: ClientContext + 'static> FromClientConfigAndContext<C> for FutureProduce
 /// Once completed, the future will contain an `OwnedDeliveryResult` with information on the
 /// delivery status of the message.
 pub struct DeliveryFuture {
-    rx: Oneshot<OwnedDeliveryResult>,
+    rx: Oneshot<DeliveryReport>,
+}
+
+impl DeliveryFuture {
+    /// Returns a future containing the `OwnedDeliveryResult` together with the latency of the
+    /// production in microseconds, from the enqueue of the message to its acknowledgement or
+    /// failure, if available. See `BorrowedMessage::latency_us`.
+    pub fn with_latency(self) -> DeliveryWithLatency {
+        DeliveryWithLatency { rx: self.rx }
+    }
 }
 
 impl Future for DeliveryFuture {
     type Item = OwnedDeliveryResult;
     type Error = Canceled;
 
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.rx.poll()? {
+            Async::Ready((result, _)) => Ok(Async::Ready(result)),
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+}
+
+/// A [Future] wrapping the result of the message production and its latency in microseconds. It
+/// is returned by [DeliveryFuture::with_latency].
+pub struct DeliveryWithLatency {
+    rx: Oneshot<DeliveryReport>,
+}
+
+impl Future for DeliveryWithLatency {
+    type Item = (OwnedDeliveryResult, Option<i64>);
+    type Error = Canceled;
+
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
         self.rx.poll()
     }
 }
 
+/// Wakes up a thread blocked in [FutureProducer::send_and_wait].
+struct ThreadNotify(Thread);
+
+impl Notify for ThreadNotify {
+    fn notify(&self, _id: usize) {
+        self.0.unpark();
+    }
+}
+
+/// A [Future] that enqueues a [FutureRecord], waiting for space in the producer queue if it is
+/// full. It is returned by [FutureProducer::send_with_backpressure].
+///
+/// Once completed, the future will contain the [DeliveryFuture] of the enqueued message. If the
+/// message can't be enqueued, the error is returned alongside the [FutureRecord] provided.
+#[must_use = "Futures do nothing unless polled"]
+pub struct SendWithBackpressure<'a, C, K, P>
+where
+    C: ClientContext + 'static,
+    K: ToBytes + ?Sized + 'a,
+    P: ToBytes + ?Sized + 'a,
+{
+    producer: FutureProducer<C>,
+    record: Option<FutureRecord<'a, K, P>>,
+    deadline: Option<Instant>,
+    timer: Option<DeadlineTimer>,
+}
+
+/// Notifies a task once a deadline is reached, from a dedicated thread, so that a
+/// [SendWithBackpressure] times out even if no delivery report wakes it up. The thread
+/// terminates early when the timer is dropped.
+struct DeadlineTimer {
+    cancelled: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl DeadlineTimer {
+    fn start(deadline: Instant, task: Task) -> DeadlineTimer {
+        let cancelled = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread_cancelled = cancelled.clone();
+        thread::Builder::new()
+            .name("backpressure-timer".to_string())
+            .spawn(move || {
+                let (ref lock, ref condvar) = *thread_cancelled;
+                let mut cancelled = lock.lock().unwrap();
+                loop {
+                    if *cancelled {
+                        return;
+                    }
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    cancelled = condvar.wait_timeout(cancelled, deadline - now).unwrap().0;
+                }
+                task.notify();
+            })
+            .expect("Failed to start backpressure timer thread");
+        DeadlineTimer { cancelled }
+    }
+}
+
+impl Drop for DeadlineTimer {
+    fn drop(&mut self) {
+        let (ref lock, ref condvar) = *self.cancelled;
+        *lock.lock().unwrap() = true;
+        condvar.notify_one();
+    }
+}
+
+impl<'a, C, K, P> Future for SendWithBackpressure<'a, C, K, P>
+where
+    C: ClientContext + 'static,
+    K: ToBytes + ?Sized,
+    P: ToBytes + ?Sized,
+{
+    type Item = DeliveryFuture;
+    type Error = (KafkaError, FutureRecord<'a, K, P>);
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut record = self
+            .record
+            .take()
+            .expect("SendWithBackpressure polled after completion");
+        let mut registered = false;
+        loop {
+            match self.producer.send_result(record) {
+                Ok(delivery_future) => return Ok(Async::Ready(delivery_future)),
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), returned)) => {
+                    if registered {
+                        self.record = Some(returned);
+                        return Ok(Async::NotReady);
+                    }
+                    let timed_out = match self.deadline {
+                        Some(deadline) => Instant::now() >= deadline,
+                        None => false,
+                    };
+                    if timed_out {
+                        return Err((
+                            KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull),
+                            returned,
+                        ));
+                    }
+                    // Try again after registering, in case a delivery report freed up some space
+                    // in the meantime.
+                    self.producer.queue_space_waiters.register_current();
+                    if let (Some(deadline), None) = (self.deadline, self.timer.as_ref()) {
+                        self.timer = Some(DeadlineTimer::start(deadline, task::current()));
+                    }
+                    registered = true;
+                    record = returned;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Decides if and when a record is sent again by [FutureProducer::send_with_policy], after the
+/// failure of an attempt with a retriable error.
+pub trait RetryPolicy {
+    /// Returns how long to wait before the next attempt, after the failure of the attempt number
+    /// `attempt` (starting from 1) with `err`, or `None` to give up and return the error.
+    fn next_backoff(&mut self, attempt: u32, err: &KafkaError) -> Option<Duration>;
+
+    /// Returns true if the records whose delivery failed should be sent again, and not only the
+    /// records that failed to be enqueued. A record whose delivery timed out might have been
+    /// written by the broker anyway, so sending it again can duplicate it. Defaults to false.
+    fn retry_delivery_failures(&self) -> bool {
+        false
+    }
+}
+
+impl<R: RetryPolicy + ?Sized> RetryPolicy for &mut R {
+    fn next_backoff(&mut self, attempt: u32, err: &KafkaError) -> Option<Duration> {
+        (**self).next_backoff(attempt, err)
+    }
+
+    fn retry_delivery_failures(&self) -> bool {
+        (**self).retry_delivery_failures()
+    }
+}
+
+/// A [RetryPolicy] doubling the backoff after every attempt, up to a maximum backoff, and giving
+/// up after a maximum number of retries. By default, the first backoff is 100 milliseconds, the
+/// maximum backoff is 10 seconds, the record is retried up to 5 times, and delivery failures are
+/// not retried.
+#[derive(Clone, Debug)]
+pub struct ExponentialBackoff {
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    max_retries: u32,
+    retry_delivery_failures: bool,
+}
+
+impl Default for ExponentialBackoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExponentialBackoff {
+    /// Creates a new policy with the default values.
+    pub fn new() -> ExponentialBackoff {
+        ExponentialBackoff {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            max_retries: 5,
+            retry_delivery_failures: false,
+        }
+    }
+
+    /// Sets the backoff after the first attempt.
+    pub fn initial_backoff(mut self, backoff: Duration) -> ExponentialBackoff {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Sets the maximum backoff.
+    pub fn max_backoff(mut self, backoff: Duration) -> ExponentialBackoff {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Sets the maximum number of retries, after the first attempt.
+    pub fn max_retries(mut self, retries: u32) -> ExponentialBackoff {
+        self.max_retries = retries;
+        self
+    }
+
+    /// If set to true, the records whose delivery failed are retried as well, which can
+    /// duplicate them. See [RetryPolicy::retry_delivery_failures].
+    pub fn retry_delivery_failures(mut self, retry: bool) -> ExponentialBackoff {
+        self.retry_delivery_failures = retry;
+        self
+    }
+}
+
+impl RetryPolicy for ExponentialBackoff {
+    fn next_backoff(&mut self, attempt: u32, _err: &KafkaError) -> Option<Duration> {
+        if attempt == 0 || attempt > self.max_retries {
+            return None;
+        }
+        let backoff = 2u32
+            .checked_pow(attempt - 1)
+            .and_then(|factor| self.initial_backoff.checked_mul(factor))
+            .unwrap_or(self.max_backoff);
+        Some(cmp::min(backoff, self.max_backoff))
+    }
+
+    fn retry_delivery_failures(&self) -> bool {
+        self.retry_delivery_failures
+    }
+}
+
 impl<C: ClientContext + 'static> FutureProducer<C> {
     /// Sends the provided [FutureRecord]. Returns a [DeliveryFuture] that will eventually contain the
     /// result of the send. The `block_ms` parameter will control for how long the producer
     /// is allowed to block if the queue is full. Set it to -1 to block forever, or 0 to never block.
-    /// If `block_ms` is reached and the queue is still full, a [RDKafkaError::QueueFull] will be
+    /// If `block_ms` is reached and the queue is still full, a [RDKafkaErrorCode::QueueFull] will be
     /// reported in the [DeliveryFuture].
+    /// Records larger than `message.max.bytes` are never enqueued, and the
+    /// [RDKafkaErrorCode::MessageSizeTooLarge] error is reported in the [DeliveryFuture] right
+    /// away. See `BaseProducer::send`.
     pub fn send<K, P>(&self, record: FutureRecord<K, P>, block_ms: i64) -> DeliveryFuture
     where
         K: ToBytes + ?Sized,
@@ -226,7 +565,7 @@ impl<C: ClientContext + 'static> FutureProducer<C> {
         loop {
             match self.producer.send(base_record) {
                 Ok(_) => break DeliveryFuture { rx },
-                Err((KafkaError::MessageProduction(RDKafkaError::QueueFull), record)) => {
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), record)) => {
                     base_record = record;
                     if block_ms == -1 {
                         continue;
@@ -249,7 +588,7 @@ impl<C: ClientContext + 'static> FutureProducer<C> {
                         0,
                         record.headers,
                     );
-                    let _ = record.delivery_opaque.send(Err((e, owned_message)));
+                    let _ = record.delivery_opaque.send((Err((e, owned_message)), None));
                     break DeliveryFuture { rx };
                 }
             }
@@ -274,21 +613,186 @@ impl<C: ClientContext + 'static> FutureProducer<C> {
             .map_err(|(e, record)| (e, FutureRecord::from_base_record(record)))
     }
 
+    /// Sends the provided [FutureRecord], waiting for space in the producer queue if it is full.
+    ///
+    /// Unlike [FutureProducer::send], which blocks the calling thread while the queue is
+    /// full, this method returns a [SendWithBackpressure] future, that is woken up when the
+    /// delivery reports received by the polling thread free up space in the queue. The future
+    /// resolves to the [DeliveryFuture] of the message once it is enqueued. If the queue is
+    /// still full once `timeout` has expired, the future fails with [RDKafkaErrorCode::QueueFull]
+    /// and returns the record. If `timeout` is `None`, the future waits until the message is
+    /// enqueued.
+    ///
+    /// To be woken up at the deadline, a pending future with a timeout starts a timer thread the
+    /// first time it has to wait. The thread terminates at the deadline, or earlier if the future
+    /// is dropped.
+    pub fn send_with_backpressure<'a, K, P, T>(
+        &self,
+        record: FutureRecord<'a, K, P>,
+        timeout: T,
+    ) -> SendWithBackpressure<'a, C, K, P>
+    where
+        K: ToBytes + ?Sized,
+        P: ToBytes + ?Sized,
+        T: Into<Timeout>,
+    {
+        SendWithBackpressure {
+            producer: self.clone(),
+            record: Some(record),
+            deadline: match timeout.into() {
+                Timeout::After(timeout) => Some(Instant::now() + timeout),
+                Timeout::Never => None,
+            },
+            timer: None,
+        }
+    }
+
+    /// Sends the provided [FutureRecord] and blocks the calling thread until it is delivered,
+    /// returning the partition and the offset of the message. The `timeout` covers both the
+    /// time spent waiting for space in the queue and the time spent waiting for the delivery
+    /// report. If it expires, a [RDKafkaErrorCode::MessageTimedOut] error is returned, but the
+    /// message might still be delivered later, unless `message.timeout.ms` is lower than
+    /// `timeout`.
+    ///
+    /// This method is meant for scripts and tests: use [FutureProducer::send] to produce many
+    /// messages without waiting for each of them.
+    pub fn send_and_wait<K, P, T>(
+        &self,
+        record: FutureRecord<K, P>,
+        timeout: T,
+    ) -> KafkaResult<(i32, i64)>
+    where
+        K: ToBytes + ?Sized,
+        P: ToBytes + ?Sized,
+        T: Into<Timeout>,
+    {
+        let timeout = timeout.into();
+        let deadline = match timeout {
+            Timeout::After(duration) => Some(Instant::now() + duration),
+            Timeout::Never => None,
+        };
+        let delivery_future = self.send(record, i64::from(timeout.as_millis()));
+
+        let notify = NotifyHandle::from(Arc::new(ThreadNotify(thread::current())));
+        let mut delivery = executor::spawn(delivery_future);
+        loop {
+            match delivery.poll_future_notify(&notify, 0) {
+                Ok(Async::Ready(Ok(delivered))) => return Ok(delivered),
+                Ok(Async::Ready(Err((e, _)))) => return Err(e),
+                Err(Canceled) => return Err(KafkaError::Canceled),
+                Ok(Async::NotReady) => {}
+            }
+            match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(KafkaError::MessageProduction(
+                            RDKafkaErrorCode::MessageTimedOut,
+                        ));
+                    }
+                    thread::park_timeout(deadline - now);
+                }
+                None => thread::park(),
+            }
+        }
+    }
+
+    /// Sends the provided [FutureRecord] and blocks the calling thread until it is delivered,
+    /// sending it again when enqueuing it fails with a retriable error (see
+    /// [KafkaError::is_retriable]), such as a full queue, after the backoff returned by `policy`.
+    /// Returns the partition and the offset of the message, or the last error if `policy` gives
+    /// up. Errors that are not retriable are returned immediately, without consulting `policy`.
+    ///
+    /// Delivery failures are only retried if [RetryPolicy::retry_delivery_failures] returns true.
+    /// The delivery is then at least once: a record whose delivery timed out might have been
+    /// written by the broker, and sending it again duplicates it.
+    ///
+    /// As [FutureProducer::send_and_wait], this method is not meant to be used from an event
+    /// loop, since the backoff blocks the calling thread.
+    pub fn send_with_policy<K, P, R>(
+        &self,
+        record: FutureRecord<K, P>,
+        mut policy: R,
+    ) -> KafkaResult<(i32, i64)>
+    where
+        K: ToBytes + ?Sized,
+        P: ToBytes + ?Sized,
+        R: RetryPolicy,
+    {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let (err, delivery_failed) = match self.send_result(record.duplicate()) {
+                Ok(delivery_future) => match delivery_future.wait() {
+                    Ok(Ok(delivered)) => return Ok(delivered),
+                    Ok(Err((e, _))) => (e, true),
+                    Err(Canceled) => return Err(KafkaError::Canceled),
+                },
+                Err((e, _)) => (e, false),
+            };
+            if !err.is_retriable() || (delivery_failed && !policy.retry_delivery_failures()) {
+                return Err(err);
+            }
+            match policy.next_backoff(attempt, &err) {
+                Some(backoff) => thread::sleep(backoff),
+                None => return Err(err),
+            }
+        }
+    }
+
     /// Polls the internal producer. This is not normally required since the `ThreadedProducer` had
     /// a thread dedicated to calling `poll` regularly.
-    pub fn poll<T: Into<Option<Duration>>>(&self, timeout: T) {
+    pub fn poll<T: Into<Timeout>>(&self, timeout: T) {
         self.producer.poll(timeout);
     }
 
-    /// Flushes the producer. Should be called before termination.
-    pub fn flush<T: Into<Option<Duration>>>(&self, timeout: T) {
-        self.producer.flush(timeout);
+    /// Flushes the producer, waiting for all the messages to be delivered. Should be called before
+    /// termination. Returns an error if some messages are still waiting to be delivered when the
+    /// timeout expires; `in_flight_count` can be used to track the progress.
+    ///
+    /// This method blocks the calling thread until the messages are delivered or the timeout
+    /// expires, so it should not be called from an event loop that has other futures to drive.
+    pub fn flush<T: Into<Timeout>>(&self, timeout: T) -> KafkaResult<()> {
+        self.producer.flush(timeout)
+    }
+
+    /// Same as `flush`, but the error reports the number of messages that are still waiting to
+    /// be delivered. See `BaseProducer::flush_with_report`.
+    pub fn flush_with_report<T: Into<Timeout>>(&self, timeout: T) -> Result<(), FlushError> {
+        self.producer.flush_with_report(timeout)
+    }
+
+    /// Sets the maximum time the producer waits for the pending messages to be delivered when
+    /// the last clone of the producer is dropped, blocking the drop. `None`, the default,
+    /// disables the flush. The messages still pending after the flush are purged, and their
+    /// `DeliveryFuture`s resolve with a `RDKafkaErrorCode::PurgeQueue` or
+    /// `RDKafkaErrorCode::PurgeInflight` error. A warning is logged if some messages are purged.
+    pub fn set_drop_flush_timeout<T: Into<Option<Duration>>>(&self, timeout: T) {
+        self.producer.set_drop_flush_timeout(timeout);
     }
 
     /// Returns the number of messages waiting to be sent, or send but not acknowledged yet.
     pub fn in_flight_count(&self) -> i32 {
         self.producer.in_flight_count()
     }
+
+    /// Returns the fatal error raised by the producer, if any. See `Client::fatal_error`.
+    pub fn fatal_error(&self) -> Option<(RDKafkaErrorCode, String)> {
+        self.producer.fatal_error()
+    }
+
+    /// Returns the name of the client instance. See `Client::name`.
+    pub fn name(&self) -> String {
+        self.producer.name()
+    }
+
+    /// Purges the messages of the producer selected by the `PurgeConfig`. The `DeliveryFuture`s
+    /// of the purged messages resolve with a `KafkaError::MessageProduction` error, carrying
+    /// `RDKafkaErrorCode::PurgeQueue` or `RDKafkaErrorCode::PurgeInflight`. See
+    /// `BaseProducer::purge`.
+    pub fn purge(&self, config: PurgeConfig) -> KafkaResult<()> {
+        self.producer.purge(config)
+    }
 }
 
 #[cfg(test)]
@@ -297,6 +801,7 @@ mod tests {
     // traits (Clone, Send, Sync etc.). Behavior is tested in the integrations tests.
     use super::*;
     use crate::config::ClientConfig;
+    use crate::message::Headers;
 
     struct TestContext;
 
@@ -309,6 +814,47 @@ mod tests {
         }
     }
 
+    // Messages can't be delivered without a broker, so the flush on drop should time out, and
+    // the pending message should be purged. Without a flush timeout, the drop doesn't wait.
+    #[test]
+    fn test_future_producer_drop_flush_timeout() {
+        for &timeout in &[None, Some(Duration::from_millis(500))] {
+            let producer: FutureProducer = ClientConfig::new()
+                .set("message.timeout.ms", "10000")
+                .create()
+                .unwrap();
+            producer.set_drop_flush_timeout(timeout);
+            let delivery_future =
+                producer.send(FutureRecord::to("topic").payload("payload").key("key"), 0);
+
+            let start = Instant::now();
+            drop(producer);
+            match timeout {
+                Some(timeout) => assert!(start.elapsed() >= timeout),
+                None => assert!(start.elapsed() < Duration::from_millis(500)),
+            }
+            match delivery_future.wait() {
+                Ok(Err((KafkaError::MessageProduction(RDKafkaErrorCode::PurgeQueue), _))) => {}
+                result => panic!("Unexpected delivery result: {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_future_record_header() {
+        let record = FutureRecord::<(), ()>::to("topic")
+            .headers(OwnedHeaders::new().add("first", "A"))
+            .header("second", Some("B"))
+            .header::<str>("null", None)
+            .header("integer", Some(&1i32));
+        let headers = record.headers.unwrap();
+        assert_eq!(headers.count(), 4);
+        assert_eq!(headers.get_as::<str>(0), Some(("first", Ok("A"))));
+        assert_eq!(headers.get_as::<str>(1), Some(("second", Ok("B"))));
+        assert_eq!(headers.get_nullable(2), Some(("null", None)));
+        assert_eq!(headers.get(3), Some(("integer", &[0, 0, 0, 1][..])));
+    }
+
     // Verify that the future producer is clone, according to documentation.
     #[test]
     fn test_future_producer_clone() {
@@ -316,6 +862,353 @@ mod tests {
         let _producer_clone = producer.clone();
     }
 
+    // Messages can't be delivered without a broker, so flushing should time out.
+    #[test]
+    fn test_future_producer_flush_timeout() {
+        let producer = ClientConfig::new()
+            .set("message.timeout.ms", "10000")
+            .create::<FutureProducer>()
+            .unwrap();
+        let _delivery_future =
+            producer.send(FutureRecord::to("topic").payload("payload").key("key"), 0);
+
+        assert_eq!(producer.in_flight_count(), 1);
+        assert_eq!(
+            producer.flush(Duration::from_millis(100)),
+            Err(KafkaError::Flush(RDKafkaErrorCode::OperationTimedOut))
+        );
+        assert_eq!(producer.in_flight_count(), 1);
+    }
+
+    // Without a broker the message can't be delivered, so waiting for it should time out.
+    #[test]
+    fn test_future_producer_send_and_wait_timeout() {
+        let producer = ClientConfig::new()
+            .set("message.timeout.ms", "10000")
+            .create::<FutureProducer>()
+            .unwrap();
+        let start = Instant::now();
+        let result = producer.send_and_wait(
+            FutureRecord::to("topic").payload("payload").key("key"),
+            Duration::from_millis(200),
+        );
+        assert_eq!(
+            result,
+            Err(KafkaError::MessageProduction(
+                RDKafkaErrorCode::MessageTimedOut
+            ))
+        );
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert_eq!(producer.in_flight_count(), 1);
+    }
+
+    // Purging the queue should fail the pending deliveries without waiting for a broker.
+    #[test]
+    fn test_future_producer_purge() {
+        let producer = ClientConfig::new()
+            .set("message.timeout.ms", "10000")
+            .create::<FutureProducer>()
+            .unwrap();
+        let delivery_future =
+            producer.send(FutureRecord::to("topic").payload("payload").key("key"), 0);
+
+        assert_eq!(producer.in_flight_count(), 1);
+        producer.purge(PurgeConfig::new().queue()).unwrap();
+        match delivery_future.wait() {
+            Ok(Err((err, message))) => {
+                assert_eq!(
+                    err,
+                    KafkaError::MessageProduction(RDKafkaErrorCode::PurgeQueue)
+                );
+                assert_eq!(message.payload_view::<str>(), Some(Ok("payload")));
+            }
+            other => panic!("unexpected delivery result: {:?}", other),
+        }
+        assert_eq!(producer.flush(Duration::from_secs(5)), Ok(()));
+        assert_eq!(producer.in_flight_count(), 0);
+    }
+
+    // The latency of a failed delivery is measured from the enqueue of the message, and is also
+    // available on the returned message.
+    #[test]
+    fn test_future_producer_delivery_latency() {
+        let producer = ClientConfig::new()
+            .set("message.timeout.ms", "10000")
+            .create::<FutureProducer>()
+            .unwrap();
+        let delivery_future =
+            producer.send(FutureRecord::to("topic").payload("payload").key("key"), 0);
+        thread::sleep(Duration::from_millis(10));
+        producer.purge(PurgeConfig::new().queue()).unwrap();
+
+        match delivery_future.with_latency().wait() {
+            Ok((Err((_, message)), Some(latency))) => {
+                assert!(latency >= 10_000);
+                assert_eq!(message.latency_us(), Some(latency));
+            }
+            other => panic!("unexpected delivery result: {:?}", other),
+        }
+        let message = OwnedMessage::new(
+            None,
+            None,
+            "topic".to_owned(),
+            Timestamp::NotAvailable,
+            0,
+            0,
+            None,
+        );
+        assert_eq!(message.latency_us(), None);
+    }
+
+    /// Records the attempts it is consulted for, and retries up to `retries` times.
+    #[derive(Default)]
+    struct RecordingPolicy {
+        attempts: Vec<(u32, KafkaError)>,
+        retries: u32,
+        retry_delivery_failures: bool,
+    }
+
+    impl RetryPolicy for RecordingPolicy {
+        fn next_backoff(&mut self, attempt: u32, err: &KafkaError) -> Option<Duration> {
+            self.attempts.push((attempt, err.clone()));
+            if attempt <= self.retries {
+                Some(Duration::from_millis(1))
+            } else {
+                None
+            }
+        }
+
+        fn retry_delivery_failures(&self) -> bool {
+            self.retry_delivery_failures
+        }
+    }
+
+    #[test]
+    fn test_exponential_backoff() {
+        let mut policy = ExponentialBackoff::new()
+            .initial_backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_millis(500))
+            .max_retries(4);
+        let err = KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull);
+        let backoffs = (1..=5)
+            .map(|attempt| policy.next_backoff(attempt, &err))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            backoffs,
+            vec![
+                Some(Duration::from_millis(100)),
+                Some(Duration::from_millis(200)),
+                Some(Duration::from_millis(400)),
+                Some(Duration::from_millis(500)),
+                None,
+            ]
+        );
+        let mut policy = ExponentialBackoff::new().max_retries(100);
+        assert_eq!(policy.next_backoff(64, &err), Some(Duration::from_secs(10)));
+    }
+
+    // A full queue is retriable, so the record is sent again until the policy gives up.
+    #[test]
+    fn test_future_producer_send_with_policy_gives_up() {
+        let producer = ClientConfig::new()
+            .set("message.timeout.ms", "10000")
+            .set("queue.buffering.max.messages", "1")
+            .create::<FutureProducer>()
+            .unwrap();
+        let _delivery_future =
+            producer.send(FutureRecord::to("topic").payload("first").key("key"), 0);
+
+        let mut policy = RecordingPolicy {
+            retries: 2,
+            ..RecordingPolicy::default()
+        };
+        let result = producer.send_with_policy(
+            FutureRecord::to("topic").payload("second").key("key"),
+            &mut policy,
+        );
+        let queue_full = KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull);
+        assert_eq!(result, Err(queue_full.clone()));
+        assert_eq!(
+            policy.attempts,
+            vec![
+                (1, queue_full.clone()),
+                (2, queue_full.clone()),
+                (3, queue_full)
+            ]
+        );
+    }
+
+    // A timed out delivery is only retried if the policy asks for it, as the record might have
+    // been written anyway.
+    #[test]
+    fn test_future_producer_send_with_policy_delivery_failure() {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", "localhost:1")
+            .set("message.timeout.ms", "100")
+            .create::<FutureProducer>()
+            .unwrap();
+        let timed_out = KafkaError::MessageProduction(RDKafkaErrorCode::MessageTimedOut);
+
+        let mut policy = RecordingPolicy {
+            retries: 1,
+            ..RecordingPolicy::default()
+        };
+        let result = producer.send_with_policy(
+            FutureRecord::to("topic").payload("A").key("key"),
+            &mut policy,
+        );
+        assert_eq!(result, Err(timed_out.clone()));
+        assert!(policy.attempts.is_empty());
+
+        let mut policy = RecordingPolicy {
+            retries: 1,
+            retry_delivery_failures: true,
+            ..RecordingPolicy::default()
+        };
+        let result = producer.send_with_policy(
+            FutureRecord::to("topic").payload("A").key("key"),
+            &mut policy,
+        );
+        assert_eq!(result, Err(timed_out.clone()));
+        assert_eq!(
+            policy.attempts,
+            vec![(1, timed_out.clone()), (2, timed_out)]
+        );
+    }
+
+    // An oversized message is rejected without consulting the policy.
+    #[test]
+    fn test_future_producer_send_with_policy_not_retriable() {
+        let producer = ClientConfig::new()
+            .set("message.max.bytes", "1000")
+            .create::<FutureProducer>()
+            .unwrap();
+        let payload = vec![0u8; 2000];
+
+        let mut policy = RecordingPolicy {
+            retries: 2,
+            ..RecordingPolicy::default()
+        };
+        let result = producer.send_with_policy(
+            FutureRecord::<(), _>::to("topic").payload(&payload),
+            &mut policy,
+        );
+        assert_eq!(
+            result,
+            Err(KafkaError::MessageProduction(
+                RDKafkaErrorCode::MessageSizeTooLarge
+            ))
+        );
+        assert!(policy.attempts.is_empty());
+    }
+
+    // Without a broker, the queued message is only removed from the queue when it times out.
+    #[test]
+    fn test_future_producer_send_with_backpressure() {
+        let producer = ClientConfig::new()
+            .set("message.timeout.ms", "500")
+            .set("queue.buffering.max.messages", "1")
+            .create::<FutureProducer>()
+            .unwrap();
+        let first_delivery =
+            producer.send(FutureRecord::to("topic").payload("first").key("key"), 0);
+        assert_eq!(producer.in_flight_count(), 1);
+
+        let second_delivery = producer
+            .send_with_backpressure(
+                FutureRecord::to("topic").payload("second").key("key"),
+                Duration::from_secs(10),
+            )
+            .wait()
+            .unwrap();
+
+        match first_delivery.wait() {
+            Ok(Err((
+                KafkaError::MessageProduction(RDKafkaErrorCode::MessageTimedOut),
+                message,
+            ))) => {
+                assert_eq!(message.payload_view::<str>(), Some(Ok("first")))
+            }
+            res => panic!("Unexpected delivery result: {:?}", res),
+        }
+        assert_eq!(producer.in_flight_count(), 1);
+        drop(second_delivery);
+    }
+
+    #[test]
+    fn test_future_producer_send_with_backpressure_timeout() {
+        let producer = ClientConfig::new()
+            .set("message.timeout.ms", "10000")
+            .set("queue.buffering.max.messages", "1")
+            .create::<FutureProducer>()
+            .unwrap();
+        let _delivery_future =
+            producer.send(FutureRecord::to("topic").payload("first").key("key"), 0);
+
+        match producer
+            .send_with_backpressure(
+                FutureRecord::to("topic").payload("second").key("key"),
+                Duration::from_secs(0),
+            )
+            .wait()
+        {
+            Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), record)) => {
+                assert_eq!(record.payload, Some("second"))
+            }
+            Ok(_) => panic!("Message enqueued in a full queue"),
+            Err((e, _)) => panic!("Unexpected error: {:?}", e),
+        }
+    }
+
+    // The timeout should expire even if no delivery report frees up the queue in the meantime.
+    #[test]
+    fn test_future_producer_send_with_backpressure_deadline() {
+        let producer = ClientConfig::new()
+            .set("message.timeout.ms", "10000")
+            .set("queue.buffering.max.messages", "1")
+            .create::<FutureProducer>()
+            .unwrap();
+        let _delivery_future =
+            producer.send(FutureRecord::to("topic").payload("first").key("key"), 0);
+
+        let start = Instant::now();
+        match producer
+            .send_with_backpressure(
+                FutureRecord::to("topic").payload("second").key("key"),
+                Duration::from_millis(200),
+            )
+            .wait()
+        {
+            Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), _)) => {}
+            Ok(_) => panic!("Message enqueued in a full queue"),
+            Err((e, _)) => panic!("Unexpected error: {:?}", e),
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(200));
+        assert!(elapsed < Duration::from_secs(5));
+    }
+
+    // The delivery report is served as soon as it is available, without waiting for the poll
+    // interval to elapse.
+    #[test]
+    fn test_future_producer_poll_interval() {
+        let producer = FutureProducer::with_polling_config(
+            ClientConfig::new().set("message.timeout.ms", "100"),
+            DefaultClientContext,
+            PollingConfig::new().poll_interval(Duration::from_secs(3)),
+        )
+        .unwrap();
+        let start = Instant::now();
+        let delivery_result = producer
+            .send(FutureRecord::to("topic").payload("payload").key("key"), 0)
+            .wait();
+        assert!(start.elapsed() < Duration::from_millis(2500));
+        match delivery_result {
+            Ok(Err((KafkaError::MessageProduction(RDKafkaErrorCode::MessageTimedOut), _))) => {}
+            res => panic!("Unexpected delivery result: {:?}", res),
+        }
+    }
+
     // Test that the future producer can be cloned even if the context is not Clone.
     #[test]
     fn test_base_future_topic_send_sync() {
@@ -13,7 +13,7 @@
 //! The librdkafka user is responsible for calling the `poll` function at regular intervals to
 //! process those events; the thread calling `poll` will be the one executing the user-specified
 //! delivery callback for every delivery event. If `poll` is not called, or not frequently
-//! enough, the producer will return a `RDKafkaError::QueueFull` error and it won't be able to send any other
+//! enough, the producer will return a `RDKafkaErrorCode::QueueFull` error and it won't be able to send any other
 //! message until more delivery event are processed via `poll`. The `QueueFull` error can also be
 //! returned if Kafka is not able to receive the messages quickly enough.
 //!
@@ -78,7 +78,11 @@ pub mod base_producer;
 pub mod future_producer;
 
 pub use self::base_producer::{
-    BaseProducer, BaseRecord, DefaultProducerContext, DeliveryResult, ProducerContext,
-    ThreadedProducer,
+    BaseProducer, BaseRecord, DefaultProducerContext, DeliveryResult, DeliveryStream,
+    DeliveryStreamContext, FlushError, InterceptedRecord, OwnedDeliveryResult, PollingConfig,
+    ProducerContext, PurgeConfig, ThreadedProducer, Topic,
+};
+pub use self::future_producer::{
+    DeliveryFuture, DeliveryWithLatency, ExponentialBackoff, FutureProducer, FutureRecord,
+    RetryPolicy, SendWithBackpressure,
 };
-pub use self::future_producer::{DeliveryFuture, FutureProducer, FutureRecord};
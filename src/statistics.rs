@@ -5,9 +5,15 @@ use std::collections::HashMap;
 
 /// Statistics from librdkafka. Refer to the [librdkafka documentation](https://github.com/edenhill/librdkafka/wiki/Statistics)
 /// for details.
+///
+/// The set of fields emitted by librdkafka changes from version to version. Fields that are not
+/// available in all versions default to zero (or to an empty value) when they are missing, so that
+/// parsing doesn't fail.
 #[derive(Deserialize, Debug)]
 pub struct Statistics {
     pub name: String,
+    #[serde(default)]
+    pub client_id: String,
     #[serde(rename = "type")]
     pub client_type: String,
     pub ts: i64,
@@ -17,7 +23,26 @@ pub struct Statistics {
     pub msg_size: i64,
     pub msg_max: i64,
     pub msg_size_max: i64,
+    #[serde(default)]
     pub simple_cnt: i64,
+    #[serde(default)]
+    pub metadata_cache_cnt: i64,
+    #[serde(default)]
+    pub tx: i64,
+    #[serde(default)]
+    pub tx_bytes: i64,
+    #[serde(default)]
+    pub rx: i64,
+    #[serde(default)]
+    pub rx_bytes: i64,
+    #[serde(default)]
+    pub txmsgs: i64,
+    #[serde(default)]
+    pub txmsg_bytes: i64,
+    #[serde(default)]
+    pub rxmsgs: i64,
+    #[serde(default)]
+    pub rxmsg_bytes: i64,
     pub brokers: HashMap<String, Broker>,
     pub topics: HashMap<String, Topic>,
     pub cgrp: Option<ConsumerGroup>,
@@ -27,6 +52,10 @@ pub struct Statistics {
 pub struct Broker {
     pub name: String,
     pub nodeid: i32,
+    #[serde(default)]
+    pub nodename: String,
+    #[serde(default)]
+    pub source: String,
     pub state: String,
     pub stateage: i64,
     pub outbuf_cnt: i64,
@@ -43,12 +72,19 @@ pub struct Broker {
     pub rxerrs: i64,
     pub rxcorriderrs: i64,
     pub rxpartial: i64,
+    #[serde(default)]
     pub zbuf_grow: i64,
+    #[serde(default)]
     pub buf_grow: i64,
     pub wakeups: Option<i64>,
+    pub connects: Option<i64>,
+    pub disconnects: Option<i64>,
     pub int_latency: Option<Window>,
+    pub outbuf_latency: Option<Window>,
     pub rtt: Option<Window>,
     pub throttle: Option<Window>,
+    #[serde(default)]
+    pub req: HashMap<String, i64>,
     pub toppars: HashMap<String, TopicPartition>,
 }
 
@@ -59,6 +95,24 @@ pub struct Window {
     pub avg: i64,
     pub sum: i64,
     pub cnt: i64,
+    #[serde(default)]
+    pub stddev: i64,
+    #[serde(default)]
+    pub p50: i64,
+    #[serde(default)]
+    pub p75: i64,
+    #[serde(default)]
+    pub p90: i64,
+    #[serde(default)]
+    pub p95: i64,
+    #[serde(default)]
+    pub p99: i64,
+    #[serde(default)]
+    pub p99_99: i64,
+    #[serde(default)]
+    pub outofrange: i64,
+    #[serde(default)]
+    pub hdrsize: i64,
 }
 
 #[derive(Deserialize, Debug)]
@@ -71,6 +125,8 @@ pub struct TopicPartition {
 pub struct Topic {
     pub topic: String,
     pub metadata_age: i64,
+    pub batchsize: Option<Window>,
+    pub batchcnt: Option<Window>,
     pub partitions: HashMap<i32, Partition>,
 }
 
@@ -95,15 +151,35 @@ pub struct Partition {
     pub eof_offset: i64,
     pub lo_offset: i64,
     pub hi_offset: i64,
+    #[serde(default)]
+    pub ls_offset: i64,
     pub consumer_lag: i64,
     pub txmsgs: i64,
     pub txbytes: i64,
+    #[serde(default)]
+    pub rxmsgs: i64,
+    #[serde(default)]
+    pub rxbytes: i64,
     pub msgs: i64,
     pub rx_ver_drops: i64,
+    #[serde(default)]
+    pub msgs_inflight: i64,
+    #[serde(default)]
+    pub next_ack_seq: i64,
+    #[serde(default)]
+    pub next_err_seq: i64,
+    #[serde(default)]
+    pub acked_msgid: i64,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct ConsumerGroup {
+    #[serde(default)]
+    pub state: String,
+    #[serde(default)]
+    pub stateage: i64,
+    #[serde(default)]
+    pub join_state: String,
     pub rebalance_age: i64,
     pub rebalance_cnt: i64,
     pub assignment_size: i32,
@@ -131,6 +207,43 @@ mod tests {
 
         assert_eq!(stats.brokers.len(), 4);
         assert_eq!(stats.topics.len(), 1);
+
+        // Fields missing from older versions of librdkafka get a default value.
+        assert_eq!(stats.client_id, "");
+        assert_eq!(stats.txmsgs, 0);
+        let broker = &stats.brokers["localhost:9092/2"];
+        assert!(broker.outbuf_latency.is_none());
+        assert!(broker.req.is_empty());
+        assert_eq!(broker.rtt.as_ref().unwrap().p99, 0);
+    }
+
+    #[test]
+    fn test_statistics_librdkafka_1_2() {
+        let stats: Statistics = serde_json::from_str(EXAMPLE_1_2).unwrap();
+
+        assert_eq!(stats.name, "rdkafka#producer-1");
+        assert_eq!(stats.client_id, "rdkafka");
+        assert_eq!(stats.client_type, "producer");
+        assert_eq!(stats.msg_cnt, 1);
+        assert_eq!(stats.msg_max, 100_000);
+        assert!(stats.cgrp.is_none());
+
+        let broker = &stats.brokers["localhost:9092/bootstrap"];
+        assert_eq!(broker.nodeid, -1);
+        assert_eq!(broker.nodename, "localhost:9092");
+        assert_eq!(broker.source, "configured");
+        assert_eq!(broker.state, "INIT");
+        assert_eq!(broker.connects, Some(1));
+        assert_eq!(broker.rtt.as_ref().unwrap().hdrsize, 13424);
+        assert_eq!(broker.outbuf_latency.as_ref().unwrap().hdrsize, 11376);
+        assert_eq!(broker.req["Produce"], 0);
+
+        let topic = &stats.topics["test"];
+        assert_eq!(topic.batchcnt.as_ref().unwrap().hdrsize, 8304);
+        let partition = &topic.partitions[&-1];
+        assert_eq!(partition.msgq_cnt, 1);
+        assert_eq!(partition.ls_offset, -1001);
+        assert_eq!(partition.consumer_lag, -1);
     }
 
     // Example from https://github.com/edenhill/librdkafka/wiki/Statistics
@@ -497,4 +610,206 @@ mod tests {
           }
         }
         "#;
+
+    // Captured from a producer using librdkafka v1.2.1, with no reachable broker.
+    const EXAMPLE_1_2: &str = r#"
+        {
+          "name": "rdkafka#producer-1",
+          "client_id": "rdkafka",
+          "type": "producer",
+          "ts": 1398993552,
+          "time": 1791973645,
+          "replyq": 0,
+          "msg_cnt": 1,
+          "msg_size": 1,
+          "msg_max": 100000,
+          "msg_size_max": 1073741824,
+          "simple_cnt": 0,
+          "metadata_cache_cnt": 0,
+          "brokers": {
+            "localhost:9092/bootstrap": {
+              "name": "localhost:9092/bootstrap",
+              "nodeid": -1,
+              "nodename": "localhost:9092",
+              "source": "configured",
+              "state": "INIT",
+              "stateage": 499453,
+              "outbuf_cnt": 0,
+              "outbuf_msg_cnt": 0,
+              "waitresp_cnt": 0,
+              "waitresp_msg_cnt": 0,
+              "tx": 0,
+              "txbytes": 0,
+              "txerrs": 0,
+              "txretries": 0,
+              "req_timeouts": 0,
+              "rx": 0,
+              "rxbytes": 0,
+              "rxerrs": 0,
+              "rxcorriderrs": 0,
+              "rxpartial": 0,
+              "zbuf_grow": 0,
+              "buf_grow": 0,
+              "wakeups": 1,
+              "connects": 1,
+              "disconnects": 0,
+              "int_latency": {
+                "min": 0,
+                "max": 0,
+                "avg": 0,
+                "sum": 0,
+                "stddev": 0,
+                "p50": 0,
+                "p75": 0,
+                "p90": 0,
+                "p95": 0,
+                "p99": 0,
+                "p99_99": 0,
+                "outofrange": 0,
+                "hdrsize": 11376,
+                "cnt": 0
+              },
+              "outbuf_latency": {
+                "min": 0,
+                "max": 0,
+                "avg": 0,
+                "sum": 0,
+                "stddev": 0,
+                "p50": 0,
+                "p75": 0,
+                "p90": 0,
+                "p95": 0,
+                "p99": 0,
+                "p99_99": 0,
+                "outofrange": 0,
+                "hdrsize": 11376,
+                "cnt": 0
+              },
+              "rtt": {
+                "min": 0,
+                "max": 0,
+                "avg": 0,
+                "sum": 0,
+                "stddev": 0,
+                "p50": 0,
+                "p75": 0,
+                "p90": 0,
+                "p95": 0,
+                "p99": 0,
+                "p99_99": 0,
+                "outofrange": 0,
+                "hdrsize": 13424,
+                "cnt": 0
+              },
+              "throttle": {
+                "min": 0,
+                "max": 0,
+                "avg": 0,
+                "sum": 0,
+                "stddev": 0,
+                "p50": 0,
+                "p75": 0,
+                "p90": 0,
+                "p95": 0,
+                "p99": 0,
+                "p99_99": 0,
+                "outofrange": 0,
+                "hdrsize": 17520,
+                "cnt": 0
+              },
+              "req": {
+                "Produce": 0,
+                "Offset": 0,
+                "Metadata": 0,
+                "SaslHandshake": 0,
+                "ApiVersion": 0,
+                "InitProducerId": 0
+              },
+              "toppars": {}
+            }
+          },
+          "topics": {
+            "test": {
+              "topic": "test",
+              "metadata_age": 0,
+              "batchsize": {
+                "min": 0,
+                "max": 0,
+                "avg": 0,
+                "sum": 0,
+                "stddev": 0,
+                "p50": 0,
+                "p75": 0,
+                "p90": 0,
+                "p95": 0,
+                "p99": 0,
+                "p99_99": 0,
+                "outofrange": 0,
+                "hdrsize": 14448,
+                "cnt": 0
+              },
+              "batchcnt": {
+                "min": 0,
+                "max": 0,
+                "avg": 0,
+                "sum": 0,
+                "stddev": 0,
+                "p50": 0,
+                "p75": 0,
+                "p90": 0,
+                "p95": 0,
+                "p99": 0,
+                "p99_99": 0,
+                "outofrange": 0,
+                "hdrsize": 8304,
+                "cnt": 0
+              },
+              "partitions": {
+                "-1": {
+                  "partition": -1,
+                  "leader": -1,
+                  "desired": false,
+                  "unknown": false,
+                  "msgq_cnt": 1,
+                  "msgq_bytes": 1,
+                  "xmit_msgq_cnt": 0,
+                  "xmit_msgq_bytes": 0,
+                  "fetchq_cnt": 0,
+                  "fetchq_size": 0,
+                  "fetch_state": "none",
+                  "query_offset": -1001,
+                  "next_offset": 0,
+                  "app_offset": -1001,
+                  "stored_offset": -1001,
+                  "commited_offset": -1001,
+                  "committed_offset": -1001,
+                  "eof_offset": -1001,
+                  "lo_offset": -1001,
+                  "hi_offset": -1001,
+                  "ls_offset": -1001,
+                  "consumer_lag": -1,
+                  "txmsgs": 0,
+                  "txbytes": 0,
+                  "rxmsgs": 0,
+                  "rxbytes": 0,
+                  "msgs": 1,
+                  "rx_ver_drops": 0,
+                  "msgs_inflight": 0,
+                  "next_ack_seq": 0,
+                  "next_err_seq": 0,
+                  "acked_msgid": 0
+                }
+              }
+            }
+          },
+          "tx": 0,
+          "tx_bytes": 0,
+          "rx": 0,
+          "rx_bytes": 0,
+          "txmsgs": 0,
+          "txmsg_bytes": 0,
+          "rxmsgs": 0,
+          "rxmsg_bytes": 0
+        }
+    "#;
 }
@@ -10,6 +10,7 @@ use std::ffi::{CStr, CString};
 use std::fmt;
 use std::ptr;
 use std::slice;
+use std::str;
 
 const PARTITION_UNASSIGNED: i32 = -1;
 
@@ -17,6 +18,7 @@ const OFFSET_BEGINNING: i64 = rdsys::RD_KAFKA_OFFSET_BEGINNING as i64;
 const OFFSET_END: i64 = rdsys::RD_KAFKA_OFFSET_END as i64;
 const OFFSET_STORED: i64 = rdsys::RD_KAFKA_OFFSET_STORED as i64;
 const OFFSET_INVALID: i64 = rdsys::RD_KAFKA_OFFSET_INVALID as i64;
+const OFFSET_TAIL_BASE: i64 = rdsys::RD_KAFKA_OFFSET_TAIL_BASE as i64;
 
 /// A librdkafka offset.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -25,12 +27,18 @@ pub enum Offset {
     Beginning,
     /// Start consuming from the end of the partition.
     End,
-    /// Start consuming from the stored offset.
+    /// Start consuming from the stored offset, i.e. the offset committed by the consumer group.
+    /// Unlike `Beginning`, which always starts from the first message of the partition, it
+    /// resumes from where the group left off; when assigning partitions, `auto.offset.reset`
+    /// applies to the partitions without a committed offset.
     Stored,
     /// Offset not assigned or invalid.
     Invalid,
     /// A specific offset to consume from.
     Offset(i64),
+    /// Start consuming the specified number of messages before the end of the partition. The
+    /// number of messages must not be negative.
+    OffsetTail(i64),
 }
 
 impl Offset {
@@ -41,11 +49,15 @@ impl Offset {
             OFFSET_END => Offset::End,
             OFFSET_STORED => Offset::Stored,
             OFFSET_INVALID => Offset::Invalid,
+            // Can't overflow, as `n` is negative.
+            n if n <= OFFSET_TAIL_BASE => Offset::OffsetTail(OFFSET_TAIL_BASE - n),
             n => Offset::Offset(n),
         }
     }
 
-    /// Converts the `Offset` to the internal integer representation used by librdkafka.
+    /// Converts the `Offset` to the internal integer representation used by librdkafka. An
+    /// `Offset::OffsetTail` with a negative number of messages, or with a number of messages
+    /// too large to be represented, is converted to the representation of `Offset::Invalid`.
     pub fn to_raw(&self) -> i64 {
         match *self {
             Offset::Beginning => OFFSET_BEGINNING,
@@ -53,6 +65,10 @@ impl Offset {
             Offset::Stored => OFFSET_STORED,
             Offset::Invalid => OFFSET_INVALID,
             Offset::Offset(n) => n,
+            Offset::OffsetTail(n) if n >= 0 => {
+                OFFSET_TAIL_BASE.checked_sub(n).unwrap_or(OFFSET_INVALID)
+            }
+            Offset::OffsetTail(_) => OFFSET_INVALID,
         }
     }
 }
@@ -106,11 +122,33 @@ impl<'a> TopicPartitionListElem<'a> {
         Offset::from_raw(raw_offset)
     }
 
+    /// Returns the metadata associated with the entry, or an empty string if the entry has no
+    /// metadata or if the metadata is not valid UTF-8.
+    pub fn metadata(&self) -> &str {
+        let bytes = unsafe {
+            if (*self.ptr).metadata.is_null() {
+                return "";
+            }
+            slice::from_raw_parts((*self.ptr).metadata as *const u8, (*self.ptr).metadata_size)
+        };
+        str::from_utf8(bytes).unwrap_or("")
+    }
+
     /// Sets the offset.
     pub fn set_offset(&self, offset: Offset) {
         let raw_offset = offset.to_raw();
         unsafe { (*self.ptr).offset = raw_offset };
     }
+
+    /// Returns the error code associated to the entry.
+    pub(crate) fn error_code(&self) -> RDKafkaRespErr {
+        unsafe { (*self.ptr).err }
+    }
+
+    /// Sets the error code associated to the entry.
+    pub(crate) fn set_error(&self, error: RDKafkaRespErr) {
+        unsafe { (*self.ptr).err = error };
+    }
 }
 
 impl<'a> PartialEq for TopicPartitionListElem<'a> {
@@ -126,6 +164,11 @@ pub struct TopicPartitionList {
     ptr: *mut RDKafkaTopicPartitionList,
 }
 
+// SAFETY: the native list is owned exclusively by the `TopicPartitionList`, which is the only one
+// to access and destroy it, and librdkafka doesn't tie it to the thread that created it. It is not
+// `Sync`, as `TopicPartitionListElem::set_offset` modifies the list through a shared reference.
+unsafe impl Send for TopicPartitionList {}
+
 impl Clone for TopicPartitionList {
     fn clone(&self) -> Self {
         let new_tpl = unsafe { rdsys::rd_kafka_topic_partition_list_copy(self.ptr) };
@@ -259,36 +302,44 @@ impl TopicPartitionList {
         }
     }
 
-    /// Sets all partitions in the list to the specified offset.
-    pub fn set_all_offsets(&mut self, offset: Offset) {
-        let slice = unsafe { slice::from_raw_parts_mut((*self.ptr).elems, self.count()) };
-        for elem_ptr in slice {
-            let elem = unsafe { TopicPartitionListElem::from_ptr(self, &mut *elem_ptr) };
+    /// Sets all partitions in the list to the specified offset. Fails with
+    /// `RDKafkaErrorCode::InvalidArgument`, without modifying the list, if librdkafka can't
+    /// represent the offset: an `Offset::Offset` or an `Offset::OffsetTail` with a negative
+    /// value, or an `Offset::OffsetTail` too large to fit.
+    pub fn set_all_offsets(&mut self, offset: Offset) -> KafkaResult<()> {
+        let valid = match offset {
+            Offset::Offset(n) => n >= 0,
+            Offset::OffsetTail(_) => Offset::from_raw(offset.to_raw()) == offset,
+            _ => true,
+        };
+        if !valid {
+            return Err(KafkaError::SetPartitionOffset(
+                RDKafkaErrorCode::InvalidArgument,
+            ));
+        }
+        for elem in self.iter() {
             elem.set_offset(offset);
         }
+        Ok(())
+    }
+
+    /// Returns an iterator over the elements of the list. The list can't be modified while the
+    /// iterator is alive.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            list: self,
+            next_idx: 0,
+        }
     }
 
     /// Returns all the elements of the list.
     pub fn elements(&self) -> Vec<TopicPartitionListElem> {
-        let slice = unsafe { slice::from_raw_parts_mut((*self.ptr).elems, self.count()) };
-        let mut vec = Vec::with_capacity(slice.len());
-        for elem_ptr in slice {
-            vec.push(unsafe { TopicPartitionListElem::from_ptr(self, &mut *elem_ptr) });
-        }
-        vec
+        self.iter().collect()
     }
 
     /// Returns all the elements of the list that belong to the specified topic.
     pub fn elements_for_topic<'a>(&'a self, topic: &str) -> Vec<TopicPartitionListElem<'a>> {
-        let slice = unsafe { slice::from_raw_parts_mut((*self.ptr).elems, self.count()) };
-        let mut vec = Vec::with_capacity(slice.len());
-        for elem_ptr in slice {
-            let tp = unsafe { TopicPartitionListElem::from_ptr(self, &mut *elem_ptr) };
-            if tp.topic() == topic {
-                vec.push(tp);
-            }
-        }
-        vec
+        self.iter().filter(|elem| elem.topic() == topic).collect()
     }
 
     /// Returns a hashmap-based representation of the list.
@@ -300,6 +351,41 @@ impl TopicPartitionList {
     }
 }
 
+impl<'a> IntoIterator for &'a TopicPartitionList {
+    type Item = TopicPartitionListElem<'a>;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the elements of a `TopicPartitionList`.
+pub struct Iter<'a> {
+    list: &'a TopicPartitionList,
+    next_idx: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = TopicPartitionListElem<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_idx >= self.list.count() {
+            return None;
+        }
+        let elem_ptr = unsafe { (*self.list.ptr).elems.add(self.next_idx) };
+        self.next_idx += 1;
+        Some(unsafe { TopicPartitionListElem::from_ptr(self.list, elem_ptr) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.list.count() - self.next_idx;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
 impl Drop for TopicPartitionList {
     fn drop(&mut self) {
         if !self.ptr.is_null() {
@@ -401,6 +487,58 @@ mod tests {
         assert_eq!(tp3.offset(), Offset::Offset(1234));
     }
 
+    #[test]
+    fn set_all_offsets() {
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition_offset("topic1", 0, Offset::Offset(5));
+        tpl.add_partition_offset("topic1", 1, Offset::Stored);
+        tpl.add_partition("topic2", 0);
+
+        tpl.set_all_offsets(Offset::Beginning).unwrap();
+
+        let mut expected = TopicPartitionList::new();
+        expected.add_partition_offset("topic1", 0, Offset::Beginning);
+        expected.add_partition_offset("topic1", 1, Offset::Beginning);
+        expected.add_partition_offset("topic2", 0, Offset::Beginning);
+        assert_eq!(tpl, expected);
+
+        let topic1 = tpl.elements_for_topic("topic1");
+        assert_eq!(
+            topic1
+                .iter()
+                .map(|elem| (elem.topic(), elem.partition(), elem.offset()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("topic1", 0, Offset::Beginning),
+                ("topic1", 1, Offset::Beginning)
+            ]
+        );
+        assert!(tpl.elements_for_topic("topic3").is_empty());
+    }
+
+    #[test]
+    fn set_all_offsets_invalid() {
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition_offset("topic1", 0, Offset::Offset(5));
+
+        for &offset in &[
+            Offset::Offset(-1),
+            Offset::OffsetTail(-1),
+            Offset::OffsetTail(9_223_372_036_854_775_807),
+        ] {
+            assert_eq!(
+                tpl.set_all_offsets(offset),
+                Err(KafkaError::SetPartitionOffset(
+                    RDKafkaErrorCode::InvalidArgument
+                ))
+            );
+        }
+        assert_eq!(
+            tpl.find_partition("topic1", 0).unwrap().offset(),
+            Offset::Offset(5)
+        );
+    }
+
     #[test]
     fn add_partition_range() {
         let mut tpl = TopicPartitionList::new();
@@ -470,4 +608,86 @@ mod tests {
         assert_eq!(topic_map, topic_map2);
         assert_eq!(tpl, tpl2);
     }
+
+    #[test]
+    #[allow(clippy::legacy_numeric_constants)]
+    fn test_offset_raw() {
+        let offsets = vec![
+            (Offset::Beginning, -2),
+            (Offset::End, -1),
+            (Offset::Stored, -1000),
+            (Offset::Invalid, -1001),
+            (Offset::Offset(0), 0),
+            (Offset::Offset(123), 123),
+            (Offset::OffsetTail(0), -2000),
+            (Offset::OffsetTail(100), -2100),
+        ];
+        for (offset, raw) in offsets {
+            assert_eq!(offset.to_raw(), raw);
+            assert_eq!(Offset::from_raw(raw), offset);
+        }
+        let max_tail = std::i64::MAX - 2000 + 1;
+        assert_eq!(Offset::OffsetTail(max_tail).to_raw(), std::i64::MIN);
+        assert_eq!(
+            Offset::from_raw(std::i64::MIN),
+            Offset::OffsetTail(max_tail)
+        );
+        for &offset in &[
+            Offset::OffsetTail(-1),
+            Offset::OffsetTail(max_tail + 1),
+            Offset::OffsetTail(std::i64::MAX),
+        ] {
+            assert_eq!(Offset::from_raw(offset.to_raw()), Offset::Invalid);
+        }
+
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition("topic1", 0);
+        tpl.set_partition_offset("topic1", 0, Offset::OffsetTail(10))
+            .unwrap();
+        assert_eq!(
+            tpl.find_partition("topic1", 0).unwrap().offset(),
+            Offset::OffsetTail(10)
+        );
+    }
+
+    #[test]
+    fn iter() {
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition_offset("topic1", 0, Offset::Offset(10));
+        tpl.add_partition_offset("topic1", 1, Offset::Invalid);
+        tpl.add_partition_offset("topic2", 0, Offset::Beginning);
+        tpl.add_partition_offset("topic2", 1, Offset::End);
+
+        let iter = tpl.iter();
+        assert_eq!(iter.len(), 4);
+        let elems = iter
+            .map(|elem| (elem.topic().to_owned(), elem.partition(), elem.offset()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            elems,
+            vec![
+                ("topic1".to_owned(), 0, Offset::Offset(10)),
+                ("topic1".to_owned(), 1, Offset::Invalid),
+                ("topic2".to_owned(), 0, Offset::Beginning),
+                ("topic2".to_owned(), 1, Offset::End),
+            ]
+        );
+
+        let invalid = (&tpl)
+            .into_iter()
+            .filter(|elem| elem.offset() == Offset::Invalid)
+            .map(|elem| elem.partition())
+            .collect::<Vec<_>>();
+        assert_eq!(invalid, vec![1]);
+
+        let mut count = 0;
+        for elem in &tpl {
+            assert_eq!(elem.metadata(), "");
+            assert!(elem.error().is_ok());
+            count += 1;
+        }
+        assert_eq!(count, 4);
+
+        assert!(TopicPartitionList::new().iter().next().is_none());
+    }
 }
@@ -22,12 +22,62 @@ pub fn duration_to_millis(duration: Duration) -> u64 {
     duration.as_secs() * 1000 + u64::from(duration.subsec_nanos()) / 1_000_000
 }
 
+/// A timeout for a blocking operation.
+///
+/// librdkafka represents timeouts as milliseconds, where -1 means that the operation blocks
+/// forever and 0 that it doesn't block at all. `Timeout` makes the distinction explicit: use
+/// `Timeout::Never` to block forever, and `Timeout::After(Duration::from_secs(0))` to not block.
+/// A `Duration` converts to `Timeout::After`, and an `Option<Duration>` converts to
+/// `Timeout::Never` if it is `None`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Timeout {
+    /// Time out after the specified duration.
+    After(Duration),
+    /// Block forever.
+    Never,
+}
+
+impl Timeout {
+    /// Converts the timeout to the milliseconds representation used by librdkafka. Durations
+    /// longer than `i32::MAX` milliseconds are clamped to `i32::MAX`.
+    // The associated constants of the integer types are not available before Rust 1.43.
+    #[allow(clippy::legacy_numeric_constants)]
+    pub fn as_millis(&self) -> i32 {
+        match *self {
+            Timeout::After(duration) => {
+                let millis = duration
+                    .as_secs()
+                    .saturating_mul(1000)
+                    .saturating_add(u64::from(duration.subsec_nanos()) / 1_000_000);
+                if millis > std::i32::MAX as u64 {
+                    std::i32::MAX
+                } else {
+                    millis as i32
+                }
+            }
+            Timeout::Never => -1,
+        }
+    }
+}
+
+impl From<Duration> for Timeout {
+    fn from(duration: Duration) -> Timeout {
+        Timeout::After(duration)
+    }
+}
+
+impl From<Option<Duration>> for Timeout {
+    fn from(duration: Option<Duration>) -> Timeout {
+        match duration {
+            Some(duration) => Timeout::After(duration),
+            None => Timeout::Never,
+        }
+    }
+}
+
 /// Converts a timeout to the kafka's expected representation
-pub(crate) fn timeout_to_ms<T: Into<Option<Duration>>>(timeout: T) -> i32 {
-    timeout
-        .into()
-        .map(|t| duration_to_millis(t) as i32)
-        .unwrap_or(-1)
+pub(crate) fn timeout_to_ms<T: Into<Timeout>>(timeout: T) -> i32 {
+    timeout.into().as_millis()
 }
 
 /// Converts the given time to milliseconds since unix epoch.
@@ -189,4 +239,31 @@ mod tests {
         assert_eq!(duration_to_millis(Duration::from_millis(1500)), 1500);
         assert_eq!(duration_to_millis(Duration::new(5, 123_000_000)), 5123);
     }
+
+    #[test]
+    #[allow(clippy::legacy_numeric_constants)]
+    fn test_timeout() {
+        assert_eq!(Timeout::from(Duration::from_millis(1500)).as_millis(), 1500);
+        assert_eq!(Timeout::from(Duration::from_secs(0)).as_millis(), 0);
+        assert_eq!(
+            Timeout::from(Some(Duration::from_secs(2))).as_millis(),
+            2000
+        );
+        assert_eq!(Timeout::from(None).as_millis(), -1);
+        assert_eq!(Timeout::Never.as_millis(), -1);
+        // Durations that don't fit in an i32 are clamped.
+        assert_eq!(
+            Timeout::from(Duration::from_millis(std::i32::MAX as u64 + 1)).as_millis(),
+            std::i32::MAX
+        );
+        assert_eq!(
+            Timeout::from(Duration::from_secs(std::u64::MAX)).as_millis(),
+            std::i32::MAX
+        );
+        assert_eq!(
+            timeout_to_ms(Duration::from_secs(30 * 24 * 3600)),
+            std::i32::MAX
+        );
+        assert_eq!(timeout_to_ms(None), -1);
+    }
 }
@@ -2,7 +2,7 @@
 
 use backoff::{ExponentialBackoff, Operation};
 
-use futures::Future;
+use futures::{future, Future};
 
 use std::time::Duration;
 
@@ -12,7 +12,7 @@ use rdkafka::admin::{
 };
 use rdkafka::client::DefaultClientContext;
 use rdkafka::consumer::{BaseConsumer, Consumer, DefaultConsumerContext};
-use rdkafka::error::{KafkaError, RDKafkaError};
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
 use rdkafka::metadata::Metadata;
 use rdkafka::ClientConfig;
 
@@ -115,6 +115,9 @@ fn test_topics() {
         assert_eq!(&name2, metadata_topic2.name());
         assert_eq!(1, metadata_topic1.partitions().len());
         assert_eq!(3, metadata_topic2.partitions().len());
+        for partition in metadata_topic2.partitions() {
+            assert_eq!(&[0], partition.replicas());
+        }
 
         let res = admin_client
             .describe_configs(
@@ -183,6 +186,17 @@ fn test_topics() {
         .retry(&mut backoff)
         .unwrap();
 
+        // Decreasing the number of partitions is rejected by the broker.
+        let partitions1 = NewPartitions::new(&name1, 2);
+        let res = admin_client
+            .create_partitions(&[partitions1], &opts)
+            .wait()
+            .expect("partition creation failed");
+        assert_eq!(
+            res,
+            &[Err((name1.clone(), RDKafkaErrorCode::InvalidPartitions))]
+        );
+
         let res = admin_client
             .delete_topics(&[&name1, &name2], &opts)
             .wait()
@@ -239,7 +253,10 @@ fn test_topics() {
             .create_partitions(&[partitions], &opts)
             .wait()
             .expect("partition creation failed");
-        assert_eq!(res, &[Err((name, RDKafkaError::InvalidReplicaAssignment))],);
+        assert_eq!(
+            res,
+            &[Err((name, RDKafkaErrorCode::InvalidReplicaAssignment))],
+        );
     }
 
     // Verify that deleting a non-existent topic fails.
@@ -249,7 +266,10 @@ fn test_topics() {
             .delete_topics(&[&name], &opts)
             .wait()
             .expect("delete topics failed");
-        assert_eq!(res, &[Err((name, RDKafkaError::UnknownTopicOrPartition))]);
+        assert_eq!(
+            res,
+            &[Err((name, RDKafkaErrorCode::UnknownTopicOrPartition))]
+        );
     }
 
     // Verify that mixed-success operations properly report the successful and
@@ -275,7 +295,7 @@ fn test_topics() {
         assert_eq!(
             res,
             &[
-                Err((name1.clone(), RDKafkaError::TopicAlreadyExists)),
+                Err((name1.clone(), RDKafkaErrorCode::TopicAlreadyExists)),
                 Ok(name2.clone())
             ]
         );
@@ -296,12 +316,43 @@ fn test_topics() {
             res,
             &[
                 Ok(name2.clone()),
-                Err((name1.clone(), RDKafkaError::UnknownTopicOrPartition))
+                Err((name1.clone(), RDKafkaErrorCode::UnknownTopicOrPartition))
             ]
         );
     }
 }
 
+// Verify that concurrent operations each receive their own result.
+#[test]
+fn test_concurrent_create_topics() {
+    let admin_client = create_admin_client();
+    let opts = AdminOptions::new().operation_timeout(Duration::from_secs(1));
+
+    let names: Vec<String> = (0..5).map(|_| rand_test_topic()).collect();
+    let topics: Vec<NewTopic> = names
+        .iter()
+        .map(|name| NewTopic::new(name, 1, TopicReplication::Fixed(1)))
+        .collect();
+    let futures: Vec<_> = topics
+        .iter()
+        .map(|topic| admin_client.create_topics(Some(topic), &opts))
+        .collect();
+    let results = future::join_all(futures)
+        .wait()
+        .expect("topic creation failed");
+    let expected: Vec<_> = names.iter().map(|name| vec![Ok(name.clone())]).collect();
+    assert_eq!(results, expected);
+
+    for name in &names {
+        assert_eq!(name, fetch_metadata(name).topics()[0].name());
+    }
+    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+    admin_client
+        .delete_topics(&names, &opts)
+        .wait()
+        .expect("topic deletion failed");
+}
+
 #[test]
 fn test_configs() {
     let admin_client = create_admin_client();
@@ -381,40 +432,51 @@ fn test_event_errors() {
     // Configure an admin client to target a Kafka server that doesn't exist,
     // then set an impossible timeout. This will ensure that every request fails
     // with an OperationTimedOut error, assuming, of course, that the request
-    // passes client-side validation.
+    // passes client-side validation. The error carries the detailed error
+    // string reported by librdkafka.
     let admin_client = ClientConfig::new()
         .set("bootstrap.servers", "noexist")
         .create::<AdminClient<DefaultClientContext>>()
         .expect("admin client creation failed");
     let opts = AdminOptions::new().request_timeout(Duration::from_nanos(1));
+    let timed_out = || {
+        KafkaError::AdminOp(
+            RDKafkaErrorCode::OperationTimedOut,
+            Some("Timed out initializing".to_owned()),
+        )
+    };
 
     let res = admin_client.create_topics(&[], &opts).wait();
-    assert_eq!(
-        res,
-        Err(KafkaError::AdminOp(RDKafkaError::OperationTimedOut))
-    );
+    assert_eq!(res, Err(timed_out()));
 
     let res = admin_client.create_partitions(&[], &opts).wait();
-    assert_eq!(
-        res,
-        Err(KafkaError::AdminOp(RDKafkaError::OperationTimedOut))
-    );
+    assert_eq!(res, Err(timed_out()));
 
     let res = admin_client.delete_topics(&[], &opts).wait();
-    assert_eq!(
-        res,
-        Err(KafkaError::AdminOp(RDKafkaError::OperationTimedOut))
-    );
+    assert_eq!(res, Err(timed_out()));
 
     let res = admin_client.describe_configs(&[], &opts).wait();
-    assert_eq!(
-        res.err(),
-        Some(KafkaError::AdminOp(RDKafkaError::OperationTimedOut))
-    );
+    assert_eq!(res.err(), Some(timed_out()));
 
     let res = admin_client.alter_configs(&[], &opts).wait();
-    assert_eq!(
-        res,
-        Err(KafkaError::AdminOp(RDKafkaError::OperationTimedOut))
-    );
+    assert_eq!(res, Err(timed_out()));
+}
+
+#[test]
+fn test_cluster_id_and_controller_id() {
+    let admin_client = create_admin_client();
+    let timeout = Duration::from_secs(10);
+
+    let cluster_id = admin_client.cluster_id(timeout).unwrap();
+    assert!(!cluster_id.is_empty());
+    assert_eq!(admin_client.cluster_id(timeout).unwrap(), cluster_id);
+
+    let controller_id = admin_client.controller_id(timeout).unwrap();
+    let consumer: BaseConsumer<DefaultConsumerContext> =
+        create_config().create().expect("consumer creation failed");
+    let metadata = consumer.fetch_metadata(None, timeout).unwrap();
+    assert!(metadata
+        .brokers()
+        .iter()
+        .any(|broker| broker.id() == controller_id));
 }
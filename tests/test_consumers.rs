@@ -7,7 +7,11 @@ extern crate rdkafka_sys;
 
 use futures::*;
 
-use rdkafka::consumer::{BaseConsumer, CommitMode, Consumer, ConsumerContext, StreamConsumer};
+use rdkafka::config::OffsetReset;
+use rdkafka::consumer::{
+    BaseConsumer, CommitMode, Consumer, ConsumerContext, ConsumerEvent, Rebalance, RebalanceKind,
+    StreamConfig, StreamConsumer,
+};
 use rdkafka::error::{KafkaError, KafkaResult};
 use rdkafka::topic_partition_list::{Offset, TopicPartitionList};
 use rdkafka::util::current_time_millis;
@@ -17,6 +21,9 @@ mod utils;
 use crate::utils::*;
 
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
 use std::time::{Duration, Instant};
 
 struct TestContext {
@@ -120,6 +127,43 @@ fn test_produce_consume_iter() {
     }
 }
 
+// Messages received with `recv` can be processed in place, or detached to be retained.
+#[test]
+fn test_produce_consume_recv() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    let message_map = populate_topic(&topic_name, 10, &value_fn, &key_fn, None, None);
+    let consumer = create_stream_consumer(&rand_test_group(), None);
+    consumer.subscribe(&[topic_name.as_str()]).unwrap();
+    let mut stream = consumer.start();
+
+    // Process a borrowed message without copying it.
+    {
+        let message = stream.recv().wait().unwrap();
+        let id = message_map[&(message.partition(), message.offset())];
+        assert_eq!(
+            message.payload_view::<str>().unwrap().unwrap(),
+            value_fn(id)
+        );
+        assert_eq!(message.key_view::<str>().unwrap().unwrap(), key_fn(id));
+        assert_eq!(message.topic(), topic_name.as_str());
+    }
+
+    // Detach a message and keep it after receiving the following ones.
+    let detached = stream.recv().wait().unwrap().detach();
+    for _ in 0..8 {
+        stream.recv().wait().unwrap();
+    }
+    let id = message_map[&(detached.partition(), detached.offset())];
+    assert_eq!(
+        detached.payload_view::<str>().unwrap().unwrap(),
+        value_fn(id)
+    );
+    assert_eq!(detached.key_view::<str>().unwrap().unwrap(), key_fn(id));
+    assert_eq!(detached.topic(), topic_name.as_str());
+}
+
 // All produced messages should be consumed.
 #[test]
 fn test_produce_consume_base() {
@@ -184,6 +228,213 @@ fn test_produce_consume_seek() {
     ensure_empty(&consumer, "There should be no messages left");
 }
 
+// After seek_and_wait, the next message should be the one at the returned offset, even if
+// messages after the previous position were already fetched.
+#[test]
+fn test_consumer_seek_and_wait() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, 10, &value_fn, &key_fn, Some(0), None);
+    let consumer = create_base_consumer(&rand_test_group(), None);
+    consumer.subscribe(&[topic_name.as_str()]).unwrap();
+
+    for message in consumer.iter().take(5) {
+        message.unwrap();
+    }
+
+    let offset = consumer
+        .seek_and_wait(&topic_name, 0, Offset::Beginning, Duration::from_secs(10))
+        .unwrap();
+    assert_eq!(offset, Offset::Offset(0));
+    let message = consumer
+        .poll(Duration::from_secs(10))
+        .expect("No message received after the seek")
+        .unwrap();
+    assert_eq!(message.offset(), 0);
+
+    let offset = consumer
+        .seek_and_wait(
+            &topic_name,
+            0,
+            Offset::OffsetTail(3),
+            Duration::from_secs(10),
+        )
+        .unwrap();
+    assert_eq!(offset, Offset::Offset(7));
+    let message = consumer
+        .poll(Duration::from_secs(10))
+        .expect("No message received after the seek")
+        .unwrap();
+    assert_eq!(message.offset(), 7);
+}
+
+// Seeking to the stored offset should resume from the committed offset.
+#[test]
+fn test_produce_consume_seek_stored() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, 5, &value_fn, &key_fn, Some(0), None);
+    let consumer = create_base_consumer(&rand_test_group(), None);
+    consumer.subscribe(&[topic_name.as_str()]).unwrap();
+
+    for (i, message) in consumer.iter().take(2).enumerate() {
+        let message = message.unwrap();
+        assert_eq!(message.offset(), i as i64);
+        consumer.commit_message(&message, CommitMode::Sync).unwrap();
+    }
+    for (i, message) in consumer.iter().take(2).enumerate() {
+        assert_eq!(message.unwrap().offset(), i as i64 + 2);
+    }
+
+    consumer
+        .seek(&topic_name, 0, Offset::Stored, Duration::from_secs(5))
+        .unwrap();
+    let message = consumer.iter().next().unwrap().unwrap();
+    assert_eq!(message.offset(), 2);
+}
+
+// A slow consumer should never have more than `max_inflight` messages polled but not processed.
+#[test]
+fn test_produce_consume_max_inflight() {
+    let _r = env_logger::try_init();
+
+    let max_inflight = 2;
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, 10, &value_fn, &key_fn, Some(0), None);
+    let consumer = create_stream_consumer(&rand_test_group(), None);
+    consumer.subscribe(&[topic_name.as_str()]).unwrap();
+
+    let _consumer_future = consumer
+        .start_with_config(StreamConfig::new().max_inflight(max_inflight))
+        .take(10)
+        .for_each(|message| {
+            let message = match message {
+                Ok(m) => m,
+                Err(e) => panic!("Error receiving message: {:?}", e),
+            };
+            thread::sleep(Duration::from_millis(200));
+            let position = consumer.position().unwrap();
+            let position = position
+                .find_partition(&topic_name, 0)
+                .map(|elem| elem.offset());
+            match position {
+                Some(Offset::Offset(offset)) => {
+                    assert!(offset <= message.offset() + 1 + max_inflight as i64)
+                }
+                other => panic!("Unexpected position: {:?}", other),
+            }
+            Ok(())
+        })
+        .wait();
+}
+
+#[test]
+fn test_produce_consume_seek_partitions() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, 5, &value_fn, &key_fn, Some(0), None);
+    populate_topic(&topic_name, 5, &value_fn, &key_fn, Some(1), None);
+    let consumer = create_base_consumer(&rand_test_group(), None);
+    consumer.subscribe(&[topic_name.as_str()]).unwrap();
+
+    for message in consumer.iter().take(10) {
+        if let Err(e) = message {
+            panic!("Error receiving message: {:?}", e);
+        }
+    }
+
+    let mut tpl = TopicPartitionList::new();
+    tpl.add_partition_offset(&topic_name, 0, Offset::Offset(3));
+    tpl.add_partition_offset(&topic_name, 1, Offset::Offset(4));
+    let seeked = consumer.seek_partitions(&tpl, None).unwrap();
+    for elem in seeked.elements() {
+        assert_eq!(elem.error(), Ok(()));
+    }
+
+    let mut partition_count = vec![0, 0];
+    for message in consumer.iter().take(3) {
+        match message {
+            Ok(m) => partition_count[m.partition() as usize] += 1,
+            Err(e) => panic!("Error receiving message: {:?}", e),
+        }
+    }
+    assert_eq!(partition_count, vec![2, 1]);
+
+    ensure_empty(&consumer, "There should be no messages left");
+}
+
+#[test]
+fn test_produce_consume_seek_to_beginning() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, 5, &value_fn, &key_fn, Some(0), None);
+    let consumer = create_base_consumer(&rand_test_group(), None);
+    consumer.subscribe(&[topic_name.as_str()]).unwrap();
+
+    for message in consumer.iter().take(5) {
+        if let Err(e) = message {
+            panic!("Error receiving message: {:?}", e);
+        }
+    }
+
+    let seeked = consumer.seek_to_beginning(None).unwrap();
+    assert!(seeked.count() > 0);
+    for elem in seeked.elements() {
+        assert_eq!(elem.error(), Ok(()));
+    }
+    let message = consumer.iter().next().unwrap().unwrap();
+    assert_eq!(message.offset(), 0);
+
+    let seeked = consumer.seek_to_end(None).unwrap();
+    for elem in seeked.elements() {
+        assert_eq!(elem.error(), Ok(()));
+    }
+    ensure_empty(&consumer, "There should be no messages left");
+}
+
+// Each partition stream should receive all the messages of its partition, in order.
+#[test]
+fn test_produce_consume_split_partition_queues() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    for partition in 0..3 {
+        populate_topic(&topic_name, 10, &value_fn, &key_fn, Some(partition), None);
+    }
+    let consumer = create_stream_consumer(&rand_test_group(), None);
+    consumer.subscribe(&[topic_name.as_str()]).unwrap();
+
+    let partition_streams = consumer.split_partition_queues();
+    let _main_stream = consumer.start();
+
+    let streams = partition_streams.take(3).collect().wait().unwrap();
+    let mut partitions = Vec::new();
+    for stream in streams {
+        assert_eq!(stream.topic(), topic_name);
+        let partition = stream.partition();
+        let offsets = stream
+            .take(10)
+            .map(|message| match message {
+                Ok(m) => {
+                    assert_eq!(m.partition(), partition);
+                    m.offset()
+                }
+                Err(e) => panic!("Error receiving message: {:?}", e),
+            })
+            .collect()
+            .wait()
+            .unwrap();
+        assert_eq!(offsets, (0..10).collect::<Vec<_>>());
+        partitions.push(partition);
+    }
+    partitions.sort();
+    assert_eq!(partitions, vec![0, 1, 2]);
+}
+
 // All produced messages should be consumed.
 #[test]
 fn test_produce_consume_base_assign() {
@@ -255,6 +506,49 @@ fn test_produce_consume_with_timestamp() {
     assert_eq!(tp.offset(), Offset::Offset(100));
     assert_eq!(tp.partition(), 0);
     assert_eq!(tp.error(), Ok(()));
+
+    // The special timestamps -2 and -1 look up the earliest and the latest offsets.
+    for &(timestamp, offset) in &[(-2, 0), (-1, 110)] {
+        let tpl = consumer
+            .offsets_for_timestamp(timestamp, Duration::from_secs(10))
+            .unwrap();
+        let tp = tpl.find_partition(&topic_name, 0).unwrap();
+        assert_eq!(tp.offset(), Offset::Offset(offset));
+    }
+}
+
+#[test]
+fn test_offsets_for_times() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, 10, &value_fn, &key_fn, Some(0), Some(1111));
+    populate_topic(&topic_name, 10, &value_fn, &key_fn, Some(0), Some(999_999));
+    let consumer = create_stream_consumer(&rand_test_group(), None);
+
+    // The offset field carries the timestamps to look up.
+    let mut timestamps = TopicPartitionList::new();
+    timestamps.add_partition_offset(&topic_name, 0, Offset::Offset(999_999));
+    timestamps.add_partition_offset(&topic_name, 1, Offset::Offset(999_999));
+    timestamps.add_partition_offset(&topic_name, 100, Offset::Offset(999_999));
+
+    let tpl = consumer
+        .offsets_for_times(timestamps, Duration::from_secs(10))
+        .unwrap();
+    assert_eq!(tpl.count(), 3);
+
+    let tp = tpl.find_partition(&topic_name, 0).unwrap();
+    assert_eq!(tp.offset(), Offset::Offset(10));
+    assert_eq!(tp.error(), Ok(()));
+
+    // No message was produced to partition 1 after the timestamp.
+    let tp = tpl.find_partition(&topic_name, 1).unwrap();
+    assert_eq!(tp.offset(), Offset::End);
+    assert_eq!(tp.error(), Ok(()));
+
+    // Errors are reported for each partition.
+    let tp = tpl.find_partition(&topic_name, 100).unwrap();
+    assert!(tp.error().is_err());
 }
 
 #[test]
@@ -333,6 +627,14 @@ fn test_consumer_commit_message() {
         (0, 12)
     );
 
+    // The cached high watermarks have been updated by the consumption above.
+    for (partition, high) in &[(0, 10), (1, 11), (2, 12)] {
+        let (_, cached_high) = consumer
+            .get_watermark_offsets(&topic_name, *partition)
+            .unwrap();
+        assert_eq!(cached_high, *high);
+    }
+
     let mut assignment = TopicPartitionList::new();
     assignment.add_partition_offset(&topic_name, 0, Offset::Invalid);
     assignment.add_partition_offset(&topic_name, 1, Offset::Invalid);
@@ -352,6 +654,62 @@ fn test_consumer_commit_message() {
     assert_eq!(position, consumer.position().unwrap());
 }
 
+#[test]
+fn test_consumer_store_offsets_auto_commit() {
+    let _r = env_logger::try_init();
+
+    let topic_name1 = rand_test_topic();
+    let topic_name2 = rand_test_topic();
+    populate_topic(&topic_name1, 10, &value_fn, &key_fn, Some(0), None);
+    populate_topic(&topic_name2, 10, &value_fn, &key_fn, Some(0), None);
+    let mut config = HashMap::new();
+    config.insert("enable.auto.offset.store", "false");
+    config.insert("enable.auto.commit", "true");
+    config.insert("auto.commit.interval.ms", "100");
+    let consumer = create_stream_consumer(&rand_test_group(), Some(config));
+    consumer
+        .subscribe(&[topic_name1.as_str(), topic_name2.as_str()])
+        .unwrap();
+
+    let _consumer_future = consumer.start().take(20).wait();
+
+    let mut offsets = TopicPartitionList::new();
+    offsets.add_partition_offset(&topic_name1, 0, Offset::Offset(4));
+    offsets.add_partition_offset(&topic_name2, 0, Offset::Offset(7));
+    consumer.store_offsets(&offsets).unwrap();
+    for elem in offsets.iter() {
+        assert_eq!(elem.error(), Ok(()));
+    }
+
+    // Wait for the auto commit.
+    let timeout = Duration::from_secs(5);
+    let start = Instant::now();
+    loop {
+        let committed = consumer.committed(timeout).unwrap();
+        if committed == offsets {
+            break;
+        }
+        if start.elapsed() > Duration::from_secs(10) {
+            panic!("Stored offsets not committed: {:?}", committed);
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+
+    consumer
+        .store_partition_offset(&topic_name1, 0, Offset::Offset(9))
+        .unwrap();
+    let mut expected = TopicPartitionList::new();
+    expected.add_partition_offset(&topic_name1, 0, Offset::Offset(9));
+    expected.add_partition_offset(&topic_name2, 0, Offset::Offset(7));
+    let start = Instant::now();
+    while consumer.committed(timeout).unwrap() != expected {
+        if start.elapsed() > Duration::from_secs(10) {
+            panic!("Stored offset not committed");
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
 #[test]
 fn test_consumer_store_offset_commit() {
     let _r = env_logger::try_init();
@@ -376,7 +734,7 @@ fn test_consumer_store_offset_commit() {
                         consumer.store_offset(&m).unwrap();
                     }
                 }
-                Err(KafkaError::PartitionEOF(_)) => {}
+                Err(KafkaError::PartitionEOF { .. }) => {}
                 Err(e) => panic!("Error receiving message: {:?}", e),
             };
             Ok(())
@@ -470,3 +828,499 @@ fn test_pause_resume_consumer_iter() {
 
     ensure_empty(&consumer, "There should be no messages left");
 }
+
+#[test]
+fn test_pause_all_resume_all() {
+    const MESSAGE_COUNT: i32 = 100;
+
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, MESSAGE_COUNT, &value_fn, &key_fn, None, None);
+    let group_id = rand_test_group();
+    let consumer = create_base_consumer(&group_id, None);
+    consumer.subscribe(&[topic_name.as_str()]).unwrap();
+
+    // Consume a first message, so that the partitions are assigned before pausing them.
+    consumer.iter().next().unwrap().unwrap();
+    consumer.pause_all().unwrap();
+    ensure_empty(
+        &consumer,
+        "The consumer is paused - we should not receive anything",
+    );
+
+    consumer.resume_all().unwrap();
+    let mut num_taken = 0;
+    for message in consumer.iter().take(MESSAGE_COUNT as usize - 1) {
+        message.unwrap();
+        num_taken += 1;
+    }
+    assert_eq!(num_taken, MESSAGE_COUNT - 1);
+    ensure_empty(&consumer, "There should be no messages left");
+}
+
+#[test]
+fn test_pause_resume_stream_consumer() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, 10, &value_fn, &key_fn, Some(0), None);
+    let consumer = create_stream_consumer(&rand_test_group(), None);
+    consumer.subscribe(&[topic_name.as_str()]).unwrap();
+
+    let stream = consumer.start_with_config(
+        StreamConfig::new()
+            .poll_interval(Duration::from_millis(100))
+            .no_message_error(true)
+            .max_inflight(1),
+    );
+    let mut offsets = Vec::new();
+    let mut paused = false;
+    let mut empty_polls = 0;
+    for message in stream.wait() {
+        match message.unwrap() {
+            Ok(m) => {
+                // While paused, no message should be received once the stream has gone quiet.
+                assert!(!paused || empty_polls == 0, "Message received while paused");
+                offsets.push(m.offset());
+                if offsets.len() == 5 {
+                    consumer.pause(&consumer.assignment().unwrap()).unwrap();
+                    paused = true;
+                }
+                if offsets.len() == 10 {
+                    break;
+                }
+            }
+            Err(KafkaError::NoMessageReceived) if paused => {
+                empty_polls += 1;
+                if empty_polls == 10 {
+                    consumer.resume(&consumer.assignment().unwrap()).unwrap();
+                    paused = false;
+                }
+            }
+            Err(KafkaError::NoMessageReceived) => (),
+            Err(e) => panic!("Error receiving message: {:?}", e),
+        }
+    }
+
+    assert_eq!(empty_polls, 10);
+    // Resuming should neither skip nor replay messages.
+    assert_eq!(offsets, (0..10).collect::<Vec<_>>());
+}
+
+struct RebalanceRecordingContext {
+    revoked: Arc<AtomicBool>,
+}
+
+impl ClientContext for RebalanceRecordingContext {}
+
+impl ConsumerContext for RebalanceRecordingContext {
+    fn post_rebalance(&self, rebalance: &Rebalance) {
+        if let Rebalance::Revoke = rebalance {
+            self.revoked.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+#[test]
+fn test_consumer_close() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, 10, &value_fn, &key_fn, Some(0), None);
+    let revoked = Arc::new(AtomicBool::new(false));
+    let context = RebalanceRecordingContext {
+        revoked: revoked.clone(),
+    };
+    let consumer: BaseConsumer<_> = consumer_config(&rand_test_group(), None)
+        .create_with_context(context)
+        .expect("Consumer creation failed");
+    consumer.subscribe(&[topic_name.as_str()]).unwrap();
+
+    // Wait for the partitions to be assigned.
+    consumer.iter().next().unwrap().unwrap();
+    assert!(!revoked.load(Ordering::SeqCst));
+
+    consumer.close().unwrap();
+    assert!(revoked.load(Ordering::SeqCst));
+}
+
+struct OffsetOverrideContext {
+    topic_name: String,
+    offset: Offset,
+}
+
+impl ClientContext for OffsetOverrideContext {}
+
+impl ConsumerContext for OffsetOverrideContext {
+    fn pre_rebalance(&self, rebalance: &Rebalance) {
+        if let Rebalance::Assign(tpl) = rebalance {
+            if let Some(elem) = tpl.find_partition(&self.topic_name, 0) {
+                elem.set_offset(self.offset);
+            }
+        }
+    }
+}
+
+// The offsets set in the pre-rebalance callback should be used for the assignment.
+#[test]
+fn test_consumer_pre_rebalance_offsets() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, 10, &value_fn, &key_fn, Some(0), None);
+    let context = OffsetOverrideContext {
+        topic_name: topic_name.clone(),
+        offset: Offset::Offset(7),
+    };
+    let consumer: BaseConsumer<_> = consumer_config(&rand_test_group(), None)
+        .create_with_context(context)
+        .expect("Consumer creation failed");
+    consumer.subscribe(&[topic_name.as_str()]).unwrap();
+
+    let offsets = consumer
+        .iter()
+        .take(3)
+        .map(|message| message.unwrap().offset())
+        .collect::<Vec<_>>();
+    assert_eq!(offsets, vec![7, 8, 9]);
+}
+
+// The member id is assigned when the consumer joins the group.
+#[test]
+fn test_consumer_member_id() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, 1, &value_fn, &key_fn, Some(0), None);
+    let consumer: BaseConsumer = consumer_config(&rand_test_group(), None)
+        .create()
+        .expect("Consumer creation failed");
+    assert_eq!(consumer.member_id(), None);
+    consumer.subscribe(&[topic_name.as_str()]).unwrap();
+
+    // Wait for the partitions to be assigned.
+    consumer.iter().next().unwrap().unwrap();
+    let member_id = consumer.member_id().unwrap();
+    assert!(!member_id.is_empty());
+}
+
+// Polling distinguishes messages, the end of a partition and timeouts.
+#[test]
+fn test_consumer_poll_partition_eof() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, 5, &value_fn, &key_fn, Some(0), None);
+    let mut config = HashMap::new();
+    config.insert("enable.partition.eof", "true");
+    let consumer = create_base_consumer(&rand_test_group(), Some(config));
+    let mut tpl = TopicPartitionList::new();
+    tpl.add_partition_offset(&topic_name, 0, Offset::Beginning);
+    consumer.assign(&tpl).unwrap();
+
+    let mut offsets = Vec::new();
+    loop {
+        match consumer.poll(Duration::from_secs(10)) {
+            Some(Ok(message)) => offsets.push(message.offset()),
+            Some(Err(KafkaError::PartitionEOF {
+                topic,
+                partition,
+                offset,
+            })) => {
+                assert_eq!(topic, topic_name);
+                assert_eq!(partition, 0);
+                assert_eq!(offset, 5);
+                break;
+            }
+            Some(Err(e)) => panic!("Error receiving message: {:?}", e),
+            None => panic!("Timeout before the end of the partition"),
+        }
+    }
+    assert_eq!(offsets, (0..5).collect::<Vec<_>>());
+
+    assert!(consumer.poll(Duration::from_millis(500)).is_none());
+}
+
+// The stream consumer should report the end of the partition as an event, after its messages.
+#[test]
+fn test_stream_consumer_recv_event_eof() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, 3, &value_fn, &key_fn, Some(0), None);
+    let mut config = HashMap::new();
+    config.insert("enable.partition.eof", "true");
+    let consumer = create_stream_consumer(&rand_test_group(), Some(config));
+    let mut tpl = TopicPartitionList::new();
+    tpl.add_partition_offset(&topic_name, 0, Offset::Beginning);
+    consumer.assign(&tpl).unwrap();
+
+    let mut stream = consumer.start();
+    let mut offsets = Vec::new();
+    loop {
+        match stream.recv_event().wait().unwrap() {
+            ConsumerEvent::Message(message) => offsets.push(message.offset()),
+            ConsumerEvent::Eof {
+                topic,
+                partition,
+                offset,
+            } => {
+                assert_eq!(topic, topic_name);
+                assert_eq!(partition, 0);
+                assert_eq!(offset, 3);
+                break;
+            }
+            ConsumerEvent::Idle => panic!("Unexpected idle event"),
+            ConsumerEvent::Error(e) => panic!("Error receiving message: {:?}", e),
+        }
+    }
+    assert_eq!(offsets, vec![0, 1, 2]);
+}
+
+// Committing the current state should return the committed offsets.
+#[test]
+fn test_consumer_commit_consumer_state_offsets() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, 5, &value_fn, &key_fn, Some(0), None);
+    populate_topic(&topic_name, 3, &value_fn, &key_fn, Some(1), None);
+    let consumer = create_base_consumer(&rand_test_group(), None);
+    let mut tpl = TopicPartitionList::new();
+    tpl.add_partition_offset(&topic_name, 0, Offset::Beginning);
+    tpl.add_partition_offset(&topic_name, 1, Offset::Beginning);
+    consumer.assign(&tpl).unwrap();
+
+    for message in consumer.iter().take(8) {
+        message.unwrap();
+    }
+    let committed = consumer.commit_consumer_state(CommitMode::Sync).unwrap();
+
+    let mut expected = TopicPartitionList::new();
+    expected.add_partition_offset(&topic_name, 0, Offset::Offset(5));
+    expected.add_partition_offset(&topic_name, 1, Offset::Offset(3));
+    assert_eq!(committed, expected);
+    assert_eq!(committed, consumer.position().unwrap());
+    assert!(committed.iter().all(|elem| elem.error().is_ok()));
+    assert_eq!(
+        committed,
+        consumer.committed(Duration::from_secs(5)).unwrap()
+    );
+}
+
+// The committed offsets of the group can be fetched for partitions that are not assigned to
+// the consumer.
+#[test]
+fn test_consumer_committed_offsets_unassigned() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, 5, &value_fn, &key_fn, Some(0), None);
+    populate_topic(&topic_name, 3, &value_fn, &key_fn, Some(1), None);
+    let group_id = rand_test_group();
+    let consumer = create_base_consumer(&group_id, None);
+    let mut tpl = TopicPartitionList::new();
+    tpl.add_partition_offset(&topic_name, 0, Offset::Beginning);
+    consumer.assign(&tpl).unwrap();
+    let mut offsets = TopicPartitionList::new();
+    offsets.add_partition_offset(&topic_name, 0, Offset::Offset(4));
+    offsets.add_partition_offset(&topic_name, 1, Offset::Offset(2));
+    consumer.commit(&offsets, CommitMode::Sync).unwrap();
+
+    // The monitoring consumer is part of the same group, but has no assignment.
+    let monitor = create_base_consumer(&group_id, None);
+    let mut query = TopicPartitionList::new();
+    query.add_partition(&topic_name, 0);
+    query.add_partition(&topic_name, 1);
+    query.add_partition(&topic_name, 2);
+    let committed = monitor
+        .committed_offsets(query, Duration::from_secs(5))
+        .unwrap();
+    assert_eq!(monitor.assignment().unwrap().count(), 0);
+
+    let mut expected = offsets;
+    expected.add_partition_offset(&topic_name, 2, Offset::Invalid);
+    assert_eq!(committed, expected);
+    assert!(committed.iter().all(|elem| elem.error().is_ok()));
+}
+
+// The messages buffered for a revoked partition should not be yielded by the stream.
+#[test]
+fn test_stream_consumer_drain_on_revoke() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, 100, &value_fn, &key_fn, Some(0), None);
+    let consumer = create_stream_consumer(&rand_test_group(), None);
+    consumer.subscribe(&[topic_name.as_str()]).unwrap();
+
+    let mut stream = consumer
+        .start_with_config(
+            StreamConfig::new()
+                .no_message_error(true)
+                .drain_on_revoke(true),
+        )
+        .wait();
+    // Wait for the first message, while the polling thread fills the buffer.
+    loop {
+        match stream.next().unwrap().unwrap() {
+            Ok(_) => break,
+            Err(KafkaError::NoMessageReceived) => {}
+            Err(e) => panic!("Error receiving message: {:?}", e),
+        }
+    }
+    thread::sleep(Duration::from_millis(500));
+
+    // Unsubscribing revokes the partition. The polling thread serves the revocation as soon as
+    // it can poll again, and the rest of the buffer is then dropped.
+    consumer.unsubscribe();
+    let mut received_after_revoke = 0;
+    loop {
+        match stream.next().unwrap().unwrap() {
+            Ok(_) => received_after_revoke += 1,
+            Err(KafkaError::NoMessageReceived) => break,
+            Err(e) => panic!("Error receiving message: {:?}", e),
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+    assert!(
+        received_after_revoke <= 2,
+        "{} buffered messages received after the revocation",
+        received_after_revoke
+    );
+}
+
+// The offset committed for each message yielded by an auto-committing stream should be the offset
+// of the next message to read.
+#[test]
+fn test_stream_consumer_auto_committing() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, 10, &value_fn, &key_fn, Some(0), None);
+    let consumer = create_stream_consumer(&rand_test_group(), None);
+    consumer.subscribe(&[topic_name.as_str()]).unwrap();
+
+    let mut last_offset = -1;
+    for message in consumer
+        .start()
+        .auto_committing(Duration::from_secs(0), CommitMode::Sync)
+        .take(10)
+        .wait()
+    {
+        last_offset = message.unwrap().unwrap().offset();
+    }
+    assert_eq!(last_offset, 9);
+
+    let committed = consumer.committed(Duration::from_secs(5)).unwrap();
+    assert_eq!(
+        committed
+            .find_partition(&topic_name, 0)
+            .map(|elem| elem.offset()),
+        Some(Offset::Offset(last_offset + 1))
+    );
+}
+
+// The assignment stream should report the partitions assigned by the first rebalance.
+#[test]
+fn test_stream_consumer_assignment_stream() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, 1, &value_fn, &key_fn, Some(0), None);
+    let consumer = create_stream_consumer(&rand_test_group(), None);
+    consumer.subscribe(&[topic_name.as_str()]).unwrap();
+
+    let assignments = consumer.assignment_stream();
+    let _stream = consumer.start();
+    let (kind, tpl) = assignments.wait().next().unwrap().unwrap();
+    assert_eq!(kind, RebalanceKind::Assign);
+    assert!(tpl.find_partition(&topic_name, 0).is_some());
+}
+
+// try_recv should return the messages without blocking, and None once they are all consumed.
+#[test]
+fn test_produce_consume_try_recv() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, 5, &value_fn, &key_fn, Some(0), None);
+    let consumer = create_stream_consumer(&rand_test_group(), None);
+    consumer.subscribe(&[topic_name.as_str()]).unwrap();
+
+    let mut offsets = Vec::new();
+    let deadline = Instant::now() + Duration::from_secs(20);
+    while offsets.len() < 5 && Instant::now() < deadline {
+        match consumer.try_recv() {
+            Some(message) => offsets.push(message.unwrap().offset()),
+            None => thread::sleep(Duration::from_millis(10)),
+        }
+    }
+    assert_eq!(offsets, vec![0, 1, 2, 3, 4]);
+    assert!(consumer.try_recv().is_none());
+}
+
+// The reset requested with subscribe_with_reset should only apply to the partitions without a
+// committed offset.
+#[test]
+fn test_consumer_subscribe_with_reset() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, 10, &value_fn, &key_fn, Some(0), None);
+    let mut config_overrides = HashMap::new();
+    config_overrides.insert("auto.offset.reset", "latest");
+
+    let first_offset = |group_id: &str| {
+        let consumer = create_base_consumer(group_id, Some(config_overrides.clone()));
+        consumer
+            .subscribe_with_reset(&[topic_name.as_str()], OffsetReset::Earliest)
+            .unwrap();
+        let message = consumer.poll(Duration::from_secs(20)).unwrap().unwrap();
+        assert_eq!(message.partition(), 0);
+        message.offset()
+    };
+
+    // A fresh group starts from the beginning, despite auto.offset.reset.
+    let group_id = rand_test_group();
+    assert_eq!(first_offset(&group_id), 0);
+
+    // An existing committed offset is respected.
+    let consumer = create_base_consumer(&group_id, None);
+    let mut tpl = TopicPartitionList::new();
+    tpl.add_partition_offset(&topic_name, 0, Offset::Offset(4));
+    consumer.commit(&tpl, CommitMode::Sync).unwrap();
+    drop(consumer);
+    assert_eq!(first_offset(&group_id), 4);
+}
+
+// The seeded partitions should be consumed from the seeded offsets, and the other partitions
+// according to auto.offset.reset.
+#[test]
+fn test_consumer_subscribe_and_seed() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    for partition in 0..3 {
+        populate_topic(&topic_name, 10, &value_fn, &key_fn, Some(partition), None);
+    }
+    let consumer = create_base_consumer(&rand_test_group(), None);
+    let mut offsets = TopicPartitionList::new();
+    offsets.add_partition_offset(&topic_name, 0, Offset::Offset(3));
+    offsets.add_partition_offset(&topic_name, 1, Offset::Offset(6));
+    consumer.subscribe_and_seed(&offsets).unwrap();
+
+    let mut first_offsets = HashMap::new();
+    while first_offsets.len() < 3 {
+        let message = consumer
+            .poll(Duration::from_secs(20))
+            .expect("No message received")
+            .unwrap();
+        first_offsets
+            .entry(message.partition())
+            .or_insert_with(|| message.offset());
+    }
+    let expected = vec![(0, 3), (1, 6), (2, 0)].into_iter().collect();
+    assert_eq!(first_offsets, expected);
+}
@@ -6,11 +6,18 @@ extern crate rdkafka;
 use futures::Future;
 
 use rdkafka::config::ClientConfig;
-use rdkafka::message::{Headers, Message, OwnedHeaders};
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::message::{Header, Headers, Message, OwnedHeaders};
 use rdkafka::producer::future_producer::FutureRecord;
-use rdkafka::producer::FutureProducer;
+use rdkafka::producer::{FutureProducer, RetryPolicy};
+use rdkafka::topic_partition_list::{Offset, TopicPartitionList};
 
 use std::error::Error;
+use std::time::Duration;
+
+mod utils;
+use crate::utils::*;
 
 #[test]
 fn test_future_producer_send_fail() {
@@ -49,3 +56,315 @@ fn test_future_producer_send_fail() {
         }
     }
 }
+
+#[test]
+fn test_future_producer_flush() {
+    let producer = ClientConfig::new()
+        .set("bootstrap.servers", get_bootstrap_server().as_str())
+        .set("message.timeout.ms", "5000")
+        .create::<FutureProducer>()
+        .expect("Failed to create producer");
+
+    let topic_name = rand_test_topic();
+    let futures = (0..10)
+        .map(|i| {
+            let value = value_fn(i);
+            producer.send(FutureRecord::to(&topic_name).payload(&value).key("key"), 0)
+        })
+        .collect::<Vec<_>>();
+
+    producer.flush(Duration::from_secs(10)).unwrap();
+    assert_eq!(producer.in_flight_count(), 0);
+
+    for future in futures {
+        match future.wait() {
+            Ok(Ok(_)) => {}
+            e => panic!("Unexpected delivery result: {:?}", e),
+        }
+    }
+}
+
+#[test]
+fn test_future_producer_delivery_latency() {
+    let producer = ClientConfig::new()
+        .set("bootstrap.servers", get_bootstrap_server().as_str())
+        .set("message.timeout.ms", "5000")
+        .create::<FutureProducer>()
+        .expect("Failed to create producer");
+
+    let topic_name = rand_test_topic();
+    let future = producer.send(
+        FutureRecord::to(&topic_name).payload("payload").key("key"),
+        0,
+    );
+
+    match future.with_latency().wait() {
+        Ok((Ok(_), Some(latency))) => assert!(latency >= 0),
+        e => panic!("Unexpected delivery result: {:?}", e),
+    }
+}
+
+/// Flushes the producer after the first failed attempt, so that the second attempt succeeds.
+struct FlushingPolicy {
+    producer: FutureProducer,
+    attempts: Vec<(u32, KafkaError)>,
+}
+
+impl RetryPolicy for FlushingPolicy {
+    fn next_backoff(&mut self, attempt: u32, err: &KafkaError) -> Option<Duration> {
+        self.attempts.push((attempt, err.clone()));
+        self.producer.flush(Duration::from_secs(10)).unwrap();
+        Some(Duration::from_millis(0))
+    }
+}
+
+#[test]
+fn test_future_producer_send_with_policy() {
+    let producer = ClientConfig::new()
+        .set("bootstrap.servers", get_bootstrap_server().as_str())
+        .set("message.timeout.ms", "5000")
+        .set("queue.buffering.max.messages", "1")
+        .create::<FutureProducer>()
+        .expect("Failed to create producer");
+
+    let topic_name = rand_test_topic();
+    let first = producer.send(FutureRecord::to(&topic_name).payload("first").key("key"), 0);
+    let mut policy = FlushingPolicy {
+        producer: producer.clone(),
+        attempts: Vec::new(),
+    };
+    let result = producer.send_with_policy(
+        FutureRecord::to(&topic_name).payload("second").key("key"),
+        &mut policy,
+    );
+
+    assert!(result.is_ok(), "Unexpected result: {:?}", result);
+    assert_eq!(
+        policy.attempts,
+        vec![(
+            1,
+            KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull)
+        )]
+    );
+    match first.wait() {
+        Ok(Ok(_)) => {}
+        e => panic!("Unexpected delivery result: {:?}", e),
+    }
+}
+
+#[test]
+fn test_future_producer_send_with_backpressure() {
+    let producer = ClientConfig::new()
+        .set("bootstrap.servers", get_bootstrap_server().as_str())
+        .set("message.timeout.ms", "5000")
+        .set("queue.buffering.max.messages", "10")
+        .create::<FutureProducer>()
+        .expect("Failed to create producer");
+
+    let topic_name = rand_test_topic();
+    let values = (0..50).map(value_fn).collect::<Vec<_>>();
+    // The first records fill the queue, the others have to wait for the deliveries.
+    let futures = values
+        .iter()
+        .map(|value| {
+            producer
+                .send_with_backpressure(
+                    FutureRecord::to(&topic_name).payload(value).key("key"),
+                    Duration::from_secs(10),
+                )
+                .wait()
+                .map_err(|(e, _record)| e)
+                .expect("Failed to enqueue message")
+        })
+        .collect::<Vec<_>>();
+
+    for future in futures {
+        match future.wait() {
+            Ok(Ok(_)) => {}
+            e => panic!("Unexpected delivery result: {:?}", e),
+        }
+    }
+}
+
+#[test]
+fn test_future_producer_headers_round_trip() {
+    let topic_name = rand_test_topic();
+    let producer = ClientConfig::new()
+        .set("bootstrap.servers", get_bootstrap_server().as_str())
+        .set("message.timeout.ms", "5000")
+        .create::<FutureProducer>()
+        .expect("Failed to create producer");
+
+    let headers = OwnedHeaders::new()
+        .insert(Header {
+            key: "string",
+            value: Some("A"),
+        })
+        .insert(Header {
+            key: "integer",
+            value: Some(&-2i64),
+        })
+        .insert::<str>(Header {
+            key: "null",
+            value: None,
+        })
+        .insert(Header {
+            key: "string",
+            value: Some(&[1u8, 2][..]),
+        });
+    let (partition, offset) = producer
+        .send(
+            FutureRecord::to(&topic_name)
+                .payload("payload")
+                .key("key")
+                .partition(0)
+                .headers(headers),
+            10000,
+        )
+        .wait()
+        .unwrap()
+        .unwrap();
+
+    let consumer = ClientConfig::new()
+        .set("bootstrap.servers", get_bootstrap_server().as_str())
+        .set("group.id", &rand_test_group())
+        .create::<BaseConsumer>()
+        .expect("Failed to create consumer");
+    let mut tpl = TopicPartitionList::new();
+    tpl.add_partition_offset(&topic_name, partition, Offset::Offset(offset));
+    consumer.assign(&tpl).unwrap();
+
+    let message = consumer
+        .poll(Duration::from_secs(30))
+        .expect("No message received")
+        .unwrap();
+    let headers = message.headers().unwrap();
+    assert_eq!(headers.count(), 4);
+    assert_eq!(headers.get_as::<str>(0), Some(("string", Ok("A"))));
+    assert_eq!(
+        headers.get(1),
+        Some(("integer", &(-2i64).to_be_bytes()[..]))
+    );
+    assert_eq!(headers.get(2), Some(("null", &[][..])));
+    assert_eq!(headers.get(3), Some(("string", &[1, 2][..])));
+}
+
+#[test]
+fn test_future_producer_header_builder() {
+    let topic_name = rand_test_topic();
+    let producer = ClientConfig::new()
+        .set("bootstrap.servers", get_bootstrap_server().as_str())
+        .set("message.timeout.ms", "5000")
+        .create::<FutureProducer>()
+        .expect("Failed to create producer");
+
+    let (partition, offset) = producer
+        .send(
+            FutureRecord::to(&topic_name)
+                .payload("payload")
+                .key("key")
+                .partition(0)
+                .header("string", Some("A"))
+                .header::<str>("null", None)
+                .header("integer", Some(&7u16)),
+            10000,
+        )
+        .wait()
+        .unwrap()
+        .unwrap();
+
+    let consumer = ClientConfig::new()
+        .set("bootstrap.servers", get_bootstrap_server().as_str())
+        .set("group.id", &rand_test_group())
+        .create::<BaseConsumer>()
+        .expect("Failed to create consumer");
+    let mut tpl = TopicPartitionList::new();
+    tpl.add_partition_offset(&topic_name, partition, Offset::Offset(offset));
+    consumer.assign(&tpl).unwrap();
+
+    let message = consumer
+        .poll(Duration::from_secs(30))
+        .expect("No message received")
+        .unwrap();
+    let headers = message.headers().unwrap();
+    assert_eq!(headers.count(), 3);
+    assert_eq!(headers.get_as::<str>(0), Some(("string", Ok("A"))));
+    assert_eq!(headers.get_nullable(1), Some(("null", None)));
+    assert_eq!(headers.get(2), Some(("integer", &[0, 7][..])));
+}
+
+#[test]
+fn test_future_producer_send_and_wait() {
+    let producer = ClientConfig::new()
+        .set("bootstrap.servers", get_bootstrap_server().as_str())
+        .set("message.timeout.ms", "5000")
+        .create::<FutureProducer>()
+        .expect("Failed to create producer");
+    let topic_name = rand_test_topic();
+
+    let first = producer
+        .send_and_wait(
+            FutureRecord::to(&topic_name)
+                .payload("payload")
+                .key("key")
+                .partition(1),
+            Duration::from_secs(10),
+        )
+        .unwrap();
+    assert_eq!(first, (1, 0));
+    let delivery_report = producer
+        .send(
+            FutureRecord::to(&topic_name)
+                .payload("payload")
+                .key("key")
+                .partition(1),
+            0,
+        )
+        .wait()
+        .unwrap()
+        .unwrap();
+    assert_eq!(delivery_report, (1, 1));
+    let third = producer
+        .send_and_wait(
+            FutureRecord::to(&topic_name)
+                .payload("payload")
+                .key("key")
+                .partition(1),
+            None,
+        )
+        .unwrap();
+    assert_eq!(third, (1, 2));
+}
+
+// Dropping the producer without flushing it should still deliver the pending messages, if the
+// flush on drop is enabled.
+#[test]
+fn test_future_producer_drop_flush() {
+    let producer = ClientConfig::new()
+        .set("bootstrap.servers", get_bootstrap_server().as_str())
+        .set("message.timeout.ms", "5000")
+        .create::<FutureProducer>()
+        .expect("Failed to create producer");
+    producer.set_drop_flush_timeout(Duration::from_secs(5));
+    let topic_name = rand_test_topic();
+
+    let futures = (0..10)
+        .map(|i| {
+            let value = format!("Message {}", i);
+            producer.send(
+                FutureRecord::to(&topic_name)
+                    .payload(&value)
+                    .key("key")
+                    .partition(0),
+                0,
+            )
+        })
+        .collect::<Vec<_>>();
+    drop(producer);
+
+    for (i, future) in futures.into_iter().enumerate() {
+        let (partition, offset) = future.wait().unwrap().unwrap();
+        assert_eq!(partition, 0);
+        assert_eq!(offset, i as i64);
+    }
+}
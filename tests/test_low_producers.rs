@@ -3,14 +3,18 @@ extern crate futures;
 extern crate rand;
 extern crate rdkafka;
 
+use futures::{Future, Stream};
+
+use rdkafka::client::DefaultClientContext;
 use rdkafka::config::ClientConfig;
-use rdkafka::error::{KafkaError, RDKafkaError};
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
 use rdkafka::message::{Headers, Message, OwnedHeaders, OwnedMessage};
 use rdkafka::producer::{
-    BaseProducer, BaseRecord, DeliveryResult, ProducerContext, ThreadedProducer,
+    BaseProducer, BaseRecord, DeliveryResult, DeliveryStreamContext, InterceptedRecord,
+    ProducerContext, ThreadedProducer,
 };
 use rdkafka::util::current_time_millis;
-use rdkafka::{ClientContext, Statistics};
+use rdkafka::{ClientContext, Statistics, Timestamp};
 
 #[macro_use]
 mod utils;
@@ -20,7 +24,7 @@ use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::sync::Arc;
 use std::sync::Mutex;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 struct PrintingContext {
     _n: i64, // Add data for memory access validation
@@ -144,7 +148,7 @@ fn test_base_producer_queue_full() {
     let errors = results
         .iter()
         .filter(|&e| {
-            if let &Err((KafkaError::MessageProduction(RDKafkaError::QueueFull), _)) = e {
+            if let &Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), _)) = e {
                 true
             } else {
                 false
@@ -180,7 +184,7 @@ fn test_base_producer_timeout() {
         .filter(|r| r.is_ok())
         .count();
 
-    producer.flush(Duration::from_secs(10));
+    producer.flush(Duration::from_secs(10)).unwrap();
 
     assert_eq!(results_count, 10);
 
@@ -191,7 +195,9 @@ fn test_base_producer_timeout() {
         assert_eq!(message.key_view::<str>(), Some(Ok("B")));
         assert_eq!(
             error,
-            &Some(KafkaError::MessageProduction(RDKafkaError::MessageTimedOut))
+            &Some(KafkaError::MessageProduction(
+                RDKafkaErrorCode::MessageTimedOut
+            ))
         );
         ids.insert(id);
     }
@@ -247,12 +253,119 @@ fn test_base_producer_headers() {
         .filter(|r| r.is_ok())
         .count();
 
-    producer.flush(Duration::from_secs(10));
+    producer.flush(Duration::from_secs(10)).unwrap();
 
     assert_eq!(results_count, 10);
     assert_eq!((*ids_set.lock().unwrap()).len(), 10);
 }
 
+#[test]
+fn test_base_producer_timestamp() {
+    let context = CollectingContext::new();
+    let producer = base_producer_with_context(context.clone(), HashMap::new());
+    let topic_name = rand_test_topic();
+
+    producer
+        .send(
+            BaseRecord::with_opaque_to(&topic_name, 1)
+                .payload("payload")
+                .key("key")
+                .timestamp(1_234_567),
+        )
+        .unwrap();
+    producer
+        .send(BaseRecord::<str, str, usize>::with_opaque_to(&topic_name, 2).payload("payload"))
+        .unwrap();
+    producer.flush(Duration::from_secs(10)).unwrap();
+
+    let start_time = current_time_millis();
+    let results = context.results.lock().unwrap();
+    assert_eq!(results.len(), 2);
+    for (message, error, id) in results.iter() {
+        assert_eq!(*error, None);
+        match (*id, message.timestamp()) {
+            (1, timestamp) => assert_eq!(timestamp, Timestamp::CreateTime(1_234_567)),
+            (2, Timestamp::CreateTime(timestamp)) => assert!(timestamp <= start_time),
+            (id, timestamp) => panic!("Unexpected timestamp for message {}: {:?}", id, timestamp),
+        }
+    }
+}
+
+#[test]
+fn test_base_producer_payload_slices() {
+    let context = CollectingContext::new();
+    let producer = base_producer_with_context(context.clone(), HashMap::new());
+    let topic_name = rand_test_topic();
+
+    let slices: &[&[u8]] = &[b"pay", b"", b"load"];
+    producer
+        .send(BaseRecord::<str, str, usize>::with_opaque_to(&topic_name, 1).payload_slices(slices))
+        .unwrap();
+    producer
+        .send(
+            BaseRecord::<str, str, usize>::with_opaque_to(&topic_name, 2)
+                .payload("ignored")
+                .payload_slices(&[b"first", b"second"]),
+        )
+        .unwrap();
+    producer.flush(Duration::from_secs(10)).unwrap();
+
+    let results = context.results.lock().unwrap();
+    assert_eq!(results.len(), 2);
+    for (message, error, id) in results.iter() {
+        assert_eq!(*error, None);
+        match *id {
+            1 => assert_eq!(message.payload(), Some(&b"payload"[..])),
+            2 => assert_eq!(message.payload(), Some(&b"firstsecond"[..])),
+            id => panic!("Unexpected message {}", id),
+        }
+    }
+}
+
+// Sends the same number of records by topic name and through a topic handle, which should all be
+// delivered to the same topic, and compares the time spent producing them.
+#[test]
+fn test_base_producer_send_to() {
+    let context = CollectingContext::new();
+    let producer = base_producer_with_context(context.clone(), HashMap::new());
+    let topic_name = rand_test_topic();
+    let count = 1000;
+
+    let start = Instant::now();
+    for i in 0..count {
+        producer
+            .send(BaseRecord::<str, str, usize>::with_opaque_to(&topic_name, i).payload("by name"))
+            .unwrap();
+    }
+
+    let by_name = start.elapsed();
+
+    let start = Instant::now();
+    let topic = producer.topic(&topic_name).unwrap();
+    for i in count..2 * count {
+        producer
+            .send_to(
+                &topic,
+                BaseRecord::<str, str, usize>::with_opaque_to("", i).payload("cached"),
+            )
+            .unwrap();
+    }
+    println!(
+        "Produced {} records by name in {:?}, through a topic handle in {:?}",
+        count,
+        by_name,
+        start.elapsed()
+    );
+    producer.flush(Duration::from_secs(10)).unwrap();
+
+    let results = context.results.lock().unwrap();
+    assert_eq!(results.len(), 2 * count);
+    for (message, error, _) in results.iter() {
+        assert_eq!(*error, None);
+        assert_eq!(message.topic(), topic_name);
+    }
+}
+
 #[test]
 fn test_threaded_producer_send() {
     let context = CollectingContext::new();
@@ -271,7 +384,7 @@ fn test_threaded_producer_send() {
         .count();
 
     assert_eq!(results_count, 10);
-    producer.flush(Duration::from_secs(10));
+    producer.flush(Duration::from_secs(10)).unwrap();
 
     let delivery_results = context.results.lock().unwrap();
     let mut ids = HashSet::new();
@@ -311,9 +424,214 @@ fn test_base_producer_opaque_arc() -> Result<(), Box<dyn Error>> {
         .filter(|r| r.is_ok())
         .count();
 
-    producer.flush(Duration::from_secs(10));
+    producer.flush(Duration::from_secs(10)).unwrap();
 
     let shared_count = Arc::try_unwrap(shared_count).unwrap().into_inner()?;
     assert_eq!(results_count, shared_count);
     Ok(())
 }
+
+type PartitionerCall = (String, Option<Vec<u8>>, i32);
+
+struct LastPartitionContext {
+    collecting: CollectingContext,
+    partitions: Arc<Mutex<Vec<PartitionerCall>>>,
+}
+
+impl ClientContext for LastPartitionContext {}
+
+impl ProducerContext for LastPartitionContext {
+    type DeliveryOpaque = usize;
+
+    fn delivery(&self, delivery_result: &DeliveryResult, delivery_opaque: Self::DeliveryOpaque) {
+        self.collecting.delivery(delivery_result, delivery_opaque);
+    }
+
+    fn has_custom_partitioner(&self) -> bool {
+        true
+    }
+
+    fn partition(&self, topic: &str, key: Option<&[u8]>, partition_cnt: i32) -> i32 {
+        let partition = partition_cnt - 1;
+        let mut partitions = self.partitions.lock().unwrap();
+        (*partitions).push((topic.to_owned(), key.map(|k| k.to_vec()), partition));
+        partition
+    }
+}
+
+#[test]
+fn test_base_producer_custom_partitioner() {
+    let collecting = CollectingContext::new();
+    let partitions = Arc::new(Mutex::new(Vec::new()));
+    let context = LastPartitionContext {
+        collecting: collecting.clone(),
+        partitions: partitions.clone(),
+    };
+    let producer = base_producer_with_context(context, HashMap::new());
+    let topic_name = rand_test_topic();
+
+    for id in 0..10 {
+        producer
+            .send(
+                BaseRecord::with_opaque_to(&topic_name, id)
+                    .payload("A")
+                    .key("B"),
+            )
+            .unwrap();
+    }
+    producer.flush(Duration::from_secs(10)).unwrap();
+
+    let partitions = partitions.lock().unwrap();
+    assert_eq!(partitions.len(), 10);
+    let expected_partition = partitions[0].2;
+    for (topic, key, partition) in partitions.iter() {
+        assert_eq!(topic, &topic_name);
+        assert_eq!(key, &Some(b"B".to_vec()));
+        assert_eq!(*partition, expected_partition);
+    }
+
+    let delivery_results = collecting.results.lock().unwrap();
+    assert_eq!(delivery_results.len(), 10);
+    for (message, error, _) in delivery_results.iter() {
+        assert_eq!(error, &None);
+        assert_eq!(message.partition(), expected_partition);
+    }
+}
+
+struct TraceIdContext {
+    collecting: CollectingContext,
+}
+
+impl ClientContext for TraceIdContext {}
+
+impl ProducerContext for TraceIdContext {
+    type DeliveryOpaque = usize;
+
+    fn delivery(&self, delivery_result: &DeliveryResult, delivery_opaque: Self::DeliveryOpaque) {
+        self.collecting.delivery(delivery_result, delivery_opaque);
+    }
+
+    fn on_send(&self, record: &mut InterceptedRecord) {
+        let headers = record
+            .take_headers()
+            .unwrap_or_default()
+            .add("trace-id", record.topic());
+        record.set_headers(headers);
+    }
+}
+
+#[test]
+fn test_base_producer_on_send() {
+    let collecting = CollectingContext::new();
+    let context = TraceIdContext {
+        collecting: collecting.clone(),
+    };
+    let producer = base_producer_with_context(context, HashMap::new());
+    let topic_name = rand_test_topic();
+
+    for id in 0..10 {
+        producer
+            .send::<str, str>(BaseRecord::with_opaque_to(&topic_name, id).payload("A"))
+            .unwrap();
+    }
+    producer.flush(Duration::from_secs(10)).unwrap();
+
+    let delivery_results = collecting.results.lock().unwrap();
+    assert_eq!(delivery_results.len(), 10);
+    for (message, error, _) in delivery_results.iter() {
+        assert_eq!(error, &None);
+        let headers = message.headers().unwrap();
+        assert_eq!(headers.count(), 1);
+        assert_eq!(
+            headers.get_as::<str>(0),
+            Some(("trace-id", Ok(topic_name.as_str())))
+        );
+    }
+}
+
+#[test]
+fn test_base_producer_send_batch() {
+    let context = CollectingContext::new();
+    let producer = base_producer_with_context(
+        context.clone(),
+        map!("queue.buffering.max.messages" => "10"),
+    );
+    let topic_name = rand_test_topic();
+
+    // The batch doesn't fit in the producer queue, so sending it requires
+    // waiting for some messages to be delivered.
+    let failures = producer.send_batch((0..30).map(|id| {
+        BaseRecord::with_opaque_to(&topic_name, id)
+            .payload("payload")
+            .key("key")
+    }));
+    assert!(failures.is_empty());
+    producer.flush(Duration::from_secs(10)).unwrap();
+
+    let delivery_results = context.results.lock().unwrap();
+    let mut ids = HashSet::new();
+    for (_, error, id) in delivery_results.iter() {
+        assert_eq!(error, &None);
+        ids.insert(*id);
+    }
+    assert_eq!(ids.len(), 30);
+}
+
+#[test]
+fn test_base_producer_send_batch_failures() {
+    let producer = base_producer(HashMap::new());
+    let topic_name = rand_test_topic();
+    let too_big = vec![0u8; 2_000_000];
+
+    let failures = producer.send_batch(vec![
+        BaseRecord::<(), [u8], _>::with_opaque_to(&topic_name, 0).payload(&b"payload"[..]),
+        BaseRecord::with_opaque_to(&topic_name, 1).payload(too_big.as_slice()),
+        BaseRecord::with_opaque_to(&topic_name, 2).payload(&b"payload"[..]),
+    ]);
+    assert_eq!(
+        failures,
+        vec![(
+            1,
+            KafkaError::MessageProduction(RDKafkaErrorCode::MessageSizeTooLarge)
+        )]
+    );
+    producer.flush(Duration::from_secs(10)).unwrap();
+}
+
+#[test]
+fn test_base_producer_delivery_report_stream() {
+    let producer = base_producer_with_context(
+        DeliveryStreamContext::<usize>::new(DefaultClientContext),
+        HashMap::new(),
+    );
+    let stream = producer.delivery_report_stream().unwrap();
+    let topic_name = rand_test_topic();
+
+    for id in 0..10 {
+        let key = format!("key{}", id);
+        producer
+            .send(
+                BaseRecord::with_opaque_to(&topic_name, id)
+                    .payload("payload")
+                    .key(&key),
+            )
+            .unwrap();
+    }
+    producer.flush(Duration::from_secs(10)).unwrap();
+    drop(producer);
+
+    let reports = stream.collect().wait().unwrap();
+    assert_eq!(reports.len(), 10);
+    let mut ids = HashSet::new();
+    for (result, id) in reports {
+        let message = result.unwrap();
+        assert_eq!(
+            message.key_view::<str>(),
+            Some(Ok(format!("key{}", id).as_str()))
+        );
+        assert!(message.partition() >= 0);
+        assert!(message.offset() >= 0);
+        ids.insert(id);
+    }
+    assert_eq!(ids.len(), 10);
+}
@@ -0,0 +1,127 @@
+//! Test the recycling of message buffers with a `MessagePool`.
+extern crate futures;
+extern crate rand;
+extern crate rdkafka;
+
+use futures::Stream;
+
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{BaseConsumer, Consumer, StreamConfig, StreamConsumer};
+use rdkafka::message::{Message, MessagePool, OwnedMessage};
+
+mod utils;
+use crate::utils::*;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::Arc;
+
+/// An allocator counting the allocations performed by each thread.
+struct CountingAllocator;
+
+thread_local! {
+    // Not const-initialized, which requires a newer compiler than the one supported.
+    #[allow(clippy::missing_const_for_thread_local)]
+    static ALLOCATIONS: Cell<usize> = Cell::new(0);
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Returns the number of allocations performed by the current thread while running `f`.
+fn count_allocations<F: FnOnce()>(f: F) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+fn consumer_config(group_id: &str) -> ClientConfig {
+    let mut config = ClientConfig::new();
+    config
+        .set("group.id", group_id)
+        .set("bootstrap.servers", get_bootstrap_server().as_str())
+        .set("session.timeout.ms", "6000")
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "earliest");
+    config
+}
+
+// Detaching messages with a pool should allocate less than detaching them without.
+#[test]
+fn test_message_pool_allocations() {
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, 100, &value_fn, &key_fn, Some(0), None);
+    let consumer: BaseConsumer = consumer_config(&rand_test_group())
+        .create()
+        .expect("Consumer creation failed");
+    consumer.subscribe(&[topic_name.as_str()]).unwrap();
+    let messages = consumer
+        .iter()
+        .take(100)
+        .map(Result::unwrap)
+        .collect::<Vec<_>>();
+
+    let pool = Arc::new(MessagePool::new(10));
+    // Warm up the pool.
+    drop(messages[0].detach_with_pool(&pool));
+    assert_eq!(pool.len(), 2);
+
+    let without_pool = count_allocations(|| {
+        for message in &messages {
+            let owned: OwnedMessage = message.detach();
+            assert!(owned.payload().is_some());
+        }
+    });
+    let with_pool = count_allocations(|| {
+        for message in &messages {
+            let owned = message.detach_with_pool(&pool);
+            assert!(owned.payload().is_some());
+        }
+    });
+    // Without the pool the key, the payload and the topic are allocated for each message, with
+    // the pool only the topic is.
+    assert!(with_pool <= without_pool - 2 * messages.len());
+    assert_eq!(pool.len(), 2);
+}
+
+// Messages of a stream configured with a pool should be detached using the pool.
+#[test]
+fn test_stream_consumer_message_pool() {
+    let topic_name = rand_test_topic();
+    let message_map = populate_topic(&topic_name, 10, &value_fn, &key_fn, Some(0), None);
+    let consumer: StreamConsumer = consumer_config(&rand_test_group())
+        .create()
+        .expect("Consumer creation failed");
+    consumer.subscribe(&[topic_name.as_str()]).unwrap();
+
+    let pool = Arc::new(MessagePool::new(10));
+    let detached = consumer
+        .start_with_config(StreamConfig::new().message_pool(pool.clone()))
+        .take(10)
+        .wait()
+        .map(|message| message.unwrap().unwrap().detach())
+        .collect::<Vec<_>>();
+    assert!(pool.is_empty());
+    for message in &detached {
+        let id = message_map[&(message.partition(), message.offset())];
+        assert_eq!(message.key_view::<str>().unwrap().unwrap(), key_fn(id));
+        assert_eq!(
+            message.payload_view::<str>().unwrap().unwrap(),
+            value_fn(id)
+        );
+    }
+
+    drop(detached);
+    assert_eq!(pool.len(), 10);
+}
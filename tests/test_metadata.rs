@@ -87,6 +87,15 @@ fn test_metadata() {
         .fetch_metadata(Some(&topic_name), Duration::from_secs(5))
         .unwrap();
     assert_eq!(metadata_one_topic.topics().len(), 1);
+
+    let owned_metadata = consumer
+        .fetch_metadata_for_topic(&topic_name, Duration::from_secs(5))
+        .unwrap();
+    assert_eq!(owned_metadata.brokers().len(), 1);
+    assert_eq!(owned_metadata.topics().len(), 1);
+    assert_eq!(owned_metadata.topics()[0].name(), topic_name);
+    assert_eq!(owned_metadata.topics()[0].partitions().len(), 3);
+    assert_eq!(owned_metadata.topics()[0].partitions()[0].isr(), &[0]);
 }
 
 #[test]
@@ -0,0 +1,93 @@
+//! Test seeking to the last messages of a partition with `Offset::OffsetTail`.
+extern crate env_logger;
+extern crate rdkafka;
+
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::error::KafkaError;
+use rdkafka::topic_partition_list::{Offset, TopicPartitionList};
+use rdkafka::{ClientConfig, Message};
+
+mod utils;
+use crate::utils::*;
+
+use std::time::Duration;
+
+fn create_base_consumer(group_id: &str) -> BaseConsumer {
+    ClientConfig::new()
+        .set("group.id", group_id)
+        .set("client.id", "rdkafka_integration_test_client")
+        .set("bootstrap.servers", get_bootstrap_server().as_str())
+        .set("enable.partition.eof", "false")
+        .set("session.timeout.ms", "6000")
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "earliest")
+        .create()
+        .expect("Consumer creation failed")
+}
+
+// Seeking to the tail of a partition should replay its last messages.
+#[test]
+fn test_seek_offset_tail() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    populate_topic(&topic_name, 5, &value_fn, &key_fn, Some(0), None);
+    let consumer = create_base_consumer(&rand_test_group());
+    consumer.subscribe(&[topic_name.as_str()]).unwrap();
+
+    for (i, message) in consumer.iter().take(5).enumerate() {
+        match message {
+            Ok(message) => assert_eq!(message.offset(), i as i64),
+            Err(e) => panic!("Error receiving message: {:?}", e),
+        }
+    }
+
+    consumer
+        .seek(&topic_name, 0, Offset::OffsetTail(2), None)
+        .unwrap();
+
+    for (i, message) in consumer.iter().take(2).enumerate() {
+        match message {
+            Ok(message) => assert_eq!(message.offset(), i as i64 + 3),
+            Err(e) => panic!("Error receiving message: {:?}", e),
+        }
+    }
+
+    assert_eq!(
+        consumer
+            .seek_and_wait(
+                &topic_name,
+                0,
+                Offset::OffsetTail(10),
+                Duration::from_secs(10)
+            )
+            .unwrap(),
+        Offset::Offset(0)
+    );
+}
+
+// A negative tail can't be represented by librdkafka, and should be rejected before seeking.
+#[test]
+fn test_seek_negative_offset_tail() {
+    let _r = env_logger::try_init();
+
+    let topic_name = rand_test_topic();
+    let consumer = create_base_consumer(&rand_test_group());
+    let mut tpl = TopicPartitionList::new();
+    tpl.add_partition(&topic_name, 0);
+    consumer.assign(&tpl).unwrap();
+
+    match consumer.seek(&topic_name, 0, Offset::OffsetTail(-1), None) {
+        Err(KafkaError::Seek(_)) => {}
+        result => panic!("Unexpected result: {:?}", result),
+    }
+    match consumer.seek_and_wait(
+        &topic_name,
+        0,
+        Offset::OffsetTail(-1),
+        Duration::from_secs(10),
+    ) {
+        Err(KafkaError::Seek(_)) => {}
+        result => panic!("Unexpected result: {:?}", result),
+    }
+}